@@ -0,0 +1,101 @@
+use std::time::Instant;
+
+use tensorflake::{functions::*, *};
+
+fn bench(name: &str, n: usize, f: impl Fn() -> ComputedNDA, xs: &[ComputedNDA]) {
+    let mut times = Vec::new();
+    for _ in 0..n {
+        let y = f();
+        let start = Instant::now();
+        gradients(&[y], xs, false);
+        times.push(start.elapsed());
+    }
+    times.sort();
+    println!("{:<24} {:>10.3?} (median of {})", name, times[n / 2], n);
+}
+
+/// Measures forward and backward.
+fn bench_step(name: &str, n: usize, f: impl Fn() -> ComputedNDA, xs: &[ComputedNDA]) {
+    let mut times = Vec::new();
+    for _ in 0..n {
+        let start = Instant::now();
+        let y = f();
+        gradients(&[y], xs, false);
+        times.push(start.elapsed());
+    }
+    times.sort();
+    println!("{:<24} {:>10.3?} (median of {})", name, times[n / 2], n);
+}
+
+fn main() {
+    // a long chain of 10k function calls
+    let x = backprop(NDArray::ones(&[4, 4][..]));
+    bench(
+        "chain 10k",
+        5,
+        || {
+            let mut y = x.clone();
+            for _ in 0..10_000 {
+                y = &y * &x;
+            }
+            y
+        },
+        &[x.clone()],
+    );
+
+    // an unrolled rnn of 2.5k steps (10k function calls)
+    let w = backprop(NDArray::ones(&[4, 4][..]) * 0.1);
+    let xs: Vec<_> = (0..2_500)
+        .map(|_| ComputedNDA::new(NDArray::ones(&[1, 4][..])))
+        .collect();
+    bench(
+        "unrolled rnn 10k",
+        5,
+        || {
+            let mut h = ComputedNDA::new(NDArray::zeros(&[1, 4][..]));
+            for x in &xs {
+                h = (&matmul(&h, &w) + x).tanh();
+            }
+            h.sum([0, 1], false)
+        },
+        &[w.clone()],
+    );
+
+    // element-wise operations of a normalization
+    let x = backprop(NDArray::ones(&[64, 64, 64][..]));
+    let mean = ComputedNDA::new(NDArray::ones(&[64, 1, 1][..]));
+    let std = ComputedNDA::new(NDArray::ones(&[64, 1, 1][..]));
+    let gamma = backprop(NDArray::ones(&[64, 64][..]));
+    let beta = backprop(NDArray::zeros(&[64, 64][..]));
+    let normalize = || (&x - &mean) * (&gamma / &std) + beta.clone();
+    let params = [x.clone(), gamma.clone(), beta.clone()];
+    bench_step("normalization eager", 5, normalize, &params);
+    bench_step(
+        "normalization lazy",
+        5,
+        || {
+            let _guard = lazy();
+            normalize()
+        },
+        &params,
+    );
+    // memory of a backward of a deep network
+    let w: ComputedNDA = backprop(NDArray::ones(&[256, 256][..]) * 0.01);
+    for retain_graph in [true, false] {
+        let tracker = MemoryTracker::start();
+        let y = {
+            let mut y = ComputedNDA::new(NDArray::ones(&[64, 256][..]));
+            for _ in 0..50 {
+                y = matmul(&y, &w).tanh();
+            }
+            y.sum([0, 1], false)
+        };
+        gradients_with(&[y.clone()], &[w.clone()], false, retain_graph);
+        println!(
+            "{:<24} {:>10} bytes (held by the loss after backward: {})",
+            format!("peak retain_graph={}", retain_graph),
+            tracker.peak_bytes(),
+            tracker.current_bytes()
+        );
+    }
+}
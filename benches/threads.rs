@@ -0,0 +1,58 @@
+use std::time::Instant;
+
+use rayon::prelude::*;
+use tensorflake::{functions::*, *};
+
+/// A forward and backward pass of a small MLP whose params are shared by all threads.
+fn step(params: &[ParamNDA], x: &ComputedNDA) -> GradientsAccumulator<NDArray> {
+    let mut y = x.clone();
+    for (i, w) in params.iter().enumerate() {
+        y = matmul(&y, &w.get());
+        if i + 1 < params.len() {
+            y = y.tanh();
+        }
+    }
+    let loss = y.sum([0, 1], false);
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&loss);
+    ga
+}
+
+fn bench_threads(name: &str, threads: usize, steps: usize, params: &[ParamNDA], x: &ComputedNDA) {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    let mut times = Vec::new();
+    for _ in 0..5 {
+        let start = Instant::now();
+        pool.install(|| {
+            (0..steps).into_par_iter().for_each(|_| {
+                step(params, x);
+            })
+        });
+        times.push(start.elapsed());
+    }
+    times.sort();
+    let steps_per_sec = steps as f64 / times[2].as_secs_f64();
+    println!(
+        "{:<24} {:>2} threads {:>10.0} steps/s (median of 5)",
+        name, threads, steps_per_sec
+    );
+}
+
+fn main() {
+    let params: Vec<_> = (0..8)
+        .map(|i| {
+            ParamNDA::new(
+                NDArray::ones(&[8, 8][..]) * 0.1,
+                format!("w{}", i).into(),
+                optimizers::SGD::new(0.01),
+            )
+        })
+        .collect();
+    let x = ComputedNDA::new(NDArray::ones(&[4, 8][..]));
+    for threads in [1, 2, 4, 8] {
+        bench_threads("mlp 8x8 forward/backward", threads, 5_000, &params, &x);
+    }
+}
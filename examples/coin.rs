@@ -30,7 +30,7 @@ fn main() {
     );
 
     let model = Model::new(init_kernel.scope("coin"), init_bias.scope("coin"));
-    // param_bin::import_from_file(&mut model.all_params(), "coin_50.bin");
+    // param_bin::import_from_file(&mut model.all_params(), "coin_50.bin").unwrap();
     // gen_image([img.height(), img.width()], &model, "coin_final.png");
     // return;
 
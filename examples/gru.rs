@@ -19,7 +19,7 @@ use tensorflake::{
         rnn::{Cell, Gru},
         *,
     },
-    training::{TrainConfig, UpdateStrategy},
+    training::{ClipGrad, TrainConfig, UpdateStrategy},
     *,
 };
 
@@ -76,6 +76,7 @@ fn main() {
         batch_size: usize::MAX,
         parallel_chunk_size: 100,
         update_strategy: UpdateStrategy::Chunk(1),
+        clip_grad: Some(ClipGrad::Norm(5.0)),
         ..Default::default()
     }
     .build();
@@ -113,9 +114,7 @@ fn main() {
             let yy = concat(&y.iter().skip(eqp - 1).cloned().collect::<Vec<_>>(), 0);
             let yy = output_fn(yy);
             let loss = softmax_cross_entropy(t, &yy);
-            if ctx.train {
-                optimize(&loss);
-            }
+            ctx.optimize(&loss);
             ctx.count(strs.len());
             ctx.add_metric(metrics::Loss::new(loss[[]], strs.len()));
         });
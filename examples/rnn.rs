@@ -47,6 +47,7 @@ fn main() {
             let loss = softmax_cross_entropy(arith::encode(&str[eqp + 1..]), &yy);
             gradients.compute(&loss);
             if i % 10 == 0 {
+                gradients.clip_grad_norm(5.0);
                 gradients.optimize();
             }
             if i % 5000 == 0 {
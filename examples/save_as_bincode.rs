@@ -3,17 +3,17 @@ use tensorflake::{
     initializers::{
         random_initializer::RandomInitializer, with_optimizer::InitializerWithOptimizer, Scope,
     },
-    nn::Linear,
-    optimizers,
+    nn::{Layer, Linear},
+    optimize, optimizers, ComputedNDA, NDArray,
 };
 
 fn main() {
     let init = InitializerWithOptimizer::new(
         RandomInitializer::new(Uniform::new(0., 0.01)),
-        optimizers::SGD::new(0.01),
+        optimizers::Adam::new(),
     );
 
-    let linear = Linear::new(10, 10, init.scope("w"), Some(init.scope("b")));
+    let linear: Linear = Linear::new(10, 10, init.scope("w"), Some(init.scope("b")));
 
     dbg!(&*linear.b.as_ref().unwrap().get());
 
@@ -30,5 +30,11 @@ fn main() {
         let mut r = std::fs::File::open(file).unwrap();
         let linear: Linear = bincode::deserialize_from(&mut r).unwrap();
         dbg!(&*linear.b.as_ref().unwrap().get());
+
+        // the optimizer states are restored, so the training can be resumed.
+        // optimizers other than the ones in `optimizers` need `param::register_optimizer`
+        let y = linear.call(ComputedNDA::new(NDArray::ones(&[1, 10][..])), true);
+        optimize(&y.sum(vec![0, 1], false));
+        dbg!(&*linear.b.as_ref().unwrap().get());
     }
 }
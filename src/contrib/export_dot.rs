@@ -1,6 +1,6 @@
 use std::sync::{Arc, Weak};
 
-use crate::{graph::collect_function_calls, ComputedNDA};
+use crate::{graph::collect_function_calls, ComputedNDA, FunctionCall, NDArray, Profile};
 
 pub fn export_dot(vars: &[ComputedNDA], file: &str) -> Result<(), std::io::Error> {
     let f = std::fs::File::create(file).unwrap();
@@ -9,10 +9,77 @@ pub fn export_dot(vars: &[ComputedNDA], file: &str) -> Result<(), std::io::Error
     write_dot(&mut w, vars, &mut default_var_printer)
 }
 
+/// Exports the graph with the time of each function measured by `Profiler`.
+pub fn export_dot_with_profile(
+    vars: &[ComputedNDA],
+    profile: &Profile,
+    file: &str,
+) -> Result<(), std::io::Error> {
+    let f = std::fs::File::create(file).unwrap();
+    let mut w = std::io::BufWriter::new(f);
+
+    write_dot_with_profile(&mut w, vars, &mut default_var_printer, profile)
+}
+
 pub fn write_dot(
     w: &mut impl std::io::Write,
     vars: &[ComputedNDA],
     var_printer: &mut impl FnMut(&ComputedNDA) -> String,
+) -> Result<(), std::io::Error> {
+    write_graph(w, vars, var_printer, &mut |fc| {
+        format!(
+            "label={:?} color=lightblue, style=filled, shape=box",
+            fc.backward.get_function_name()
+        )
+    })
+}
+
+/// Writes the graph with the forward and backward time of each function in its label.
+/// Functions are colored redder as they take more time.
+pub fn write_dot_with_profile(
+    w: &mut impl std::io::Write,
+    vars: &[ComputedNDA],
+    var_printer: &mut impl FnMut(&ComputedNDA) -> String,
+    profile: &Profile,
+) -> Result<(), std::io::Error> {
+    let times = profile.node_times();
+    let max_time = times
+        .values()
+        .map(|(f, b)| f.unwrap_or_default() + b.unwrap_or_default())
+        .max()
+        .unwrap_or_default();
+
+    write_graph(w, vars, var_printer, &mut |fc| {
+        let mut label = fc.backward.get_function_name().to_string();
+        let (forward, backward) = times
+            .get(&(Arc::as_ptr(fc) as usize))
+            .cloned()
+            .unwrap_or_default();
+        if let Some(t) = forward {
+            label += &format!("\\nforward {:.3?}", t);
+        }
+        if let Some(t) = backward {
+            label += &format!("\\nbackward {:.3?}", t);
+        }
+        let time = forward.unwrap_or_default() + backward.unwrap_or_default();
+        let ratio = if max_time.is_zero() {
+            0.0
+        } else {
+            time.as_secs_f64() / max_time.as_secs_f64()
+        };
+        format!(
+            "label=\"{}\" color=\"0.000 {:.3} 1.000\", style=filled, shape=box",
+            label.replace('"', "\\\""),
+            ratio
+        )
+    })
+}
+
+fn write_graph(
+    w: &mut impl std::io::Write,
+    vars: &[ComputedNDA],
+    var_printer: &mut impl FnMut(&ComputedNDA) -> String,
+    fc_printer: &mut impl FnMut(&Arc<FunctionCall<NDArray>>) -> String,
 ) -> Result<(), std::io::Error> {
     let fcs = collect_function_calls(vars.to_vec());
     let mut vars = fcs
@@ -37,12 +104,7 @@ pub fn write_dot(
 
     for fc in fcs.iter() {
         let fc_id = Arc::as_ptr(fc) as usize;
-        let fc_name = fc.backward.get_function_name();
-        writeln!(
-            w,
-            "{} [label={:?} color=lightblue, style=filled, shape=box]",
-            fc_id, fc_name
-        )?;
+        writeln!(w, "{} [{}]", fc_id, fc_printer(fc))?;
 
         for v in fc.xs.iter() {
             let v_id = Arc::as_ptr(&v.inner) as usize;
@@ -84,4 +146,18 @@ fn test() {
     })
     .unwrap();
     println!("{}", String::from_utf8(w).unwrap());
+
+    // print the time of functions
+    let a = backprop(scalar(2.0)).named("a");
+    let b = backprop(scalar(3.0)).named("b");
+    let profiler = crate::Profiler::start();
+    let y = (&a * &b).named("y");
+    let _ = crate::gradients(&[y.clone()], &[a], false);
+    let profile = profiler.stop();
+    let mut w = Vec::new();
+    write_dot_with_profile(&mut w, &[y.clone()], &mut default_var_printer, &profile).unwrap();
+    let dot = String::from_utf8(w).unwrap();
+    println!("{}", dot);
+    assert!(dot.contains("\\nforward "));
+    assert!(dot.contains("\\nbackward "));
 }
@@ -39,16 +39,8 @@ impl PuningLinear {
     pub fn build(&self) -> Self {
         Self {
             output_size: self.output_size,
-            w: Param::new(
-                (*self.w.get()).clone(),
-                self.w.get_function_name(),
-                Fixed,
-            ),
-            b: Param::new(
-                (*self.b.get()).clone(),
-                self.w.get_function_name(),
-                Fixed,
-            ),
+            w: Param::new((*self.w.get()).clone(), self.w.get_function_name(), Fixed),
+            b: Param::new((*self.b.get()).clone(), self.w.get_function_name(), Fixed),
             pruned_w: Arc::new(Mutex::new(self.pruned_w.lock().unwrap().clone())),
         }
     }
@@ -78,6 +70,7 @@ pub fn pruning_linear_forward(
     b: &Computed,
     pruned_w: Arc<Vec<(usize, usize, f32)>>,
 ) -> Computed {
+    let _scope = profile_scope("puning_linear");
     let xa = (**x).to_owned().into_dimensionality::<Ix2>().unwrap();
     let mut y = (**b)
         .to_owned()
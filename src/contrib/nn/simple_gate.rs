@@ -6,6 +6,7 @@ use ndarray::{azip, Array, Axis};
 use crate::*;
 
 pub fn simple_gate(x: &ComputedNDA, axis: usize) -> ComputedNDA {
+    let _scope = profile_scope("simple_gate");
     let (a, b) = x.view().split_at(Axis(axis), x.shape()[axis] / 2);
     let mut y = NDArray::zeros(a.shape());
     azip!((a in &a, b in &b, c in &mut y) *c = a * b);
@@ -1,43 +1,119 @@
 use std::io::{Read, Write};
 
-use crate::*;
+use crate::{nn::StateDict, *};
 
 pub fn export_to_file(params: &[ParamNDA], path: &str) {
     let f = std::fs::File::create(path).unwrap();
     let mut writer = std::io::BufWriter::new(f);
     for param in params {
-        let tensor = param.get();
-        writer
-            .write_all(&(tensor.ndim() as u32).to_le_bytes())
-            .unwrap();
-        for s in tensor.shape() {
-            writer.write_all(&(*s as u32).to_le_bytes()).unwrap();
-        }
-        for x in tensor.iter() {
-            writer.write_all(&x.to_le_bytes()).unwrap();
-        }
+        write_ndarray(&mut writer, &param.get()).unwrap();
     }
+    writer.flush().unwrap();
 }
 
-pub fn import_from_file(params: &mut [ParamNDA], path: &str) {
-    let f = std::fs::File::open(path).unwrap();
+/// Reads the params in the order they were exported.
+/// Fails without changing any param if the file does not match the shapes of `params`.
+/// Prefer `export_state_dict` and `import_state_dict`, which match the params by name.
+pub fn import_from_file(params: &mut [ParamNDA], path: &str) -> std::io::Result<()> {
+    let f = std::fs::File::open(path)?;
     let mut reader = std::io::BufReader::new(f);
-    let mut buf = [0u8; 4];
-    for param in params {
-        reader.read(&mut buf).unwrap();
-        let ndim = u32::from_le_bytes(buf) as usize;
-        let mut shape = Vec::new();
-        for _ in 0..ndim {
-            reader.read(&mut buf).unwrap();
-            shape.push(u32::from_le_bytes(buf) as usize);
+    let mut tensors = Vec::with_capacity(params.len());
+    for param in params.iter() {
+        let tensor = read_ndarray(&mut reader)?;
+        let shape = param.get().shape().to_vec();
+        if tensor.shape() != shape {
+            return Err(invalid_data(format!(
+                "the shape of {} is {:?}, but {:?} is in the file",
+                param.name(),
+                shape,
+                tensor.shape()
+            )));
         }
-        let mut data = Vec::with_capacity(shape.iter().product());
-        for _ in 0..shape.iter().product() {
-            reader.read(&mut buf).unwrap();
-            data.push(f32::from_le_bytes(buf));
+        tensors.push(tensor);
+    }
+    for (param, tensor) in params.iter_mut().zip(tensors) {
+        param.set(tensor);
+    }
+    Ok(())
+}
+
+/// Writes `state_dict` (see `Layer::state_dict`) with the names of the params.
+pub fn export_state_dict(state_dict: &StateDict, path: &str) -> std::io::Result<()> {
+    let f = std::fs::File::create(path)?;
+    let mut writer = std::io::BufWriter::new(f);
+    writer.write_all(&(state_dict.len() as u32).to_le_bytes())?;
+    for (name, tensor) in state_dict {
+        writer.write_all(&(name.len() as u32).to_le_bytes())?;
+        writer.write_all(name.as_bytes())?;
+        write_ndarray(&mut writer, tensor)?;
+    }
+    writer.flush()
+}
+
+/// Reads a state dict written by `export_state_dict`. Load it with `Layer::load_state_dict`.
+pub fn import_state_dict(path: &str) -> std::io::Result<StateDict> {
+    let f = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(f);
+    let len = read_u32(&mut reader)?;
+    let mut state_dict = StateDict::new();
+    for _ in 0..len {
+        let len = read_u32(&mut reader)? as u64;
+        let mut name = Vec::new();
+        (&mut reader).take(len).read_to_end(&mut name)?;
+        if name.len() as u64 != len {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
         }
-        param.set(NDArray::from_shape_vec(shape, data).unwrap());
+        let name = String::from_utf8(name).map_err(invalid_data)?;
+        state_dict.insert(name, read_ndarray(&mut reader)?);
+    }
+    Ok(state_dict)
+}
+
+fn write_ndarray(writer: &mut impl Write, tensor: &NDArray) -> std::io::Result<()> {
+    writer.write_all(&(tensor.ndim() as u32).to_le_bytes())?;
+    for s in tensor.shape() {
+        writer.write_all(&(*s as u32).to_le_bytes())?;
+    }
+    for x in tensor.iter() {
+        writer.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// The largest `ndim` accepted, to fail on corrupt headers before allocating.
+const MAX_NDIM: usize = 32;
+
+fn read_ndarray(reader: &mut impl Read) -> std::io::Result<NDArray> {
+    let ndim = read_u32(reader)? as usize;
+    if ndim > MAX_NDIM {
+        return Err(invalid_data(format!("ndim {} is too large", ndim)));
+    }
+    let mut shape = Vec::with_capacity(ndim);
+    for _ in 0..ndim {
+        shape.push(read_u32(reader)? as usize);
+    }
+    let len = shape
+        .iter()
+        .try_fold(1usize, |a, &s| a.checked_mul(s))
+        .ok_or_else(|| invalid_data(format!("the shape {:?} is too large", shape)))?;
+    // grow as the data is read, since a corrupt header can claim any length
+    let mut data = Vec::with_capacity(len.min(1 << 16));
+    let mut buf = [0u8; 4];
+    for _ in 0..len {
+        reader.read_exact(&mut buf)?;
+        data.push(f32::from_le_bytes(buf));
     }
+    NDArray::from_shape_vec(shape, data).map_err(invalid_data)
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
 }
 
 pub fn params_summary(params: &[ParamNDA]) {
@@ -64,7 +140,52 @@ fn test() {
     ];
     let path = "/tmp/tensorflake_param_bin_test.bin";
     export_to_file(&params, path);
-    import_from_file(&mut params, path);
+    import_from_file(&mut params, path).unwrap();
     assert_eq!(&*params[0].get(), &ndarrays[0]);
     assert_eq!(&*params[1].get(), &ndarrays[1]);
+
+    // the shapes must match
+    params.swap(0, 1);
+    assert!(import_from_file(&mut params, path).is_err());
+    assert_eq!(&*params[0].get(), &ndarrays[1]);
+}
+
+#[test]
+fn test_state_dict() {
+    let state_dict: StateDict = [
+        (
+            "a:w".to_string(),
+            NDArray::from_shape_vec(vec![2], vec![1.0, 2.0]).unwrap(),
+        ),
+        ("b".to_string(), scalar(3.0)),
+    ]
+    .into_iter()
+    .collect();
+    let path = "/tmp/tensorflake_param_bin_test_state_dict.bin";
+    export_state_dict(&state_dict, path).unwrap();
+    assert_eq!(import_state_dict(path).unwrap(), state_dict);
+
+    // a truncated file is an error
+    let bytes = std::fs::read(path).unwrap();
+    std::fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(import_state_dict(path).is_err());
+
+    // corrupt headers are errors, not panics or huge allocations
+    let header = |ndim: u32, shape: &[u32]| {
+        let mut bytes = [1u32.to_le_bytes(), 1u32.to_le_bytes()].concat();
+        bytes.push(b'a');
+        bytes.extend(ndim.to_le_bytes());
+        for s in shape {
+            bytes.extend(s.to_le_bytes());
+        }
+        bytes
+    };
+    for bytes in [
+        header(u32::MAX, &[]),
+        header(8, &[u32::MAX; 8]),
+        header(2, &[u32::MAX, u32::MAX]),
+    ] {
+        std::fs::write(path, bytes).unwrap();
+        assert!(import_state_dict(path).is_err());
+    }
 }
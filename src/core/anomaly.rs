@@ -0,0 +1,207 @@
+use std::{
+    any::Any,
+    borrow::Cow,
+    cell::{Cell, RefCell},
+};
+
+use super::Computed;
+use crate::{float::as_float_array, Error};
+
+/// What to do when a function outputs or a backward returns a non-finite value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyMode {
+    Disabled,
+    /// Panic at the function that produced the value.
+    Panic,
+    /// Make `try_gradients` return `Error::Anomaly`.
+    ///
+    /// Anomalies found in forward are kept on the thread until `take_anomaly`
+    /// or `try_gradients` is called, or the guard restores a mode other than `Error`.
+    Error,
+}
+
+thread_local! {
+    static ANOMALY_MODE: Cell<AnomalyMode> = const { Cell::new(AnomalyMode::Disabled) };
+    static PENDING_ANOMALY: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous anomaly mode of the current thread when dropped.
+///
+/// Pending anomalies are discarded unless the previous mode is `Error`,
+/// so they are not reported by a later `try_gradients` outside the scope.
+#[must_use]
+pub struct AnomalyModeGuard {
+    prev: AnomalyMode,
+}
+
+impl Drop for AnomalyModeGuard {
+    fn drop(&mut self) {
+        ANOMALY_MODE.with(|m| m.set(self.prev));
+        if self.prev != AnomalyMode::Error {
+            take_anomaly();
+        }
+    }
+}
+
+pub fn anomaly_mode() -> AnomalyMode {
+    ANOMALY_MODE.with(|m| m.get())
+}
+
+/// Sets the anomaly mode of the current thread until the guard is dropped.
+///
+/// Every output of `chain` and every gradient returned by `Backward::backward`
+/// is checked for NaN and infinity, which slows down the computation.
+pub fn set_anomaly_mode(mode: AnomalyMode) -> AnomalyModeGuard {
+    let prev = ANOMALY_MODE.with(|m| m.replace(mode));
+    AnomalyModeGuard { prev }
+}
+
+/// Takes the first anomaly found in forward on the current thread.
+pub fn take_anomaly() -> Option<Error> {
+    PENDING_ANOMALY.with(|p| p.borrow_mut().take())
+}
+
+pub(crate) fn check_forward<T: 'static>(
+    function: &'static str,
+    xs: &[Computed<T>],
+    ys: &[Computed<T>],
+) {
+    if anomaly_mode() == AnomalyMode::Disabled || ys.iter().all(|y| is_finite(&**y)) {
+        return;
+    }
+    let error = anomaly(function.into(), false, xs);
+    match anomaly_mode() {
+        AnomalyMode::Panic => panic!("{}", error),
+        _ => PENDING_ANOMALY.with(|p| {
+            p.borrow_mut().get_or_insert(error);
+        }),
+    }
+}
+
+pub(crate) fn check_backward<T: 'static>(
+    function: Cow<'static, str>,
+    xs: &[Computed<T>],
+    gxs: &[Computed<T>],
+) -> Result<(), Error> {
+    if anomaly_mode() == AnomalyMode::Disabled || gxs.iter().all(|gx| is_finite(&**gx)) {
+        return Ok(());
+    }
+    let error = anomaly(function, true, xs);
+    match anomaly_mode() {
+        AnomalyMode::Panic => panic!("{}", error),
+        _ => Err(error),
+    }
+}
+
+fn anomaly<T: 'static>(function: Cow<'static, str>, backward: bool, xs: &[Computed<T>]) -> Error {
+    Error::Anomaly {
+        function,
+        backward,
+        shapes: xs
+            .iter()
+            .map(|x| {
+                as_float_array(&**x as &dyn Any)
+                    .map(|x| x.shape().to_vec())
+                    .unwrap_or_default()
+            })
+            .collect(),
+        names: xs.iter().map(|x| x.get_name()).collect(),
+    }
+}
+
+fn is_finite<T: 'static>(x: &T) -> bool {
+    as_float_array(x as &dyn Any).is_none_or(|x| x.is_finite())
+}
+
+#[test]
+fn test() {
+    use crate::*;
+
+    let x = backprop(ndarray::array![1.0, -1.0].into_ndarray()).named("x");
+    let y = backprop(ndarray::array![0.0, 2.0].into_ndarray()).named("y");
+    let _guard = set_anomaly_mode(AnomalyMode::Error);
+
+    // forward
+    let z = x.log();
+    assert_eq!(
+        take_anomaly(),
+        Some(Error::Anomaly {
+            function: "log".into(),
+            backward: false,
+            shapes: vec![vec![2]],
+            names: vec!["x".into()],
+        })
+    );
+    assert_eq!(take_anomaly(), None);
+
+    // pending anomalies are returned by try_gradients
+    let _ = x.log();
+    assert!(matches!(
+        try_gradients(&[z], &[x.clone()], false),
+        Err(Error::Anomaly {
+            backward: false,
+            ..
+        })
+    ));
+
+    // backward
+    let z = &x / &y;
+    let _ = take_anomaly();
+    assert_eq!(
+        try_gradients(&[z], &[x.clone()], false).err(),
+        Some(Error::Anomaly {
+            function: "div".into(),
+            backward: true,
+            shapes: vec![vec![2], vec![2]],
+            names: vec!["x".into(), "y".into()],
+        })
+    );
+
+    // anomalies in an ended scope are not reported
+    {
+        let _guard = set_anomaly_mode(AnomalyMode::Disabled);
+        {
+            let _guard = set_anomaly_mode(AnomalyMode::Error);
+            let _ = x.log();
+        }
+        let z = &x * &x;
+        assert!(try_gradients(&[z], &[x.clone()], false).is_ok());
+    }
+
+    // the mode is per thread
+    std::thread::spawn(|| assert_eq!(anomaly_mode(), AnomalyMode::Disabled))
+        .join()
+        .unwrap();
+
+    let _guard = set_anomaly_mode(AnomalyMode::Panic);
+    let x = x.clone();
+    let result = std::panic::catch_unwind(|| x.log());
+    assert!(result.is_err());
+}
+
+#[cfg(feature = "half")]
+#[test]
+fn test_half() {
+    use crate::{ndarray_util::cast, *};
+
+    // 300^2 overflows in f16
+    let x = cast::<f32, half::f16>(&ndarray::array![1.0, 300.0].into_ndarray());
+    let x = backprop(x).named("x");
+    let _guard = set_anomaly_mode(AnomalyMode::Error);
+    let _ = &x * &x;
+    assert_eq!(
+        take_anomaly(),
+        Some(Error::Anomaly {
+            function: "mul".into(),
+            backward: false,
+            shapes: vec![vec![2], vec![2]],
+            names: vec!["x".into(), "x".into()],
+        })
+    );
+
+    // but not in bf16
+    let x = cast::<f32, half::bf16>(&ndarray::array![1.0, 300.0].into_ndarray());
+    let x = backprop(x);
+    let _ = &x * &x;
+    assert_eq!(take_anomaly(), None);
+}
@@ -1,6 +1,6 @@
-use std::{borrow::Cow, marker::PhantomData, sync::Arc};
+use std::{any::Any, borrow::Cow, marker::PhantomData, sync::Arc};
 
-use super::{Computed, FunctionCall};
+use super::{anomaly, is_grad_enabled, profiler, trace, Computed, FunctionCall};
 
 pub trait Backward<T>: Sync + Send + 'static {
     fn backward(
@@ -36,6 +36,7 @@ struct FnBackward<
 > {
     f: F,
     name: &'static str,
+    args: Option<FunctionArgs>,
     _t: PhantomData<T>,
 }
 
@@ -59,6 +60,21 @@ impl<
     fn get_function_name(&self) -> Cow<'static, str> {
         self.name.into()
     }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        self.args.as_ref().map(|a| a as &dyn Any)
+    }
+}
+
+/// The arguments of a function other than its inputs, kept by `chain_with_args` so that
+/// the function can be rebuilt from its `FunctionCall`, such as by `symbolic::Graph::from_recorded`.
+/// Returned by `Backward::as_any`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum FunctionArgs {
+    Sum { axes: Vec<usize>, keep_dim: bool },
+    Reshape(Vec<usize>),
+    Transpose(Vec<usize>),
+    Broadcast(Vec<usize>),
 }
 
 pub fn chain<T: Sync + Send + 'static>(
@@ -71,16 +87,62 @@ pub fn chain<T: Sync + Send + 'static>(
         + Send
         + 'static,
 ) {
+    chain_impl(xs, ys, force_create_graph, name, None, backward);
+}
+
+/// Same as `chain`, but keeps `args` in the function call.
+pub(crate) fn chain_with_args<T: Sync + Send + 'static>(
+    xs: &[Computed<T>],
+    ys: &[Computed<T>],
+    force_create_graph: bool,
+    name: &'static str,
+    args: FunctionArgs,
+    backward: impl Fn(&Vec<Computed<T>>, &Vec<Computed<T>>, &Vec<Computed<T>>) -> Vec<Computed<T>>
+        + Sync
+        + Send
+        + 'static,
+) {
+    chain_impl(xs, ys, force_create_graph, name, Some(args), backward);
+}
+
+fn chain_impl<T: Sync + Send + 'static>(
+    xs: &[Computed<T>],
+    ys: &[Computed<T>],
+    force_create_graph: bool,
+    name: &'static str,
+    args: Option<FunctionArgs>,
+    backward: impl Fn(&Vec<Computed<T>>, &Vec<Computed<T>>, &Vec<Computed<T>>) -> Vec<Computed<T>>
+        + Sync
+        + Send
+        + 'static,
+) {
+    let start = profiler::forward_start(name);
+    anomaly::check_forward(name, xs, ys);
+
+    let mut node = 0;
+    if !is_grad_enabled() {
+        profiler::record_forward(name, start, ys, node);
+        return;
+    }
+    let backward = FnBackward {
+        f: backward,
+        name,
+        args,
+        _t: Default::default(),
+    };
+    // the function call is bound to the plan if it is replayed
+    let Some(backward) = trace::record(xs, ys, force_create_graph, name, backward) else {
+        profiler::record_forward(name, start, ys, node);
+        return;
+    };
     if force_create_graph || xs.iter().any(|x| x.has_creator()) {
-        let backward = Box::new(FnBackward {
-            f: backward,
-            name,
-            _t: Default::default(),
-        });
-        let fc = FunctionCall::new(backward, xs.to_vec(), &ys);
+        let fc = FunctionCall::new(Box::new(backward), xs.to_vec(), &ys);
         let fc = Arc::new(fc);
+        node = Arc::as_ptr(&fc) as usize;
         for y in ys {
-            y.inner.attrs.lock().unwrap().creator = Some(fc.clone());
+            y.set_creator(fc.clone());
         }
     }
+
+    profiler::record_forward(name, start, ys, node);
 }
@@ -0,0 +1,140 @@
+use std::{collections::HashSet, sync::Arc};
+
+use super::{backprop, graph::collect_function_calls, is_grad_enabled, set_grad_enabled};
+use crate::{chain, gradients, ComputedNDA, Float, NDArray};
+
+/// Runs `f` without keeping its intermediate graph and recomputes it during backward.
+///
+/// Only the inputs and outputs of the segment are kept alive after the forward pass,
+/// which trades compute for memory. Params and other leaves used in `f` receive their
+/// gradients as usual. `f` is called again in backward, so it must be deterministic;
+/// keep layers that draw random numbers, such as `Dropout`, out of the segment.
+/// Higher order gradients through the segment are not supported.
+pub fn checkpoint<T: Float>(
+    f: impl Fn(&[ComputedNDA<T>]) -> Vec<ComputedNDA<T>> + Send + Sync + 'static,
+    xs: &[ComputedNDA<T>],
+) -> Vec<ComputedNDA<T>> {
+    if !is_grad_enabled() {
+        return f(xs);
+    }
+
+    let (ys, leaves) = {
+        let inner_xs: Vec<_> = xs.iter().map(|x| backprop((**x).clone())).collect();
+        let ys = f(&inner_xs);
+        let inner_xs: HashSet<_> = inner_xs.iter().map(|x| Arc::as_ptr(&x.inner)).collect();
+        // params and the other leaves reachable from the segment
+        let leaves: Vec<_> = collect_function_calls(ys.clone())
+            .into_iter()
+            .filter(|fc| fc.xs.is_empty())
+            .flat_map(|fc| fc.get_ys())
+            .filter(|y| !inner_xs.contains(&Arc::as_ptr(&y.inner)))
+            .collect();
+        let ys: Vec<_> = ys.iter().map(|y| ComputedNDA::new((**y).clone())).collect();
+        (ys, leaves)
+        // the intermediate graph is dropped here
+    };
+
+    let n = xs.len();
+    chain(
+        &[xs, &leaves].concat(),
+        &ys,
+        false,
+        "checkpoint",
+        move |xs, _ys, gys| {
+            let _guard = set_grad_enabled(true);
+            let inner_xs: Vec<_> = xs[..n].iter().map(|x| backprop((**x).clone())).collect();
+            let ys = f(&inner_xs);
+            let loss = ys
+                .iter()
+                .zip(gys)
+                .map(|(y, gy)| {
+                    (y * &ComputedNDA::new((**gy).clone())).sum(Vec::from_iter(0..y.ndim()), false)
+                })
+                .reduce(|a, b| &a + &b)
+                .unwrap();
+
+            let targets: Vec<_> = inner_xs.iter().chain(&xs[n..]).cloned().collect();
+            let reachable: HashSet<_> = collect_function_calls(vec![loss.clone()])
+                .iter()
+                .flat_map(|fc| fc.ys.iter().map(|y| y.as_ptr()))
+                .collect();
+            let found: Vec<_> = targets
+                .iter()
+                .filter(|x| reachable.contains(&Arc::as_ptr(&x.inner)))
+                .cloned()
+                .collect();
+            let mut grads = gradients(&[loss], &found, false).into_iter();
+
+            targets
+                .iter()
+                .map(|x| {
+                    if reachable.contains(&Arc::as_ptr(&x.inner)) {
+                        grads.next().unwrap()
+                    } else {
+                        ComputedNDA::new(NDArray::zeros(x.shape()))
+                    }
+                })
+                .collect()
+        },
+    );
+
+    ys
+}
+
+#[test]
+fn test() {
+    use std::sync::{Mutex, Weak};
+
+    use crate::{gradients, scalar, Computed, IntoNDArray};
+
+    let a = backprop(ndarray::array![[0.1, 0.2], [0.3, -0.4]].into_ndarray());
+    let w = backprop(ndarray::array![[1.0, -2.0], [0.5, 1.5]].into_ndarray());
+    let b = backprop(scalar(0.3));
+
+    let intermediates = Arc::new(Mutex::new(Vec::<Weak<_>>::new()));
+    let f = {
+        let (w, b, intermediates) = (w.clone(), b.clone(), intermediates.clone());
+        move |xs: &[ComputedNDA]| {
+            let h = (xs[0].matmul(&w) + b.clone()).tanh();
+            intermediates.lock().unwrap().push(Arc::downgrade(&h.inner));
+            vec![h.matmul(&w).sin(), xs[1].clone() * h]
+        }
+    };
+
+    let expected = {
+        let ys = f(&[a.clone(), a.clone()]);
+        let y = (&ys[0] + &ys[1]).sum([0, 1], false);
+        gradients(&[y], &[a.clone(), w.clone(), b.clone()], false)
+    };
+
+    let ys = checkpoint(f, &[a.clone(), a.clone()]);
+    // the intermediate values are freed after the forward pass
+    assert!(intermediates
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|h| h.upgrade().is_none()));
+    let y = (&ys[0] + &ys[1]).sum([0, 1], false);
+    let grads = gradients(&[y], &[a.clone(), w.clone(), b.clone()], false);
+
+    for (g, e) in grads.iter().zip(&expected) {
+        assert!(
+            (&**g - &**e).iter().all(|d| d.abs() < 1e-6),
+            "{:?} {:?}",
+            &**g,
+            &**e
+        );
+    }
+
+    // a leaf that does not contribute
+    let c = backprop(scalar(1.0));
+    let ys = checkpoint(
+        {
+            let c = c.clone();
+            move |xs| vec![&xs[0] + &(&c * &Computed::new(scalar(0.0)))]
+        },
+        &[a.clone()],
+    );
+    let grads = gradients(&[ys[0].sum([0, 1], false)], &[c], false);
+    assert_eq!(grads[0][[]], 0.0);
+}
@@ -1,67 +1,240 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
 
-use super::FunctionCall;
+use arc_swap::ArcSwapOption;
 
-#[derive(Clone)]
-pub(crate) struct ComputedAttrs<T> {
-    pub name: String,
-    pub creator: Option<Arc<FunctionCall<T>>>,
+use super::{memory, FunctionCall};
+
+pub type Hook<T> = Arc<dyn Fn(&Computed<T>) -> Option<Computed<T>> + Send + Sync>;
+
+/// Attributes that most values do not have.
+pub(crate) struct Extras<T> {
+    pub hooks: Vec<Hook<T>>,
+    pub tangent: Option<Computed<T>>,
+}
+
+impl<T> Clone for Extras<T> {
+    fn clone(&self) -> Self {
+        Self {
+            hooks: self.hooks.clone(),
+            tangent: self.tangent.clone(),
+        }
+    }
+}
+
+/// A value that is computed when it is accessed for the first time.
+pub(crate) trait Pending<T>: Send + Sync {
+    fn evaluate(&self) -> T;
+
+    /// The name of the function recording the graph, for the profiler.
+    fn name(&self) -> &'static str;
+
+    /// Called after the value is stored to record the graph.
+    fn record(&self, y: &Computed<T>);
+
+    fn as_any(&self) -> &dyn Any;
 }
 
+/// A pending value with the function counting its data by `MemoryTracker` once it is computed.
+type PendingEntry<T> = (Box<dyn Pending<T>>, fn(&T) -> Option<memory::Allocation>);
+
+/// The creator and the name are read without locking since they are accessed for every
+/// function call. `extras` is locked only if `has_extras` is set.
 pub(crate) struct ComputedInner<T> {
-    pub data: T,
-    pub attrs: Mutex<ComputedAttrs<T>>,
+    pub data: OnceLock<T>,
+    pending: Mutex<Option<PendingEntry<T>>>,
+    /// Set once the creator of a pending value is recorded. The data is published before
+    /// that, so readers of the creator wait on `pending` until this is set.
+    recorded: AtomicBool,
+    creator: ArcSwapOption<FunctionCall<T>>,
+    name: ArcSwapOption<String>,
+    has_extras: AtomicBool,
+    extras: Mutex<Extras<T>>,
+    /// The data counted by `MemoryTracker`.
+    allocation: OnceLock<memory::Allocation>,
+}
+
+// The attributes are only replaced as a whole, so a panic cannot leave them inconsistent.
+impl<T: std::panic::RefUnwindSafe> std::panic::RefUnwindSafe for ComputedInner<T> {}
+
+impl<T> ComputedInner<T> {
+    fn new(
+        data: OnceLock<T>,
+        pending: Option<PendingEntry<T>>,
+        allocation: Option<memory::Allocation>,
+    ) -> Self {
+        let cell = OnceLock::new();
+        if let Some(allocation) = allocation {
+            let _ = cell.set(allocation);
+        }
+        Self {
+            data,
+            recorded: AtomicBool::new(pending.is_none()),
+            pending: Mutex::new(pending),
+            creator: ArcSwapOption::empty(),
+            name: ArcSwapOption::empty(),
+            has_extras: AtomicBool::new(false),
+            extras: Mutex::new(Extras {
+                hooks: Vec::new(),
+                tangent: None,
+            }),
+            allocation: cell,
+        }
+    }
+
+    fn extras(&self) -> Option<Extras<T>> {
+        if !self.has_extras.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(self.extras.lock().unwrap().clone())
+    }
+
+    fn update_extras(&self, f: impl FnOnce(&mut Extras<T>)) {
+        f(&mut self.extras.lock().unwrap());
+        self.has_extras.store(true, Ordering::Release);
+    }
 }
 
 pub struct Computed<T> {
     pub(crate) inner: Arc<ComputedInner<T>>,
 }
 
-impl<T> Computed<T> {
+impl<T: 'static> Computed<T> {
     pub fn new(data: T) -> Self {
+        let allocation = memory::allocate(&data);
         Computed {
-            inner: Arc::new(ComputedInner {
-                data,
-                attrs: Mutex::new(ComputedAttrs {
-                    name: "".to_string(),
-                    creator: None,
-                }),
-            }),
+            inner: Arc::new(ComputedInner::new(OnceLock::from(data), None, allocation)),
         }
     }
 
+    pub(crate) fn pending(pending: Box<dyn Pending<T>>) -> Self {
+        let pending = (pending, memory::allocate::<T> as fn(&T) -> _);
+        Computed {
+            inner: Arc::new(ComputedInner::new(OnceLock::new(), Some(pending), None)),
+        }
+    }
+}
+
+impl<T> Computed<T> {
+    /// Returns whether the value is recorded in lazy mode and its graph is not recorded yet.
+    /// The value itself may already be computed.
+    pub fn is_pending(&self) -> bool {
+        !self.inner.recorded.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn with_pending<R>(&self, f: impl FnOnce(&dyn Pending<T>) -> R) -> Option<R> {
+        if !self.is_pending() {
+            return None;
+        }
+        let pending = self.inner.pending.lock().unwrap();
+        pending.as_ref().map(|(p, _)| f(&**p))
+    }
+
+    fn force(&self) -> &T {
+        let mut pending = self.inner.pending.lock().unwrap();
+        if let Some((p, allocate)) = pending.take() {
+            let _scope = super::profile_scope(p.name());
+            // `record` reads the data, so it is published first; other threads see
+            // `is_pending` until the creator is recorded and wait for the lock
+            let data = p.evaluate();
+            if let Some(allocation) = allocate(&data) {
+                let _ = self.inner.allocation.set(allocation);
+            }
+            let _ = self.inner.data.set(data);
+            p.record(self);
+            self.inner.recorded.store(true, Ordering::Release);
+        }
+        drop(pending);
+        self.inner.data.get().unwrap()
+    }
+
     pub fn named(self, name: impl Into<String>) -> Self {
-        self.inner.attrs.lock().unwrap().name = name.into();
+        self.set_name(name);
         self
     }
 
     pub fn set_name(&self, name: impl Into<String>) {
-        self.inner.attrs.lock().unwrap().name = name.into();
+        self.inner.name.store(Some(Arc::new(name.into())));
     }
 
     pub fn get_name(&self) -> String {
-        self.inner.attrs.lock().unwrap().name.to_owned()
+        self.inner
+            .name
+            .load()
+            .as_deref()
+            .cloned()
+            .unwrap_or_default()
     }
 
     pub fn has_creator(&self) -> bool {
-        self.inner.attrs.lock().unwrap().creator.is_some()
+        // the creator of a pending value is recorded when it is computed
+        if self.is_pending() {
+            self.force();
+        }
+        self.inner.creator.load().is_some()
+    }
+
+    pub(crate) fn creator(&self) -> Option<Arc<FunctionCall<T>>> {
+        if self.is_pending() {
+            self.force();
+        }
+        self.inner.creator.load_full()
+    }
+
+    pub(crate) fn set_creator(&self, creator: Arc<FunctionCall<T>>) {
+        self.inner.creator.store(Some(creator));
+    }
+
+    /// Returns the tangent propagated by forward-mode differentiation, if any.
+    pub fn tangent(&self) -> Option<Computed<T>> {
+        self.inner.extras()?.tangent
+    }
+
+    pub fn set_tangent(&self, tangent: Computed<T>) {
+        self.inner.update_extras(|e| e.tangent = Some(tangent));
+    }
+
+    /// Registers a hook called with the gradient of this value once it is fully accumulated.
+    ///
+    /// If the hook returns `Some`, the returned value replaces the gradient.
+    /// Hooks are called in the order they were registered.
+    pub fn register_hook(
+        &self,
+        hook: impl Fn(&Computed<T>) -> Option<Computed<T>> + Send + Sync + 'static,
+    ) {
+        self.inner.update_extras(|e| e.hooks.push(Arc::new(hook)));
+    }
+
+    pub(crate) fn call_hooks(&self, grad: Computed<T>) -> Computed<T> {
+        let Some(extras) = self.inner.extras() else {
+            return grad;
+        };
+        extras
+            .hooks
+            .iter()
+            .fold(grad, |grad, hook| hook(&grad).unwrap_or(grad))
     }
 
     pub fn unchain(&self) {
-        self.inner.attrs.lock().unwrap().creator = None;
+        self.inner.creator.store(None);
     }
 
     pub fn unchained(&self) -> Self
     where
         T: Clone,
     {
-        let mut attrs = self.inner.attrs.lock().unwrap().clone();
-        attrs.creator = None;
+        let inner = ComputedInner::new(OnceLock::from((**self).clone()), None, None);
+        inner.name.store(self.inner.name.load_full());
+        if let Some(extras) = self.inner.extras() {
+            inner.update_extras(|e| *e = extras);
+        }
         Computed {
-            inner: Arc::new(ComputedInner {
-                data: self.inner.data.clone(),
-                attrs: Mutex::new(attrs),
-            }),
+            inner: Arc::new(inner),
         }
     }
 }
@@ -70,7 +243,10 @@ impl<T> std::ops::Deref for Computed<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        &self.inner.data
+        match self.inner.data.get() {
+            Some(data) => data,
+            None => self.force(),
+        }
     }
 }
 
@@ -95,3 +271,44 @@ impl<T> std::hash::Hash for Computed<T> {
         Arc::as_ptr(&self.inner).hash(state);
     }
 }
+
+#[test]
+fn test_force() {
+    use crate::*;
+
+    /// Records the graph slowly to widen the window after the value is published.
+    struct Slow;
+
+    impl Pending<NDArray> for Slow {
+        fn evaluate(&self) -> NDArray {
+            scalar(1.0)
+        }
+
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn record(&self, y: &ComputedNDA) {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            chain(&[], &[y.clone()], true, "slow", |_, _, _| vec![]);
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    let y = Computed::pending(Box::new(Slow));
+    assert!(y.is_pending());
+    std::thread::scope(|s| {
+        s.spawn(|| assert_eq!(*y, scalar(1.0)));
+        s.spawn(|| {
+            while y.inner.data.get().is_none() {
+                std::thread::yield_now();
+            }
+            // the creator is seen once the value is
+            assert!(y.has_creator());
+            assert!(!y.is_pending());
+        });
+    });
+}
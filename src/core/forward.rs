@@ -0,0 +1,206 @@
+use std::cell::Cell;
+
+use super::Computed;
+use crate::{ComputedNDA, Float, NDArray};
+
+thread_local! {
+    static COMPUTING_TANGENTS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the previous flag when dropped, even if `tangents` panics.
+struct ComputingTangentsGuard {
+    prev: bool,
+}
+
+impl Drop for ComputingTangentsGuard {
+    fn drop(&mut self) {
+        COMPUTING_TANGENTS.with(|c| c.set(self.prev));
+    }
+}
+
+/// Propagates tangents from `xs` to `ys` if any of `xs` has a tangent.
+///
+/// `tangents` receives `xs`, `ys` and the tangents of `xs` (`None` means zero)
+/// and returns the tangents of `ys`. Functions called inside `tangents` do not
+/// propagate tangents themselves.
+pub fn forward_derivative<T>(
+    xs: impl AsRef<[Computed<T>]>,
+    ys: impl AsRef<[Computed<T>]>,
+    tangents: impl FnOnce(&[Computed<T>], &[Computed<T>], &[Option<Computed<T>>]) -> Vec<Computed<T>>,
+) {
+    let (xs, ys) = (xs.as_ref(), ys.as_ref());
+    if COMPUTING_TANGENTS.with(|c| c.get()) {
+        return;
+    }
+    let txs: Vec<_> = xs.iter().map(|x| x.tangent()).collect();
+    if txs.iter().all(|t| t.is_none()) {
+        return;
+    }
+
+    let tys = {
+        let _guard = ComputingTangentsGuard {
+            prev: COMPUTING_TANGENTS.with(|c| c.replace(true)),
+        };
+        tangents(xs, ys, &txs)
+    };
+
+    assert_eq!(ys.len(), tys.len());
+    for (y, ty) in ys.iter().zip(tys) {
+        y.set_tangent(ty);
+    }
+}
+
+/// Computes the outputs of `f` at `primals` and their Jacobian-vector products with `tangents`
+/// in a single forward pass.
+///
+/// Only functions that register their tangents with `forward_derivative` propagate them;
+/// the element-wise, matmul, reduction, shape and activation functions do.
+///
+/// ```
+/// use tensorflake::*;
+///
+/// let (ys, tys) = jvp(
+///     |xs| vec![&xs[0] * &xs[0]],
+///     &[scalar(3.0)],
+///     &[scalar(1.0)],
+/// );
+/// assert_eq!(ys[0][[]], 9.0);
+/// assert_eq!(tys[0][[]], 6.0);
+/// ```
+pub fn jvp<T: Float>(
+    f: impl FnOnce(&[ComputedNDA<T>]) -> Vec<ComputedNDA<T>>,
+    primals: &[NDArray<T>],
+    tangents: &[NDArray<T>],
+) -> (Vec<ComputedNDA<T>>, Vec<ComputedNDA<T>>) {
+    assert_eq!(primals.len(), tangents.len());
+    let xs: Vec<_> = primals
+        .iter()
+        .zip(tangents)
+        .map(|(x, t)| {
+            assert_eq!(
+                x.shape(),
+                t.shape(),
+                "tangent must have the shape of the primal"
+            );
+            let x = ComputedNDA::new(x.clone());
+            x.set_tangent(ComputedNDA::new(t.clone()));
+            x
+        })
+        .collect();
+
+    let ys = f(&xs);
+    let tys = ys
+        .iter()
+        .map(|y| {
+            y.tangent()
+                .unwrap_or_else(|| ComputedNDA::new(NDArray::zeros(y.shape())))
+        })
+        .collect();
+    (ys, tys)
+}
+
+#[test]
+fn test() {
+    use crate::{functions::*, gradcheck::jvpcheck, losses, nn::activations::*, IntoNDArray};
+
+    let a = ndarray::array![[0.1, -0.2, 0.3], [0.4, 0.5, -0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0, 3.0], [1.5, 2.5, -0.5]].into_ndarray();
+    let c = ndarray::array![[0.5, 1.0, 1.5]].into_ndarray();
+    let w = ndarray::array![[1.0, -2.0], [3.0, 0.5], [-1.5, 2.5]].into_ndarray();
+
+    let unary: Vec<(&str, Box<dyn Fn(&ComputedNDA) -> ComputedNDA>)> = vec![
+        ("abs", Box::new(|x| x.abs())),
+        ("neg", Box::new(|x| -x)),
+        ("exp", Box::new(|x| x.exp())),
+        ("log", Box::new(|x| x.abs().log())),
+        ("sin", Box::new(|x| x.sin())),
+        ("cos", Box::new(|x| x.cos())),
+        ("tanh", Box::new(|x| x.tanh())),
+        ("pow_const", Box::new(|x| x.pow_const(3.0))),
+        ("sum", Box::new(|x| x.sum([1], false))),
+        ("sum keep_dim", Box::new(|x| x.sum([0], true))),
+        ("max", Box::new(|x| max(1, x))),
+        ("broadcast", Box::new(|x| x.broadcast([4, 2, 3]))),
+        ("reshape", Box::new(|x| x.reshape([3, 2]))),
+        ("transpose", Box::new(|x| x.transpose([1, 0]))),
+        ("t", Box::new(|x| x.t())),
+        ("select", Box::new(|x| select(1, vec![2, 0, 2], x))),
+        ("slice", Box::new(|x| slice(x, ndarray::s![.., 1..]))),
+        ("relu", Box::new(relu)),
+        ("leaky_relu", Box::new(|x| leaky_relu(0.1, x))),
+        ("sigmoid", Box::new(sigmoid)),
+        ("gelu", Box::new(gelu)),
+        ("softmax", Box::new(softmax)),
+        (
+            "softmax_cross_entropy",
+            Box::new(|x| losses::softmax_cross_entropy(vec![1, 2], x)),
+        ),
+    ];
+    for (name, f) in unary {
+        let report = jvpcheck(|xs| f(&xs[0]), &[a.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}: {}", name, report);
+    }
+
+    let binary: Vec<(&str, Box<dyn Fn(&ComputedNDA, &ComputedNDA) -> ComputedNDA>)> = vec![
+        ("add", Box::new(|x, y| x + y)),
+        ("sub", Box::new(|x, y| x - y)),
+        ("mul", Box::new(|x, y| x * y)),
+        ("div", Box::new(|x, y| x / y)),
+        ("pow", Box::new(|x, y| pow(&x.abs(), y))),
+        (
+            "multi_add",
+            Box::new(|x, y| multi_add(&[x.clone(), y.clone()])),
+        ),
+        (
+            "multi_mul",
+            Box::new(|x, y| multi_mul(&[x.clone(), y.clone()])),
+        ),
+        (
+            "concat",
+            Box::new(|x, y| concat(&[x.clone(), y.clone()], 0)),
+        ),
+    ];
+    for (name, f) in binary {
+        let report = jvpcheck(|xs| f(&xs[0], &xs[1]), &[a.clone(), b.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}: {}", name, report);
+        // broadcast
+        if name != "concat" && name != "pow" {
+            let report = jvpcheck(|xs| f(&xs[0], &xs[1]), &[c.clone(), b.clone()], 1e-3, 1e-2);
+            assert!(report.passed, "{} broadcast: {}", name, report);
+        }
+    }
+
+    let report = jvpcheck(
+        |xs| matmul(&xs[0], &xs[1]),
+        &[a.clone(), w.clone()],
+        1e-3,
+        1e-2,
+    );
+    assert!(report.passed, "{}", report);
+    let bias = ndarray::array![0.1, 0.2].into_ndarray();
+    let report = jvpcheck(
+        |xs| matmul_add(&xs[0], &xs[1], &xs[2]),
+        &[a.clone(), w, bias],
+        1e-3,
+        1e-2,
+    );
+    assert!(report.passed, "{}", report);
+
+    // outputs that do not depend on the primals have zero tangents
+    let (_, tys) = jvp(
+        |_| vec![ComputedNDA::new(c.clone())],
+        &[a.clone()],
+        &[a.clone()],
+    );
+    assert_eq!(&*tys[0], &NDArray::zeros(c.shape()));
+
+    // a panic in `tangents` does not leave tangent propagation disabled
+    let x = ComputedNDA::new(a.clone());
+    x.set_tangent(ComputedNDA::new(a.clone()));
+    let result = std::panic::catch_unwind(|| {
+        forward_derivative([x.clone()], [x.clone()], |_, _, _| panic!())
+    });
+    assert!(result.is_err());
+    let (_, tys) = jvp(|xs| vec![&xs[0] * &xs[0]], &[a.clone()], &[a.clone()]);
+    assert_eq!(&*tys[0], &(&a * &a * 2.0));
+}
@@ -1,6 +1,13 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
 
-use super::{backprop, Computed, FunctionCall};
+use super::{
+    anomaly, backprop, computed::ComputedInner, profiler, set_grad_enabled, take_anomaly, trace,
+    Computed, FunctionCall,
+};
+use crate::error::{unwrap_or_panic, Error};
 
 pub trait One {
     fn clone_filled_ones(&self) -> Self;
@@ -15,6 +22,53 @@ pub fn gradients<T: One + Send + Sync + 'static>(
 where
     for<'a> &'a Computed<T>: std::ops::Add<&'a Computed<T>, Output = Computed<T>>,
 {
+    unwrap_or_panic(try_gradients(ys, xs, create_graph))
+}
+
+pub fn try_gradients<T: One + Send + Sync + 'static>(
+    ys: &[Computed<T>],
+    xs: &[Computed<T>],
+    create_graph: bool,
+) -> Result<Vec<Computed<T>>, Error>
+where
+    for<'a> &'a Computed<T>: std::ops::Add<&'a Computed<T>, Output = Computed<T>>,
+{
+    try_gradients_with(ys, xs, create_graph, true)
+}
+
+/// Same as `gradients`, but if `retain_graph` is false, each function call and its inputs are
+/// released as soon as its backward has run, and `ys` are unchained.
+/// The graph cannot be differentiated again, except for the values without inputs
+/// such as `backprop` values and params. Other graphs sharing the released values lose
+/// the part of the graph behind them.
+pub fn gradients_with<T: One + Send + Sync + 'static>(
+    ys: &[Computed<T>],
+    xs: &[Computed<T>],
+    create_graph: bool,
+    retain_graph: bool,
+) -> Vec<Computed<T>>
+where
+    for<'a> &'a Computed<T>: std::ops::Add<&'a Computed<T>, Output = Computed<T>>,
+{
+    unwrap_or_panic(try_gradients_with(ys, xs, create_graph, retain_graph))
+}
+
+pub fn try_gradients_with<T: One + Send + Sync + 'static>(
+    ys: &[Computed<T>],
+    xs: &[Computed<T>],
+    create_graph: bool,
+    retain_graph: bool,
+) -> Result<Vec<Computed<T>>, Error>
+where
+    for<'a> &'a Computed<T>: std::ops::Add<&'a Computed<T>, Output = Computed<T>>,
+{
+    // Backward functions record the graph only if higher order gradients are requested.
+    let _guard = set_grad_enabled(create_graph);
+
+    if let Some(e) = take_anomaly() {
+        return Err(e);
+    }
+
     let mut grads = HashMap::new();
 
     for y in ys.iter() {
@@ -28,15 +82,31 @@ where
         );
     }
 
+    let xs_set: HashSet<_> = xs.iter().map(|x| Arc::as_ptr(&x.inner)).collect();
+    let mut finalized = HashSet::new();
+    // outputs of function calls not processed yet, kept alive after their consumers are released
+    let mut alive = HashMap::new();
     let function_calls = collect_function_calls(ys.to_vec());
-    for fc in sort_for_backward(function_calls) {
+    for fc in on_paths_from(sort_for_backward(function_calls)?, &xs_set) {
         let ys = fc.get_ys();
+        // the gradients of ys are finalized since all their consumers have been processed
         let gys = ys
             .iter()
-            .map(|y| grads[&Arc::as_ptr(&y.inner)].clone())
+            .map(|y| {
+                let gy = y.call_hooks(grads[&Arc::as_ptr(&y.inner)].clone());
+                grads.insert(Arc::as_ptr(&y.inner), gy.clone());
+                finalized.insert(Arc::as_ptr(&y.inner));
+                gy
+            })
             .collect();
 
+        let span = profiler::BackwardSpan::enter();
         let gxs = fc.backward.backward(&fc.xs, &ys, &gys);
+        span.exit(
+            fc.backward.get_function_name(),
+            &gxs,
+            Arc::as_ptr(&fc) as usize,
+        );
 
         if !create_graph {
             for gx in &gxs {
@@ -45,13 +115,13 @@ where
         }
 
         if fc.xs.len() != gxs.len() {
-            panic!(
-                "backward of {} has {} inputs, but {} gradients returned",
-                fc.backward.get_function_name(),
-                fc.xs.len(),
-                gxs.len()
-            );
+            return Err(Error::GradientCountMismatch {
+                function: fc.backward.get_function_name(),
+                inputs: fc.xs.len(),
+                gradients: gxs.len(),
+            });
         }
+        anomaly::check_backward(fc.backward.get_function_name(), &fc.xs, &gxs)?;
 
         for (x, gx) in fc.xs.iter().zip(gxs.iter()) {
             match grads.entry(Arc::as_ptr(&x.inner)) {
@@ -65,67 +135,134 @@ where
         }
 
         for y in &ys {
-            if !xs.contains(&y) {
+            if !xs_set.contains(&Arc::as_ptr(&y.inner)) {
                 grads.remove(&Arc::as_ptr(&y.inner));
             }
         }
+
+        if !retain_graph {
+            for x in &fc.xs {
+                if x.has_creator() {
+                    alive.insert(Arc::as_ptr(&x.inner), x.clone());
+                }
+            }
+            for y in &ys {
+                alive.remove(&Arc::as_ptr(&y.inner));
+                if !fc.xs.is_empty() {
+                    y.unchain();
+                }
+            }
+        }
+    }
+
+    // values without creators
+    for x in xs {
+        if finalized.insert(Arc::as_ptr(&x.inner)) {
+            if let Some(gx) = grads.get(&Arc::as_ptr(&x.inner)).cloned() {
+                grads.insert(Arc::as_ptr(&x.inner), x.call_hooks(gx));
+            }
+        }
     }
 
     xs.iter()
         .map(|x| {
             grads
                 .get(&Arc::as_ptr(&x.inner))
-                .unwrap_or_else(|| panic!("grad not found {}", x.get_name()))
-                .clone()
+                .cloned()
+                .ok_or_else(|| Error::GradNotFound { name: x.get_name() })
         })
         .collect()
 }
 
-pub fn collect_variables<T>(vars: Vec<Computed<T>>) -> Vec<Computed<T>> {
+pub fn collect_variables<T: 'static>(vars: Vec<Computed<T>>) -> Vec<Computed<T>> {
     let fcs = collect_function_calls(vars);
     let mut vars: Vec<_> = fcs.iter().flat_map(|fc| fc.xs.iter()).cloned().collect();
     vars.dedup();
     vars
 }
 
+/// Sorts function calls so that each one comes after all the calls that consume its outputs.
+///
+/// Runs in linear time in the number of function calls and their inputs.
 pub(crate) fn sort_for_backward<T>(
-    mut fcs: Vec<Arc<FunctionCall<T>>>,
-) -> Vec<Arc<FunctionCall<T>>> {
-    let mut sorted = Vec::with_capacity(fcs.len());
-    let ys = fcs.iter().flat_map(|fc| fc.get_ys()).collect::<Vec<_>>();
-    let mut visited: Vec<_> = fcs
+    fcs: Vec<Arc<FunctionCall<T>>>,
+) -> Result<Vec<Arc<FunctionCall<T>>>, Error> {
+    // index of the function call that created each output
+    let creators: HashMap<_, _> = fcs
         .iter()
-        .flat_map(|fc| &fc.xs)
-        .filter(|v| !ys.contains(v))
-        .cloned()
+        .enumerate()
+        .flat_map(|(i, fc)| fc.ys.iter().map(move |y| (y.as_ptr(), i)))
         .collect();
-    while !fcs.is_empty() {
-        let (a, b): (Vec<_>, _) = fcs
-            .into_iter()
-            .partition(|fc| fc.xs.iter().all(|x| visited.contains(&x)));
-        if a.is_empty() {
-            panic!("cycle detected");
+    let creator_indices = |fc: &FunctionCall<T>| {
+        fc.xs
+            .iter()
+            .filter_map(|x| creators.get(&Arc::as_ptr(&x.inner)).copied())
+            .collect::<Vec<_>>()
+    };
+
+    // number of inputs consuming the outputs of each function call
+    let mut consumers = vec![0usize; fcs.len()];
+    for fc in &fcs {
+        for i in creator_indices(fc) {
+            consumers[i] += 1;
+        }
+    }
+
+    let mut queue: VecDeque<_> = (0..fcs.len()).filter(|&i| consumers[i] == 0).collect();
+    let mut sorted = Vec::with_capacity(fcs.len());
+    while let Some(i) = queue.pop_front() {
+        for j in creator_indices(&fcs[i]) {
+            consumers[j] -= 1;
+            if consumers[j] == 0 {
+                queue.push_back(j);
+            }
+        }
+        sorted.push(i);
+    }
+    if sorted.len() != fcs.len() {
+        return Err(Error::CycleDetected);
+    }
+
+    let mut fcs: Vec<_> = fcs.into_iter().map(Some).collect();
+    Ok(sorted.into_iter().map(|i| fcs[i].take().unwrap()).collect())
+}
+
+/// Keeps the function calls whose inputs depend on any of `xs`, since the others do not
+/// contribute to the gradients of `xs`.
+fn on_paths_from<T>(
+    sorted: Vec<Arc<FunctionCall<T>>>,
+    xs: &HashSet<*const ComputedInner<T>>,
+) -> Vec<Arc<FunctionCall<T>>> {
+    let mut reached = xs.clone();
+    let mut keep = vec![false; sorted.len()];
+    for (i, fc) in sorted.iter().enumerate().rev() {
+        if fc
+            .xs
+            .iter()
+            .any(|x| reached.contains(&Arc::as_ptr(&x.inner)))
+        {
+            keep[i] = true;
+            reached.extend(fc.ys.iter().map(|y| y.as_ptr()));
         }
-        visited.extend(a.iter().flat_map(|fc| fc.get_ys()));
-        sorted.extend(a);
-        fcs = b;
     }
-    sorted.reverse();
     sorted
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(fc, keep)| keep.then_some(fc))
+        .collect()
 }
 
-pub(crate) fn collect_function_calls<T>(mut vars: Vec<Computed<T>>) -> Vec<Arc<FunctionCall<T>>> {
+pub(crate) fn collect_function_calls<T: 'static>(
+    mut vars: Vec<Computed<T>>,
+) -> Vec<Arc<FunctionCall<T>>> {
+    trace::graph_accessed::<T>();
     let mut function_call_vec = Vec::new();
-    let mut closed_function_calls = Vec::new();
+    let mut closed_function_calls = HashSet::new();
     while let Some(var) = vars.pop() {
-        if let Some(creator) = var.inner.attrs.lock().unwrap().creator.clone() {
-            if closed_function_calls
-                .iter()
-                .any(|fc| Arc::as_ptr(fc) == Arc::as_ptr(&creator))
-            {
+        if let Some(creator) = var.creator() {
+            if !closed_function_calls.insert(Arc::as_ptr(&creator)) {
                 continue;
             }
-            closed_function_calls.push(creator.clone());
 
             vars.extend(creator.xs.iter().cloned());
             vars.extend(creator.get_ys());
@@ -135,6 +272,69 @@ pub(crate) fn collect_function_calls<T>(mut vars: Vec<Computed<T>>) -> Vec<Arc<F
     function_call_vec
 }
 
+#[test]
+fn test_try_gradients() {
+    use crate::{backprop, scalar};
+    let x = backprop(scalar(1.0)).named("x");
+    let y = backprop(scalar(2.0)).named("y");
+    let z = &x * &x;
+    assert_eq!(
+        try_gradients(&[z], &[y], false).err(),
+        Some(Error::GradNotFound { name: "y".into() })
+    );
+}
+
+#[test]
+fn test_hooks() {
+    use crate::{backprop, scalar};
+    let x = backprop(scalar(3.0));
+    let y = &x * &x;
+    let z = &y + &x;
+
+    // double the gradient of y
+    y.register_hook(|gy| Some(gy + gy));
+    let gx = gradients(&[z.clone()], &[x.clone()], false);
+    assert_eq!(gx[0][[]], 13.0);
+
+    // observe without modifying
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen_ = seen.clone();
+    x.register_hook(move |gx| {
+        seen_.lock().unwrap().push(gx[[]]);
+        None
+    });
+    let gx = gradients(&[z.clone()], &[x.clone()], false);
+    assert_eq!(gx[0][[]], 13.0);
+    assert_eq!(*seen.lock().unwrap(), vec![13.0]);
+
+    // gradient reversal on a value without a creator
+    let w = crate::ComputedNDA::new(scalar(2.0));
+    w.register_hook(|gw| Some(-gw));
+    let gw = gradients(&[&w * &x], &[w.clone()], false);
+    assert_eq!(gw[0][[]], -3.0);
+}
+
+#[test]
+fn test_retain_graph() {
+    use crate::{backprop, scalar};
+    let x = backprop(scalar(2.0));
+    let a = &x * &x;
+    let inner = Arc::downgrade(&a.inner);
+    let y = a.exp() + x.clone();
+    drop(a);
+
+    let expected = gradients(&[y.clone()], &[x.clone()], false);
+    let gx = gradients_with(&[y.clone()], &[x.clone()], false, false);
+    assert_eq!(gx[0][[]], expected[0][[]]);
+    assert!(!y.has_creator());
+    assert!(inner.upgrade().is_none());
+
+    // the leaf can be differentiated again
+    assert!(x.has_creator());
+    let gx = gradients(&[&x * &x], &[x.clone()], false);
+    assert_eq!(gx[0][[]], 4.0);
+}
+
 #[test]
 fn test_collect_function_calls() {
     use crate::{backprop, scalar};
@@ -147,3 +347,22 @@ fn test_collect_function_calls() {
     let function_call_vec = collect_function_calls(vec![f.clone()]);
     assert_eq!(function_call_vec.len(), 4);
 }
+
+#[test]
+fn test_sort_for_backward() {
+    use crate::{backprop, scalar};
+    let x = backprop(scalar(1.0));
+    let a = &x * &x;
+    let b = &a + &x;
+    let c = &a * &b;
+    let fcs = collect_function_calls(vec![c.clone()]);
+    let sorted = sort_for_backward(fcs).unwrap();
+    assert_eq!(sorted.len(), 4);
+
+    // every function call comes after the consumers of its outputs
+    for (i, fc) in sorted.iter().enumerate() {
+        for later in &sorted[i + 1..] {
+            assert!(later.xs.iter().all(|x| !fc.get_ys().contains(x)));
+        }
+    }
+}
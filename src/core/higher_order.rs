@@ -0,0 +1,106 @@
+use super::{backprop, gradients, Computed};
+use crate::{functions::select, ComputedNDA, Float, NDArray};
+
+/// Computes the Jacobian of `f` at `x` with one backward pass per output element.
+///
+/// The result has the shape `f(x).shape() ++ x.shape()`.
+pub fn jacobian<T: Float>(
+    f: impl Fn(&ComputedNDA<T>) -> ComputedNDA<T>,
+    x: &NDArray<T>,
+) -> NDArray<T> {
+    let x = backprop(x.clone());
+    let y = f(&x);
+    rows(&y, &x)
+}
+
+/// Computes the Hessian of the scalar function `f` at `x`.
+///
+/// The result has the shape `x.shape() ++ x.shape()`.
+pub fn hessian<T: Float>(
+    f: impl Fn(&ComputedNDA<T>) -> ComputedNDA<T>,
+    x: &NDArray<T>,
+) -> NDArray<T> {
+    let x = backprop(x.clone());
+    let y = f(&x);
+    assert_eq!(y.len(), 1, "hessian requires a scalar function");
+    let gx = gradients(&[y], std::slice::from_ref(&x), true)
+        .pop()
+        .unwrap();
+    rows(&gx, &x)
+}
+
+/// Computes the products of the Hessian of `loss` with respect to `params` and `v`
+/// without materializing the Hessian.
+///
+/// `v` has an element of the same shape for each of `params`.
+pub fn hessian_vector_product<T: Float>(
+    loss: &ComputedNDA<T>,
+    params: &[ComputedNDA<T>],
+    v: &[ComputedNDA<T>],
+) -> Vec<ComputedNDA<T>> {
+    assert_eq!(params.len(), v.len());
+    let gs = gradients(std::slice::from_ref(loss), params, true);
+    let gv = gs
+        .iter()
+        .zip(v)
+        .map(|(g, v)| (g * v).sum(Vec::from_iter(0..g.ndim()), false))
+        .reduce(|a, b| &a + &b)
+        .expect("no params");
+    gradients(&[gv], params, false)
+}
+
+/// Stacks the gradients of each element of `y` with respect to `x`.
+fn rows<T: Float>(y: &ComputedNDA<T>, x: &ComputedNDA<T>) -> NDArray<T> {
+    let y_flat = y.reshape([y.len()]);
+    let mut rows = Vec::with_capacity(y.len() * x.len());
+    for i in 0..y.len() {
+        let yi = select(0, vec![i], &y_flat);
+        let gx = match crate::try_gradients(&[yi], std::slice::from_ref(x), false) {
+            Ok(mut gs) => gs.pop().unwrap(),
+            // the element does not depend on x
+            Err(crate::Error::GradNotFound { .. }) => Computed::new(NDArray::zeros(x.shape())),
+            Err(e) => panic!("{}", e),
+        };
+        rows.extend(gx.iter().copied());
+    }
+    NDArray::from_shape_vec([y.shape(), x.shape()].concat(), rows).unwrap()
+}
+
+#[test]
+fn test() {
+    use crate::{scalar, IntoNDArray};
+
+    // f(x) = [x0 * x1, sin(x0)]
+    let x = ndarray::array![2.0, 3.0].into_ndarray();
+    let j = jacobian(
+        |x| {
+            let x0 = select(0, vec![0], x);
+            let x1 = select(0, vec![1], x);
+            crate::functions::concat(&[&x0 * &x1, x0.sin()], 0)
+        },
+        &x,
+    );
+    let expected = ndarray::array![[3.0, 2.0], [2.0f32.cos(), 0.0]].into_ndarray();
+    assert!((&j - &expected).iter().all(|d| d.abs() < 1e-6), "{:?}", j);
+
+    // f(x) = x0^2 * x1 + x1^3
+    let f = |x: &ComputedNDA| {
+        let x0 = select(0, vec![0], x);
+        let x1 = select(0, vec![1], x);
+        (&x0.pow_const(2.0) * &x1 + x1.pow_const(3.0)).sum([0], false)
+    };
+    let h = hessian(f, &x);
+    let expected = ndarray::array![[6.0, 4.0], [4.0, 18.0]].into_ndarray();
+    assert!((&h - &expected).iter().all(|d| d.abs() < 1e-4), "{:?}", h);
+
+    let p = backprop(x.clone());
+    let v = Computed::new(ndarray::array![1.0, -1.0].into_ndarray());
+    let hv = hessian_vector_product(&f(&p), &[p.clone()], &[v]);
+    let expected = ndarray::array![2.0, -14.0].into_ndarray();
+    assert!((&*hv[0] - &expected).iter().all(|d| d.abs() < 1e-4));
+
+    // an output that does not depend on x
+    let j = jacobian(|_| Computed::new(scalar(1.0)), &x);
+    assert_eq!(j.shape(), &[2]);
+    assert!(j.iter().all(|v| *v == 0.0));
+}
@@ -0,0 +1,52 @@
+use std::cell::Cell;
+
+thread_local! {
+    static LAZY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the previous lazy mode of the current thread when dropped.
+#[must_use]
+pub struct LazyModeGuard {
+    prev: bool,
+}
+
+impl Drop for LazyModeGuard {
+    fn drop(&mut self) {
+        LAZY.with(|l| l.set(self.prev));
+    }
+}
+
+/// Returns whether element-wise functions are recorded lazily on the current thread.
+pub fn is_lazy() -> bool {
+    LAZY.with(|l| l.get())
+}
+
+/// Enables or disables lazy mode on the current thread until the guard is dropped.
+pub fn set_lazy(enabled: bool) -> LazyModeGuard {
+    let prev = LAZY.with(|l| l.replace(enabled));
+    LazyModeGuard { prev }
+}
+
+/// Enables lazy mode on the current thread until the guard is dropped.
+///
+/// In lazy mode, element-wise functions do not compute their outputs.
+/// Consecutive element-wise functions are fused into a single function,
+/// which is computed in a single pass when the value is accessed.
+/// Intermediate values are not kept, so the backward recomputes them in its own fused passes.
+///
+/// ```
+/// use tensorflake::*;
+///
+/// let x = backprop(ndarray::array![1.0, 2.0, 3.0].into_ndarray());
+/// let y = {
+///     let _guard = lazy();
+///     (&x - &ComputedNDA::new(scalar(2.0))) * x.clone() + x.exp()
+/// };
+/// assert!(y.is_pending());
+/// let gx = gradients(&[y.clone()], &[x.clone()], false);
+/// assert!(!y.is_pending());
+/// assert_eq!(gx[0].shape(), &[3]);
+/// ```
+pub fn lazy() -> LazyModeGuard {
+    set_lazy(true)
+}
@@ -0,0 +1,204 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::NDArray;
+
+/// The number of the running trackers, checked before locking `TRACKERS`.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+static TRACKERS: Mutex<Vec<Arc<Mutex<Counts>>>> = Mutex::new(Vec::new());
+
+#[derive(Default)]
+struct Counts {
+    current: usize,
+    peak: usize,
+    /// The bytes and the number of the values sharing each buffer by its address.
+    buffers: HashMap<usize, (usize, usize)>,
+}
+
+impl Counts {
+    fn add(&mut self, buffer: Option<usize>, bytes: usize) {
+        if let Some(buffer) = buffer {
+            let (_, count) = self.buffers.entry(buffer).or_insert((bytes, 0));
+            *count += 1;
+            if *count > 1 {
+                return;
+            }
+        }
+        self.current += bytes;
+        self.peak = self.peak.max(self.current);
+    }
+
+    fn remove(&mut self, buffer: Option<usize>, bytes: usize) {
+        let bytes = match buffer {
+            Some(buffer) => {
+                let (bytes, count) = self.buffers.get_mut(&buffer).unwrap();
+                *count -= 1;
+                if *count > 0 {
+                    return;
+                }
+                let bytes = *bytes;
+                self.buffers.remove(&buffer);
+                bytes
+            }
+            None => bytes,
+        };
+        self.current -= bytes;
+    }
+}
+
+/// Tracks the bytes held by the `Computed` values created while it is running.
+///
+/// Each tracker counts from zero when it starts, so the values created before are not counted,
+/// and trackers running at the same time do not affect each other. The values created by any
+/// thread are counted. Values sharing a buffer, such as clones of an array, are counted once.
+/// Values computed in lazy mode are counted when they are computed.
+///
+/// ```
+/// use tensorflake::*;
+///
+/// let tracker = MemoryTracker::start();
+/// let x: ComputedNDA = ComputedNDA::new(NDArray::zeros(&[10, 10][..]));
+/// let y = &x + &x;
+/// assert_eq!(tracker.peak_bytes(), 2 * 100 * 4);
+/// ```
+#[must_use]
+pub struct MemoryTracker {
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl MemoryTracker {
+    pub fn start() -> Self {
+        let counts = Arc::new(Mutex::new(Counts::default()));
+        TRACKERS.lock().unwrap().push(counts.clone());
+        RUNNING.fetch_add(1, Ordering::SeqCst);
+        MemoryTracker { counts }
+    }
+
+    /// Returns the bytes held by the tracked values that are alive.
+    pub fn current_bytes(&self) -> usize {
+        self.counts.lock().unwrap().current
+    }
+
+    /// Returns the maximum of `current_bytes` since the tracker started.
+    pub fn peak_bytes(&self) -> usize {
+        self.counts.lock().unwrap().peak
+    }
+}
+
+impl Drop for MemoryTracker {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+        TRACKERS
+            .lock()
+            .unwrap()
+            .retain(|c| !Arc::ptr_eq(c, &self.counts));
+    }
+}
+
+/// A value counted by the trackers running when it is created, released on drop.
+pub(crate) struct Allocation {
+    buffer: Option<usize>,
+    bytes: usize,
+    trackers: Vec<Arc<Mutex<Counts>>>,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        for counts in &self.trackers {
+            counts.lock().unwrap().remove(self.buffer, self.bytes);
+        }
+    }
+}
+
+/// Counts `x` by the running trackers. Returns `None` if no tracker is running.
+pub(crate) fn allocate<T: 'static>(x: &T) -> Option<Allocation> {
+    if RUNNING.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let trackers = TRACKERS.lock().unwrap().clone();
+    if trackers.is_empty() {
+        return None;
+    }
+    let (buffer, bytes) = bytes_of(x);
+    for counts in &trackers {
+        counts.lock().unwrap().add(buffer, bytes);
+    }
+    Some(Allocation {
+        buffer,
+        bytes,
+        trackers,
+    })
+}
+
+/// Returns the address of the buffer of `x` if it is an array, and the bytes of `x`.
+fn bytes_of<T: 'static>(x: &T) -> (Option<usize>, usize) {
+    fn array<T>(x: &NDArray<T>) -> (Option<usize>, usize) {
+        (
+            Some(x.as_ptr() as usize),
+            x.len() * std::mem::size_of::<T>(),
+        )
+    }
+
+    let x = x as &dyn Any;
+    if let Some(x) = x.downcast_ref::<NDArray<f32>>() {
+        return array(x);
+    }
+    if let Some(x) = x.downcast_ref::<NDArray<f64>>() {
+        return array(x);
+    }
+    #[cfg(feature = "half")]
+    {
+        if let Some(x) = x.downcast_ref::<NDArray<half::f16>>() {
+            return array(x);
+        }
+        if let Some(x) = x.downcast_ref::<NDArray<half::bf16>>() {
+            return array(x);
+        }
+    }
+    (None, std::mem::size_of::<T>())
+}
+
+#[test]
+fn test() {
+    use crate::ComputedNDA;
+
+    // other tests may allocate concurrently, so the sizes are large enough to tell apart
+    const MB: usize = 1 << 20;
+    let array = || NDArray::<f32>::zeros(&[MB][..]);
+
+    let tracker = MemoryTracker::start();
+    let x = ComputedNDA::new(array());
+    assert!(tracker.current_bytes() >= 4 * MB);
+    assert!(tracker.current_bytes() < 8 * MB);
+
+    // another tracker counts from its own start and does not reset the peak of the first
+    drop(x);
+    let other = MemoryTracker::start();
+    let y = ComputedNDA::new(array());
+    assert!(tracker.current_bytes() >= 4 * MB && tracker.current_bytes() < 8 * MB);
+    assert!(other.peak_bytes() >= 4 * MB && other.peak_bytes() < 8 * MB);
+    drop(other);
+
+    // the values sharing a buffer are counted once
+    let z = ComputedNDA::new((*y).clone());
+    assert!(tracker.current_bytes() < 8 * MB);
+    drop((y, z));
+    assert!(tracker.current_bytes() < 4 * MB);
+
+    // lazy values are counted when they are computed
+    let x = ComputedNDA::new(array());
+    let y = {
+        let _lazy = crate::set_lazy(true);
+        x.exp()
+    };
+    assert!(tracker.current_bytes() < 8 * MB);
+    assert_eq!(y[0], 1.0);
+    assert!(tracker.current_bytes() >= 8 * MB);
+    assert!(tracker.peak_bytes() >= 8 * MB);
+}
@@ -1,18 +1,47 @@
+mod anomaly;
 mod backward;
+mod checkpoint;
 mod computed;
+mod forward;
 mod function_call;
 pub mod graph;
+mod higher_order;
+mod lazy;
+mod memory;
+mod no_grad;
 mod optimize;
 mod optimizer;
 pub mod param;
+mod per_sample;
+mod profiler;
+mod trace;
+mod vmap;
 
+pub use anomaly::{anomaly_mode, set_anomaly_mode, take_anomaly, AnomalyMode, AnomalyModeGuard};
 pub use backward::{chain, Backward};
-pub use computed::Computed;
+pub(crate) use backward::{chain_with_args, FunctionArgs};
+pub use checkpoint::checkpoint;
+pub(crate) use computed::Pending;
+pub use computed::{Computed, Hook};
+pub use forward::{forward_derivative, jvp};
 pub use function_call::FunctionCall;
-pub use graph::gradients;
-pub use optimize::{optimize, GradientsAccumulator};
-pub use optimizer::Optimizer;
+pub use graph::{gradients, gradients_with, try_gradients, try_gradients_with};
+pub use higher_order::{hessian, hessian_vector_product, jacobian};
+pub use lazy::{is_lazy, lazy, set_lazy, LazyModeGuard};
+pub use memory::MemoryTracker;
+pub use no_grad::{is_grad_enabled, no_grad, set_grad_enabled, GradModeGuard};
+pub use optimize::{optimize, ClippedExamples, GradientsAccumulator};
+pub use optimizer::{Optimizer, OptimizerRecord, SaveOptimizer};
 pub use param::Param;
+pub use per_sample::{
+    per_sample_gradients, per_sample_gradients_of, try_per_sample_gradients,
+    try_per_sample_gradients_of,
+};
+pub use profiler::{
+    profile_scope, FunctionProfile, Profile, ProfileEvent, ProfilePhase, ProfileScope, Profiler,
+};
+pub use trace::Trace;
+pub use vmap::{try_vmap, vmap};
 
 pub fn backprop<T: Send + Sync + 'static>(x: T) -> Computed<T> {
     let y = Computed::new(x);
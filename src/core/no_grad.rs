@@ -0,0 +1,77 @@
+use std::cell::Cell;
+
+thread_local! {
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Restores the previous grad mode of the current thread when dropped.
+#[must_use]
+pub struct GradModeGuard {
+    prev: bool,
+}
+
+impl Drop for GradModeGuard {
+    fn drop(&mut self) {
+        GRAD_ENABLED.with(|g| g.set(self.prev));
+    }
+}
+
+/// Returns whether `chain` and `Param::get` record the graph on the current thread.
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(|g| g.get())
+}
+
+/// Enables or disables graph recording on the current thread until the guard is dropped.
+pub fn set_grad_enabled(enabled: bool) -> GradModeGuard {
+    let prev = GRAD_ENABLED.with(|g| g.replace(enabled));
+    GradModeGuard { prev }
+}
+
+/// Disables graph recording on the current thread until the guard is dropped.
+///
+/// ```
+/// use tensorflake::*;
+///
+/// let x = backprop(scalar(2.0));
+/// let y = {
+///     let _guard = no_grad();
+///     &x * &x
+/// };
+/// assert!(!y.has_creator());
+/// ```
+pub fn no_grad() -> GradModeGuard {
+    set_grad_enabled(false)
+}
+
+#[test]
+fn test() {
+    use crate::*;
+
+    let x = backprop(scalar(2.0));
+    {
+        let _guard = no_grad();
+        assert!(!is_grad_enabled());
+        let y = &x * &x;
+        assert!(!y.has_creator());
+
+        {
+            let _guard = set_grad_enabled(true);
+            let y = &x * &x;
+            assert!(y.has_creator());
+        }
+        assert!(!is_grad_enabled());
+
+        let p = ParamNDA::new(scalar(1.0), "param".into(), optimizers::Fixed);
+        assert!(!p.get().has_creator());
+        assert!(p.get() != p.get());
+    }
+    assert!(is_grad_enabled());
+    let y = &x * &x;
+    assert!(y.has_creator());
+
+    // other threads are not affected
+    let _guard = no_grad();
+    std::thread::spawn(|| assert!(is_grad_enabled()))
+        .join()
+        .unwrap();
+}
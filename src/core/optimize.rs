@@ -1,4 +1,18 @@
-use super::{gradients, graph, Computed, Param};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{graph, trace, Computed, Param};
+
+/// The examples in the gradients applied by `GradientsAccumulator::optimize`,
+/// all of which were clipped per example by `GradientsAccumulator::compute_clipped`.
+/// Given to `Optimizer::update_clipped`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClippedExamples {
+    pub count: usize,
+    /// The largest `max_norm` the gradients of the examples were clipped to.
+    pub max_norm: f32,
+    /// Identifies the call of `GradientsAccumulator::optimize`, which updates all the params.
+    pub step: u64,
+}
 
 pub fn optimize<T: Clone + Default + Send + Sync + 'static>(loss: &Computed<T>)
 where
@@ -12,6 +26,12 @@ where
 
 pub struct GradientsAccumulator<T: Default + Send + Sync + 'static> {
     pub table: std::collections::HashMap<Param<T>, T>,
+    /// The number of examples clipped by `compute_clipped`.
+    clipped_examples: usize,
+    /// The largest `max_norm` given to `compute_clipped`.
+    max_norm: f32,
+    /// Whether any gradient was added without clipping.
+    unclipped: bool,
 }
 
 impl<T: Clone + Default + Send + Sync + 'static> GradientsAccumulator<T>
@@ -22,17 +42,47 @@ where
     pub fn new() -> Self {
         Self {
             table: std::collections::HashMap::new(),
+            clipped_examples: 0,
+            max_norm: 0.0,
+            unclipped: false,
         }
     }
 
     pub fn compute(&mut self, loss: &Computed<T>) {
-        let (params, grads) = collect_params_grads(loss);
+        self.compute_with(loss, true);
+    }
+
+    /// Same as `compute`, but releases the graph of `loss` during the backward.
+    /// See `graph::gradients_with`.
+    ///
+    /// Intermediate values in the graph are unchained, so other losses sharing them
+    /// (e.g. a second head on the same trunk) no longer reach the params through them.
+    /// Compute such losses before this, or sum them into one loss.
+    pub fn compute_and_release(&mut self, loss: &Computed<T>) {
+        self.compute_with(loss, false);
+    }
+
+    fn compute_with(&mut self, loss: &Computed<T>, retain_graph: bool) {
+        let (params, grads) =
+            trace::params_grads(loss, || collect_params_grads(loss, retain_graph));
         for (param, grad) in params.into_iter().zip(grads.into_iter()) {
             self.push(param, (*grad).clone());
         }
     }
 
+    /// Panics if the optimizer of `param` needs the gradients clipped per example.
+    /// See `Optimizer::per_example_clip`.
     pub fn push(&mut self, param: Param<T>, grad: T) {
+        assert!(
+            param.per_example_clip().is_none(),
+            "the optimizer of {} needs the gradients of the examples clipped by GradientsAccumulator::compute_clipped",
+            param.name()
+        );
+        self.unclipped = true;
+        self.add(param, grad);
+    }
+
+    fn add(&mut self, param: Param<T>, grad: T) {
         match self.table.entry(param) {
             std::collections::hash_map::Entry::Occupied(mut e) => {
                 *e.get_mut() = grad + e.get();
@@ -43,22 +93,135 @@ where
         }
     }
 
+    /// Returns the number of examples whose gradients are added by `compute_clipped`.
+    pub fn clipped_examples(&self) -> usize {
+        self.clipped_examples
+    }
+
     pub fn optimize(&mut self) {
+        static STEP: AtomicU64 = AtomicU64::new(0);
+        let clipped = (!self.unclipped && self.clipped_examples > 0).then(|| ClippedExamples {
+            count: self.clipped_examples,
+            max_norm: self.max_norm,
+            step: STEP.fetch_add(1, Ordering::Relaxed),
+        });
         for (param, grad) in &self.table {
-            param.update(grad);
+            param.update_clipped(grad, clipped);
         }
         self.table.clear();
+        self.clipped_examples = 0;
+        self.max_norm = 0.0;
+        self.unclipped = false;
     }
 
     pub fn merge(&mut self, mut other: Self) {
         for (param, grad) in other.table.drain() {
-            self.push(param, grad);
+            self.add(param, grad);
         }
+        self.clipped_examples += other.clipped_examples;
+        self.max_norm = self.max_norm.max(other.max_norm);
+        self.unclipped |= other.unclipped;
     }
 }
 
+impl<T: crate::Float> GradientsAccumulator<crate::NDArray<T>> {
+    /// Same as `compute`, but takes the losses of the examples of the shape `[batch size]`
+    /// and scales the gradient of each example so that its total L2 norm over the params is at
+    /// most `max_norm`. Used to bound the contribution of each example for `optimizers::DpSgd`.
+    ///
+    /// The gradients of the examples are computed in a single backward by
+    /// `per_sample_gradients_of`, so the model must follow its restrictions.
+    /// Panics if `losses` is not a vector, or if `max_norm` is larger than
+    /// `Optimizer::per_example_clip` of any of the params.
+    pub fn compute_clipped(&mut self, losses: &crate::ComputedNDA<T>, max_norm: f32) {
+        let params = trainable_params(losses);
+        for param in &params {
+            if let Some(clip) = param.per_example_clip() {
+                assert!(
+                    max_norm <= clip,
+                    "the gradients are clipped to {}, larger than {} the optimizer of {} needs",
+                    max_norm,
+                    clip,
+                    param.name()
+                );
+            }
+        }
+        let grads = crate::per_sample_gradients_of(losses, &params);
+        let n = losses.shape()[0];
+        let mut clipped: Vec<_> = params
+            .iter()
+            .map(|p| crate::NDArray::zeros(p.get().shape()))
+            .collect();
+        for i in 0..n {
+            let norm = grads
+                .iter()
+                .flat_map(|g| g.index_axis(ndarray::Axis(0), i).into_iter())
+                .map(|x| x.to_f64().unwrap().powi(2))
+                .sum::<f64>()
+                .sqrt();
+            let scale = T::from_f64((max_norm as f64 / norm).min(1.0));
+            for (c, g) in clipped.iter_mut().zip(&grads) {
+                ndarray::Zip::from(c)
+                    .and(g.index_axis(ndarray::Axis(0), i))
+                    .for_each(|c, g| *c += *g * scale);
+            }
+        }
+        for (param, grad) in params.into_iter().zip(clipped) {
+            self.add(param, grad);
+        }
+        self.clipped_examples += n;
+        self.max_norm = self.max_norm.max(max_norm);
+    }
+
+    /// Returns the L2 norm of all the gradients as if they were concatenated into one vector.
+    pub fn grad_norm(&self) -> f32 {
+        self.table
+            .values()
+            .flat_map(|g| g.iter())
+            .map(|x| x.to_f64().unwrap().powi(2))
+            .sum::<f64>()
+            .sqrt() as f32
+    }
+
+    /// Scales all the gradients by the same factor so that `grad_norm` is at most `max_norm`.
+    /// Returns `grad_norm` before clipping.
+    pub fn clip_grad_norm(&mut self, max_norm: f32) -> f32 {
+        let norm = self.grad_norm();
+        if norm > max_norm {
+            let scale = T::from_f64(max_norm as f64 / norm as f64);
+            for grad in self.table.values_mut() {
+                *grad = grad.mapv(|g| g * scale).into_shared();
+            }
+        }
+        norm
+    }
+
+    /// Clamps each element of the gradients into `[-value, value]`.
+    pub fn clip_grad_value(&mut self, value: f32) {
+        assert!(value >= 0.0);
+        let (min, max) = (T::from_f32(-value), T::from_f32(value));
+        for grad in self.table.values_mut() {
+            *grad = grad.mapv(|g| g.max(min).min(max)).into_shared();
+        }
+    }
+}
+
+fn trainable_params<T: crate::Float>(loss: &crate::ComputedNDA<T>) -> Vec<crate::ParamNDA<T>> {
+    graph::collect_function_calls(vec![loss.clone()])
+        .iter()
+        .filter_map(|fc| {
+            fc.backward
+                .as_any()
+                .and_then(|o| o.downcast_ref::<crate::ParamNDA<T>>())
+                .filter(|o| o.is_trainable())
+                .cloned()
+        })
+        .collect()
+}
+
 fn collect_params_grads<T: Clone + Default + Send + Sync + 'static>(
     loss: &Computed<T>,
+    retain_graph: bool,
 ) -> (Vec<Param<T>>, Vec<Computed<T>>)
 where
     T: graph::One,
@@ -68,16 +231,18 @@ where
 
     let mut params = Vec::new();
     let mut trainables = Vec::new();
+    let mut trainable_set = std::collections::HashSet::new();
 
     for fc in function_calls {
         if let Some(o) = fc
             .backward
             .as_any()
             .and_then(|o| o.downcast_ref::<Param<T>>())
+            .filter(|o| o.is_trainable())
             .cloned()
         {
             let trainable = fc.get_ys().pop().unwrap();
-            if trainables.contains(&trainable) {
+            if !trainable_set.insert(std::sync::Arc::as_ptr(&trainable.inner)) {
                 panic!("same trainables");
                 // continue;
             }
@@ -86,6 +251,177 @@ where
         }
     }
 
-    let grads = gradients(&[loss.clone()], &trainables, false);
+    if trainables.is_empty() {
+        return (params, vec![]);
+    }
+    let grads = graph::gradients_with(&[loss.clone()], &trainables, false, retain_graph);
     (params, grads)
 }
+
+#[test]
+fn test_hooks() {
+    use crate::*;
+
+    let p = ParamNDA::new(scalar(2.0), "p".into(), optimizers::SGD::new(1.0));
+    let x = p.get();
+    x.register_hook(|g| Some(ComputedNDA::new((&**g * 0.5).into_ndarray())));
+    let loss = &x * &x;
+
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&loss);
+    assert_eq!(ga.table[&p][[]], 2.0);
+}
+
+#[test]
+fn test_compute_and_release() {
+    use crate::*;
+
+    let p = ParamNDA::new(scalar(3.0), "p".into(), optimizers::SGD::new(1.0));
+    let x = p.get();
+    let loss = (&x * &x).exp().log();
+
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_and_release(&loss);
+    assert_eq!(ga.table[&p][[]], 6.0);
+    assert!(!loss.has_creator());
+
+    // the param is still found in the next step
+    let loss = &p.get() * &p.get();
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&loss);
+    assert_eq!(ga.table[&p][[]], 6.0);
+}
+
+#[test]
+fn test_shared_trunk() {
+    use crate::*;
+
+    let p = ParamNDA::new(scalar(3.0), "p".into(), optimizers::SGD::new(1.0));
+    let trunk = || {
+        let h = &p.get() * &p.get();
+        let l1 = h.exp().log();
+        let l2 = &h + &h;
+        (l1, l2)
+    };
+
+    // the gradients of both heads reach the param
+    let (l1, l2) = trunk();
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&l1);
+    ga.compute(&l2);
+    assert_eq!(ga.table[&p][[]], 6.0 + 12.0);
+
+    // releasing the first head unchains the trunk from the second
+    let (l1, l2) = trunk();
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_and_release(&l1);
+    ga.compute(&l2);
+    assert_eq!(ga.table[&p][[]], 6.0);
+
+    // releasing the last head is safe
+    let (l1, l2) = trunk();
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&l1);
+    ga.compute_and_release(&l2);
+    assert_eq!(ga.table[&p][[]], 6.0 + 12.0);
+}
+
+#[test]
+fn test_compute_clipped() {
+    use crate::*;
+
+    let p = ParamNDA::new(
+        ndarray::array![1.0, 2.0].into_ndarray(),
+        "p".into(),
+        optimizers::SGD::new(1.0),
+    );
+    let x = ComputedNDA::new(ndarray::array![[3.0, 4.0], [0.3, 0.4]].into_ndarray());
+    let losses = || (&x * &p.get()).sum([1], false);
+
+    // the gradients of the examples [3, 4] and [0.3, 0.4] are clipped separately
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_clipped(&losses(), 1.0);
+    assert!((&ga.table[&p] - &ndarray::array![0.9, 1.2])
+        .iter()
+        .all(|d: &f32| d.abs() < 1e-6));
+    ga.compute_clipped(&losses(), 1.0);
+    assert_eq!(ga.clipped_examples(), 4);
+
+    // gradients within the bound are not changed
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_clipped(&losses(), 10.0);
+    assert_eq!(ga.table[&p], ndarray::array![3.3, 4.4].into_ndarray());
+
+    // the optimizer sees the examples only if all the gradients are clipped
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let q = ParamNDA::new(scalar(0.0), "q".into(), Spy(seen.clone()));
+    let r = ParamNDA::new(scalar(0.0), "r".into(), Spy(seen.clone()));
+    let losses = || (&x * &q.get() + &x * &r.get()).sum([1], false);
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_clipped(&losses(), 1.0);
+    ga.compute_clipped(&losses(), 0.5);
+    ga.optimize();
+    ga.compute_clipped(&losses(), 1.0);
+    ga.compute(&q.get());
+    ga.optimize();
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4);
+    let clipped = seen[0].unwrap();
+    assert_eq!((clipped.count, clipped.max_norm), (4, 1.0));
+    // the params updated together see the same step
+    assert_eq!(seen[1], Some(clipped));
+    assert_eq!(seen[2..], [None, None]);
+
+    #[derive(Clone)]
+    struct Spy(std::sync::Arc<std::sync::Mutex<Vec<Option<ClippedExamples>>>>);
+
+    impl Optimizer<NDArray> for Spy {
+        type State = ();
+
+        fn new_state(&self, _: &[usize]) {}
+
+        fn update(&mut self, _: &mut NDArray, _: &mut (), _: &NDArray) {
+            unreachable!()
+        }
+
+        fn update_clipped(
+            &mut self,
+            _: &mut NDArray,
+            _: &mut (),
+            _: &NDArray,
+            clipped: Option<ClippedExamples>,
+        ) {
+            self.0.lock().unwrap().push(clipped);
+        }
+    }
+}
+
+#[test]
+fn test_clip_grad() {
+    use crate::*;
+
+    let p = ParamNDA::new(
+        ndarray::array![3.0, 0.0].into_ndarray(),
+        "p".into(),
+        optimizers::SGD::new(1.0),
+    );
+    let q = ParamNDA::new(scalar(4.0), "q".into(), optimizers::SGD::new(1.0));
+    let loss = || {
+        ((&p.get() * &p.get()).sum([0], false) + &q.get() * &q.get())
+            * ComputedNDA::new(scalar(0.5))
+    };
+
+    // the norm is taken over all the params
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&loss());
+    assert_eq!(ga.clip_grad_norm(1.0), 5.0);
+    assert_eq!(ga.table[&p], ndarray::array![0.6, 0.0].into_ndarray());
+    assert_eq!(ga.table[&q][[]], 0.8);
+    assert!((ga.grad_norm() - 1.0).abs() < 1e-6);
+
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&loss());
+    ga.clip_grad_value(3.5);
+    assert_eq!(ga.table[&p], ndarray::array![3.0, 0.0].into_ndarray());
+    assert_eq!(ga.table[&q][[]], 3.5);
+}
@@ -1,6 +1,130 @@
+use std::collections::BTreeMap;
+
+use super::ClippedExamples;
+
 pub trait Optimizer<T: Sync + Send + 'static>: Sync + Send + 'static {
     type State: Sync + Send + 'static;
 
     fn new_state(&self, shape: &[usize]) -> Self::State;
     fn update(&mut self, data: &mut T, state: &mut Self::State, grad: &T);
+
+    /// Same as `update`, with the examples in `grad` if all of them were clipped per example.
+    /// Called by `GradientsAccumulator::optimize`.
+    fn update_clipped(
+        &mut self,
+        data: &mut T,
+        state: &mut Self::State,
+        grad: &T,
+        clipped: Option<ClippedExamples>,
+    ) {
+        let _ = clipped;
+        self.update(data, state, grad);
+    }
+
+    /// Returns the L2 norm the gradient of each example must be clipped to, if the optimizer
+    /// needs the clipping, such as `optimizers::DpSgd`. Checked by `GradientsAccumulator`.
+    fn per_example_clip(&self) -> Option<f32> {
+        None
+    }
+
+    /// Changes the learning rate, such as by `ParamGroup::set_learning_rate`.
+    /// Returns false if the optimizer ignores it, as the default does for the optimizers
+    /// without a learning rate, such as `optimizers::Fixed`.
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        let _ = learning_rate;
+        false
+    }
+}
+
+/// An optimizer whose config and state are saved with the params to resume training.
+/// The params can be saved with it once it is registered by `param::register_optimizer`.
+pub trait SaveOptimizer<T: Sync + Send + 'static>: Optimizer<T> + Clone {
+    /// Writes the config and `state` into `record`.
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<T>);
+
+    /// Restores the optimizer and the state written by `save`.
+    /// Returns `None` if any of them is missing in `record`.
+    fn load(record: &OptimizerRecord<T>) -> Option<(Self, Self::State)>;
+
+    fn to_record(&self, state: &Self::State) -> OptimizerRecord<T> {
+        let mut record = OptimizerRecord::new();
+        self.save(state, &mut record);
+        record
+    }
+}
+
+/// The config and the state of an optimizer by key, written by `SaveOptimizer::save`.
+/// Optimizers wrapping other optimizers keep the records of them as children.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OptimizerRecord<T> {
+    pub scalars: BTreeMap<String, f64>,
+    pub arrays: BTreeMap<String, T>,
+    pub children: BTreeMap<String, OptimizerRecord<T>>,
+}
+
+impl<T> Default for OptimizerRecord<T> {
+    fn default() -> Self {
+        Self {
+            scalars: BTreeMap::new(),
+            arrays: BTreeMap::new(),
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> OptimizerRecord<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_scalar(&mut self, key: &str, value: impl Into<f64>) {
+        self.scalars.insert(key.to_owned(), value.into());
+    }
+
+    pub fn scalar(&self, key: &str) -> Option<f64> {
+        self.scalars.get(key).copied()
+    }
+
+    pub fn set_array(&mut self, key: &str, value: T) {
+        self.arrays.insert(key.to_owned(), value);
+    }
+
+    pub fn array(&self, key: &str) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.arrays.get(key).cloned()
+    }
+
+    pub fn set_child(&mut self, key: &str, record: Self) {
+        self.children.insert(key.to_owned(), record);
+    }
+
+    pub fn child(&self, key: &str) -> Option<&Self> {
+        self.children.get(key)
+    }
+}
+
+#[test]
+fn test_set_learning_rate() {
+    use crate::*;
+
+    struct Ignoring;
+    impl Optimizer<NDArray> for Ignoring {
+        type State = ();
+
+        fn new_state(&self, _: &[usize]) -> Self::State {}
+
+        fn update(&mut self, _: &mut NDArray, _: &mut Self::State, _: &NDArray) {}
+    }
+
+    assert!(!Ignoring.set_learning_rate(0.1));
+    assert!(!Optimizer::<NDArray>::set_learning_rate(
+        &mut optimizers::Fixed,
+        0.1
+    ));
+    let mut sgd =
+        optimizers::WithRegularization::new(optimizers::SGD::new(1.0), regularizers::L2::new(0.1));
+    assert!(Optimizer::<NDArray>::set_learning_rate(&mut sgd, 0.1));
+    assert_eq!(sgd.optimizer.learning_rate, 0.1);
 }
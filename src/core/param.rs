@@ -1,12 +1,56 @@
 use std::{
+    any::{Any, TypeId},
     borrow::Cow,
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
 };
 
-use super::{graph::One, Backward, Computed, FunctionCall, Optimizer};
+use arc_swap::ArcSwapOption;
+
+use super::{
+    graph::One, is_grad_enabled, Backward, ClippedExamples, Computed, FunctionCall, Optimizer,
+    OptimizerRecord, SaveOptimizer,
+};
+use crate::{optimizers, Error, Float, NDArray};
 
 pub trait OptimizerStateT<T: Sync + Send + 'static>: Sync + Send + 'static {
     fn update(&mut self, data: &mut T, grad: &T);
+
+    /// See `Optimizer::update_clipped`.
+    fn update_clipped(&mut self, data: &mut T, grad: &T, clipped: Option<ClippedExamples>) {
+        let _ = clipped;
+        self.update(data, grad);
+    }
+
+    /// See `Optimizer::per_example_clip`.
+    fn per_example_clip(&self) -> Option<f32> {
+        None
+    }
+
+    /// Returns false if the optimizer ignores it. See `Optimizer::set_learning_rate`.
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        let _ = learning_rate;
+        false
+    }
+
+    /// Returns the name given to `register_optimizer` and the record of the optimizer
+    /// to save the param, or the type name of the optimizer if it is not registered.
+    fn save(&self) -> Result<(String, OptimizerRecord<T>), &'static str> {
+        Err(std::any::type_name::<Self>())
+    }
+
+    /// Returns a clone of the optimizer for `Param::optimizer`.
+    fn clone_optimizer(&self) -> Option<Box<dyn Any>> {
+        None
+    }
+
+    /// Takes the state wrapped by `optimizers::ParamGroup` out of this state if it is the wrapper.
+    fn leave_group(&mut self) -> Option<Box<dyn OptimizerStateT<T>>> {
+        None
+    }
 }
 
 pub struct OptimizerState<T: Sync + Send + 'static, O: Optimizer<T> + Clone> {
@@ -20,33 +64,206 @@ impl<T: Sync + Send + 'static, O: Optimizer<T> + Clone> OptimizerStateT<T>
     fn update(&mut self, data: &mut T, grad: &T) {
         self.optimizer.update(data, &mut self.state, grad);
     }
+
+    fn update_clipped(&mut self, data: &mut T, grad: &T, clipped: Option<ClippedExamples>) {
+        self.optimizer
+            .update_clipped(data, &mut self.state, grad, clipped);
+    }
+
+    fn per_example_clip(&self) -> Option<f32> {
+        self.optimizer.per_example_clip()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.optimizer.set_learning_rate(learning_rate)
+    }
+
+    fn save(&self) -> Result<(String, OptimizerRecord<T>), &'static str> {
+        save(&self.optimizer, &self.state)
+    }
+
+    fn clone_optimizer(&self) -> Option<Box<dyn Any>> {
+        Some(Box::new(self.optimizer.clone()))
+    }
 }
 
+/// The learning rate, if set, overrides the one of the shared optimizer for this param only.
+/// When loaded, each param has its own copy of the optimizer with the learning rate.
 pub struct SharedOptimizerState<T: Sync + Send + 'static, O: Optimizer<T> + Clone> {
     optimizer: Arc<Mutex<O>>,
     state: O::State,
+    learning_rate: Option<f32>,
+}
+
+impl<T: Sync + Send + 'static, O: Optimizer<T> + Clone> SharedOptimizerState<T, O> {
+    fn optimizer(&self) -> O {
+        let mut optimizer = self.optimizer.lock().unwrap().clone();
+        if let Some(learning_rate) = self.learning_rate {
+            optimizer.set_learning_rate(learning_rate);
+        }
+        optimizer
+    }
 }
 
 impl<T: Sync + Send + 'static, O: Optimizer<T> + Clone> OptimizerStateT<T>
     for SharedOptimizerState<T, O>
 {
     fn update(&mut self, data: &mut T, grad: &T) {
-        let mut optimizer = self.optimizer.lock().unwrap().clone();
-        optimizer.update(data, &mut self.state, grad);
+        self.optimizer().update(data, &mut self.state, grad);
+    }
+
+    fn update_clipped(&mut self, data: &mut T, grad: &T, clipped: Option<ClippedExamples>) {
+        self.optimizer()
+            .update_clipped(data, &mut self.state, grad, clipped);
+    }
+
+    fn per_example_clip(&self) -> Option<f32> {
+        self.optimizer.lock().unwrap().per_example_clip()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        let set = self.optimizer().set_learning_rate(learning_rate);
+        if set {
+            self.learning_rate = Some(learning_rate);
+        }
+        set
+    }
+
+    fn save(&self) -> Result<(String, OptimizerRecord<T>), &'static str> {
+        save(&self.optimizer(), &self.state)
+    }
+
+    fn clone_optimizer(&self) -> Option<Box<dyn Any>> {
+        Some(Box::new(self.optimizer()))
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+type Saver<T, O> = fn(&O, &<O as Optimizer<T>>::State) -> OptimizerRecord<T>;
+type Loader<T> = fn(&OptimizerRecord<T>) -> Option<Box<dyn OptimizerStateT<T>>>;
+
+#[derive(Default)]
+struct Registry {
+    /// The names and the savers (`Saver<T, O>`) by the type ids of `(T, O)`.
+    savers: HashMap<TypeId, (String, Box<dyn Any + Send + Sync>)>,
+    /// The loaders (`Loader<T>`) by the names and the type ids of `T`.
+    loaders: HashMap<(String, TypeId), Box<dyn Any + Send + Sync>>,
+}
+
+impl Registry {
+    fn register<T: Sync + Send + 'static, O: SaveOptimizer<T>>(&mut self, name: &str) {
+        let id = TypeId::of::<(T, O)>();
+        if let Some((registered, _)) = self.savers.get(&id) {
+            assert_eq!(
+                *registered,
+                name,
+                "{} is already registered as {}",
+                std::any::type_name::<O>(),
+                registered
+            );
+            return;
+        }
+        assert!(
+            !self.loaders.contains_key(&(name.to_owned(), TypeId::of::<T>())),
+            "{} is already registered for another optimizer",
+            name
+        );
+        let saver: Saver<T, O> = O::to_record;
+        let loader: Loader<T> = |record| {
+            let (optimizer, state) = O::load(record)?;
+            Some(Box::new(OptimizerState { optimizer, state }))
+        };
+        self.savers.insert(id, (name.to_owned(), Box::new(saver)));
+        self.loaders
+            .insert((name.to_owned(), TypeId::of::<T>()), Box::new(loader));
+    }
+
+    /// Registers `O` and the wrappers of `O` in `optimizers`.
+    fn register_with_wrappers<T: Float, O: SaveOptimizer<NDArray<T>>>(&mut self, name: &str) {
+        use crate::regularizers::{L1, L1L2, L2};
+        use optimizers::{DpSgd, WithRegularization};
+
+        self.register::<NDArray<T>, O>(name);
+        self.register::<NDArray<T>, WithRegularization<O, L1>>(&format!(
+            "WithRegularization<{}, L1>",
+            name
+        ));
+        self.register::<NDArray<T>, WithRegularization<O, L2>>(&format!(
+            "WithRegularization<{}, L2>",
+            name
+        ));
+        self.register::<NDArray<T>, WithRegularization<O, L1L2>>(&format!(
+            "WithRegularization<{}, L1L2>",
+            name
+        ));
+        self.register::<NDArray<T>, DpSgd<O>>(&format!("DpSgd<{}>", name));
+    }
+
+    fn register_builtins<T: Float>(&mut self) {
+        self.register_with_wrappers::<T, optimizers::Fixed>("Fixed");
+        self.register_with_wrappers::<T, optimizers::SGD>("SGD");
+        self.register_with_wrappers::<T, optimizers::MomentumSGD>("MomentumSGD");
+        self.register_with_wrappers::<T, optimizers::Adam>("Adam");
+        self.register_with_wrappers::<T, optimizers::AdamW>("AdamW");
+    }
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut registry = Registry::default();
+        registry.register_builtins::<f32>();
+        registry.register_builtins::<f64>();
+        #[cfg(feature = "half")]
+        {
+            registry.register_builtins::<half::f16>();
+            registry.register_builtins::<half::bf16>();
+        }
+        Mutex::new(registry)
+    })
+}
+
+/// Makes the params with the optimizer `O` saved with it as `name` and loadable.
+/// `name` identifies `O` in the saved files, so keep it unchanged across versions.
+/// Saving a param with an unregistered optimizer is an error.
+///
+/// The wrappers of `O` in `optimizers` are registered with it, such as
+/// `WithRegularization<O, L2>` as `"WithRegularization<{name}, L2>"` and `DpSgd<O>` as
+/// `"DpSgd<{name}>"`. The optimizers in `optimizers` are registered by their type names,
+/// such as `"Adam"`, with their wrappers. Register nested wrappers explicitly.
+pub fn register_optimizer<T: Float, O: SaveOptimizer<NDArray<T>>>(name: &str) {
+    registry()
+        .lock()
+        .unwrap()
+        .register_with_wrappers::<T, O>(name);
+}
+
+fn save<T: Sync + Send + 'static, O: Optimizer<T>>(
+    optimizer: &O,
+    state: &O::State,
+) -> Result<(String, OptimizerRecord<T>), &'static str> {
+    let registry = registry().lock().unwrap();
+    let Some((name, saver)) = registry.savers.get(&TypeId::of::<(T, O)>()) else {
+        return Err(std::any::type_name::<O>());
+    };
+    let (name, saver) = (name.clone(), *saver.downcast_ref::<Saver<T, O>>().unwrap());
+    drop(registry);
+    Ok((name, saver(optimizer, state)))
+}
+
+/// The version of the format of the saved params, which is written first. The legacy format,
+/// written before the optimizers were saved, starts with the data instead, whose first byte is
+/// the version of ndarray's format, 1.
+const FORMAT_VERSION: u8 = 0x81;
+
 struct ParamInner<T: Send + Sync + 'static> {
     data: T,
-    #[serde(skip)]
-    computed: Option<Computed<T>>,
     name: Cow<'static, str>,
-    #[serde(skip, default = "default_optimizer_state")]
     optimizer_state: Box<dyn OptimizerStateT<T>>,
+    /// The name of the `ParamGroup` the param belongs to. Not saved.
+    group: Option<String>,
 }
 
-fn default_optimizer_state<T: Send + Sync + 'static>() -> Box<dyn OptimizerStateT<T>> {
+pub(crate) fn default_optimizer_state<T: Send + Sync + 'static>() -> Box<dyn OptimizerStateT<T>> {
     Box::new(OptimizerState {
         optimizer: crate::optimizers::Fixed,
         state: Default::default(),
@@ -57,31 +274,227 @@ impl<T: Send + Sync + 'static> ParamInner<T> {
     fn new(data: T, name: Cow<'static, str>, optimizer_state: Box<dyn OptimizerStateT<T>>) -> Self {
         Self {
             data,
-            computed: None,
             name,
             optimizer_state,
+            group: None,
+        }
+    }
+
+    fn update(&mut self, grad: &T, clipped: Option<ClippedExamples>) {
+        self.optimizer_state
+            .update_clipped(&mut self.data, grad, clipped);
+    }
+}
+
+mod param_inner_serde {
+    use std::{borrow::Cow, fmt, marker::PhantomData};
+
+    use serde::{
+        de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor},
+        ser::{self, SerializeStruct},
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::{
+        default_optimizer_state, registry, Loader, OptimizerRecord, OptimizerStateT, ParamInner,
+        FORMAT_VERSION,
+    };
+
+    const FIELDS: &[&str] = &["format", "data", "name", "optimizer_state"];
+
+    impl<T: Serialize + Send + Sync + 'static> Serialize for ParamInner<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let optimizer_state = self.optimizer_state.save().map_err(|name| {
+                <S::Error as ser::Error>::custom(format!(
+                    "{} is not registered; call `param::register_optimizer`",
+                    name
+                ))
+            })?;
+            let mut state = serializer.serialize_struct("ParamInner", FIELDS.len())?;
+            state.serialize_field("format", &FORMAT_VERSION)?;
+            state.serialize_field("data", &self.data)?;
+            state.serialize_field("name", &self.name)?;
+            state.serialize_field("optimizer_state", &optimizer_state)?;
+            state.end()
+        }
+    }
+
+    impl<'de, T: Deserialize<'de> + Send + Sync + 'static> Deserialize<'de> for ParamInner<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_struct("ParamInner", FIELDS, ParamInnerVisitor(PhantomData))
+        }
+    }
+
+    fn load<T: Sync + Send + 'static, E: de::Error>(
+        (name, record): (String, OptimizerRecord<T>),
+    ) -> Result<Box<dyn OptimizerStateT<T>>, E> {
+        let loader = registry()
+            .lock()
+            .unwrap()
+            .loaders
+            .get(&(name.clone(), std::any::TypeId::of::<T>()))
+            .map(|l| *l.downcast_ref::<Loader<T>>().unwrap())
+            .ok_or_else(|| {
+                E::custom(format!(
+                    "{} is not registered; call `param::register_optimizer`",
+                    name
+                ))
+            })?;
+        loader(&record).ok_or_else(|| E::custom(format!("the record of {} is incomplete", name)))
+    }
+
+    struct ParamInnerVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de> + Send + Sync + 'static> Visitor<'de> for ParamInnerVisitor<T> {
+        type Value = ParamInner<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a param")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let first: u8 = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+            if first != FORMAT_VERSION {
+                // the legacy format: the data, whose first byte is already read, and the name
+                let data = T::deserialize(LegacyData {
+                    first: Some(first),
+                    seq: &mut seq,
+                })?;
+                let name = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                return Ok(ParamInner::new(data, name, default_optimizer_state()));
+            }
+            let data = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+            let name = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+            let optimizer_state = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+            Ok(ParamInner::new(data, name, load(optimizer_state)?))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut format = None;
+            let mut data = None;
+            let mut name: Option<Cow<'static, str>> = None;
+            let mut optimizer_state = None;
+            while let Some(key) = map.next_key::<Cow<str>>()? {
+                match &*key {
+                    "format" => format = Some(map.next_value::<u8>()?),
+                    "data" => data = Some(map.next_value()?),
+                    "name" => name = Some(map.next_value()?),
+                    "optimizer_state" => optimizer_state = Some(map.next_value()?),
+                    _ => {
+                        map.next_value::<de::IgnoredAny>()?;
+                    }
+                }
+            }
+            let data = data.ok_or_else(|| de::Error::missing_field("data"))?;
+            let name = name.ok_or_else(|| de::Error::missing_field("name"))?;
+            // the legacy format has neither the format nor the optimizer
+            let optimizer_state = match format {
+                None => default_optimizer_state(),
+                Some(FORMAT_VERSION) => load(
+                    optimizer_state.ok_or_else(|| de::Error::missing_field("optimizer_state"))?,
+                )?,
+                Some(format) => {
+                    return Err(de::Error::custom(format!(
+                        "unknown format of params: {:#x}",
+                        format
+                    )))
+                }
+            };
+            Ok(ParamInner::new(data, name, optimizer_state))
+        }
+    }
+
+    /// Reads the data of the legacy format from the elements of the param following `first`.
+    struct LegacyData<'a, A> {
+        first: Option<u8>,
+        seq: &'a mut A,
+    }
+
+    impl<'de, A: SeqAccess<'de>> Deserializer<'de> for LegacyData<'_, A> {
+        type Error = A::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+            visitor.visit_seq(self)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map struct enum identifier ignored_any
+        }
+    }
+
+    impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for LegacyData<'_, A> {
+        type Error = A::Error;
+
+        fn next_element_seed<S: DeserializeSeed<'de>>(
+            &mut self,
+            seed: S,
+        ) -> Result<Option<S::Value>, A::Error> {
+            match self.first.take() {
+                Some(first) => seed
+                    .deserialize(IntoDeserializer::<A::Error>::into_deserializer(first))
+                    .map(Some),
+                None => self.seq.next_element_seed(seed),
+            }
         }
     }
+}
+
+/// `computed` is the snapshot of the data returned by `Param::get`.
+/// It is read without locking and cleared whenever the data is changed.
+/// Serialized as `ParamInner` to keep the format of `Param`.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+struct ParamShared<T: Send + Sync + 'static> {
+    #[serde(skip)]
+    computed: ArcSwapOption<Computed<T>>,
+    #[serde(skip, default = "default_trainable")]
+    trainable: AtomicBool,
+    inner: Mutex<ParamInner<T>>,
+}
+
+fn default_trainable() -> AtomicBool {
+    AtomicBool::new(true)
+}
+
+impl<T: Send + Sync + 'static> ParamShared<T> {
+    fn new(inner: ParamInner<T>) -> Arc<Self> {
+        Arc::new(Self {
+            computed: ArcSwapOption::empty(),
+            trainable: default_trainable(),
+            inner: Mutex::new(inner),
+        })
+    }
 
-    fn update(&mut self, grad: &T) {
-        self.optimizer_state.update(&mut self.data, grad);
-        self.computed = None;
+    fn lock(&self) -> std::sync::MutexGuard<'_, ParamInner<T>> {
+        self.inner.lock().unwrap()
     }
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Param<T: Default + Send + Sync + 'static> {
-    inner: Arc<Mutex<ParamInner<T>>>,
+    inner: Arc<ParamShared<T>>,
 }
 
 impl<T: Clone + Default + Send + Sync + 'static + One> Param<T> {
-    pub fn new(data: T, name: Cow<'static, str>, optimizer: impl Optimizer<T> + Clone) -> Self {
+    pub fn new<O: Optimizer<T> + Clone>(data: T, name: Cow<'static, str>, optimizer: O) -> Self {
         let optimizer_state = Box::new(OptimizerState {
             state: optimizer.new_state(&data.shape()),
             optimizer,
         });
         Param {
-            inner: Arc::new(Mutex::new(ParamInner::new(data, name, optimizer_state))),
+            inner: ParamShared::new(ParamInner::new(data, name, optimizer_state)),
         }
     }
 
@@ -92,11 +505,15 @@ impl<T: Clone + Default + Send + Sync + 'static + One> Param<T> {
     ) -> Self {
         let state = optimizer.lock().unwrap().new_state(&data.shape());
         Param {
-            inner: Arc::new(Mutex::new(ParamInner::new(
+            inner: ParamShared::new(ParamInner::new(
                 data,
                 name,
-                Box::new(SharedOptimizerState { optimizer, state }),
-            ))),
+                Box::new(SharedOptimizerState {
+                    optimizer,
+                    state,
+                    learning_rate: None,
+                }),
+            )),
         }
     }
 
@@ -106,44 +523,123 @@ impl<T: Clone + Default + Send + Sync + 'static + One> Param<T> {
         optimizer_state: impl OptimizerStateT<T>,
     ) -> Self {
         Param {
-            inner: Arc::new(Mutex::new(ParamInner::new(
-                data,
-                name,
-                Box::new(optimizer_state),
-            ))),
+            inner: ParamShared::new(ParamInner::new(data, name, Box::new(optimizer_state))),
         }
     }
 
     pub fn get(&self) -> Computed<T> {
-        let mut inner = self.inner.lock().unwrap();
-        if inner.computed.is_none() {
-            let computed = Computed::new(inner.data.clone());
-            let creator = FunctionCall {
-                backward: Box::new(Param {
-                    inner: self.inner.clone(),
-                }),
-                xs: vec![],
-                ys: vec![std::sync::Arc::downgrade(&computed.inner)],
-            };
-            computed.inner.attrs.lock().unwrap().creator = Some(std::sync::Arc::new(creator));
-            inner.computed = Some(computed);
+        if let Some(computed) = &*self.inner.computed.load() {
+            if !is_grad_enabled() {
+                return Computed::new(T::clone(computed));
+            }
+            return (**computed).clone();
+        }
+
+        let inner = self.inner.lock();
+        if !is_grad_enabled() {
+            return Computed::new(inner.data.clone());
+        }
+        // another thread may have created the snapshot while we were waiting for the lock
+        if let Some(computed) = &*self.inner.computed.load() {
+            return (**computed).clone();
         }
-        inner.computed.clone().unwrap()
+        let computed = Computed::new(inner.data.clone());
+        let creator = FunctionCall {
+            backward: Box::new(Param {
+                inner: self.inner.clone(),
+            }),
+            xs: vec![],
+            ys: vec![Arc::downgrade(&computed.inner)],
+        };
+        computed.set_creator(Arc::new(creator));
+        self.inner.computed.store(Some(Arc::new(computed.clone())));
+        computed
     }
 
     pub fn set(&mut self, data: T) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.data = data;
+        self.inner.computed.store(None);
     }
 
     pub fn update(&self, grad: &T) {
-        let mut inner = self.inner.lock().unwrap();
-        inner.update(grad);
+        self.update_clipped(grad, None);
+    }
+
+    /// Same as `update`, with the examples in `grad` if all of them were clipped per example.
+    /// See `Optimizer::update_clipped`.
+    pub fn update_clipped(&self, grad: &T, clipped: Option<ClippedExamples>) {
+        let mut inner = self.inner.lock();
+        inner.update(grad, clipped);
+        self.inner.computed.store(None);
+    }
+
+    /// Returns the L2 norm the gradient of each example must be clipped to for the optimizer.
+    /// See `Optimizer::per_example_clip`.
+    pub fn per_example_clip(&self) -> Option<f32> {
+        self.inner.lock().optimizer_state.per_example_clip()
     }
 
     pub fn name(&self) -> Cow<'static, str> {
-        let inner = self.inner.lock().unwrap();
-        inner.name.clone()
+        self.inner.lock().name.clone()
+    }
+
+    /// Changes the learning rate of the optimizer of the param only.
+    /// Returns false if the optimizer ignores it. See `Optimizer::set_learning_rate`.
+    pub fn set_learning_rate(&self, learning_rate: f32) -> bool {
+        self.inner
+            .lock()
+            .optimizer_state
+            .set_learning_rate(learning_rate)
+    }
+
+    /// Returns a clone of the optimizer of the param if it is an `O`.
+    pub fn optimizer<O: Optimizer<T> + Clone>(&self) -> Option<O> {
+        let optimizer = self.inner.lock().optimizer_state.clone_optimizer()?;
+        optimizer.downcast().ok().map(|o| *o)
+    }
+
+    /// Returns the name of the `optimizers::ParamGroup` the param belongs to.
+    pub fn group(&self) -> Option<String> {
+        self.inner.lock().group.clone()
+    }
+
+    /// Puts the param into `group`, replacing the optimizer state with `wrap(state)`.
+    pub(crate) fn join_group(
+        &self,
+        group: &str,
+        wrap: impl FnOnce(Box<dyn OptimizerStateT<T>>) -> Box<dyn OptimizerStateT<T>>,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock();
+        if let Some(current) = &inner.group {
+            return Err(Error::ParamInGroup {
+                name: inner.name.to_string(),
+                group: current.clone(),
+            });
+        }
+        let state = std::mem::replace(&mut inner.optimizer_state, default_optimizer_state());
+        inner.optimizer_state = wrap(state);
+        inner.group = Some(group.to_owned());
+        Ok(())
+    }
+
+    /// Takes the param out of its group, restoring the optimizer state wrapped by `join_group`.
+    pub(crate) fn leave_group(&self) {
+        let mut inner = self.inner.lock();
+        if let Some(state) = inner.optimizer_state.leave_group() {
+            inner.optimizer_state = state;
+            inner.group = None;
+        }
+    }
+
+    /// Returns false if the param is frozen. `GradientsAccumulator` ignores frozen params,
+    /// and their optimizer states are kept as they are.
+    pub fn is_trainable(&self) -> bool {
+        self.inner.trainable.load(Ordering::Relaxed)
+    }
+
+    pub fn set_trainable(&self, trainable: bool) {
+        self.inner.trainable.store(trainable, Ordering::Relaxed);
     }
 }
 
@@ -185,6 +681,97 @@ impl<T: Default + Send + Sync + 'static> Backward<T> for Param<T> {
     }
 
     fn get_function_name(&self) -> Cow<'static, str> {
-        self.inner.lock().unwrap().name.clone()
+        self.inner.lock().name.clone()
+    }
+}
+
+#[test]
+fn test_serde() {
+    use crate::*;
+
+    let grad = ndarray::array![0.1, -0.2].into_ndarray();
+    let p = ParamNDA::new(
+        ndarray::array![1.0, 2.0].into_ndarray(),
+        "p".into(),
+        optimizers::Adam::new(),
+    );
+    let shared = Arc::new(Mutex::new(optimizers::MomentumSGD::new(0.1, 0.9)));
+    let q = ParamNDA::new_shared(scalar(1.0), "q".into(), shared);
+    for _ in 0..3 {
+        p.update(&grad);
+        q.update(&scalar(1.0));
+    }
+
+    let bytes = bincode::serialize(&(&p, &q)).unwrap();
+    let (p2, q2): (ParamNDA, ParamNDA) = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(p2.name(), "p");
+    let fresh = ParamNDA::new((*p.get()).clone(), "p".into(), optimizers::Adam::new());
+    for _ in 0..3 {
+        for p in [&p, &p2, &fresh] {
+            p.update(&grad);
+        }
+        q.update(&scalar(1.0));
+        q2.update(&scalar(1.0));
+    }
+    // resumed bit-exactly with the moments
+    assert_eq!(*p.get(), *p2.get());
+    assert_ne!(*p.get(), *fresh.get());
+    assert_eq!(*q.get(), *q2.get());
+
+    // the wrapping optimizers are registered with the inner ones
+    let r = ParamNDA::new(
+        scalar(1.0),
+        "r".into(),
+        optimizers::WithRegularization::new(optimizers::SGD::new(0.1), regularizers::L2::new(0.5)),
+    );
+    let bytes = bincode::serialize(&r).unwrap();
+    let r2: ParamNDA = bincode::deserialize(&bytes).unwrap();
+    for r in [&r, &r2] {
+        r.update(&scalar(1.0));
     }
+    assert_eq!(*r.get(), *r2.get());
+
+    // unregistered optimizers are errors
+    #[derive(Clone)]
+    struct Unregistered;
+    impl Optimizer<NDArray> for Unregistered {
+        type State = ();
+
+        fn new_state(&self, _: &[usize]) -> Self::State {}
+
+        fn update(&mut self, data: &mut NDArray, _: &mut Self::State, grad: &NDArray) {
+            *data -= grad;
+        }
+    }
+    let u = ParamNDA::new(scalar(1.0), "u".into(), Unregistered);
+    let error = bincode::serialize(&u).err().unwrap();
+    assert!(error.to_string().contains("Unregistered is not registered"));
+
+    // unknown names are errors
+    let bytes = bincode::serialize(&(
+        FORMAT_VERSION,
+        scalar(1.0),
+        "v",
+        ("Unknown", OptimizerRecord::<NDArray>::new()),
+    ))
+    .unwrap();
+    let error = bincode::deserialize::<ParamNDA>(&bytes).err().unwrap();
+    assert!(error.to_string().contains("Unknown is not registered"));
+
+    // the legacy format written before the optimizers were saved
+    #[derive(serde::Serialize)]
+    struct Legacy<'a> {
+        w: (&'a NDArray, &'a str),
+        b: (&'a NDArray, &'a str),
+    }
+    let (w, b) = (ndarray::array![[1.0, 2.0]].into_ndarray(), scalar(3.0));
+    let bytes = bincode::serialize(&Legacy {
+        w: (&w, "w"),
+        b: (&b, "b"),
+    })
+    .unwrap();
+    let (w2, b2): (ParamNDA, ParamNDA) = bincode::deserialize(&bytes).unwrap();
+    assert_eq!((w2.name(), &*w2.get()), ("w".into(), &w));
+    assert_eq!((b2.name(), &*b2.get()), ("b".into(), &b));
+    assert!(w2.optimizer::<optimizers::Fixed>().is_some());
 }
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ndarray::Zip;
+
+use super::{graph, Param};
+use crate::{error::unwrap_or_panic, ComputedNDA, Error, Float, IntoNDArray, NDArray, ParamNDA};
+
+/// Computes the gradients of the loss of each example of `batch` with respect to `params`.
+///
+/// `loss_fn` takes `batch`, whose leading axes are the examples, and returns the losses of
+/// the examples of the shape `[batch size]`. The result has an element of the shape
+/// `[batch size, ..param shape]` for each of `params`. The gradient of a param that the losses
+/// do not depend on is zero. See `per_sample_gradients_of` for the restrictions on `loss_fn`.
+///
+/// ```
+/// use tensorflake::{optimizers::SGD, *};
+///
+/// let w = ParamNDA::new(ndarray::array![[1.0], [2.0]].into_ndarray(), "w".into(), SGD::new(0.1));
+/// let x = ndarray::array![[1.0, 0.0], [0.0, 3.0]].into_ndarray();
+/// let gs = per_sample_gradients(|xs| xs[0].matmul(&w.get()).sum([1], false), &[w.clone()], &[x]);
+/// assert_eq!(gs[0], ndarray::array![[[1.0], [0.0]], [[0.0], [3.0]]].into_ndarray());
+/// ```
+pub fn per_sample_gradients<T: Float>(
+    loss_fn: impl FnOnce(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    params: &[ParamNDA<T>],
+    batch: &[NDArray<T>],
+) -> Vec<NDArray<T>> {
+    unwrap_or_panic(try_per_sample_gradients(loss_fn, params, batch))
+}
+
+pub fn try_per_sample_gradients<T: Float>(
+    loss_fn: impl FnOnce(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    params: &[ParamNDA<T>],
+    batch: &[NDArray<T>],
+) -> Result<Vec<NDArray<T>>, Error> {
+    let xs: Vec<_> = batch.iter().map(|x| ComputedNDA::new(x.clone())).collect();
+    try_per_sample_gradients_of(&loss_fn(&xs), params)
+}
+
+/// Same as `per_sample_gradients`, but takes the losses of the examples already computed.
+///
+/// The batch is differentiated once as a whole. The gradients of the outputs of the functions
+/// taking the params keep the batch axis, and the gradients of the params are computed from them
+/// for each example instead of summed over the batch. So the params must be taken directly by
+/// `matmul` or `matmul_add` as the right operand or the bias, or by the element-wise `add`, `sub`,
+/// `mul` and `div` broadcast over the batch axis. The functions must not mix the examples,
+/// e.g. by normalizing over the batch axis, or the results are wrong.
+pub fn per_sample_gradients_of<T: Float>(
+    losses: &ComputedNDA<T>,
+    params: &[ParamNDA<T>],
+) -> Vec<NDArray<T>> {
+    unwrap_or_panic(try_per_sample_gradients_of(losses, params))
+}
+
+pub fn try_per_sample_gradients_of<T: Float>(
+    losses: &ComputedNDA<T>,
+    params: &[ParamNDA<T>],
+) -> Result<Vec<NDArray<T>>, Error> {
+    if losses.ndim() != 1 {
+        return Err(Error::RankMismatch {
+            expected: 1,
+            shape: losses.shape().to_vec(),
+        });
+    }
+    let n = losses.shape()[0];
+    let mut grads: Vec<NDArray<T>> = params
+        .iter()
+        .map(|p| NDArray::zeros([&[n], p.get().shape()].concat()))
+        .collect();
+
+    let function_calls = graph::collect_function_calls(vec![losses.clone()]);
+    // the values of the params in the graph and their indices in `params`
+    let mut values = HashMap::new();
+    for fc in &function_calls {
+        let Some(param) = fc
+            .backward
+            .as_any()
+            .and_then(|o| o.downcast_ref::<Param<NDArray<T>>>())
+        else {
+            continue;
+        };
+        if let Some(i) = params.iter().position(|p| p == param) {
+            values.insert(Arc::as_ptr(&fc.get_ys()[0].inner), i);
+        }
+    }
+    let consumers: Vec<_> = function_calls
+        .iter()
+        .filter(|fc| {
+            fc.xs
+                .iter()
+                .any(|x| values.contains_key(&Arc::as_ptr(&x.inner)))
+        })
+        .collect();
+    if consumers.is_empty() {
+        return Ok(grads);
+    }
+
+    // the gradients of the outputs keep the batch axis
+    let ys: Vec<_> = consumers.iter().map(|fc| fc.get_ys()[0].clone()).collect();
+    let gys = graph::try_gradients(std::slice::from_ref(losses), &ys, false)?;
+
+    for ((fc, y), gy) in consumers.iter().zip(&ys).zip(&gys) {
+        for (k, x) in fc.xs.iter().enumerate() {
+            let Some(&i) = values.get(&Arc::as_ptr(&x.inner)) else {
+                continue;
+            };
+            let function = fc.backward.get_function_name();
+            let g = (y.shape().first() == Some(&n))
+                .then(|| per_example_gradient(&function, &fc.xs, k, gy))
+                .flatten()
+                .ok_or_else(|| Error::PerSampleGradientUnsupported {
+                    function,
+                    name: params[i].name().into_owned(),
+                })?;
+            Zip::from(&mut grads[i]).and(&g).for_each(|a, b| *a += *b);
+        }
+    }
+    Ok(grads)
+}
+
+/// The gradient of the `k`th input of the function for each example, or `None` if the input
+/// is not supported.
+fn per_example_gradient<T: Float>(
+    function: &str,
+    xs: &[ComputedNDA<T>],
+    k: usize,
+    gy: &NDArray<T>,
+) -> Option<NDArray<T>> {
+    let shape = xs[k].shape();
+    match (function, k) {
+        ("matmul" | "matmul_add", 1) => matmul_rhs_gradient(&xs[0], &xs[1], gy),
+        ("matmul_add", 2) | ("add", _) | ("sub", 0) => sum_per_example(gy.view(), shape),
+        ("sub", 1) => sum_per_example(gy.mapv(|g| -g).view(), shape),
+        ("mul", _) => sum_per_example((gy * &*xs[1 - k]).view(), shape),
+        ("div", 0) => sum_per_example((gy / &*xs[1]).view(), shape),
+        ("div", 1) => {
+            let g = gy * &*xs[0] / &xs[1].mapv(|x| -x * x);
+            sum_per_example(g.view(), shape)
+        }
+        _ => None,
+    }
+}
+
+/// `x[i]^T gy[i]` for each example `i`, where `x` is `[n, .., d]` and `w` is `[d, k]`.
+fn matmul_rhs_gradient<T: Float>(
+    x: &NDArray<T>,
+    w: &NDArray<T>,
+    gy: &NDArray<T>,
+) -> Option<NDArray<T>> {
+    if w.ndim() != 2 || x.ndim() < 2 || x.shape()[0] != gy.shape()[0] {
+        return None;
+    }
+    let (n, [d, k]) = (x.shape()[0], [w.shape()[0], w.shape()[1]]);
+    let x = x.to_shape((n, x.len() / (n * d), d)).ok()?;
+    let gy = gy.to_shape((n, gy.len() / (n * k), k)).ok()?;
+    ndarray_einsum_beta::einsum("nmd,nmk->ndk", &[&x, &gy])
+        .ok()
+        .map(|g| g.into_ndarray())
+}
+
+/// Sums `g` of the shape `[n, ..]` into `[n, ..shape]`, where `shape` is broadcast to
+/// the shape of `g` except for the batch axis.
+fn sum_per_example<T: Float>(g: ndarray::ArrayViewD<T>, shape: &[usize]) -> Option<NDArray<T>> {
+    let ndim = g.ndim();
+    if shape.len() > ndim {
+        return None;
+    }
+    let padded: Vec<_> = std::iter::repeat_n(1, ndim - shape.len())
+        .chain(shape.iter().copied())
+        .collect();
+    if padded[0] != 1 {
+        return None;
+    }
+    let mut g = g.to_owned();
+    for (axis, &size) in padded.iter().enumerate().skip(1) {
+        if size != g.shape()[axis] {
+            if size != 1 {
+                return None;
+            }
+            g = g
+                .sum_axis(ndarray::Axis(axis))
+                .insert_axis(ndarray::Axis(axis));
+        }
+    }
+    let n = g.shape()[0];
+    g.into_shape([&[n], shape].concat())
+        .ok()
+        .map(|g| g.into_ndarray())
+}
+
+#[test]
+fn test() {
+    use crate::{
+        functions::matmul_add,
+        gradients,
+        nn::activations::{relu, sigmoid},
+        optimizers::SGD,
+    };
+
+    let w1 = ParamNDA::new(
+        ndarray::array![[0.5, -1.0, 0.2], [0.3, 0.8, -0.4]].into_ndarray(),
+        "w1".into(),
+        SGD::new(0.1),
+    );
+    let b1 = ParamNDA::new(
+        ndarray::array![0.1, -0.2, 0.3].into_ndarray(),
+        "b1".into(),
+        SGD::new(0.1),
+    );
+    let w2 = ParamNDA::new(
+        ndarray::array![[1.0], [-0.5], [0.7]].into_ndarray(),
+        "w2".into(),
+        SGD::new(0.1),
+    );
+    let scale = ParamNDA::new(crate::scalar(2.0), "scale".into(), SGD::new(0.1));
+    let unused = ParamNDA::new(crate::scalar(1.0), "unused".into(), SGD::new(0.1));
+    let params = [w1.clone(), b1.clone(), w2.clone(), scale.clone(), unused];
+
+    // an MLP with the squared error
+    let loss_fn = |xs: &[ComputedNDA]| {
+        let h = sigmoid(&matmul_add(&xs[0], &w1.get(), &b1.get()));
+        let y = relu(&h).matmul(&w2.get()) * scale.get();
+        (y - xs[1].clone()).pow_const(2.0).sum([1], false)
+    };
+    let x = ndarray::array![[1.0, 2.0], [3.0, 0.0], [-1.0, 1.0]].into_ndarray();
+    let t = ndarray::array![[1.0], [0.0], [2.0]].into_ndarray();
+    let gs = per_sample_gradients(loss_fn, &params, &[x.clone(), t.clone()]);
+
+    // the same as differentiating each example alone
+    for i in 0..3 {
+        let xs = [
+            x.slice(ndarray::s![i..i + 1, ..]),
+            t.slice(ndarray::s![i..i + 1, ..]),
+        ]
+        .map(|x| ComputedNDA::new(x.into_ndarray()));
+        let loss = loss_fn(&xs).sum([0], false);
+        let expected = gradients(
+            &[loss],
+            &params[..4].iter().map(|p| p.get()).collect::<Vec<_>>(),
+            false,
+        );
+        for (g, e) in gs.iter().zip(&expected) {
+            let g = g.index_axis(ndarray::Axis(0), i);
+            assert!(Zip::from(&g).and(&**e).all(|a, b| (a - b).abs() < 1e-6));
+        }
+    }
+    assert_eq!(gs[4], NDArray::zeros(&[3][..]));
+
+    // a param reshaped before use
+    let result = try_per_sample_gradients(
+        |xs| xs[0].matmul(&w1.get().reshape([3, 2])).sum([1], false),
+        &params,
+        &[ndarray::array![[1.0, 2.0, 3.0]].into_ndarray()],
+    );
+    assert_eq!(
+        result,
+        Err(Error::PerSampleGradientUnsupported {
+            function: "reshape".into(),
+            name: "w1".into(),
+        })
+    );
+
+    // the losses are not per example
+    assert!(matches!(
+        try_per_sample_gradients(|xs| loss_fn(xs).sum([0], false), &params, &[x, t]),
+        Err(Error::RankMismatch { expected: 1, .. })
+    ));
+}
@@ -0,0 +1,486 @@
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::Computed;
+use crate::float::as_float_array;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProfilePhase {
+    Forward,
+    Backward,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileEvent {
+    pub name: Cow<'static, str>,
+    pub phase: ProfilePhase,
+    /// Elapsed time from the start of the profiler.
+    pub start: Duration,
+    pub duration: Duration,
+    /// Size of the outputs of the forward or the gradients returned by the backward.
+    pub output_bytes: usize,
+    pub thread: usize,
+    /// Address of the `FunctionCall`, or 0 if the graph was not recorded.
+    pub node: usize,
+}
+
+/// Statistics of a function aggregated by name.
+#[derive(Debug, Clone, Default)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub forward_calls: usize,
+    pub forward_time: Duration,
+    pub forward_bytes: usize,
+    pub backward_calls: usize,
+    pub backward_time: Duration,
+    pub backward_bytes: usize,
+}
+
+impl FunctionProfile {
+    pub fn total_time(&self) -> Duration {
+        self.forward_time + self.backward_time
+    }
+}
+
+struct ProfilerInner {
+    origin: Instant,
+    events: Mutex<Vec<ProfileEvent>>,
+}
+
+/// Whether `ACTIVE` is not empty, checked before locking it.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ACTIVE: Mutex<Vec<Arc<ProfilerInner>>> = Mutex::new(Vec::new());
+static THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_ID: usize = THREAD_COUNT.fetch_add(1, Ordering::Relaxed);
+    static BACKWARD_DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// The function name and the start of the innermost `ProfileScope`.
+    static SCOPE: Cell<Option<(&'static str, Instant)>> = const { Cell::new(None) };
+}
+
+/// Records the time and the output size of the forward and the backward of every function
+/// in the process until it is stopped or dropped.
+///
+/// The forward of a function is recorded by `chain`, which is called after the outputs are
+/// computed, so its time starts at the `profile_scope` of the function, which the functions
+/// in this crate open before computing. A function without it is timed from its `chain` call
+/// and the time of its computation is not recorded.
+/// Calls made inside a backward (e.g. functions used by the backward) are
+/// included in the time of the backward and not recorded separately.
+///
+/// Profilers may run at the same time, such as nested ones, and each of them records
+/// all the events while it is running.
+#[must_use]
+pub struct Profiler {
+    inner: Arc<ProfilerInner>,
+}
+
+impl Profiler {
+    /// Starts profiling. The profilers already running keep recording.
+    pub fn start() -> Self {
+        let inner = Arc::new(ProfilerInner {
+            origin: Instant::now(),
+            events: Mutex::new(Vec::new()),
+        });
+        let mut active = ACTIVE.lock().unwrap();
+        active.push(inner.clone());
+        ENABLED.store(true, Ordering::Relaxed);
+        Profiler { inner }
+    }
+
+    pub fn stop(self) -> Profile {
+        self.deactivate();
+        let events = std::mem::take(&mut *self.inner.events.lock().unwrap());
+        Profile { events }
+    }
+
+    fn deactivate(&self) {
+        let mut active = ACTIVE.lock().unwrap();
+        active.retain(|a| !Arc::ptr_eq(a, &self.inner));
+        ENABLED.store(!active.is_empty(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.deactivate();
+    }
+}
+
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Starts the forward of the function `name` for the profiler. Keep it until the function
+/// calls `chain` with `name`, which records the time from this call.
+///
+/// ```
+/// use tensorflake::*;
+///
+/// fn square(x: &ComputedNDA) -> ComputedNDA {
+///     let _scope = profile_scope("square");
+///     let y = ComputedNDA::new(x.mapv(|x| x * x).into_ndarray());
+///     chain(&[x.clone()], &[y.clone()], false, "square", |xs, _ys, gys| {
+///         vec![&gys[0] * &xs[0] * ComputedNDA::new(scalar(2.0))]
+///     });
+///     y
+/// }
+/// # square(&backprop(scalar(3.0)));
+/// ```
+pub fn profile_scope(name: &'static str) -> ProfileScope {
+    if !is_enabled() {
+        return ProfileScope { prev: None };
+    }
+    ProfileScope {
+        prev: Some(SCOPE.with(|s| s.replace(Some((name, Instant::now()))))),
+    }
+}
+
+#[must_use]
+pub struct ProfileScope {
+    /// The scope to restore, or `None` if the profiler was not running.
+    prev: Option<Option<(&'static str, Instant)>>,
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        if let Some(prev) = self.prev {
+            SCOPE.with(|s| s.set(prev));
+        }
+    }
+}
+
+/// Returns the start of the forward of `name` if the profiler is running:
+/// the start of its `profile_scope`, or now if it has none.
+pub(crate) fn forward_start(name: &str) -> Option<Instant> {
+    if !is_enabled() {
+        return None;
+    }
+    Some(match SCOPE.with(|s| s.get()) {
+        Some((scope, start)) if scope == name => start,
+        _ => Instant::now(),
+    })
+}
+
+pub(crate) fn record_forward<T: 'static>(
+    name: &'static str,
+    start: Option<Instant>,
+    ys: &[Computed<T>],
+    node: usize,
+) {
+    let Some(start) = start else {
+        return;
+    };
+    if BACKWARD_DEPTH.with(|d| d.get()) == 0 {
+        record(
+            name.into(),
+            ProfilePhase::Forward,
+            start,
+            Instant::now(),
+            output_bytes(ys),
+            node,
+        );
+    }
+}
+
+/// Measures a call of `Backward::backward`.
+pub(crate) struct BackwardSpan {
+    start: Option<Instant>,
+}
+
+impl BackwardSpan {
+    pub(crate) fn enter() -> Self {
+        if !is_enabled() {
+            return BackwardSpan { start: None };
+        }
+        BACKWARD_DEPTH.with(|d| d.set(d.get() + 1));
+        BackwardSpan {
+            start: Some(Instant::now()),
+        }
+    }
+
+    pub(crate) fn exit<T: 'static>(
+        self,
+        name: Cow<'static, str>,
+        gxs: &[Computed<T>],
+        node: usize,
+    ) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let end = Instant::now();
+        if BACKWARD_DEPTH.with(|d| d.get()) == 1 {
+            record(
+                name,
+                ProfilePhase::Backward,
+                start,
+                end,
+                output_bytes(gxs),
+                node,
+            );
+        }
+    }
+}
+
+impl Drop for BackwardSpan {
+    fn drop(&mut self) {
+        if self.start.is_some() {
+            BACKWARD_DEPTH.with(|d| d.set(d.get() - 1));
+        }
+    }
+}
+
+fn record(
+    name: Cow<'static, str>,
+    phase: ProfilePhase,
+    start: Instant,
+    end: Instant,
+    output_bytes: usize,
+    node: usize,
+) {
+    let active = ACTIVE.lock().unwrap().clone();
+    for inner in active {
+        // the call may have started before the profiler
+        let start = start.max(inner.origin).min(end);
+        let event = ProfileEvent {
+            name: name.clone(),
+            phase,
+            start: start - inner.origin,
+            duration: end - start,
+            output_bytes,
+            thread: THREAD_ID.with(|t| *t),
+            node,
+        };
+        inner.events.lock().unwrap().push(event);
+    }
+}
+
+fn output_bytes<T: 'static>(xs: &[Computed<T>]) -> usize {
+    xs.iter()
+        .map(|x| as_float_array(&**x).map_or(std::mem::size_of::<T>(), |x| x.bytes()))
+        .sum()
+}
+
+/// Events recorded by `Profiler`.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub events: Vec<ProfileEvent>,
+}
+
+impl Profile {
+    /// Aggregates the events by function name, sorted by total time descending.
+    pub fn summary(&self) -> Vec<FunctionProfile> {
+        let mut map: HashMap<&str, FunctionProfile> = HashMap::new();
+        for e in &self.events {
+            let p = map.entry(&e.name).or_insert_with(|| FunctionProfile {
+                name: e.name.to_string(),
+                ..Default::default()
+            });
+            match e.phase {
+                ProfilePhase::Forward => {
+                    p.forward_calls += 1;
+                    p.forward_time += e.duration;
+                    p.forward_bytes += e.output_bytes;
+                }
+                ProfilePhase::Backward => {
+                    p.backward_calls += 1;
+                    p.backward_time += e.duration;
+                    p.backward_bytes += e.output_bytes;
+                }
+            }
+        }
+        let mut summary: Vec<_> = map.into_values().collect();
+        summary.sort_by(|a, b| {
+            b.total_time()
+                .cmp(&a.total_time())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        summary
+    }
+
+    /// Returns the time of the latest forward and backward of each node.
+    pub fn node_times(&self) -> HashMap<usize, (Option<Duration>, Option<Duration>)> {
+        let mut map: HashMap<usize, (Option<Duration>, Option<Duration>)> = HashMap::new();
+        for e in self.events.iter().filter(|e| e.node != 0) {
+            let times = map.entry(e.node).or_default();
+            match e.phase {
+                ProfilePhase::Forward => times.0 = Some(e.duration),
+                ProfilePhase::Backward => times.1 = Some(e.duration),
+            }
+        }
+        map
+    }
+
+    /// Writes the events in the Chrome trace event format,
+    /// which can be opened with chrome://tracing or Perfetto.
+    pub fn write_chrome_trace(&self, w: &mut impl std::io::Write) -> Result<(), std::io::Error> {
+        writeln!(w, "{{\"traceEvents\":[")?;
+        for (i, e) in self.events.iter().enumerate() {
+            writeln!(
+                w,
+                "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":{},\"args\":{{\"bytes\":{},\"node\":{}}}}}{}",
+                escape_json(&e.name),
+                match e.phase {
+                    ProfilePhase::Forward => "forward",
+                    ProfilePhase::Backward => "backward",
+                },
+                e.start.as_secs_f64() * 1e6,
+                e.duration.as_secs_f64() * 1e6,
+                e.thread,
+                e.output_bytes,
+                e.node,
+                if i + 1 == self.events.len() { "" } else { "," }
+            )?;
+        }
+        writeln!(w, "],\"displayTimeUnit\":\"ms\"}}")?;
+        Ok(())
+    }
+
+    pub fn export_chrome_trace(&self, file: &str) -> Result<(), std::io::Error> {
+        let f = std::fs::File::create(file)?;
+        let mut w = std::io::BufWriter::new(f);
+        self.write_chrome_trace(&mut w)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:>8} {:>12} {:>8} {:>12} {:>12}",
+            "function", "fw calls", "fw time", "bw calls", "bw time", "fw bytes"
+        )?;
+        for p in self.summary() {
+            writeln!(
+                f,
+                "{:<24} {:>8} {:>12.3?} {:>8} {:>12.3?} {:>12}",
+                p.name,
+                p.forward_calls,
+                p.forward_time,
+                p.backward_calls,
+                p.backward_time,
+                p.forward_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r
+}
+
+#[test]
+fn test() {
+    use crate::{backprop, ndarray_util::IntoNDArray, scalar};
+    use ndarray::Array;
+
+    let profiler = Profiler::start();
+    let a = backprop(Array::ones([2, 3]).into_ndarray());
+    let b = backprop(scalar(2.0));
+    // unrelated work before a function is not attributed to it
+    std::thread::sleep(Duration::from_millis(50));
+    let y = (&a * &b).sum(vec![0, 1], false);
+    let gs = crate::gradients(&[y.clone()], &[a.clone()], false);
+    let mut profile = profiler.stop();
+    assert_eq!(gs[0].shape(), &[2, 3]);
+    // other tests may be running in parallel
+    let thread = THREAD_ID.with(|t| *t);
+    profile.events.retain(|e| e.thread == thread);
+
+    let summary = profile.summary();
+    let mul = summary.iter().find(|p| p.name == "mul").unwrap();
+    assert_eq!(mul.forward_calls, 1);
+    assert_eq!(mul.backward_calls, 1);
+    assert_eq!(mul.forward_bytes, 6 * 4);
+    // gradients of a and b
+    assert_eq!(mul.backward_bytes, 6 * 4 + 4);
+    assert!(mul.forward_time < Duration::from_millis(50));
+    // functions called inside the backward are not recorded
+    assert_eq!(
+        profile
+            .events
+            .iter()
+            .filter(|e| e.phase == ProfilePhase::Forward)
+            .count(),
+        4
+    );
+    assert!(profile
+        .events
+        .iter()
+        .filter(|e| e.name != "backprop")
+        .all(|e| e.node != 0));
+
+    let mut w = Vec::new();
+    profile.write_chrome_trace(&mut w).unwrap();
+    let json = String::from_utf8(w).unwrap();
+    assert!(json.starts_with("{\"traceEvents\":["));
+    assert_eq!(json.matches("\"ph\":\"X\"").count(), profile.events.len());
+    println!("{}", profile);
+
+    // profilers running at the same time record independently
+    let outer = Profiler::start();
+    let inner = Profiler::start();
+    let _ = &a * &b;
+    let inner = inner.stop();
+    let _ = &a * &b;
+    let outer = outer.stop();
+    let _ = &a * &b;
+    let count = |p: &Profile| {
+        let events = p.events.iter();
+        events
+            .filter(|e| e.thread == thread && e.name == "mul")
+            .count()
+    };
+    assert_eq!((count(&inner), count(&outer)), (1, 2));
+}
+
+#[test]
+fn test_scope() {
+    use crate::*;
+
+    fn slow(x: &ComputedNDA<f64>, scoped: bool) -> ComputedNDA<f64> {
+        let _scope = scoped.then(|| profile_scope("slow"));
+        std::thread::sleep(Duration::from_millis(20));
+        let y = ComputedNDA::new((**x).clone());
+        chain(&[x.clone()], &[y.clone()], false, "slow", |_, _, gys| {
+            vec![gys[0].clone()]
+        });
+        y
+    }
+
+    let profiler = Profiler::start();
+    let x = backprop(ndarray::array![1.0, 2.0, 3.0].into_ndarray());
+    slow(&x, true);
+    slow(&x, false);
+    let mut profile = profiler.stop();
+    let thread = THREAD_ID.with(|t| *t);
+    profile
+        .events
+        .retain(|e| e.thread == thread && e.name == "slow");
+
+    // the computation is timed only in the scope
+    assert!(profile.events[0].duration >= Duration::from_millis(20));
+    assert!(profile.events[1].duration < Duration::from_millis(20));
+    // the size of the f64 elements
+    assert_eq!(profile.events[0].output_bytes, 3 * 8);
+}
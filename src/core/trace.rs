@@ -0,0 +1,604 @@
+use std::{any::Any, cell::RefCell, collections::HashMap, sync::Arc};
+
+use super::{
+    anomaly, graph::One, profiler, set_grad_enabled, take_anomaly, Backward, Computed,
+    FunctionCall, Param,
+};
+use crate::error::{unwrap_or_panic, Error};
+
+thread_local! {
+    static RECORDER: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+/// Records the graph built by a closure on the first run and replays it on the later runs.
+///
+/// A replay still runs the closure to compute the forward values, but the function calls are
+/// bound to the slots of the recorded plan instead of allocating the graph, and
+/// `GradientsAccumulator::compute` runs the backward functions in the recorded order with
+/// preallocated buffers.
+/// If the shapes of the inputs change or the closure calls other functions, the replayed part
+/// is turned into an ordinary graph and the rest of the run is eager.
+///
+/// ```
+/// use tensorflake::{ndarray::array, *};
+///
+/// let p = ParamNDA::new(array![1.0, 2.0].into_ndarray(), "p".into(), optimizers::SGD::new(0.1));
+/// let mut trace = Trace::<NDArray>::new();
+/// for _ in 0..3 {
+///     trace.run(|| {
+///         let x = ComputedNDA::new(array![3.0, 4.0].into_ndarray());
+///         let loss = (&p.get() * &x).sum(vec![0], false);
+///         optimize(&loss);
+///     });
+/// }
+/// assert_eq!(trace.replays(), 2);
+/// ```
+pub struct Trace<T> {
+    plan: Option<Box<Plan<T>>>,
+    replays: usize,
+    fallbacks: usize,
+}
+
+impl<T: One + Clone + Default + Send + Sync + 'static> Trace<T> {
+    pub fn new() -> Self {
+        Self {
+            plan: None,
+            replays: 0,
+            fallbacks: 0,
+        }
+    }
+
+    pub fn run<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let mut plan = match self.plan.take() {
+            Some(plan) if !plan.replayable => {
+                self.fallbacks += 1;
+                self.plan = Some(plan);
+                return f();
+            }
+            Some(mut plan) => {
+                plan.phase = Phase::Replaying;
+                plan
+            }
+            None => Box::new(Plan::new()),
+        };
+        plan.cursor = 0;
+        plan.loss_cursor = 0;
+        plan.shape_changed = false;
+
+        let previous = RECORDER.with(|r| r.borrow_mut().replace(plan));
+        let result = f();
+        let plan = RECORDER.with(|r| std::mem::replace(&mut *r.borrow_mut(), previous));
+
+        let Some(mut plan) = plan.and_then(|p| p.downcast::<Plan<T>>().ok()) else {
+            return result;
+        };
+        let replayed = plan.phase == Phase::Replaying && plan.cursor == plan.nodes.len();
+        match plan.phase {
+            Phase::Tracing => {
+                plan.finish();
+                self.plan = Some(plan);
+                return result;
+            }
+            // the values escaping the run keep their graph
+            Phase::Replaying => plan.materialize(),
+            Phase::Eager => {}
+        }
+        for v in &mut plan.values {
+            *v = None;
+        }
+        if replayed {
+            self.replays += 1;
+            self.plan = Some(plan);
+        } else {
+            self.fallbacks += 1;
+            // keep the plan for the inputs of the traced shapes
+            if plan.shape_changed {
+                self.plan = Some(plan);
+            }
+        }
+        result
+    }
+
+    /// Returns whether a replayable plan has been recorded.
+    pub fn is_traced(&self) -> bool {
+        self.plan.as_ref().is_some_and(|p| p.replayable)
+    }
+
+    /// The number of runs that replayed the plan to the end.
+    pub fn replays(&self) -> usize {
+        self.replays
+    }
+
+    /// The number of runs that fell back to eager mode after the plan was recorded.
+    pub fn fallbacks(&self) -> usize {
+        self.fallbacks
+    }
+
+    /// Discards the plan so that the next run records it again.
+    pub fn reset(&mut self) {
+        self.plan = None;
+    }
+}
+
+impl<T: One + Clone + Default + Send + Sync + 'static> Default for Trace<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Tracing,
+    Replaying,
+    Eager,
+}
+
+struct Node {
+    name: &'static str,
+    xs: Vec<usize>,
+    ys: Vec<usize>,
+    requires_grad: bool,
+}
+
+type ParamsGrads<T> = (Vec<Param<T>>, Vec<Computed<T>>);
+
+struct Loss {
+    slot: usize,
+    /// The nodes to be called in backward.
+    order: Vec<usize>,
+    /// The leaves that receive gradients, which are the values of params.
+    leaves: Vec<usize>,
+}
+
+struct Plan<T> {
+    phase: Phase,
+    replayable: bool,
+    nodes: Vec<Node>,
+    shapes: Vec<Vec<usize>>,
+    /// Whether each slot is an output of a node.
+    produced: Vec<bool>,
+    requires_grad: Vec<bool>,
+    losses: Vec<Loss>,
+    slot_of: HashMap<usize, usize>,
+    shape: fn(&T) -> Vec<usize>,
+
+    // buffers reused by replays
+    values: Vec<Option<Computed<T>>>,
+    backwards: Vec<Option<Box<dyn Backward<T>>>>,
+    grads: Vec<Option<Computed<T>>>,
+    cursor: usize,
+    loss_cursor: usize,
+    shape_changed: bool,
+}
+
+impl<T: One + Clone + Default + Send + Sync + 'static> Plan<T> {
+    fn new() -> Self {
+        Self {
+            phase: Phase::Tracing,
+            replayable: true,
+            nodes: Vec::new(),
+            shapes: Vec::new(),
+            produced: Vec::new(),
+            requires_grad: Vec::new(),
+            losses: Vec::new(),
+            slot_of: HashMap::new(),
+            shape: |x| x.shape().to_vec(),
+            values: Vec::new(),
+            backwards: Vec::new(),
+            grads: Vec::new(),
+            cursor: 0,
+            loss_cursor: 0,
+            shape_changed: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        self.slot_of = HashMap::new();
+        // leaves that require gradients must be params, whose gradients are collected
+        for (s, value) in self.values.iter().enumerate() {
+            if !self.produced[s] && self.requires_grad[s] {
+                let is_param = value.as_ref().is_some_and(|v| param_of(v).is_some());
+                self.replayable &= is_param;
+            }
+        }
+        self.values.clear();
+        self.backwards.clear();
+        self.backwards.resize_with(self.nodes.len(), || None);
+        self.values.resize(self.shapes.len(), None);
+        self.grads.resize(self.shapes.len(), None);
+    }
+}
+
+impl<T> Plan<T> {
+    fn new_slot(&mut self, shape: Vec<usize>, produced: bool, requires_grad: bool) -> usize {
+        self.shapes.push(shape);
+        self.produced.push(produced);
+        self.requires_grad.push(requires_grad);
+        self.values.push(None);
+        self.shapes.len() - 1
+    }
+
+    fn trace(&mut self, xs: &[Computed<T>], ys: &[Computed<T>], force: bool, name: &'static str) {
+        let xs: Vec<_> = xs
+            .iter()
+            .map(|x| match self.slot_of.get(&key(x)) {
+                Some(&s) => s,
+                None => {
+                    let s = self.new_slot((self.shape)(x), false, x.has_creator());
+                    self.values[s] = Some(x.clone());
+                    self.slot_of.insert(key(x), s);
+                    s
+                }
+            })
+            .collect();
+        let requires_grad = force || xs.iter().any(|&s| self.requires_grad[s]);
+        let ys = ys
+            .iter()
+            .map(|y| {
+                let s = self.new_slot((self.shape)(y), true, requires_grad);
+                self.slot_of.insert(key(y), s);
+                s
+            })
+            .collect();
+        self.nodes.push(Node {
+            name,
+            xs,
+            ys,
+            requires_grad,
+        });
+    }
+
+    /// Binds the values to the slots of the next node. Returns `false` if they do not match.
+    fn bind(&mut self, xs: &[Computed<T>], ys: &[Computed<T>], name: &'static str) -> bool {
+        let Some(node) = self.nodes.get(self.cursor) else {
+            return false;
+        };
+        if node.name != name || node.xs.len() != xs.len() || node.ys.len() != ys.len() {
+            return false;
+        }
+        for (&s, x) in node.xs.iter().zip(xs) {
+            match &self.values[s] {
+                Some(v) => {
+                    if v != x {
+                        return false;
+                    }
+                }
+                None if self.produced[s] => return false,
+                None => {
+                    if (self.shape)(x) != self.shapes[s] {
+                        self.shape_changed = true;
+                        return false;
+                    }
+                    if x.has_creator() != self.requires_grad[s] {
+                        return false;
+                    }
+                    self.values[s] = Some(x.clone());
+                }
+            }
+        }
+        for (&s, y) in node.ys.iter().zip(ys) {
+            if (self.shape)(y) != self.shapes[s] {
+                return false;
+            }
+            self.values[s] = Some(y.clone());
+        }
+        true
+    }
+
+    /// Turns the replayed nodes into an ordinary graph and continues in eager mode.
+    fn materialize(&mut self) {
+        for (node, backward) in self.nodes.iter().zip(&mut self.backwards) {
+            let Some(backward) = backward.take() else {
+                continue;
+            };
+            let xs = node.xs.iter().map(|&s| self.values[s].clone().unwrap());
+            let ys: Vec<_> = node
+                .ys
+                .iter()
+                .map(|&s| self.values[s].clone().unwrap())
+                .collect();
+            let fc = Arc::new(FunctionCall::new(backward, xs.collect(), &ys));
+            for y in &ys {
+                y.set_creator(fc.clone());
+            }
+        }
+        self.phase = Phase::Eager;
+    }
+}
+
+impl<T: One + Clone + Default + Send + Sync + 'static> Plan<T>
+where
+    for<'a> &'a Computed<T>: std::ops::Add<&'a Computed<T>, Output = Computed<T>>,
+{
+    /// Computes the gradients of the params with the recorded backward order.
+    fn backward(&mut self, loss: &Computed<T>) -> Option<ParamsGrads<T>> {
+        let i = self.loss_cursor;
+        self.loss_cursor += 1;
+        let l = self.losses.get(i)?;
+        if self.values[l.slot].as_ref() != Some(loss)
+            || l.order.iter().any(|&n| self.backwards[n].is_none())
+        {
+            return None;
+        }
+
+        let _guard = set_grad_enabled(false);
+        if let Some(e) = take_anomaly() {
+            unwrap_or_panic::<()>(Err(e));
+        }
+
+        for g in &mut self.grads {
+            *g = None;
+        }
+        self.grads[l.slot] = Some(Computed::new(loss.clone_filled_ones()));
+
+        // skip the nodes that do not lead to trainable params
+        let trainable =
+            |s: usize| param_of(self.values[s].as_ref().unwrap()).is_some_and(|p| p.is_trainable());
+        let mut reached = vec![false; self.shapes.len()];
+        for &s in &l.leaves {
+            reached[s] = trainable(s);
+        }
+        let mut needed = vec![false; self.nodes.len()];
+        for &n in l.order.iter().rev() {
+            if self.nodes[n].xs.iter().any(|&s| reached[s]) {
+                needed[n] = true;
+                for &s in &self.nodes[n].ys {
+                    reached[s] = true;
+                }
+            }
+        }
+
+        for &n in l.order.iter().filter(|&&n| needed[n]) {
+            let node = &self.nodes[n];
+            let backward = self.backwards[n].take().unwrap();
+            let xs: Vec<_> = node
+                .xs
+                .iter()
+                .map(|&s| self.values[s].clone().unwrap())
+                .collect();
+            let ys: Vec<_> = node
+                .ys
+                .iter()
+                .map(|&s| self.values[s].clone().unwrap())
+                .collect();
+            let gys = node
+                .ys
+                .iter()
+                .zip(&ys)
+                .map(|(&s, y)| y.call_hooks(self.grads[s].take().expect("gradient not found")))
+                .collect();
+
+            let span = profiler::BackwardSpan::enter();
+            let gxs = backward.backward(&xs, &ys, &gys);
+            span.exit(backward.get_function_name(), &gxs, 0);
+            for gx in &gxs {
+                gx.unchain();
+            }
+
+            if xs.len() != gxs.len() {
+                unwrap_or_panic::<()>(Err(Error::GradientCountMismatch {
+                    function: backward.get_function_name(),
+                    inputs: xs.len(),
+                    gradients: gxs.len(),
+                }));
+            }
+            unwrap_or_panic(anomaly::check_backward(
+                backward.get_function_name(),
+                &xs,
+                &gxs,
+            ));
+
+            for (&s, gx) in node.xs.iter().zip(gxs) {
+                self.grads[s] = Some(match self.grads[s].take() {
+                    Some(g) => &g + &gx,
+                    None => gx,
+                });
+            }
+        }
+
+        let mut params = Vec::with_capacity(l.leaves.len());
+        let mut grads = Vec::with_capacity(l.leaves.len());
+        for &s in &l.leaves {
+            let x = self.values[s].as_ref().unwrap();
+            if let (Some(param), Some(grad)) = (
+                param_of(x).filter(|p| p.is_trainable()),
+                self.grads[s].take(),
+            ) {
+                params.push(param);
+                grads.push(x.call_hooks(grad));
+            }
+        }
+        Some((params, grads))
+    }
+}
+
+impl<T> Plan<T> {
+    fn push_loss(&mut self, loss: &Computed<T>) {
+        let Some(&slot) = self.slot_of.get(&key(loss)) else {
+            self.replayable = false;
+            return;
+        };
+        let mut needed = vec![false; self.shapes.len()];
+        needed[slot] = true;
+        let mut order = Vec::new();
+        for (n, node) in self.nodes.iter().enumerate().rev() {
+            if node.requires_grad && node.ys.iter().any(|&s| needed[s]) {
+                order.push(n);
+                for &s in &node.xs {
+                    needed[s] = true;
+                }
+            }
+        }
+        let leaves = (0..self.shapes.len())
+            .filter(|&s| needed[s] && !self.produced[s] && self.requires_grad[s])
+            .collect();
+        self.losses.push(Loss {
+            slot,
+            order,
+            leaves,
+        });
+    }
+}
+
+fn key<T>(x: &Computed<T>) -> usize {
+    Arc::as_ptr(&x.inner) as usize
+}
+
+fn param_of<T: One + Clone + Default + Send + Sync + 'static>(x: &Computed<T>) -> Option<Param<T>> {
+    let creator = x.creator()?;
+    let param = creator
+        .backward
+        .as_any()?
+        .downcast_ref::<Param<T>>()?
+        .clone();
+    Some(param)
+}
+
+/// Takes the plan of the current thread while `f` runs, so that the function calls in `f`
+/// are not recorded.
+fn with_plan<T: 'static, R>(f: impl FnOnce(Option<&mut Plan<T>>) -> R) -> R {
+    let Some(mut plan) = RECORDER.with(|r| r.borrow_mut().take()) else {
+        return f(None);
+    };
+    let result = f(plan.downcast_mut::<Plan<T>>());
+    RECORDER.with(|r| *r.borrow_mut() = Some(plan));
+    result
+}
+
+/// Called by `chain`. Returns the backward back if the graph should be built eagerly.
+pub(crate) fn record<T: 'static, B: Backward<T>>(
+    xs: &[Computed<T>],
+    ys: &[Computed<T>],
+    force_create_graph: bool,
+    name: &'static str,
+    backward: B,
+) -> Option<B> {
+    if RECORDER.with(|r| r.borrow().is_none()) {
+        return Some(backward);
+    }
+    with_plan(|plan: Option<&mut Plan<T>>| {
+        let Some(plan) = plan else {
+            return Some(backward);
+        };
+        match plan.phase {
+            Phase::Tracing => {
+                plan.trace(xs, ys, force_create_graph, name);
+                Some(backward)
+            }
+            Phase::Replaying => {
+                if !plan.bind(xs, ys, name) {
+                    plan.materialize();
+                    return Some(backward);
+                }
+                if plan.nodes[plan.cursor].requires_grad {
+                    plan.backwards[plan.cursor] = Some(Box::new(backward));
+                }
+                plan.cursor += 1;
+                None
+            }
+            Phase::Eager => Some(backward),
+        }
+    })
+}
+
+/// Called before the graph is traversed. The graph of a replay is materialized and a plan
+/// being traced is marked not replayable, since the traversal cannot be replayed.
+pub(crate) fn graph_accessed<T: 'static>() {
+    if RECORDER.with(|r| r.borrow().is_none()) {
+        return;
+    }
+    with_plan(|plan: Option<&mut Plan<T>>| match plan {
+        Some(plan) if plan.phase == Phase::Tracing => plan.replayable = false,
+        Some(plan) if plan.phase == Phase::Replaying => plan.materialize(),
+        _ => {}
+    })
+}
+
+/// Computes the gradients of the params for `GradientsAccumulator::compute`
+/// with the plan if it is replayed, otherwise with `eager`.
+pub(crate) fn params_grads<T: One + Clone + Default + Send + Sync + 'static>(
+    loss: &Computed<T>,
+    eager: impl FnOnce() -> ParamsGrads<T>,
+) -> ParamsGrads<T>
+where
+    for<'a> &'a Computed<T>: std::ops::Add<&'a Computed<T>, Output = Computed<T>>,
+{
+    with_plan(|plan: Option<&mut Plan<T>>| {
+        let Some(plan) = plan else {
+            return eager();
+        };
+        match plan.phase {
+            Phase::Tracing => {
+                plan.push_loss(loss);
+                eager()
+            }
+            Phase::Replaying => match plan.backward(loss) {
+                Some(result) => result,
+                None => {
+                    plan.materialize();
+                    eager()
+                }
+            },
+            Phase::Eager => eager(),
+        }
+    })
+}
+
+#[test]
+fn test() {
+    use crate::*;
+    use ndarray::array;
+
+    let run = |trace: &mut Trace<NDArray>, p: &ParamNDA, x: NDArray| {
+        trace.run(|| {
+            let x = ComputedNDA::new(x);
+            let y = (&p.get() * &x).sum(vec![1], false);
+            let loss = (&y * &y).sum(vec![0], false);
+            let mut ga = GradientsAccumulator::new();
+            ga.compute(&loss);
+            ga.table[p].clone()
+        })
+    };
+    let eager = |p: &ParamNDA, x: NDArray| {
+        let y = (&p.get() * &ComputedNDA::new(x)).sum(vec![1], false);
+        let loss = (&y * &y).sum(vec![0], false);
+        gradients(&[loss], &[p.get()], false)[0].clone()
+    };
+
+    let p = ParamNDA::new(
+        array![[1.0, -2.0]].into_ndarray(),
+        "p".into(),
+        optimizers::SGD::new(0.1),
+    );
+    let mut trace = Trace::new();
+    let x = array![[1.0, 2.0], [3.0, 4.0]].into_ndarray();
+    assert_eq!(run(&mut trace, &p, x.clone()), *eager(&p, x));
+    assert!(trace.is_traced());
+
+    let x = array![[0.5, 1.0], [-1.0, 2.0]].into_ndarray();
+    assert_eq!(run(&mut trace, &p, x.clone()), *eager(&p, x));
+    assert_eq!((trace.replays(), trace.fallbacks()), (1, 0));
+
+    // falls back to eager mode if the shape changes
+    let x = array![[0.5, 1.0], [-1.0, 2.0], [3.0, 1.0]].into_ndarray();
+    assert_eq!(run(&mut trace, &p, x.clone()), *eager(&p, x));
+    assert_eq!((trace.replays(), trace.fallbacks()), (1, 1));
+
+    let x = array![[2.0, 1.0], [1.0, 1.0]].into_ndarray();
+    assert_eq!(run(&mut trace, &p, x.clone()), *eager(&p, x));
+    assert_eq!((trace.replays(), trace.fallbacks()), (2, 1));
+
+    // frozen params are skipped on replay
+    p.set_trainable(false);
+    let x = array![[1.0, 1.0], [1.0, 1.0]].into_ndarray();
+    let grads = trace.run(|| {
+        let x = ComputedNDA::new(x);
+        let y = (&p.get() * &x).sum(vec![1], false);
+        let loss = (&y * &y).sum(vec![0], false);
+        let mut ga = GradientsAccumulator::new();
+        ga.compute(&loss);
+        ga.table.len()
+    });
+    assert_eq!(grads, 0);
+    assert_eq!((trace.replays(), trace.fallbacks()), (3, 1));
+}
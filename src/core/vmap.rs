@@ -0,0 +1,253 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ndarray::{IxDyn, SliceInfo, SliceInfoElem};
+
+use super::graph;
+use crate::{
+    backprop,
+    error::unwrap_or_panic,
+    functions::{try_broadcast, try_reshape, try_sum, try_transpose},
+    set_grad_enabled, set_lazy,
+    symbolic::Op,
+    ComputedNDA, Error, Float, IntoNDArray,
+};
+
+/// Maps `f` over the leading axis of `xs` and stacks the results along a new leading axis.
+///
+/// `f` takes one example of each of `xs` without the leading axis. The result is differentiable
+/// with respect to `xs` and anything `f` depends on.
+///
+/// `f` is called once on the first example to record its graph, and the graph is run once on
+/// the whole batch with the operations rewritten for the batch axis. So `f` must compute the
+/// result from the examples only by the functions with an `Op` of `symbolic`, and must not read
+/// the values of the examples, e.g. to branch on them. Values `f` takes from elsewhere, such as
+/// params, are shared by the examples.
+///
+/// ```
+/// use tensorflake::*;
+///
+/// let x: ComputedNDA = ComputedNDA::new(ndarray::array![[1.0, 2.0], [3.0, 4.0]].into_ndarray());
+/// let y = vmap(|xs| xs[0].sum([0], false), &[x]);
+/// assert_eq!(&*y, &ndarray::array![3.0, 7.0].into_ndarray());
+/// ```
+pub fn vmap<T: Float>(
+    f: impl Fn(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    xs: &[ComputedNDA<T>],
+) -> ComputedNDA<T> {
+    unwrap_or_panic(try_vmap(f, xs))
+}
+
+/// Returns `Error::IncompatibleShapes` if `xs` do not share a nonempty leading axis,
+/// and `Error::UnbatchableFunction` if `f` calls a function without a batching rule.
+pub fn try_vmap<T: Float>(
+    f: impl Fn(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    xs: &[ComputedNDA<T>],
+) -> Result<ComputedNDA<T>, Error> {
+    let n = batch_size(xs)?;
+    let key = |x: &ComputedNDA<T>| Arc::as_ptr(&x.inner) as *const ();
+
+    let (examples, y) = {
+        let _grad = set_grad_enabled(true);
+        let _lazy = set_lazy(false);
+        let examples: Vec<_> = xs
+            .iter()
+            .map(|x| backprop((**x).slice(index(0, x.ndim())).to_owned().into_ndarray()))
+            .collect();
+        let y = f(&examples);
+        (examples, y)
+    };
+
+    let mut creators = HashMap::new();
+    for fc in graph::collect_function_calls(vec![y.clone()]) {
+        for y in &fc.ys {
+            creators.insert(y.as_ptr() as *const (), fc.clone());
+        }
+    }
+    // the values computed from the examples, with the batch axis
+    let mut batched: HashMap<_, _> = examples
+        .iter()
+        .zip(xs)
+        .map(|(e, x)| (key(e), Some(x.clone())))
+        .collect();
+    // post-order: a value is pushed again to be batched after its inputs
+    let mut stack = vec![(y.clone(), false)];
+    while let Some((v, expanded)) = stack.pop() {
+        if batched.contains_key(&key(&v)) {
+            continue;
+        }
+        let Some(fc) = creators.get(&key(&v)) else {
+            batched.insert(key(&v), None);
+            continue;
+        };
+        if !expanded {
+            stack.push((v.clone(), true));
+            stack.extend(fc.xs.iter().map(|x| (x.clone(), false)));
+            continue;
+        }
+        let inputs: Vec<_> = fc.xs.iter().map(|x| batched[&key(x)].clone()).collect();
+        let value = if inputs.iter().all(Option::is_none) {
+            None
+        } else {
+            let op = Op::lift(&*fc.backward).map_err(|_| unbatchable(&*fc.backward))?;
+            Some(apply_batched(&op, &fc.xs, &inputs, n)?)
+        };
+        batched.insert(key(&v), value);
+    }
+
+    match batched[&key(&y)].clone() {
+        Some(y) => Ok(y),
+        // `f` does not depend on the examples
+        None => try_broadcast(&y, [&[n], y.shape()].concat()),
+    }
+}
+
+/// Applies `op`, which output an example from `xs`, to the batched `inputs`.
+/// `None` in `inputs` is the unbatched input in `xs`.
+fn apply_batched<T: Float>(
+    op: &Op<T>,
+    xs: &[ComputedNDA<T>],
+    inputs: &[Option<ComputedNDA<T>>],
+    n: usize,
+) -> Result<ComputedNDA<T>, Error> {
+    // the rank of the examples of the output, to which the batched inputs are aligned
+    let ndim = xs.iter().map(|x| x.ndim()).max().unwrap_or(0);
+    let inputs = inputs
+        .iter()
+        .zip(xs)
+        .map(|(input, x)| match input {
+            Some(input) if x.ndim() < ndim => {
+                let ones = vec![1; ndim - x.ndim()];
+                try_reshape(input, [&[n], &ones[..], x.shape()].concat())
+            }
+            Some(input) => Ok(input.clone()),
+            None => Ok(x.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let shift = |axes: &[usize]| axes.iter().map(|a| a + 1).collect::<Vec<_>>();
+    match op {
+        Op::Unary(_) | Op::Binary(_) => op.apply(&inputs),
+        Op::Sum { axes, keep_dim } => try_sum(&inputs[0], shift(axes), *keep_dim),
+        Op::Reshape(shape) => try_reshape(&inputs[0], [&[n], &shape[..]].concat()),
+        Op::Transpose(axes) => try_transpose(&inputs[0], [vec![0], shift(axes)].concat()),
+        Op::Broadcast(shape) => {
+            let ones = vec![1; shape.len() - ndim];
+            let x = try_reshape(&inputs[0], [&[n], &ones[..], xs[0].shape()].concat())?;
+            try_broadcast(&x, [&[n], &shape[..]].concat())
+        }
+        _ => Err(Error::UnbatchableFunction {
+            function: op.name().into(),
+        }),
+    }
+}
+
+fn unbatchable<T: Float>(backward: &dyn crate::Backward<crate::NDArray<T>>) -> Error {
+    Error::UnbatchableFunction {
+        function: backward.get_function_name(),
+    }
+}
+
+fn batch_size<T: Float>(xs: &[ComputedNDA<T>]) -> Result<usize, Error> {
+    let n = xs.first().and_then(|x| x.shape().first().copied());
+    match n {
+        Some(n) if n > 0 && xs.iter().all(|x| x.shape().first() == Some(&n)) => Ok(n),
+        _ => Err(Error::IncompatibleShapes {
+            function: "vmap".into(),
+            shapes: xs.iter().map(|x| x.shape().to_vec()).collect(),
+        }),
+    }
+}
+
+/// Selects the `i`th element of the leading axis.
+fn index(i: usize, ndim: usize) -> SliceInfo<Vec<SliceInfoElem>, IxDyn, IxDyn> {
+    let mut elems = vec![SliceInfoElem::from(..); ndim];
+    elems[0] = SliceInfoElem::Index(i as isize);
+    SliceInfo::try_from(elems).unwrap()
+}
+
+#[test]
+fn test() {
+    use crate::{gradients, optimizers::SGD, scalar, ParamNDA};
+
+    // a linear model with the squared error
+    let w = ParamNDA::new(
+        ndarray::array![0.5, -1.0].into_ndarray(),
+        "w".into(),
+        SGD::new(0.1),
+    );
+    let x = ndarray::array![[1.0, 2.0], [3.0, 0.0], [-1.0, 1.0]].into_ndarray();
+    let t = ndarray::array![1.0, 0.0, 2.0].into_ndarray();
+    let loss_fn = |xs: &[ComputedNDA]| {
+        let e = (&xs[0] * &w.get()).sum([0], false) - xs[1].clone();
+        &e * &e
+    };
+
+    // the same as the batched computation
+    let losses = vmap(
+        loss_fn,
+        &[ComputedNDA::new(x.clone()), ComputedNDA::new(t.clone())],
+    );
+    let e = (ComputedNDA::new(x) * w.get()).sum([1], false) - ComputedNDA::new(t);
+    let expected = &e * &e;
+    assert_eq!(&*losses, &*expected);
+
+    // gradients flow through the params
+    let g = gradients(&[losses.sum([0], false)], &[w.get()], false);
+    let ge = gradients(&[expected.sum([0], false)], &[w.get()], false);
+    assert_eq!(&*g[0], &*ge[0]);
+
+    // gradients flow through the inputs, and `f` is called once
+    let x = backprop(ndarray::array![[1.0, 2.0], [3.0, 4.0]].into_ndarray());
+    let calls = std::cell::Cell::new(0);
+    let y = vmap(
+        |xs| {
+            calls.set(calls.get() + 1);
+            &xs[0] * &xs[0]
+        },
+        &[x.clone()],
+    );
+    assert_eq!(calls.get(), 1);
+    let g = gradients(&[y.sum([0, 1], false)], &[x.clone()], false);
+    assert_eq!(&*g[0], &(&*x * 2.0));
+
+    // the example axes are shifted and the examples of lower ranks are aligned
+    let x = ComputedNDA::new(ndarray::array![[[1.0, 2.0]], [[3.0, 4.0]]].into_ndarray());
+    let v = ComputedNDA::new(ndarray::array![[1.0], [-1.0]].into_ndarray());
+    let m = ComputedNDA::new(ndarray::array![[1.0, 0.0], [0.0, 2.0]].into_ndarray());
+    let y = vmap(
+        |xs| {
+            let y = xs[0].matmul(&m) + xs[1].clone();
+            y.reshape([2]).broadcast([3, 2]).sum([1], true).exp()
+        },
+        &[x.clone(), v.clone()],
+    );
+    let expected = (x.matmul(&m) + v.reshape([2, 1, 1]))
+        .reshape([2, 2])
+        .sum([1], false)
+        .reshape([2, 1, 1])
+        .broadcast([2, 3, 1])
+        .exp();
+    assert_eq!(&*y, &*expected);
+
+    // the result not depending on the examples is repeated
+    let y = vmap(|_| scalar(1.0).into(), &[v]);
+    assert_eq!(&*y, &ndarray::array![1.0, 1.0].into_ndarray());
+
+    // the functions without a batching rule are errors
+    let x = ComputedNDA::new(ndarray::array![[1.0, 2.0], [3.0, 4.0]].into_ndarray());
+    let error = try_vmap(|xs| xs[0].pow_const(2.0), &[x.clone()])
+        .err()
+        .unwrap();
+    assert!(matches!(error, Error::UnbatchableFunction { .. }));
+
+    // the leading axes must match
+    let error = try_vmap(|xs| xs[0].clone(), &[x, ComputedNDA::new(scalar(1.0))])
+        .err()
+        .unwrap();
+    assert_eq!(
+        error,
+        Error::IncompatibleShapes {
+            function: "vmap".into(),
+            shapes: vec![vec![2, 2], vec![]],
+        }
+    );
+}
@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The shapes of the inputs cannot be combined by the function.
+    IncompatibleShapes {
+        function: Cow<'static, str>,
+        shapes: Vec<Vec<usize>>,
+    },
+    IllegalBroadcast {
+        function: Cow<'static, str>,
+        from: Vec<usize>,
+        to: Vec<usize>,
+    },
+    IllegalReshape {
+        function: Cow<'static, str>,
+        from: Vec<usize>,
+        to: Vec<usize>,
+    },
+    IllegalAxes {
+        function: Cow<'static, str>,
+        shape: Vec<usize>,
+        axes: Vec<usize>,
+    },
+    /// The backward function returned a wrong number of gradients.
+    GradientCountMismatch {
+        function: Cow<'static, str>,
+        inputs: usize,
+        gradients: usize,
+    },
+    /// The variable is not reachable from the outputs.
+    GradNotFound {
+        name: String,
+    },
+    CycleDetected,
+    /// The rank of the array does not match the rank of the `Tensor`.
+    RankMismatch {
+        expected: usize,
+        shape: Vec<usize>,
+    },
+    /// A placeholder of `symbolic::Graph` is not given a value.
+    MissingFeed {
+        name: String,
+    },
+    /// A function output or a gradient contains NaN or infinity. See `AnomalyMode`.
+    Anomaly {
+        function: Cow<'static, str>,
+        backward: bool,
+        /// The shapes of the inputs of the function.
+        shapes: Vec<Vec<usize>>,
+        /// The names of the inputs of the function.
+        names: Vec<String>,
+    },
+    /// Distinct params of a layer share the name, so they cannot be told apart in a state dict.
+    DuplicateParamName {
+        name: String,
+    },
+    /// The state dict does not match the params of the layer in the strict mode.
+    StateDictMismatch {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+        /// The names with the shapes of the params and the shapes in the state dict.
+        shape_mismatches: Vec<(String, Vec<usize>, Vec<usize>)>,
+    },
+    /// The param is taken by a function that cannot compute its gradient for each example.
+    /// See `per_sample_gradients_of`.
+    PerSampleGradientUnsupported {
+        function: Cow<'static, str>,
+        name: String,
+    },
+    /// The param already belongs to another `optimizers::ParamGroup`.
+    ParamInGroup {
+        name: String,
+        group: String,
+    },
+    /// The function taking a batched value in `vmap` has no batching rule.
+    UnbatchableFunction {
+        function: Cow<'static, str>,
+    },
+    /// The function recorded eagerly has no operation in `symbolic::Graph`.
+    /// See `Graph::from_recorded`.
+    UnliftableFunction {
+        function: Cow<'static, str>,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IncompatibleShapes { function, shapes } => {
+                write!(f, "{}: incompatible shapes {:?}", function, shapes)
+            }
+            Error::IllegalBroadcast { function, from, to } => {
+                write!(f, "{}: illegal broadcast: {:?} to {:?}", function, from, to)
+            }
+            Error::IllegalReshape { function, from, to } => {
+                write!(f, "{}: illegal reshape: {:?} to {:?}", function, from, to)
+            }
+            Error::IllegalAxes {
+                function,
+                shape,
+                axes,
+            } => {
+                write!(
+                    f,
+                    "{}: illegal axes {:?} for shape {:?}",
+                    function, axes, shape
+                )
+            }
+            Error::GradientCountMismatch {
+                function,
+                inputs,
+                gradients,
+            } => write!(
+                f,
+                "backward of {} has {} inputs, but {} gradients returned",
+                function, inputs, gradients
+            ),
+            Error::GradNotFound { name } => write!(f, "grad not found {}", name),
+            Error::CycleDetected => write!(f, "cycle detected"),
+            Error::RankMismatch { expected, shape } => {
+                write!(f, "expected rank {}, but got shape {:?}", expected, shape)
+            }
+            Error::MissingFeed { name } => write!(f, "placeholder {} is not fed", name),
+            Error::Anomaly {
+                function,
+                backward,
+                shapes,
+                names,
+            } => write!(
+                f,
+                "{} of {} produced a non-finite value; input shapes: {:?}, input names: {:?}",
+                if *backward { "backward" } else { "forward" },
+                function,
+                shapes,
+                names
+            ),
+            Error::DuplicateParamName { name } => write!(f, "duplicate param name {}", name),
+            Error::StateDictMismatch {
+                missing,
+                unexpected,
+                shape_mismatches,
+            } => write!(
+                f,
+                "state dict mismatch; missing: {:?}, unexpected: {:?}, shape mismatches: {:?}",
+                missing, unexpected, shape_mismatches
+            ),
+            Error::PerSampleGradientUnsupported { function, name } => write!(
+                f,
+                "per-sample gradients of {} taken by {} are not supported",
+                name, function
+            ),
+            Error::ParamInGroup { name, group } => {
+                write!(f, "{} already belongs to the group {}", name, group)
+            }
+            Error::UnbatchableFunction { function } => {
+                write!(f, "{} cannot be batched by vmap", function)
+            }
+            Error::UnliftableFunction { function } => {
+                write!(f, "{} cannot be lifted into a symbolic graph", function)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub(crate) fn unwrap_or_panic<T>(result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
@@ -0,0 +1,171 @@
+use std::{
+    any::Any,
+    fmt::{Debug, Display},
+    iter::Sum,
+    ops::{AddAssign, DivAssign, MulAssign, SubAssign},
+};
+
+use crate::NDArray;
+
+/// Element type of tensors.
+///
+/// Implemented for `f32` (the default) and `f64`,
+/// and for `half::f16` and `half::bf16` with the `half` feature.
+pub trait Float:
+    num_traits::Float
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+    + Default
+    + Debug
+    + Display
+    + Send
+    + Sync
+    + serde::Serialize
+    + serde::de::DeserializeOwned
+    + 'static
+{
+    /// Converts a constant. Use `num_traits::ToPrimitive` for the other direction.
+    fn from_f64(x: f64) -> Self;
+
+    fn from_f32(x: f32) -> Self {
+        Self::from_f64(x as f64)
+    }
+
+    /// The epsilon added to the denominator of Adam-like optimizers.
+    /// It must not round to zero, or a zero gradient makes the update 0/0.
+    fn adam_eps() -> Self {
+        Self::from_f64(1e-8)
+    }
+}
+
+impl Float for f32 {
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+}
+
+impl Float for f64 {
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+}
+
+#[cfg(feature = "half")]
+impl Float for half::f16 {
+    fn from_f64(x: f64) -> Self {
+        half::f16::from_f64(x)
+    }
+
+    fn from_f32(x: f32) -> Self {
+        half::f16::from_f32(x)
+    }
+
+    fn adam_eps() -> Self {
+        // 1e-8 is below the smallest subnormal of f16
+        half::f16::from_f64(1e-4)
+    }
+}
+
+#[cfg(feature = "half")]
+impl Float for half::bf16 {
+    fn from_f64(x: f64) -> Self {
+        half::bf16::from_f64(x)
+    }
+
+    fn from_f32(x: f32) -> Self {
+        half::bf16::from_f32(x)
+    }
+}
+
+/// An `NDArray` of any `Float` seen without its element type, for the code generic over
+/// the values of `Computed`, such as the profiler and the anomaly mode.
+pub(crate) trait FloatArray {
+    /// The size of the elements.
+    fn bytes(&self) -> usize;
+
+    fn shape(&self) -> &[usize];
+
+    /// Returns false if any element is NaN or infinite.
+    fn is_finite(&self) -> bool;
+}
+
+impl<T: Float> FloatArray for NDArray<T> {
+    fn bytes(&self) -> usize {
+        self.len() * std::mem::size_of::<T>()
+    }
+
+    fn shape(&self) -> &[usize] {
+        NDArray::shape(self)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.iter().all(|x| x.is_finite())
+    }
+}
+
+/// Returns `x` as a `FloatArray` if it is an `NDArray` of any `Float`.
+pub(crate) fn as_float_array(x: &dyn Any) -> Option<&dyn FloatArray> {
+    fn downcast<T: Float>(x: &dyn Any) -> Option<&dyn FloatArray> {
+        x.downcast_ref::<NDArray<T>>().map(|x| x as &dyn FloatArray)
+    }
+
+    #[cfg(feature = "half")]
+    if let Some(x) = downcast::<half::f16>(x).or_else(|| downcast::<half::bf16>(x)) {
+        return Some(x);
+    }
+    downcast::<f32>(x).or_else(|| downcast::<f64>(x))
+}
+
+#[test]
+fn test() {
+    use crate::{gradcheck::gradcheck, *};
+
+    // gradient checks can be much tighter in f64
+    let x: NDArray<f64> = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let f = |xs: &[ComputedNDA<f64>]| (&xs[0] * &xs[0].exp()).tanh().sum([1], false);
+    let report = gradcheck(f, &[x], 1e-6, 1e-8);
+    assert!(report.passed, "{}", report);
+
+    let p = ParamNDA::new(
+        scalar_of::<f64>(0.0),
+        "param".into(),
+        optimizers::Adam::new_with_params(0.1, 0.9, 0.999),
+    );
+    for _ in 0..500 {
+        optimize(&(p.get() - ComputedNDA::new(scalar_of(3.0))).pow_const(2.0));
+    }
+    assert!((p.get()[[]] - 3.0).abs() < 1e-6);
+}
+
+#[cfg(feature = "half")]
+#[test]
+fn test_half() {
+    use crate::{ndarray_util::cast, *};
+    use half::f16;
+
+    let weights = ndarray::array![[0.0, 1.0], [2.0, 3.0], [4.0, 5.0]].into_ndarray();
+    let embedding = nn::Embedding {
+        embedding_size: 2,
+        weights: ParamNDA::new(
+            cast::<f32, f16>(&weights),
+            "embedding".into(),
+            optimizers::SGD::new(0.5),
+        ),
+    };
+    let y = embedding.call(vec![2, 0, 2], true);
+    assert_eq!(y[[0, 1]], f16::from_f32(5.0));
+
+    optimize(&y.sum([0, 1], false));
+    let weights = cast::<f16, f32>(&embedding.weights.get());
+    assert_eq!(
+        weights,
+        ndarray::array![[-0.5, 0.5], [2.0, 3.0], [3.0, 4.0]].into_ndarray()
+    );
+}
@@ -1,9 +1,6 @@
-use ndarray::{ArrayBase, OwnedArcRepr};
 use ndarray_rand::rand_distr::num_traits;
 
-use crate::*;
-
-type NDArray<T> = ArrayBase<OwnedArcRepr<T>, ndarray::IxDyn>;
+use crate::{error::unwrap_or_panic, *};
 
 pub fn broadcast<
     T: Clone + std::ops::Add<Output = T> + num_traits::Zero + Send + Sync + 'static,
@@ -11,40 +8,102 @@ pub fn broadcast<
     x: &Computed<NDArray<T>>,
     shape: impl Into<Vec<usize>>,
 ) -> Computed<NDArray<T>> {
+    unwrap_or_panic(try_broadcast(x, shape))
+}
+
+pub fn try_broadcast<
+    T: Clone + std::ops::Add<Output = T> + num_traits::Zero + Send + Sync + 'static,
+>(
+    x: &Computed<NDArray<T>>,
+    shape: impl Into<Vec<usize>>,
+) -> Result<Computed<NDArray<T>>, Error> {
+    let _scope = profile_scope("broadcast");
     let shape = shape.into();
     let y = Computed::new(
         (**x)
             .broadcast(shape.as_slice())
-            .unwrap_or_else(|| panic!("illegal broadcast: {:?} to {:?}", x.shape(), shape))
+            .ok_or_else(|| Error::IllegalBroadcast {
+                function: "broadcast".into(),
+                from: x.shape().to_vec(),
+                to: shape.clone(),
+            })?
             .into_dyn()
             .to_shared(),
     );
 
-    chain(
+    let shape_ = shape.clone();
+    forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+        vec![broadcast(txs[0].as_ref().unwrap(), shape_)]
+    });
+
+    chain_with_args(
         &[x.clone()],
         &[y.clone()],
         false,
         "broadcast",
+        FunctionArgs::Broadcast(shape.clone()),
         move |xs, _ys, gys| {
-            let mut axes = Vec::new();
-            let mut target = xs[0].shape().to_vec();
-            for (axis, size) in shape.iter().enumerate() {
-                if let Some(s) = target.first() {
-                    if s == size {
-                        target.remove(0);
-                        continue;
-                    }
-                }
-                axes.push(axis);
-            }
-
-            let gx = super::sum(&gys[0], axes, false);
+            // sum the leading axes added by the broadcast and the axes stretched from 1
+            let offset = shape.len() - xs[0].ndim();
+            let gx = super::sum(&gys[0], (0..offset).collect::<Vec<_>>(), false);
+            let axes: Vec<_> = (0..xs[0].ndim())
+                .filter(|&axis| xs[0].shape()[axis] == 1 && gx.shape()[axis] != 1)
+                .collect();
+            let gx = super::sum(&gx, axes, true);
 
             vec![gx]
         },
     );
 
-    y
+    Ok(y)
+}
+
+/// Adds up the tangents of the inputs and broadcasts the sum to the shape of the output.
+pub(crate) fn sum_tangents<T: Float>(
+    tangents: impl IntoIterator<Item = Option<ComputedNDA<T>>>,
+    shape: &[usize],
+) -> ComputedNDA<T> {
+    let t = tangents
+        .into_iter()
+        .flatten()
+        .reduce(|a, b| &a + &b)
+        .expect("no tangents");
+    if t.shape() == shape {
+        t
+    } else {
+        broadcast(&t, shape)
+    }
+}
+
+/// Returns the shape that `a` and `b` are broadcast to in element-wise operations.
+pub(crate) fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| {
+            let a = (i + a.len()).checked_sub(len).map_or(1, |i| a[i]);
+            let b = (i + b.len()).checked_sub(len).map_or(1, |i| b[i]);
+            if a == b || b == 1 {
+                Some(a)
+            } else if a == 1 {
+                Some(b)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn check_broadcast_shapes(
+    function: &'static str,
+    a: &[usize],
+    b: &[usize],
+) -> Result<(), Error> {
+    broadcast_shape(a, b)
+        .map(|_| ())
+        .ok_or_else(|| Error::IncompatibleShapes {
+            function: function.into(),
+            shapes: vec![a.to_vec(), b.to_vec()],
+        })
 }
 
 #[test]
@@ -62,4 +121,40 @@ fn test() {
         // dbg!(&*y);
         assert_eq!(y.shape(), &[4, 2, 3]);
     }
+
+    {
+        let x = backprop(ndarray::array![[1., 2., 3.], [4., 5., 6.]].into_ndarray());
+        assert_eq!(
+            try_broadcast(&x, vec![3, 3]).err(),
+            Some(Error::IllegalBroadcast {
+                function: "broadcast".into(),
+                from: vec![2, 3],
+                to: vec![3, 3],
+            })
+        );
+    }
+}
+
+#[test]
+fn test_broadcast_shape() {
+    assert_eq!(broadcast_shape(&[2, 1, 4], &[3, 1]), Some(vec![2, 3, 4]));
+    assert_eq!(broadcast_shape(&[], &[3]), Some(vec![3]));
+    assert_eq!(broadcast_shape(&[2, 3], &[4, 3]), None);
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    for x in [
+        ndarray::array![0.1, 0.2, 0.3].into_ndarray(),
+        ndarray::array![[0.1, 0.2, 0.3]].into_ndarray(),
+        ndarray::array![[0.1], [0.2]].into_ndarray(),
+    ] {
+        let f = |xs: &[ComputedNDA]| broadcast(&xs[0], vec![4, 2, 3]);
+        let report = gradcheck(f, &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(f, &[x], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
 }
@@ -1,13 +1,45 @@
 use ndarray::{Axis, IxDyn, SliceInfo, SliceInfoElem};
 
+use crate::error::unwrap_or_panic;
 use crate::functions::*;
 use crate::*;
 
-pub fn concat(xs: &[ComputedNDA], axis: usize) -> ComputedNDA {
+pub fn concat<T: Float>(xs: &[ComputedNDA<T>], axis: usize) -> ComputedNDA<T> {
+    unwrap_or_panic(try_concat(xs, axis))
+}
+
+pub fn try_concat<T: Float>(xs: &[ComputedNDA<T>], axis: usize) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("concat");
+    let compatible = xs.first().is_some_and(|x0| {
+        axis < x0.ndim()
+            && xs.iter().all(|x| {
+                x.ndim() == x0.ndim()
+                    && (0..x0.ndim()).all(|i| i == axis || x.shape()[i] == x0.shape()[i])
+            })
+    });
+    if !compatible {
+        return Err(Error::IncompatibleShapes {
+            function: "concat".into(),
+            shapes: xs.iter().map(|x| x.shape().to_vec()).collect(),
+        });
+    }
+
     let y =
         ndarray::concatenate(Axis(axis), &xs.iter().map(|x| x.view()).collect::<Vec<_>>()).unwrap();
     let y = ComputedNDA::new(y.into_ndarray());
 
+    forward_derivative(xs, [y.clone()], move |xs, _, txs| {
+        let txs: Vec<_> = xs
+            .iter()
+            .zip(txs)
+            .map(|(x, t)| {
+                t.clone()
+                    .unwrap_or_else(|| ComputedNDA::new(NDArray::zeros(x.shape())))
+            })
+            .collect();
+        vec![concat(&txs, axis)]
+    });
+
     chain(xs, &[y.clone()], false, "concat", move |xs, _ys, gys| {
         let gy = &gys[0];
         let mut acc = 0;
@@ -40,7 +72,7 @@ pub fn concat(xs: &[ComputedNDA], axis: usize) -> ComputedNDA {
             .collect()
     });
 
-    y
+    Ok(y)
 }
 
 #[test]
@@ -69,6 +101,28 @@ fn test() {
     // dbg!(&*gs[0]);
     // dbg!(&*gs[1]);
 
-    let y = concat(&[a, b], 1);
+    let y = concat(&[a.clone(), b], 1);
     assert_eq!(y.shape(), [2, 6]);
+
+    let c = backprop(Array::zeros([3, 2]).into_ndarray());
+    assert!(try_concat(&[a.clone(), c.clone()], 0).is_err());
+    assert!(try_concat(&[a.clone(), c], 1).is_err());
+    assert!(try_concat(&[a], 2).is_err());
+    assert!(try_concat::<f32>(&[], 0).is_err());
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0, 3.0]].into_ndarray();
+    let report = gradcheck(|xs| concat(xs, 0), &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| concat(xs, 0), &[a.clone(), b], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradcheck(|xs| concat(xs, 1), &[a.clone(), a.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| concat(xs, 1), &[a.clone(), a], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
 }
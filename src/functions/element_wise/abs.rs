@@ -1,6 +1,13 @@
 use crate::*;
 
-pub fn abs(x: &ComputedNDA) -> ComputedNDA {
+use super::super::{lazy_unary, Expr};
+
+pub fn abs<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("abs");
+    if let Some(y) = lazy_unary(x, Expr::Abs) {
+        return y;
+    }
+
     let y = ComputedNDA::new(x.map(|x| x.abs()).into_ndarray());
 
     chain(
@@ -13,13 +20,18 @@ pub fn abs(x: &ComputedNDA) -> ComputedNDA {
                 &gys[0]
                     * &ComputedNDA::new(
                         xs[0]
-                            .map(|x| if *x >= 0.0 { 1.0 } else { -1.0 })
+                            .map(|x| if *x >= T::zero() { T::one() } else { -T::one() })
                             .into_ndarray(),
                     ),
             ]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |xs, _, txs| {
+        let sign = xs[0].map(|x| if *x >= T::zero() { T::one() } else { -T::one() });
+        vec![txs[0].as_ref().unwrap() * &ComputedNDA::new(sign.into_ndarray())]
+    });
+
     y
 }
 
@@ -35,3 +47,14 @@ fn test() {
         &ndarray::array![1., 1., -1., 1., -1.].into_ndarray()
     );
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| abs(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| abs(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
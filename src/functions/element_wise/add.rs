@@ -1,8 +1,18 @@
-use crate::*;
+use crate::{error::unwrap_or_panic, *};
 
-use super::super::sum_axes_to_desire;
+use super::super::{check_broadcast_shapes, lazy_binary, sum_tangents, sum_to, Expr};
 
-pub fn add(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
+pub fn add<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_add(a, b))
+}
+
+pub fn try_add<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("add");
+    if let Some(y) = lazy_binary(a, b, Expr::Add) {
+        return Ok(y);
+    }
+
+    check_broadcast_shapes("add", a.shape(), b.shape())?;
     let y = ComputedNDA::new((&**a + &**b).into_ndarray());
 
     chain(
@@ -16,20 +26,25 @@ pub fn add(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
 
             // fit shape
             if xs[0].shape() != gx1.shape() {
-                gx1 = gx1.sum(sum_axes_to_desire(gx1.shape(), xs[0].shape()), false);
+                gx1 = sum_to(&gx1, xs[0].shape());
             }
             if xs[1].shape() != gx2.shape() {
-                gx2 = gx2.sum(sum_axes_to_desire(gx2.shape(), xs[1].shape()), false);
+                gx2 = sum_to(&gx2, xs[1].shape());
             }
 
             vec![gx1, gx2]
         },
     );
 
-    y
+    forward_derivative([a.clone(), b.clone()], [y.clone()], |_, ys, txs| {
+        vec![sum_tangents(txs.iter().cloned(), ys[0].shape())]
+    });
+
+    Ok(y)
 }
 
-pub fn multi_add(xs: &[ComputedNDA]) -> ComputedNDA {
+pub fn multi_add<T: Float>(xs: &[ComputedNDA<T>]) -> ComputedNDA<T> {
+    let _scope = profile_scope("multi_add");
     let mut y = (*xs[0]).clone();
     for x in xs.iter().skip(1) {
         y = y + &**x;
@@ -43,7 +58,7 @@ pub fn multi_add(xs: &[ComputedNDA]) -> ComputedNDA {
 
                 // fit shape
                 if x.shape() != gx.shape() {
-                    gx = gx.sum(sum_axes_to_desire(gx.shape(), x.shape()), false);
+                    gx = sum_to(&gx, x.shape());
                 }
 
                 gx
@@ -51,6 +66,10 @@ pub fn multi_add(xs: &[ComputedNDA]) -> ComputedNDA {
             .collect()
     });
 
+    forward_derivative(xs, [y.clone()], |_, ys, txs| {
+        vec![sum_tangents(txs.iter().cloned(), ys[0].shape())]
+    });
+
     y
 }
 
@@ -79,3 +98,22 @@ fn test() {
         assert_eq!(grads[0][[]], 2.0);
     }
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let b = ndarray::array![1.0, -2.0, 3.0].into_ndarray();
+    let f = |xs: &[ComputedNDA]| add(&xs[0], &xs[1]);
+    let report = gradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| multi_add(xs);
+    let report = gradcheck(f, &[a.clone(), b.clone(), a.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a.clone(), b, a], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
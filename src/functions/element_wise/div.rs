@@ -1,6 +1,20 @@
-use crate::{functions::sum_axes_to_desire, *};
+use crate::{
+    error::unwrap_or_panic,
+    functions::{check_broadcast_shapes, lazy_binary, sum_tangents, sum_to, Expr},
+    *,
+};
 
-pub fn div(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
+pub fn div<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_div(a, b))
+}
+
+pub fn try_div<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("div");
+    if let Some(y) = lazy_binary(a, b, Expr::Div) {
+        return Ok(y);
+    }
+
+    check_broadcast_shapes("div", a.shape(), b.shape())?;
     let y = ComputedNDA::new((&**a / &**b).into_ndarray());
 
     chain(
@@ -9,22 +23,48 @@ pub fn div(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
         false,
         "div",
         |xs, _ys, gys| {
-            let mut gx0 = &gys[0] / &xs[0];
+            let mut gx0 = &gys[0] / &xs[1];
 
             let mut gx1 = &gys[0] * &(-&xs[0] / xs[1].pow_const(2.0));
 
             // fit shape
             if xs[0].shape() != gx0.shape() {
-                gx0 = gx0.sum(sum_axes_to_desire(gx0.shape(), xs[0].shape()), false);
+                gx0 = sum_to(&gx0, xs[0].shape());
             }
 
             if xs[1].shape() != gx1.shape() {
-                gx1 = gx1.sum(sum_axes_to_desire(gx1.shape(), xs[0].shape()), false);
+                gx1 = sum_to(&gx1, xs[1].shape());
             }
 
             vec![gx0, gx1]
         },
     );
 
-    y
+    forward_derivative([a.clone(), b.clone()], [y.clone()], |xs, ys, txs| {
+        vec![sum_tangents(
+            [
+                txs[0].as_ref().map(|t| t / &xs[1]),
+                txs[1].as_ref().map(|t| -&(&(t * &ys[0]) / &xs[1])),
+            ],
+            ys[0].shape(),
+        )]
+    });
+
+    Ok(y)
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, -0.2, 0.3], [0.4, 0.5, -0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0, 3.0], [1.5, 2.5, -0.5]].into_ndarray();
+    let c = ndarray::array![1.0, -2.0, 3.0].into_ndarray();
+    let f = |xs: &[ComputedNDA]| div(&xs[0], &xs[1]);
+    for inputs in [[a.clone(), b], [a.clone(), c.clone()], [c, a]] {
+        let report = gradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
 }
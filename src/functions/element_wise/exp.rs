@@ -1,6 +1,13 @@
 use crate::*;
 
-pub fn exp(x: &ComputedNDA) -> ComputedNDA {
+use super::super::{lazy_unary, Expr};
+
+pub fn exp<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("exp");
+    if let Some(y) = lazy_unary(x, Expr::Exp) {
+        return y;
+    }
+
     let y = ComputedNDA::new((**x).map(|x| x.exp()).into_ndarray());
 
     chain(
@@ -14,5 +21,20 @@ pub fn exp(x: &ComputedNDA) -> ComputedNDA {
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |_, ys, txs| {
+        vec![txs[0].as_ref().unwrap() * &ys[0]]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [1.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| exp(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| exp(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
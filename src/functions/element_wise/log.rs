@@ -1,6 +1,13 @@
 use crate::*;
 
-pub fn log(x: &ComputedNDA) -> ComputedNDA {
+use super::super::{lazy_unary, Expr};
+
+pub fn log<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("log");
+    if let Some(y) = lazy_unary(x, Expr::Log) {
+        return y;
+    }
+
     let y = ComputedNDA::new(x.map(|x| x.ln()).into_ndarray());
 
     chain(
@@ -11,5 +18,20 @@ pub fn log(x: &ComputedNDA) -> ComputedNDA {
         move |xs, _, gys| vec![&gys[0] / &xs[0]],
     );
 
+    forward_derivative([x.clone()], [y.clone()], |xs, _, txs| {
+        vec![txs[0].as_ref().unwrap() / &xs[0]]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, 1.0], [2.0, 1.5]].into_ndarray();
+    let report = gradcheck(|xs| log(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| log(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
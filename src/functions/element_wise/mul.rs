@@ -1,8 +1,18 @@
-use crate::{functions::sum, *};
+use crate::{error::unwrap_or_panic, *};
 
-use super::super::sum_axes_to_desire;
+use super::super::{check_broadcast_shapes, lazy_binary, sum_tangents, sum_to, Expr};
 
-pub fn mul(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
+pub fn mul<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_mul(a, b))
+}
+
+pub fn try_mul<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("mul");
+    if let Some(y) = lazy_binary(a, b, Expr::Mul) {
+        return Ok(y);
+    }
+
+    check_broadcast_shapes("mul", a.shape(), b.shape())?;
     let y = ComputedNDA::new((&**a * &**b).into_ndarray());
 
     chain(
@@ -16,21 +26,32 @@ pub fn mul(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
 
             // fit shape
             if xs[0].shape() != gx0.shape() {
-                gx0 = gx0.sum(sum_axes_to_desire(gx0.shape(), xs[0].shape()), false);
+                gx0 = sum_to(&gx0, xs[0].shape());
             }
 
             if xs[1].shape() != gx1.shape() {
-                gx1 = gx1.sum(sum_axes_to_desire(gx1.shape(), xs[1].shape()), false);
+                gx1 = sum_to(&gx1, xs[1].shape());
             }
 
             vec![gx0, gx1]
         },
     );
 
-    y
+    forward_derivative([a.clone(), b.clone()], [y.clone()], |xs, ys, txs| {
+        vec![sum_tangents(
+            [
+                txs[0].as_ref().map(|t| t * &xs[1]),
+                txs[1].as_ref().map(|t| &xs[0] * t),
+            ],
+            ys[0].shape(),
+        )]
+    });
+
+    Ok(y)
 }
 
-pub fn multi_mul(xs: &[ComputedNDA]) -> ComputedNDA {
+pub fn multi_mul<T: Float>(xs: &[ComputedNDA<T>]) -> ComputedNDA<T> {
+    let _scope = profile_scope("multi_mul");
     assert!(xs.len() >= 1);
 
     // NOTE: This assert is unnecessary?
@@ -63,7 +84,7 @@ pub fn multi_mul(xs: &[ComputedNDA]) -> ComputedNDA {
 
                 // fit shape
                 if x.shape() != g.shape() {
-                    g = sum(&g, sum_axes_to_desire(g.shape(), x.shape()), false);
+                    g = sum_to(&g, x.shape());
                     // TODO: https://github.com/oreilly-japan/deep-learning-from-scratch-3/blob/06419d7fb2e7ea19aa3719efc27795edbdc41a1f/dezero/utils.py#L125
                 }
 
@@ -72,10 +93,27 @@ pub fn multi_mul(xs: &[ComputedNDA]) -> ComputedNDA {
             .collect()
     });
 
+    forward_derivative(xs, [y.clone()], |xs, ys, txs| {
+        let tangents = txs.iter().enumerate().map(|(i, t)| {
+            t.as_ref().map(|t| {
+                multi_mul(
+                    &(0..xs.len())
+                        .filter(|j| *j != i)
+                        .map(|j| xs[j].clone())
+                        .chain([t.clone()])
+                        .collect::<Vec<_>>(),
+                )
+            })
+        });
+        vec![sum_tangents(tangents, ys[0].shape())]
+    });
+
     y
 }
 
-pub fn broadcasted_shape(xs: &[impl std::ops::Deref<Target = NDArray>]) -> Option<Vec<usize>> {
+pub fn broadcasted_shape<T>(
+    xs: &[impl std::ops::Deref<Target = NDArray<T>>],
+) -> Option<Vec<usize>> {
     let mut shape = xs[0].shape().to_vec();
     for x in xs.iter().skip(1) {
         let x_shape = x.shape();
@@ -108,16 +146,36 @@ pub fn broadcasted_shape(xs: &[impl std::ops::Deref<Target = NDArray>]) -> Optio
 #[test]
 fn test() {
     let s = broadcasted_shape(&[
-        &ndarray::Array::zeros([1, 1, 1]).into_ndarray(),
+        &ndarray::Array::<f32, _>::zeros([1, 1, 1]).into_ndarray(),
         &ndarray::Array::zeros([1, 1, 2]).into_ndarray(),
         &ndarray::Array::zeros([3, 1, 1]).into_ndarray(),
     ]);
     assert_eq!(s, Some(vec![3, 1, 2]));
 
     let s = broadcasted_shape(&[
-        &ndarray::Array::zeros([1, 4, 1]).into_ndarray(),
+        &ndarray::Array::<f32, _>::zeros([1, 4, 1]).into_ndarray(),
         &ndarray::Array::zeros([3, 4, 2]).into_ndarray(),
         &ndarray::Array::zeros([1, 4, 2]).into_ndarray(),
     ]);
     assert_eq!(s, Some(vec![3, 4, 2]));
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, -0.2, 0.3], [0.4, 0.5, -0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0, 3.0]].into_ndarray();
+    let c = ndarray::array![[1.5], [-0.5]].into_ndarray();
+    let f = |xs: &[ComputedNDA]| mul(&xs[0], &xs[1]);
+    let report = gradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| multi_mul(xs);
+    let report = gradcheck(f, &[a.clone(), b.clone(), c.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a, b, c], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
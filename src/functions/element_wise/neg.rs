@@ -1,7 +1,14 @@
 use crate::*;
 
-pub fn neg(x: &ComputedNDA) -> ComputedNDA {
-    let y = ComputedNDA::new((-&**x).into_ndarray());
+use super::super::{lazy_unary, Expr};
+
+pub fn neg<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("neg");
+    if let Some(y) = lazy_unary(x, Expr::Neg) {
+        return y;
+    }
+
+    let y = ComputedNDA::new(x.map(|x| -*x).into_ndarray());
 
     chain(
         &[x.clone()],
@@ -14,5 +21,20 @@ pub fn neg(x: &ComputedNDA) -> ComputedNDA {
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |_, _, txs| {
+        vec![-txs[0].as_ref().unwrap()]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| neg(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| neg(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
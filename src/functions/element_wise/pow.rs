@@ -2,7 +2,14 @@ use ndarray::azip;
 
 use crate::*;
 
-pub fn pow(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
+use super::super::{lazy_binary, lazy_unary, Expr};
+
+pub fn pow<T: Float>(a: &ComputedNDA<T>, b: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("pow");
+    if let Some(y) = lazy_binary(a, b, Expr::Pow) {
+        return y;
+    }
+
     let mut y = NDArray::zeros(a.shape());
     azip!((y in &mut y, a in &**a, b in &**b) *y = a.powf(*b));
     let y = Computed::new(y);
@@ -14,17 +21,34 @@ pub fn pow(a: &ComputedNDA, b: &ComputedNDA) -> ComputedNDA {
         "pow",
         move |xs, ys, gys| {
             let ga =
-                &gys[0] * &pow(&xs[0], &(&xs[1] - &Computed::new(scalar(1.0)))) * xs[1].clone();
+                &gys[0] * &pow(&xs[0], &(&xs[1] - &Computed::new(scalar_of(1.0)))) * xs[1].clone();
             let gb = &gys[0] * &ys[0] * xs[0].log();
             vec![ga, gb]
         },
     );
 
+    forward_derivative([a.clone(), b.clone()], [y.clone()], |xs, ys, txs| {
+        vec![super::super::sum_tangents(
+            [
+                txs[0].as_ref().map(|t| {
+                    t * &pow(&xs[0], &(&xs[1] - &Computed::new(scalar_of(1.0)))) * xs[1].clone()
+                }),
+                txs[1].as_ref().map(|t| t * &ys[0] * xs[0].log()),
+            ],
+            ys[0].shape(),
+        )]
+    });
+
     y
 }
 
-pub fn pow_const(x: &ComputedNDA, a: f32) -> ComputedNDA {
-    let y = ComputedNDA::new((**x).map(|x| x.powf(a)).into_ndarray());
+pub fn pow_const<T: Float>(x: &ComputedNDA<T>, a: f32) -> ComputedNDA<T> {
+    let _scope = profile_scope("pow_const");
+    if let Some(y) = lazy_unary(x, |x| Expr::Powf(x, a)) {
+        return y;
+    }
+
+    let y = ComputedNDA::new((**x).map(|x| x.powf(T::from_f32(a))).into_ndarray());
 
     chain(
         &[x.clone()],
@@ -32,11 +56,19 @@ pub fn pow_const(x: &ComputedNDA, a: f32) -> ComputedNDA {
         false,
         "pow_const",
         move |xs, _ys, gys| {
-            let gx = &gys[0] * &xs[0].pow_const(a - 1.0) * ComputedNDA::new(scalar(a));
+            let gx = &gys[0] * &xs[0].pow_const(a - 1.0) * ComputedNDA::new(scalar_of(a as f64));
             vec![gx]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], move |xs, _, txs| {
+        vec![
+            txs[0].as_ref().unwrap()
+                * &xs[0].pow_const(a - 1.0)
+                * ComputedNDA::new(scalar_of(a as f64)),
+        ]
+    });
+
     y
 }
 
@@ -57,3 +89,22 @@ fn test_pow() {
     assert_eq!(*grads[0], scalar(10.0));
     assert_eq!(*grads[1], scalar(25.0) * &*a.log());
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.5, 1.0], [2.0, 1.5]].into_ndarray();
+    let b = ndarray::array![[1.5, -1.0], [2.0, 0.5]].into_ndarray();
+    let f = |xs: &[ComputedNDA]| pow(&xs[0], &xs[1]);
+    let report = gradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a.clone(), b], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| pow_const(&xs[0], 2.5);
+    let report = gradcheck(f, &[a.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
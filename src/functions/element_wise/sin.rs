@@ -1,6 +1,13 @@
 use crate::*;
 
-pub fn sin(x: &ComputedNDA) -> ComputedNDA {
+use super::super::{lazy_unary, Expr};
+
+pub fn sin<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("sin");
+    if let Some(y) = lazy_unary(x, Expr::Sin) {
+        return y;
+    }
+
     let y = ComputedNDA::new((**x).map(|x| x.sin()).into_ndarray());
 
     chain(
@@ -14,10 +21,19 @@ pub fn sin(x: &ComputedNDA) -> ComputedNDA {
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |xs, _, txs| {
+        vec![txs[0].as_ref().unwrap() * &xs[0].cos()]
+    });
+
     y
 }
 
-pub fn cos(x: &ComputedNDA) -> ComputedNDA {
+pub fn cos<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("cos");
+    if let Some(y) = lazy_unary(x, Expr::Cos) {
+        return y;
+    }
+
     let y = ComputedNDA::new((**x).map(|x| x.cos()).into_ndarray());
 
     chain(
@@ -31,5 +47,22 @@ pub fn cos(x: &ComputedNDA) -> ComputedNDA {
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |xs, _, txs| {
+        vec![txs[0].as_ref().unwrap() * &-&xs[0].sin()]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    for f in [sin, cos] {
+        let report = gradcheck(|xs| f(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(|xs| f(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
+}
@@ -1,6 +1,23 @@
-use crate::{functions::sum_axes_to_desire, *};
+use crate::{
+    error::unwrap_or_panic,
+    functions::{check_broadcast_shapes, lazy_binary, sum_tangents, sum_to, Expr},
+    *,
+};
 
-pub fn sub(lhs: &ComputedNDA, rhs: &ComputedNDA) -> ComputedNDA {
+pub fn sub<T: Float>(lhs: &ComputedNDA<T>, rhs: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_sub(lhs, rhs))
+}
+
+pub fn try_sub<T: Float>(
+    lhs: &ComputedNDA<T>,
+    rhs: &ComputedNDA<T>,
+) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("sub");
+    if let Some(y) = lazy_binary(lhs, rhs, Expr::Sub) {
+        return Ok(y);
+    }
+
+    check_broadcast_shapes("sub", lhs.shape(), rhs.shape())?;
     let y = ComputedNDA::new((&**lhs - &**rhs).into_ndarray());
 
     chain(
@@ -14,18 +31,25 @@ pub fn sub(lhs: &ComputedNDA, rhs: &ComputedNDA) -> ComputedNDA {
 
             // fit shape
             if xs[0].shape() != gx0.shape() {
-                gx0 = gx0.sum(sum_axes_to_desire(gx0.shape(), xs[0].shape()), false);
+                gx0 = sum_to(&gx0, xs[0].shape());
             }
 
             if xs[1].shape() != gx1.shape() {
-                gx1 = gx1.sum(sum_axes_to_desire(gx1.shape(), xs[0].shape()), false);
+                gx1 = sum_to(&gx1, xs[1].shape());
             }
 
             vec![gx0, gx1]
         },
     );
 
-    y
+    forward_derivative([lhs.clone(), rhs.clone()], [y.clone()], |_, ys, txs| {
+        vec![sum_tangents(
+            [txs[0].clone(), txs[1].as_ref().map(|t| -t)],
+            ys[0].shape(),
+        )]
+    });
+
+    Ok(y)
 }
 
 #[test]
@@ -40,4 +64,29 @@ fn test_sub() {
     let grads = gradients(&[y], &[a.clone(), b.clone()], false);
     assert_eq!(&*grads[0], scalar(1.0));
     assert_eq!(&*grads[1], scalar(-1.0));
+
+    let a = backprop(<NDArray>::zeros(&[2, 3][..]));
+    let b = backprop(<NDArray>::zeros(&[2][..]));
+    assert_eq!(
+        try_sub(&a, &b).err(),
+        Some(Error::IncompatibleShapes {
+            function: "sub".into(),
+            shapes: vec![vec![2, 3], vec![2]],
+        })
+    );
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let b = ndarray::array![1.0, -2.0, 3.0].into_ndarray();
+    let f = |xs: &[ComputedNDA]| sub(&xs[0], &xs[1]);
+    for inputs in [[a.clone(), b.clone()], [b, a]] {
+        let report = gradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
 }
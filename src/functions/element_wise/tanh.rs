@@ -1,6 +1,13 @@
 use crate::*;
 
-pub fn tanh(x: &ComputedNDA) -> ComputedNDA {
+use super::super::{lazy_unary, Expr};
+
+pub fn tanh<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("tanh");
+    if let Some(y) = lazy_unary(x, Expr::Tanh) {
+        return y;
+    }
+
     let y = ComputedNDA::new(x.map(|x| x.tanh()).into_ndarray());
 
     chain(
@@ -9,10 +16,25 @@ pub fn tanh(x: &ComputedNDA) -> ComputedNDA {
         false,
         "tanh",
         move |_xs, ys, gys| {
-            let gx = &gys[0] * &(ComputedNDA::new(scalar(1.0)) - ys[0].pow_const(2.0));
+            let gx = &gys[0] * &(ComputedNDA::new(scalar_of(1.0)) - ys[0].pow_const(2.0));
             vec![gx]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |_, ys, txs| {
+        vec![txs[0].as_ref().unwrap() * &(ComputedNDA::new(scalar_of(1.0)) - ys[0].pow_const(2.0))]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| tanh(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| tanh(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
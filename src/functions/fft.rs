@@ -19,6 +19,7 @@ impl Fft {
     }
 
     pub fn fft(&self, x: &ComputedNDA) -> ComputedNDA {
+        let _scope = profile_scope("fft");
         let shape = x.shape();
         assert!(
             shape.len() >= 2 && shape[shape.len() - 1] == 2,
@@ -41,6 +42,11 @@ impl Fft {
         let buf_iter = buf.iter().flat_map(|x| [x.re, x.im]);
         let y = Computed::new(ArrayBase::from_iter(buf_iter).into_shape(shape).unwrap());
 
+        let this = self.clone();
+        forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+            vec![this.fft(txs[0].as_ref().unwrap())]
+        });
+
         let this = self.clone();
         chain(
             &[x.clone()],
@@ -58,6 +64,7 @@ impl Fft {
     }
 
     pub fn ifft(&self, x: &ComputedNDA) -> ComputedNDA {
+        let _scope = profile_scope("ifft");
         let shape = x.shape();
         assert!(
             shape.len() >= 2 && shape[shape.len() - 1] == 2,
@@ -80,6 +87,11 @@ impl Fft {
         let buf_iter = buf.iter().flat_map(|x| [x.re, x.im]);
         let y = Computed::new(ArrayBase::from_iter(buf_iter).into_shape(shape).unwrap());
 
+        let this = self.clone();
+        forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+            vec![this.ifft(txs[0].as_ref().unwrap())]
+        });
+
         let this = self.clone();
         chain(
             &[x.clone()],
@@ -156,3 +168,17 @@ fn test() {
         .into_ndarray(),
     ));
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.1, 0.2], [0.3, -0.4], [0.5, 0.6], [-0.7, 0.8]].into_ndarray();
+    let fft = Fft::new();
+    let report = gradcheck(|xs| fft.fft(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| fft.fft(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradcheck(|xs| fft.ifft(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
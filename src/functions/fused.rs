@@ -0,0 +1,741 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use ndarray::{ArrayView1, ArrayViewD, Axis};
+
+use crate::{core::Pending, error::unwrap_or_panic, *};
+
+use super::{broadcast_shape, sum_to};
+
+/// An element-wise expression over the inputs of `fused`.
+///
+/// Subexpressions may be shared by `Arc`s. The shared nodes are differentiated and computed once.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Input(usize),
+    Const(f32),
+    Neg(Arc<Expr>),
+    Abs(Arc<Expr>),
+    Sign(Arc<Expr>),
+    Exp(Arc<Expr>),
+    Log(Arc<Expr>),
+    Sin(Arc<Expr>),
+    Cos(Arc<Expr>),
+    Tanh(Arc<Expr>),
+    Powf(Arc<Expr>, f32),
+    Add(Arc<Expr>, Arc<Expr>),
+    Sub(Arc<Expr>, Arc<Expr>),
+    Mul(Arc<Expr>, Arc<Expr>),
+    Div(Arc<Expr>, Arc<Expr>),
+    Pow(Arc<Expr>, Arc<Expr>),
+}
+
+impl Expr {
+    /// Returns the derivative with respect to the `i`th input.
+    pub fn diff(self: &Arc<Self>, i: usize) -> Arc<Expr> {
+        self.diff_with(i, &mut HashMap::new())
+    }
+
+    fn diff_with(self: &Arc<Self>, i: usize, memo: &mut Memo<Arc<Expr>>) -> Arc<Expr> {
+        use Expr::*;
+        if let Some(d) = memo.get(&Arc::as_ptr(self)) {
+            return d.clone();
+        }
+        let d = match &**self {
+            Input(j) => Arc::new(Const(if *j == i { 1.0 } else { 0.0 })),
+            Const(_) | Sign(_) => Arc::new(Const(0.0)),
+            Neg(a) => neg(a.diff_with(i, memo)),
+            Abs(a) => mul(a.diff_with(i, memo), Arc::new(Sign(a.clone()))),
+            Exp(a) => mul(a.diff_with(i, memo), self.clone()),
+            Log(a) => div(a.diff_with(i, memo), a.clone()),
+            Sin(a) => mul(a.diff_with(i, memo), Arc::new(Cos(a.clone()))),
+            Cos(a) => neg(mul(a.diff_with(i, memo), Arc::new(Sin(a.clone())))),
+            Tanh(a) => mul(
+                a.diff_with(i, memo),
+                sub(Arc::new(Const(1.0)), mul(self.clone(), self.clone())),
+            ),
+            Powf(a, p) => mul(
+                a.diff_with(i, memo),
+                mul(Arc::new(Const(*p)), powf(a.clone(), p - 1.0)),
+            ),
+            Add(a, b) => add(a.diff_with(i, memo), b.diff_with(i, memo)),
+            Sub(a, b) => sub(a.diff_with(i, memo), b.diff_with(i, memo)),
+            Mul(a, b) => add(
+                mul(a.diff_with(i, memo), b.clone()),
+                mul(a.clone(), b.diff_with(i, memo)),
+            ),
+            Div(a, b) => sub(
+                div(a.diff_with(i, memo), b.clone()),
+                mul(b.diff_with(i, memo), div(self.clone(), b.clone())),
+            ),
+            Pow(a, b) => add(
+                mul(
+                    a.diff_with(i, memo),
+                    mul(
+                        b.clone(),
+                        Arc::new(Pow(a.clone(), sub(b.clone(), Arc::new(Const(1.0))))),
+                    ),
+                ),
+                mul(
+                    b.diff_with(i, memo),
+                    mul(self.clone(), Arc::new(Log(a.clone()))),
+                ),
+            ),
+        };
+        memo.insert(Arc::as_ptr(self), d.clone());
+        d
+    }
+
+    fn map_inputs(
+        self: &Arc<Self>,
+        f: &impl Fn(usize) -> usize,
+        memo: &mut Memo<Arc<Expr>>,
+    ) -> Arc<Expr> {
+        use Expr::*;
+        if let Some(e) = memo.get(&Arc::as_ptr(self)) {
+            return e.clone();
+        }
+        let e = Arc::new(match &**self {
+            Input(i) => Input(f(*i)),
+            Const(c) => Const(*c),
+            Neg(a) => Neg(a.map_inputs(f, memo)),
+            Abs(a) => Abs(a.map_inputs(f, memo)),
+            Sign(a) => Sign(a.map_inputs(f, memo)),
+            Exp(a) => Exp(a.map_inputs(f, memo)),
+            Log(a) => Log(a.map_inputs(f, memo)),
+            Sin(a) => Sin(a.map_inputs(f, memo)),
+            Cos(a) => Cos(a.map_inputs(f, memo)),
+            Tanh(a) => Tanh(a.map_inputs(f, memo)),
+            Powf(a, p) => Powf(a.map_inputs(f, memo), *p),
+            Add(a, b) => Add(a.map_inputs(f, memo), b.map_inputs(f, memo)),
+            Sub(a, b) => Sub(a.map_inputs(f, memo), b.map_inputs(f, memo)),
+            Mul(a, b) => Mul(a.map_inputs(f, memo), b.map_inputs(f, memo)),
+            Div(a, b) => Div(a.map_inputs(f, memo), b.map_inputs(f, memo)),
+            Pow(a, b) => Pow(a.map_inputs(f, memo), b.map_inputs(f, memo)),
+        });
+        memo.insert(Arc::as_ptr(self), e.clone());
+        e
+    }
+
+    fn inputs(self: &Arc<Self>, used: &mut [bool], visited: &mut HashSet<*const Expr>) {
+        use Expr::*;
+        if !visited.insert(Arc::as_ptr(self)) {
+            return;
+        }
+        match &**self {
+            Input(i) => used[*i] = true,
+            Const(_) => {}
+            Neg(a)
+            | Abs(a)
+            | Sign(a)
+            | Exp(a)
+            | Log(a)
+            | Sin(a)
+            | Cos(a)
+            | Tanh(a)
+            | Powf(a, _) => a.inputs(used, visited),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) => {
+                a.inputs(used, visited);
+                b.inputs(used, visited);
+            }
+        }
+    }
+
+    /// Appends the instructions of the nodes not compiled yet and returns the index of the
+    /// instruction computing this.
+    fn compile(self: &Arc<Self>, code: &mut Vec<Instr>, memo: &mut Memo<usize>) -> usize {
+        use Expr::*;
+        if let Some(&k) = memo.get(&Arc::as_ptr(self)) {
+            return k;
+        }
+        let instr = match &**self {
+            Input(i) => Instr::Input(*i),
+            Const(c) => Instr::Const(*c),
+            Neg(a) => Instr::Neg(a.compile(code, memo)),
+            Abs(a) => Instr::Abs(a.compile(code, memo)),
+            Sign(a) => Instr::Sign(a.compile(code, memo)),
+            Exp(a) => Instr::Exp(a.compile(code, memo)),
+            Log(a) => Instr::Log(a.compile(code, memo)),
+            Sin(a) => Instr::Sin(a.compile(code, memo)),
+            Cos(a) => Instr::Cos(a.compile(code, memo)),
+            Tanh(a) => Instr::Tanh(a.compile(code, memo)),
+            Powf(a, p) => Instr::Powf(a.compile(code, memo), *p),
+            Add(a, b) => Instr::Add(a.compile(code, memo), b.compile(code, memo)),
+            Sub(a, b) => Instr::Sub(a.compile(code, memo), b.compile(code, memo)),
+            Mul(a, b) => Instr::Mul(a.compile(code, memo), b.compile(code, memo)),
+            Div(a, b) => Instr::Div(a.compile(code, memo), b.compile(code, memo)),
+            Pow(a, b) => Instr::Pow(a.compile(code, memo), b.compile(code, memo)),
+        };
+        code.push(instr);
+        memo.insert(Arc::as_ptr(self), code.len() - 1);
+        code.len() - 1
+    }
+}
+
+/// The results of a traversal by the addresses of the nodes visited.
+type Memo<V> = HashMap<*const Expr, V>;
+
+// constructors that fold constants to keep derivatives small
+
+fn neg(a: Arc<Expr>) -> Arc<Expr> {
+    match &*a {
+        Expr::Const(c) => Arc::new(Expr::Const(-c)),
+        Expr::Neg(a) => a.clone(),
+        _ => Arc::new(Expr::Neg(a)),
+    }
+}
+
+fn add(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+    match (&*a, &*b) {
+        (Expr::Const(x), Expr::Const(y)) => Arc::new(Expr::Const(x + y)),
+        (Expr::Const(x), _) if *x == 0.0 => b,
+        (_, Expr::Const(y)) if *y == 0.0 => a,
+        _ => Arc::new(Expr::Add(a, b)),
+    }
+}
+
+fn sub(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+    match (&*a, &*b) {
+        (Expr::Const(x), Expr::Const(y)) => Arc::new(Expr::Const(x - y)),
+        (Expr::Const(x), _) if *x == 0.0 => neg(b),
+        (_, Expr::Const(y)) if *y == 0.0 => a,
+        _ => Arc::new(Expr::Sub(a, b)),
+    }
+}
+
+fn mul(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+    match (&*a, &*b) {
+        (Expr::Const(x), Expr::Const(y)) => Arc::new(Expr::Const(x * y)),
+        (Expr::Const(x), _) | (_, Expr::Const(x)) if *x == 0.0 => Arc::new(Expr::Const(0.0)),
+        (Expr::Const(x), _) if *x == 1.0 => b,
+        (_, Expr::Const(y)) if *y == 1.0 => a,
+        _ => Arc::new(Expr::Mul(a, b)),
+    }
+}
+
+fn div(a: Arc<Expr>, b: Arc<Expr>) -> Arc<Expr> {
+    match (&*a, &*b) {
+        (Expr::Const(x), _) if *x == 0.0 => a,
+        (_, Expr::Const(y)) if *y == 1.0 => a,
+        _ => Arc::new(Expr::Div(a, b)),
+    }
+}
+
+fn powf(a: Arc<Expr>, p: f32) -> Arc<Expr> {
+    if p == 0.0 {
+        Arc::new(Expr::Const(1.0))
+    } else if p == 1.0 {
+        a
+    } else {
+        Arc::new(Expr::Powf(a, p))
+    }
+}
+
+/// An instruction computing a node of an expression.
+/// The operands are the indices of the instructions computing them.
+#[derive(Debug, Clone, Copy)]
+enum Instr {
+    Input(usize),
+    Const(f32),
+    Neg(usize),
+    Abs(usize),
+    Sign(usize),
+    Exp(usize),
+    Log(usize),
+    Sin(usize),
+    Cos(usize),
+    Tanh(usize),
+    Powf(usize, f32),
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    Div(usize, usize),
+    Pow(usize, usize),
+}
+
+/// The number of elements processed by an instruction at once.
+const BLOCK: usize = 256;
+
+/// Runs `code` over a block of elements and appends the results of the last instruction to `out`.
+fn run<T: Float>(
+    code: &[Instr],
+    xs: &[ArrayView1<T>],
+    len: usize,
+    slots: &mut Vec<Vec<T>>,
+    out: &mut Vec<T>,
+) {
+    if slots.len() < code.len() {
+        slots.resize_with(code.len(), || vec![T::zero(); BLOCK]);
+    }
+    for (k, instr) in code.iter().enumerate() {
+        let (done, rest) = slots.split_at_mut(k);
+        let y = &mut rest[0][..len];
+        let arg = |a: usize| &done[a][..len];
+        match *instr {
+            Instr::Input(i) => {
+                if xs[i].len() == 1 || xs[i].strides()[0] == 0 {
+                    y.fill(xs[i][0]);
+                } else if let Some(x) = xs[i].as_slice() {
+                    y.copy_from_slice(x);
+                } else {
+                    y.iter_mut().zip(xs[i].iter()).for_each(|(y, x)| *y = *x);
+                }
+            }
+            Instr::Const(c) => y.fill(T::from_f32(c)),
+            Instr::Neg(a) => map(y, arg(a), |a| -a),
+            Instr::Abs(a) => map(y, arg(a), |a| a.abs()),
+            Instr::Sign(a) => map(
+                y,
+                arg(a),
+                |a| if a >= T::zero() { T::one() } else { -T::one() },
+            ),
+            Instr::Exp(a) => map(y, arg(a), |a| a.exp()),
+            Instr::Log(a) => map(y, arg(a), |a| a.ln()),
+            Instr::Sin(a) => map(y, arg(a), |a| a.sin()),
+            Instr::Cos(a) => map(y, arg(a), |a| a.cos()),
+            Instr::Tanh(a) => map(y, arg(a), |a| a.tanh()),
+            Instr::Powf(a, p) => {
+                let p = T::from_f32(p);
+                map(y, arg(a), |a| a.powf(p))
+            }
+            Instr::Add(a, b) => zip_map(y, arg(a), arg(b), |a, b| a + b),
+            Instr::Sub(a, b) => zip_map(y, arg(a), arg(b), |a, b| a - b),
+            Instr::Mul(a, b) => zip_map(y, arg(a), arg(b), |a, b| a * b),
+            Instr::Div(a, b) => zip_map(y, arg(a), arg(b), |a, b| a / b),
+            Instr::Pow(a, b) => zip_map(y, arg(a), arg(b), |a, b| a.powf(b)),
+        }
+    }
+    out.extend_from_slice(&slots[code.len() - 1][..len]);
+}
+
+fn map<T: Copy>(y: &mut [T], a: &[T], f: impl Fn(T) -> T) {
+    y.iter_mut().zip(a).for_each(|(y, a)| *y = f(*a));
+}
+
+fn zip_map<T: Copy>(y: &mut [T], a: &[T], b: &[T], f: impl Fn(T, T) -> T) {
+    y.iter_mut()
+        .zip(a.iter().zip(b))
+        .for_each(|(y, (a, b))| *y = f(*a, *b));
+}
+
+/// Merges the trailing axes where every input is either not broadcast or
+/// broadcast entirely, so that the innermost loop gets longer.
+///
+/// Returns the merged shape of the output and the inputs if any axes are merged.
+fn merge_axes<T: Float>(
+    xs: &[ComputedNDA<T>],
+    shape: &[usize],
+) -> (Vec<usize>, Option<Vec<Vec<usize>>>) {
+    if shape.is_empty() {
+        return (vec![1], None);
+    }
+    let ndim = shape.len();
+    let x_shapes: Vec<Vec<usize>> = xs
+        .iter()
+        .map(|x| {
+            std::iter::repeat_n(1, ndim - x.ndim())
+                .chain(x.shape().iter().copied())
+                .collect()
+        })
+        .collect();
+
+    let mut k = ndim - 1;
+    if xs.iter().all(|x| x.is_standard_layout()) {
+        while k > 0
+            && x_shapes
+                .iter()
+                .all(|s| s[k - 1..] == shape[k - 1..] || s[k - 1..].iter().all(|d| *d == 1))
+        {
+            k -= 1;
+        }
+    }
+    if k == ndim - 1 {
+        return (shape.to_vec(), None);
+    }
+    let merge = |s: &[usize]| -> Vec<usize> {
+        s[..k]
+            .iter()
+            .copied()
+            .chain([s[k..].iter().product()])
+            .collect()
+    };
+    (
+        merge(shape),
+        Some(x_shapes.iter().map(|s| merge(s)).collect()),
+    )
+}
+
+/// Computes `expr` in a single pass over the inputs broadcast to `shape`.
+fn evaluate<T: Float>(expr: &Arc<Expr>, xs: &[ComputedNDA<T>], shape: &[usize]) -> NDArray<T> {
+    let mut code = Vec::new();
+    expr.compile(&mut code, &mut HashMap::new());
+
+    let (lane_shape, x_shapes) = merge_axes(xs, shape);
+    let views: Vec<ArrayViewD<T>> = match x_shapes {
+        Some(x_shapes) => xs
+            .iter()
+            .zip(x_shapes)
+            .map(|(x, s)| x.view().into_shape(s).unwrap())
+            .collect(),
+        None => xs.iter().map(|x| x.view()).collect(),
+    };
+    let views: Vec<_> = views
+        .iter()
+        .map(|x| {
+            x.broadcast(lane_shape.as_slice())
+                .expect("fused: cannot broadcast")
+        })
+        .collect();
+
+    let axis = Axis(lane_shape.len() - 1);
+    let len = lane_shape[axis.index()];
+    let mut lanes: Vec<_> = views.iter().map(|x| x.lanes(axis).into_iter()).collect();
+    let size = shape.iter().product();
+    let mut values = Vec::with_capacity(size);
+    let mut slots = Vec::new();
+    let mut block = Vec::with_capacity(xs.len());
+    for _ in 0..size.checked_div(len).unwrap_or(0) {
+        let lane: Vec<_> = lanes.iter_mut().map(|l| l.next().unwrap()).collect();
+        for start in (0..len).step_by(BLOCK) {
+            let end = (start + BLOCK).min(len);
+            block.clear();
+            block.extend(lane.iter().map(|x| x.slice_move(ndarray::s![start..end])));
+            run(&code, &block, end - start, &mut slots, &mut values);
+        }
+    }
+    NDArray::from_shape_vec(shape, values).unwrap()
+}
+
+/// Computes the element-wise expression `expr` over `xs` in a single pass.
+///
+/// The inputs are broadcast to each other. The backward and the tangent are
+/// also computed by fused expressions, so they are differentiable.
+pub fn fused<T: Float>(expr: &Expr, xs: &[ComputedNDA<T>]) -> ComputedNDA<T> {
+    unwrap_or_panic(try_fused(expr, xs))
+}
+
+pub fn try_fused<T: Float>(expr: &Expr, xs: &[ComputedNDA<T>]) -> Result<ComputedNDA<T>, Error> {
+    let mut shape = vec![];
+    for x in xs {
+        shape = broadcast_shape(&shape, x.shape()).ok_or_else(|| Error::IncompatibleShapes {
+            function: "fused".into(),
+            shapes: xs.iter().map(|x| x.shape().to_vec()).collect(),
+        })?;
+    }
+    Ok(fused_to(Arc::new(expr.clone()), xs, shape))
+}
+
+/// Computes `expr` with the output shape `shape` dropping the inputs that are not used.
+fn fused_to<T: Float>(expr: Arc<Expr>, xs: &[ComputedNDA<T>], shape: Vec<usize>) -> ComputedNDA<T> {
+    let _scope = profile_scope("fused");
+    if let Expr::Input(i) = *expr {
+        if xs[i].shape() == shape {
+            return xs[i].clone();
+        }
+    }
+
+    let mut used = vec![false; xs.len()];
+    expr.inputs(&mut used, &mut HashSet::new());
+    let (expr, xs) = if used.iter().all(|u| *u) {
+        (expr, xs.to_vec())
+    } else {
+        let mut indices = vec![0; xs.len()];
+        let mut ys = vec![];
+        for (i, x) in xs.iter().enumerate().filter(|(i, _)| used[*i]) {
+            indices[i] = ys.len();
+            ys.push(x.clone());
+        }
+        (expr.map_inputs(&|i| indices[i], &mut HashMap::new()), ys)
+    };
+
+    let y = ComputedNDA::new(evaluate(&expr, &xs, &shape));
+    record(expr, &xs, &y);
+    y
+}
+
+fn record<T: Float>(expr: Arc<Expr>, xs: &[ComputedNDA<T>], y: &ComputedNDA<T>) {
+    let expr_ = expr.clone();
+    chain(
+        xs,
+        std::slice::from_ref(y),
+        false,
+        "fused",
+        move |xs, ys, gys| {
+            let mut inputs = xs.clone();
+            inputs.push(gys[0].clone());
+            let gy = Arc::new(Expr::Input(xs.len()));
+            xs.iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    let gx = fused_to(
+                        mul(gy.clone(), expr_.diff(i)),
+                        &inputs,
+                        ys[0].shape().to_vec(),
+                    );
+                    sum_to(&gx, x.shape())
+                })
+                .collect()
+        },
+    );
+
+    forward_derivative(xs, [y.clone()], |xs, ys, txs| {
+        let mut inputs = xs.to_vec();
+        let mut t = Arc::new(Expr::Const(0.0));
+        for (i, tx) in txs.iter().enumerate() {
+            if let Some(tx) = tx {
+                inputs.push(tx.clone());
+                t = add(
+                    t,
+                    mul(expr.diff(i), Arc::new(Expr::Input(inputs.len() - 1))),
+                );
+            }
+        }
+        vec![fused_to(t, &inputs, ys[0].shape().to_vec())]
+    });
+}
+
+/// An element-wise expression recorded in lazy mode.
+struct PendingFused<T> {
+    expr: Arc<Expr>,
+    xs: Vec<ComputedNDA<T>>,
+    shape: Vec<usize>,
+    grad_enabled: bool,
+}
+
+impl<T: Float> Pending<NDArray<T>> for PendingFused<T> {
+    fn evaluate(&self) -> NDArray<T> {
+        evaluate(&self.expr, &self.xs, &self.shape)
+    }
+
+    fn name(&self) -> &'static str {
+        "fused"
+    }
+
+    fn record(&self, y: &ComputedNDA<T>) {
+        let _guard = set_grad_enabled(self.grad_enabled);
+        record(self.expr.clone(), &self.xs, y);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Collects the inputs of an expression merging pending expressions.
+struct Builder<T> {
+    xs: Vec<ComputedNDA<T>>,
+    /// The pending inputs merged with their expressions over `xs` and their shapes,
+    /// so that an input taken twice is shared.
+    merged: Vec<(ComputedNDA<T>, Arc<Expr>, Vec<usize>)>,
+}
+
+impl<T: Float> Builder<T> {
+    fn new() -> Self {
+        Builder {
+            xs: vec![],
+            merged: vec![],
+        }
+    }
+
+    fn input(&mut self, x: &ComputedNDA<T>) -> (Arc<Expr>, Vec<usize>) {
+        if let Some((_, expr, shape)) = self.merged.iter().find(|(y, ..)| y == x) {
+            return (expr.clone(), shape.clone());
+        }
+        let pending = x
+            .with_pending(|p| {
+                p.as_any()
+                    .downcast_ref::<PendingFused<T>>()
+                    .filter(|p| p.grad_enabled == is_grad_enabled())
+                    .map(|p| (p.expr.clone(), p.xs.clone(), p.shape.clone()))
+            })
+            .flatten();
+        match pending {
+            Some((expr, xs, shape)) => {
+                let indices: Vec<_> = xs.iter().map(|x| self.leaf(x)).collect();
+                // the same inputs keep the nodes shared with `x`
+                let expr = if indices.iter().enumerate().all(|(i, j)| i == *j) {
+                    expr
+                } else {
+                    expr.map_inputs(&|i| indices[i], &mut HashMap::new())
+                };
+                self.merged.push((x.clone(), expr.clone(), shape.clone()));
+                (expr, shape)
+            }
+            None => (Arc::new(Expr::Input(self.leaf(x))), x.shape().to_vec()),
+        }
+    }
+
+    fn leaf(&mut self, x: &ComputedNDA<T>) -> usize {
+        self.xs.iter().position(|y| y == x).unwrap_or_else(|| {
+            self.xs.push(x.clone());
+            self.xs.len() - 1
+        })
+    }
+
+    fn finish(self, expr: Expr, shape: Vec<usize>) -> ComputedNDA<T> {
+        ComputedNDA::pending(Box::new(PendingFused {
+            expr: Arc::new(expr),
+            xs: self.xs,
+            shape,
+            grad_enabled: is_grad_enabled(),
+        }))
+    }
+}
+
+/// Records an element-wise function if lazy mode is enabled.
+pub(crate) fn lazy_unary<T: Float>(
+    x: &ComputedNDA<T>,
+    f: impl FnOnce(Arc<Expr>) -> Expr,
+) -> Option<ComputedNDA<T>> {
+    if !is_lazy() {
+        return None;
+    }
+    let mut builder = Builder::new();
+    let (x, shape) = builder.input(x);
+    Some(builder.finish(f(x), shape))
+}
+
+/// Records an element-wise function if lazy mode is enabled.
+///
+/// Returns `None` if the shapes cannot be broadcast so that the eager function reports the error.
+pub(crate) fn lazy_binary<T: Float>(
+    a: &ComputedNDA<T>,
+    b: &ComputedNDA<T>,
+    f: impl FnOnce(Arc<Expr>, Arc<Expr>) -> Expr,
+) -> Option<ComputedNDA<T>> {
+    if !is_lazy() {
+        return None;
+    }
+    let mut builder = Builder::new();
+    let (a, a_shape) = builder.input(a);
+    let (b, b_shape) = builder.input(b);
+    let shape = broadcast_shape(&a_shape, &b_shape)?;
+    Some(builder.finish(f(a, b), shape))
+}
+
+#[test]
+fn test() {
+    let x = backprop(ndarray::array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]].into_ndarray());
+    let m = ComputedNDA::new(ndarray::array![[2.0], [5.0]].into_ndarray());
+    let g = backprop(ndarray::array![0.5, 1.0, 2.0].into_ndarray());
+    let f =
+        |x: &ComputedNDA| &(x - &m) * &g / (x.pow_const(2.0) + ComputedNDA::new(scalar(1.0))).exp();
+
+    let y_eager = f(&x);
+    let gs_eager = gradients(&[y_eager.clone()], &[x.clone(), g.clone()], false);
+
+    let y = {
+        let _guard = lazy();
+        f(&x)
+    };
+    assert!(y.is_pending());
+    assert!(!is_lazy());
+    assert!((&*y - &*y_eager).iter().all(|d| d.abs() < 1e-6));
+    assert!(!y.is_pending());
+    assert!(y.has_creator());
+
+    // everything is fused into a single function
+    let fcs = crate::graph::collect_function_calls(vec![y.clone()]);
+    let names: Vec<_> = fcs
+        .iter()
+        .map(|fc| fc.backward.get_function_name())
+        .filter(|n| n != "backprop")
+        .collect();
+    assert_eq!(names, vec!["fused"]);
+
+    let gs = gradients(&[y.clone()], &[x.clone(), g.clone()], false);
+    for (g, g_eager) in gs.iter().zip(gs_eager.iter()) {
+        assert_eq!(g.shape(), g_eager.shape());
+        assert!((&**g - &**g_eager).iter().all(|d| d.abs() < 1e-5));
+    }
+
+    // values created without grad are not merged into the graph
+    let y = {
+        let _guard = lazy();
+        let z = {
+            let _guard = no_grad();
+            &x + &x
+        };
+        &z * &x
+    };
+    let gs = gradients(&[y], &[x.clone()], false);
+    assert_eq!(&*gs[0], &(&*x * 2.0));
+}
+
+#[test]
+fn test_shared() {
+    // the shared subexpressions would make 2^100 nodes as trees
+    let x = backprop(ndarray::array![1.0, -2.0].into_ndarray());
+    let y = {
+        let _guard = lazy();
+        let mut y = x.clone();
+        for _ in 0..100 {
+            y = &y + &y;
+        }
+        y.tanh() * y
+    };
+    let scale = 2.0f32.powi(100);
+    assert_eq!(&*y, &(&*x * scale).map(|x| x.tanh() * x).into_ndarray());
+    let gs = gradients(&[y.sum([0], false)], &[x.clone()], true);
+    assert_eq!(&*gs[0], &ndarray::array![scale, -scale].into_ndarray());
+    let ggs = gradients(&[gs[0].sum([0], false)], &[x.clone()], false);
+    assert_eq!(&*ggs[0], &NDArray::zeros(&[2][..]));
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck, jvpcheck};
+
+    let expr = Expr::Div(
+        Arc::new(Expr::Tanh(Arc::new(Expr::Mul(
+            Arc::new(Expr::Input(0)),
+            Arc::new(Expr::Input(1)),
+        )))),
+        Arc::new(Expr::Add(
+            Arc::new(Expr::Powf(
+                Arc::new(Expr::Sin(Arc::new(Expr::Input(0)))),
+                2.0,
+            )),
+            Arc::new(Expr::Const(1.0)),
+        )),
+    );
+    let a = ndarray::array![[0.1, -0.2, 0.3], [0.4, 0.5, -0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0, 3.0]].into_ndarray();
+    let f = |xs: &[ComputedNDA]| fused(&expr, xs);
+    let report = gradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[a.clone(), b.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = jvpcheck(f, &[a, b], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
+
+#[test]
+fn test_layout() {
+    let expr = Expr::Sub(Arc::new(Expr::Input(0)), Arc::new(Expr::Input(1)));
+
+    // scalars
+    let y = fused(&expr, &[scalar(3.0).into(), scalar(1.0).into()]);
+    assert_eq!(&*y, &scalar(2.0));
+
+    // a transposed input and an input broadcast on the leading axes
+    let a = ndarray::Array::from_shape_fn([4, 3, 2], |(i, j, k)| (i * 6 + j * 2 + k) as f32)
+        .into_ndarray();
+    let b = ndarray::array![[1.0], [2.0], [3.0]].into_ndarray();
+    let at = a.clone().reversed_axes();
+    let y = fused(&expr, &[at.clone().into(), b.clone().into()]);
+    assert_eq!(&*y, &(&at - &b).into_ndarray());
+
+    // an input broadcast on the trailing axes
+    let c = ndarray::array![[[1.0]], [[2.0]], [[3.0]], [[4.0]]].into_ndarray();
+    let y = fused(&expr, &[a.clone().into(), c.clone().into()]);
+    assert_eq!(&*y, &(&a - &c).into_ndarray());
+
+    // more elements than a block
+    let a = NDArray::ones(&[3, 1000][..]);
+    let y = fused(&expr, &[a.clone().into(), scalar(1.0).into()]);
+    assert_eq!(&*y, &NDArray::zeros(&[3, 1000][..]));
+}
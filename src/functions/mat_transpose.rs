@@ -1,24 +1,41 @@
-use crate::*;
+use crate::{error::unwrap_or_panic, *};
 
-pub fn mat_transpose(x: &ComputedNDA) -> ComputedNDA {
-    assert!(x.shape().len() >= 2);
+pub fn mat_transpose<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_mat_transpose(x))
+}
+
+pub fn try_mat_transpose<T: Float>(x: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("mat_transpose");
+    if x.ndim() < 2 {
+        return Err(Error::IncompatibleShapes {
+            function: "mat_transpose".into(),
+            shapes: vec![x.shape().to_vec()],
+        });
+    }
     let y = ComputedNDA::new(forward(&**x));
 
-    chain(
+    let mut axes: Vec<_> = (0..x.ndim()).collect();
+    axes.swap(x.ndim() - 2, x.ndim() - 1);
+    chain_with_args(
         &[x.clone()],
         &[y.clone()],
         false,
         "mat_transpose",
+        FunctionArgs::Transpose(axes),
         |_xs, _ys, gys| {
             let gx = gys[0].mat_t();
             vec![gx]
         },
     );
 
-    y
+    forward_derivative([x.clone()], [y.clone()], |_, _, txs| {
+        vec![txs[0].as_ref().unwrap().mat_t()]
+    });
+
+    Ok(y)
 }
 
-pub fn forward(x: &NDArray) -> NDArray {
+pub fn forward<T: Float>(x: &NDArray<T>) -> NDArray<T> {
     let mut axes: Vec<_> = (0..x.shape().len()).collect();
     axes[x.shape().len() - 2..].reverse();
 
@@ -28,11 +45,27 @@ pub fn forward(x: &NDArray) -> NDArray {
 #[test]
 fn test() {
     {
-        let x = backprop(ndarray::Array::zeros([1, 2, 3]).into_ndarray());
+        let x = backprop(ndarray::Array::<f32, _>::zeros([1, 2, 3]).into_ndarray());
         let y = mat_transpose(&x);
         assert_eq!(y.shape(), &[1, 3, 2]);
 
         let grads = gradients(&[y.clone()], &[x.clone()], false);
         assert_eq!(grads[0].shape(), &[1, 2, 3]);
     }
+
+    {
+        let x = backprop(ndarray::Array::<f32, _>::zeros([3]).into_ndarray());
+        assert!(try_mat_transpose(&x).is_err());
+    }
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]]].into_ndarray();
+    let report = gradcheck(|xs| mat_transpose(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| mat_transpose(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
 }
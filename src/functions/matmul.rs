@@ -1,9 +1,37 @@
 use ndarray::{Axis, Ix2};
 
+use crate::error::unwrap_or_panic;
 use crate::functions::*;
 use crate::*;
 
-pub fn matmul(lhs: &ComputedNDA, rhs: &ComputedNDA) -> ComputedNDA {
+pub fn matmul<T: Float>(lhs: &impl MatMulLhs<T>, rhs: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_matmul(lhs, rhs))
+}
+
+pub fn try_matmul<T: Float>(
+    lhs: &impl MatMulLhs<T>,
+    rhs: &ComputedNDA<T>,
+) -> Result<ComputedNDA<T>, Error> {
+    lhs.try_matmul_lhs(rhs)
+}
+
+/// The left operand of `matmul`: a `ComputedNDA` or a `sparse::SparseNDA`.
+pub trait MatMulLhs<T: Float> {
+    fn try_matmul_lhs(&self, rhs: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error>;
+}
+
+impl<T: Float> MatMulLhs<T> for ComputedNDA<T> {
+    fn try_matmul_lhs(&self, rhs: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+        try_dense_matmul(self, rhs)
+    }
+}
+
+fn try_dense_matmul<T: Float>(
+    lhs: &ComputedNDA<T>,
+    rhs: &ComputedNDA<T>,
+) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("matmul");
+    check_shapes("matmul", lhs.shape(), rhs.shape())?;
     let y = ComputedNDA::new(forward(&lhs, &rhs));
 
     chain(
@@ -14,16 +42,51 @@ pub fn matmul(lhs: &ComputedNDA, rhs: &ComputedNDA) -> ComputedNDA {
         |xs, _, gys| {
             let x = xs[0].clone();
             let w = xs[1].clone();
-            let gx = gys[0].matmul(&w.mat_t());
-            let gw = x.mat_t().matmul(&gys[0]);
+            let mut gx = gys[0].matmul(&w.mat_t());
+            let mut gw = x.mat_t().matmul(&gys[0]);
+
+            // fit shape
+            if x.shape() != gx.shape() {
+                gx = sum_to(&gx, x.shape());
+            }
+            if w.shape() != gw.shape() {
+                gw = sum_to(&gw, w.shape());
+            }
+
             vec![gx, gw]
         },
     );
 
-    y
+    forward_derivative([lhs.clone(), rhs.clone()], [y.clone()], |xs, ys, txs| {
+        vec![sum_tangents(
+            [
+                txs[0].as_ref().map(|t| t.matmul(&xs[1])),
+                txs[1].as_ref().map(|t| xs[0].matmul(t)),
+            ],
+            ys[0].shape(),
+        )]
+    });
+
+    Ok(y)
 }
 
-pub fn forward(x0: &NDArray, x1: &NDArray) -> NDArray {
+/// Checks that `x0` and `x1` can be multiplied and returns the broadcast outer shape.
+pub(crate) fn check_shapes(
+    function: &'static str,
+    x0s: &[usize],
+    x1s: &[usize],
+) -> Result<Vec<usize>, Error> {
+    let error = || Error::IncompatibleShapes {
+        function: function.into(),
+        shapes: vec![x0s.to_vec(), x1s.to_vec()],
+    };
+    if x0s.len() < 2 || x1s.len() < 2 || x0s[x0s.len() - 1] != x1s[x1s.len() - 2] {
+        return Err(error());
+    }
+    broadcast_shape(&x0s[..x0s.len() - 2], &x1s[..x1s.len() - 2]).ok_or_else(error)
+}
+
+pub fn forward<T: Float>(x0: &NDArray<T>, x1: &NDArray<T>) -> NDArray<T> {
     // let x0 = if x0.ndim() == 1 {
     //     dbg!("a", x1.shape());
     //     // extend axis if x0 is 1d
@@ -35,22 +98,7 @@ pub fn forward(x0: &NDArray, x1: &NDArray) -> NDArray {
     // 行列同士の積に限定する
     let x0s = x0.shape();
     let x1s = x1.shape();
-    assert!(2 <= x0s.len());
-    assert!(2 <= x1s.len());
-    assert_eq!(
-        x0s[x0s.len() - 1],
-        x1s[x1s.len() - 2],
-        "lhs's width must be equal to rhs's height, lhs: {:?}, rhs: {:?}",
-        x0s,
-        x1s
-    );
-
-    let outer_shape =
-        broadcast_shape(&x0s[..x0s.len() - 2], &x1s[..x1s.len() - 2]).expect(&format!(
-            "Matmul: shape mismatch: {:?} and {:?}",
-            x0.shape(),
-            x1.shape()
-        ));
+    let outer_shape = unwrap_or_panic(check_shapes("matmul", x0s, x1s));
     let mat_shape = [x0s[x0s.len() - 2], x1s[x1s.len() - 1]];
 
     if outer_shape.is_empty() {
@@ -122,30 +170,16 @@ pub fn forward(x0: &NDArray, x1: &NDArray) -> NDArray {
     }
 }
 
-pub fn backward(x: &NDArray, w: &NDArray, gy: &NDArray) -> (NDArray, NDArray) {
+pub fn backward<T: Float>(
+    x: &NDArray<T>,
+    w: &NDArray<T>,
+    gy: &NDArray<T>,
+) -> (NDArray<T>, NDArray<T>) {
     let gx = forward(gy, &mat_transpose::forward(w));
     let gw = forward(&mat_transpose::forward(x), gy);
     (gx, gw)
 }
 
-fn broadcast_shape(a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
-    (0..a.len().max(b.len()))
-        .rev()
-        .map(|i| {
-            let a = a.get(i).copied().unwrap_or(1);
-            let b = b.get(i).copied().unwrap_or(1);
-            if a == b || b == 1 {
-                Some(a)
-            } else if a == 1 {
-                Some(b)
-            } else {
-                None
-            }
-        })
-        .rev()
-        .collect()
-}
-
 #[test]
 fn test() {
     {
@@ -189,4 +223,40 @@ fn test() {
         let y = matmul(&b, &a);
         assert_eq!(&y.shape(), &[2, 3, 5, 5]);
     }
+
+    {
+        let a = backprop(<NDArray>::zeros(&[2, 3][..]));
+        let b = backprop(<NDArray>::zeros(&[2, 3][..]));
+        assert_eq!(
+            try_matmul(&a, &b).err(),
+            Some(Error::IncompatibleShapes {
+                function: "matmul".into(),
+                shapes: vec![vec![2, 3], vec![2, 3]],
+            })
+        );
+    }
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0], [3.0, 0.5], [-1.5, 2.5]].into_ndarray();
+    let c = ndarray::array![
+        [[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]],
+        [[0.7, -0.8, 0.9], [1.0, 1.1, -1.2]]
+    ]
+    .into_ndarray();
+    let f = |xs: &[ComputedNDA]| matmul(&xs[0], &xs[1]);
+    for inputs in [
+        [a, b.clone()],
+        [c.clone(), b],
+        [c.clone(), mat_transpose::forward(&c)],
+    ] {
+        let report = gradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
 }
@@ -1,7 +1,29 @@
+use crate::error::unwrap_or_panic;
 use crate::functions::*;
 use crate::*;
 
-pub fn matmul_add(x0: &ComputedNDA, x1: &ComputedNDA, x2: &ComputedNDA) -> ComputedNDA {
+pub fn matmul_add<T: Float>(
+    x0: &ComputedNDA<T>,
+    x1: &ComputedNDA<T>,
+    x2: &ComputedNDA<T>,
+) -> ComputedNDA<T> {
+    unwrap_or_panic(try_matmul_add(x0, x1, x2))
+}
+
+pub fn try_matmul_add<T: Float>(
+    x0: &ComputedNDA<T>,
+    x1: &ComputedNDA<T>,
+    x2: &ComputedNDA<T>,
+) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("matmul_add");
+    let x0s = x0.shape();
+    let x1s = x1.shape();
+    let y_shape: Vec<_> = matmul::check_shapes("matmul_add", x0s, x1s)?
+        .into_iter()
+        .chain([x0s[x0s.len() - 2], x1s[x1s.len() - 1]])
+        .collect();
+    check_broadcast_shapes("matmul_add", &y_shape, x2.shape())?;
+
     let y = matmul::forward(&x0, &x1);
     let y = y + &**x2;
     let y = ComputedNDA::new(y);
@@ -12,21 +34,42 @@ pub fn matmul_add(x0: &ComputedNDA, x1: &ComputedNDA, x2: &ComputedNDA) -> Compu
         false,
         "matmul_add",
         move |xs, _, gys| {
-            let gx0 = matmul(&gys[0], &mat_transpose(&xs[1]));
-            let gx1 = matmul(&mat_transpose(&xs[0]), &gys[0]);
+            let mut gx0 = matmul(&gys[0], &mat_transpose(&xs[1]));
+            let mut gx1 = matmul(&mat_transpose(&xs[0]), &gys[0]);
 
             let mut gx2 = gys[0].clone();
 
             // fit shape
+            if xs[0].shape() != gx0.shape() {
+                gx0 = sum_to(&gx0, xs[0].shape());
+            }
+            if xs[1].shape() != gx1.shape() {
+                gx1 = sum_to(&gx1, xs[1].shape());
+            }
             if xs[2].shape() != gx2.shape() {
-                gx2 = sum(&gx2, sum_axes_to_desire(gx2.shape(), xs[2].shape()), false);
+                gx2 = sum_to(&gx2, xs[2].shape());
             }
 
-            vec![gx0.into(), gx1.into(), gx2]
+            vec![gx0, gx1, gx2]
+        },
+    );
+
+    forward_derivative(
+        [x0.clone(), x1.clone(), x2.clone()],
+        [y.clone()],
+        |xs, ys, txs| {
+            vec![sum_tangents(
+                [
+                    txs[0].as_ref().map(|t| matmul(t, &xs[1])),
+                    txs[1].as_ref().map(|t| matmul(&xs[0], t)),
+                    txs[2].clone(),
+                ],
+                ys[0].shape(),
+            )]
         },
     );
 
-    y
+    Ok(y)
 }
 
 #[test]
@@ -61,3 +104,24 @@ fn test() {
 
     gradients(&[y], &[a, b, c], false);
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let a = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let b = ndarray::array![[1.0, -2.0], [3.0, 0.5], [-1.5, 2.5]].into_ndarray();
+    let c = ndarray::array![0.5, -1.0].into_ndarray();
+    let d = ndarray::array![
+        [[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]],
+        [[0.7, -0.8, 0.9], [1.0, 1.1, -1.2]]
+    ]
+    .into_ndarray();
+    let f = |xs: &[ComputedNDA]| matmul_add(&xs[0], &xs[1], &xs[2]);
+    for inputs in [[a, b.clone(), c.clone()], [d, b, c]] {
+        let report = gradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(f, &inputs, 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
+}
@@ -2,10 +2,11 @@ use ndarray::{prelude::*, Zip};
 
 use crate::*;
 
-pub fn max(axis: usize, x: &ComputedNDA) -> ComputedNDA {
-    let y: ComputedNDA = x
+pub fn max<T: Float>(axis: usize, x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("max");
+    let y: ComputedNDA<T> = x
         .map_axis(Axis(axis), |x| {
-            x.iter().fold(f32::NEG_INFINITY, |a, b| a.max(*b))
+            x.iter().fold(T::neg_infinity(), |a, b| a.max(*b))
         })
         .into_ndarray()
         .into();
@@ -17,24 +18,38 @@ pub fn max(axis: usize, x: &ComputedNDA) -> ComputedNDA {
         "max",
         move |xs, ys, gys| {
             let shape = max_backward_shape(&xs[0], &[axis]);
-            let mut mask = (*xs[0]).to_owned();
-            Zip::from(&mut mask)
-                .and(
-                    &ys[0]
-                        .view()
-                        .into_shape(shape.clone())
-                        .unwrap()
-                        .broadcast(xs[0].shape().to_vec())
-                        .unwrap(),
-                )
-                .for_each(|m, y| *m = if *m == *y { 1.0 } else { 0.0 });
-            vec![&ComputedNDA::new(mask.into_ndarray()) * &gys[0].reshape(shape.clone())]
+            vec![&max_mask(&xs[0], &ys[0], axis) * &gys[0].reshape(shape)]
         },
     );
+
+    forward_derivative([x.clone()], [y.clone()], move |xs, ys, txs| {
+        let mask = max_mask(&xs[0], &ys[0], axis);
+        vec![(&mask * txs[0].as_ref().unwrap()).sum([axis], false)]
+    });
+
     y
 }
 
-fn max_backward_shape(x: &NDArray, axes: &[usize]) -> Vec<usize> {
+/// Returns 1 where `x` takes the maximum `y` and 0 elsewhere.
+///
+/// The mask is piecewise constant in `x`, so it is kept out of the graph
+/// without breaking higher order derivatives.
+fn max_mask<T: Float>(x: &NDArray<T>, y: &NDArray<T>, axis: usize) -> ComputedNDA<T> {
+    let shape = max_backward_shape(x, &[axis]);
+    let mut mask = x.to_owned();
+    Zip::from(&mut mask)
+        .and(
+            &y.view()
+                .into_shape(shape)
+                .unwrap()
+                .broadcast(x.shape().to_vec())
+                .unwrap(),
+        )
+        .for_each(|m, y| *m = if *m == *y { T::one() } else { T::zero() });
+    ComputedNDA::new(mask.into_ndarray())
+}
+
+fn max_backward_shape<T: Float>(x: &NDArray<T>, axes: &[usize]) -> Vec<usize> {
     x.shape()
         .iter()
         .enumerate()
@@ -57,3 +72,16 @@ fn test() {
     let grads = gradients(&[y], &[x.clone()], false);
     assert_eq!(&*grads[0], &array![[0., 1.], [0., 1.]].into_ndarray());
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = array![[0.1, -0.5, 0.3], [0.4, 0.2, 0.6]].into_ndarray();
+    for axis in [0, 1] {
+        let report = gradcheck(|xs| max(axis, &xs[0]), &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(|xs| max(axis, &xs[0]), &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
+}
@@ -2,6 +2,7 @@ mod broadcast;
 mod concat;
 pub mod element_wise;
 mod fft;
+mod fused;
 pub mod mat_transpose;
 mod matmul;
 mod matmul_add;
@@ -9,6 +10,7 @@ mod max;
 mod reshape;
 mod select;
 mod slice;
+mod sparse_matmul;
 mod sum;
 mod t;
 mod transpose;
@@ -18,12 +20,15 @@ pub use broadcast::*;
 pub use concat::*;
 pub use element_wise::*;
 pub use fft::Fft;
-pub use mat_transpose::mat_transpose;
-pub use matmul::{backward as matmul_backward, matmul};
-pub use matmul_add::matmul_add;
+pub use fused::{fused, try_fused, Expr};
+pub(crate) use fused::{lazy_binary, lazy_unary};
+pub use mat_transpose::{mat_transpose, try_mat_transpose};
+pub use matmul::{backward as matmul_backward, matmul, try_matmul, MatMulLhs};
+pub use matmul_add::{matmul_add, try_matmul_add};
 pub use reshape::*;
 pub use select::*;
 pub use slice::*;
+pub use sparse_matmul::*;
 pub use sum::*;
 pub use t::*;
 pub use transpose::*;
@@ -1,21 +1,44 @@
-use crate::*;
+use crate::{error::unwrap_or_panic, *};
 
-pub fn reshape(x: &ComputedNDA, shape: impl Into<Vec<usize>>) -> ComputedNDA {
+pub fn reshape<T: Clone + Send + Sync + 'static>(
+    x: &Computed<NDArray<T>>,
+    shape: impl Into<Vec<usize>>,
+) -> Computed<NDArray<T>> {
+    unwrap_or_panic(try_reshape(x, shape))
+}
+
+pub fn try_reshape<T: Clone + Send + Sync + 'static>(
+    x: &Computed<NDArray<T>>,
+    shape: impl Into<Vec<usize>>,
+) -> Result<Computed<NDArray<T>>, Error> {
+    let _scope = profile_scope("reshape");
     let shape = shape.into();
-    let y = ComputedNDA::new((**x).reshape(shape.as_slice()));
+    if x.len() != shape.iter().product::<usize>() {
+        return Err(Error::IllegalReshape {
+            function: "reshape".into(),
+            from: x.shape().to_vec(),
+            to: shape,
+        });
+    }
+    let y = Computed::new((**x).reshape(shape.as_slice()));
 
-    chain(
+    forward_derivative([x.clone()], [y.clone()], |_, ys, txs| {
+        vec![reshape(txs[0].as_ref().unwrap(), ys[0].shape())]
+    });
+
+    chain_with_args(
         &[x.clone()],
         &[y.clone()],
         false,
         "reshape",
+        FunctionArgs::Reshape(shape),
         move |xs, _ys, gys| {
-            let gx = gys[0].reshape(xs[0].shape());
+            let gx = reshape(&gys[0], xs[0].shape());
             vec![gx]
         },
     );
 
-    y
+    Ok(y)
 }
 
 #[test]
@@ -28,5 +51,30 @@ fn test() {
 
         let grads = gradients(&[y], &[x.clone()], false);
         assert_eq!(grads[0].shape(), &[2, 3]);
+
+        assert_eq!(
+            try_reshape(&x, vec![4, 2]).err(),
+            Some(Error::IllegalReshape {
+                function: "reshape".into(),
+                from: vec![2, 3],
+                to: vec![4, 2],
+            })
+        );
     }
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let report = gradcheck(
+        |xs| reshape(&xs[0], vec![3, 1, 2]),
+        &[x.clone()],
+        1e-3,
+        1e-2,
+    );
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| reshape(&xs[0], vec![3, 1, 2]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
@@ -4,24 +4,82 @@ use ndarray::Axis;
 
 use crate::*;
 
-pub fn select(axis: usize, indices: Vec<usize>, x: &ComputedNDA) -> ComputedNDA {
+pub fn select<T: Float>(axis: usize, indices: Vec<usize>, x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("select");
     let y = ComputedNDA::new(x.select(Axis(axis), &indices).into_ndarray());
 
+    let indices_ = indices.clone();
+    forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+        vec![select(axis, indices_, txs[0].as_ref().unwrap())]
+    });
+
     chain(
         &[x.clone()],
         &[y.clone()],
         false,
         "select",
-        move |xs, ys, gys| {
-            let _ = ys;
-            let mut gx = NDArray::zeros(xs[0].shape());
-            for i in 0..indices.len() {
-                gx.index_axis_mut(Axis(axis), indices[i])
-                    .add_assign(&gys[0].index_axis(Axis(0), i));
-            }
-            vec![ComputedNDA::new(gx)]
+        move |xs, _ys, gys| {
+            vec![select_backward(
+                axis,
+                indices.clone(),
+                xs[0].shape(),
+                &gys[0],
+            )]
         },
     );
 
     y
 }
+
+/// Adds `gy` to the zeros of `shape` at `indices`, the adjoint of `select`.
+fn select_backward<T: Float>(
+    axis: usize,
+    indices: Vec<usize>,
+    shape: &[usize],
+    gy: &ComputedNDA<T>,
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("select_backward");
+    let mut gx = NDArray::zeros(shape);
+    for i in 0..indices.len() {
+        gx.index_axis_mut(Axis(axis), indices[i])
+            .add_assign(&gy.index_axis(Axis(axis), i));
+    }
+    let gx = ComputedNDA::new(gx);
+
+    let indices_ = indices.clone();
+    forward_derivative([gy.clone()], [gx.clone()], move |_, ys, txs| {
+        vec![select_backward(
+            axis,
+            indices_,
+            ys[0].shape(),
+            txs[0].as_ref().unwrap(),
+        )]
+    });
+
+    chain(
+        std::slice::from_ref(gy),
+        std::slice::from_ref(&gx),
+        false,
+        "select_backward",
+        move |_xs, _ys, gys| vec![select(axis, indices.clone(), &gys[0])],
+    );
+
+    gx
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let f = |xs: &[ComputedNDA]| select(1, vec![2, 0, 2], &xs[0]);
+    let report = gradcheck(f, &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let f = |xs: &[ComputedNDA]| select(0, vec![1, 1], &xs[0]);
+    let report = gradcheck(f, &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
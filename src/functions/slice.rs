@@ -4,54 +4,122 @@ use ndarray::{IxDyn, SliceArg};
 
 use crate::*;
 
-pub fn slice<I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
-    x: &ComputedNDA,
+pub fn slice<T: Float, I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
+    x: &ComputedNDA<T>,
     slice_arg: I,
-) -> ComputedNDA {
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("slice");
     let y = (&**x).slice(slice_arg.clone());
     let y = ComputedNDA::new(y.into_ndarray());
 
+    let slice_arg_ = slice_arg.clone();
+    forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+        vec![slice(txs[0].as_ref().unwrap(), slice_arg_)]
+    });
+
     chain(
         &[x.clone()],
         &[y.clone()],
         false,
         "slice",
-        move |xs, ys, gys| {
-            let x = &*xs[0];
-            let mut gx = NDArray::zeros(x.shape()); // TODO: Too large tensor!
-            gx.slice_mut(slice_arg.clone())
-                .assign(&(*gys[0]).reshape(ys[0].shape()));
-            vec![ComputedNDA::new(gx)]
-        },
+        move |xs, _ys, gys| vec![slice_backward(xs[0].shape(), slice_arg.clone(), &gys[0])],
     );
 
     y
     // Slice::new(slice_arg.clone()).forward(&[x.clone()]).pop().unwrap()
 }
 
-pub fn slices<I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
-    x: &ComputedNDA,
+pub fn slices<T: Float, I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
+    x: &ComputedNDA<T>,
     slice_args: Vec<I>,
-) -> Vec<ComputedNDA> {
+) -> Vec<ComputedNDA<T>> {
+    let _scope = profile_scope("slices");
     let xa = &**x;
     let ys: Vec<_> = slice_args
         .iter()
         .map(|slice_arg| xa.slice(slice_arg.clone()).into_ndarray().into())
         .collect();
 
-    chain(&[x.clone()], &ys, false, "slices", move |xs, ys, gys| {
-        let x = &*xs[0];
-        let mut gx = NDArray::zeros(x.shape());
-        for i in 0..xs.len() {
-            gx.slice_mut(slice_args[i].clone())
-                .add_assign(&(*gys[i]).reshape(ys[i].shape()));
-        }
-        vec![ComputedNDA::new(gx)]
+    let slice_args_ = slice_args.clone();
+    forward_derivative([x.clone()], &ys, move |_, _, txs| {
+        slices(txs[0].as_ref().unwrap(), slice_args_)
+    });
+
+    chain(&[x.clone()], &ys, false, "slices", move |xs, _ys, gys| {
+        vec![slices_backward(xs[0].shape(), slice_args.clone(), gys)]
     });
 
     ys
 }
 
+/// Pads `gy` with zeros to `shape`, the adjoint of `slice`.
+fn slice_backward<T: Float, I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
+    shape: &[usize],
+    slice_arg: I,
+    gy: &ComputedNDA<T>,
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("slice_backward");
+    let mut gx = NDArray::zeros(shape); // TODO: Too large tensor!
+    gx.slice_mut(slice_arg.clone()).assign(&**gy);
+    let gx = ComputedNDA::new(gx);
+
+    let slice_arg_ = slice_arg.clone();
+    forward_derivative([gy.clone()], [gx.clone()], move |_, ys, txs| {
+        vec![slice_backward(
+            ys[0].shape(),
+            slice_arg_,
+            txs[0].as_ref().unwrap(),
+        )]
+    });
+
+    chain(
+        std::slice::from_ref(gy),
+        std::slice::from_ref(&gx),
+        false,
+        "slice_backward",
+        move |_xs, _ys, gys| vec![slice(&gys[0], slice_arg.clone())],
+    );
+
+    gx
+}
+
+/// Adds up `gys` padded with zeros to `shape`, the adjoint of `slices`.
+fn slices_backward<T: Float, I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
+    shape: &[usize],
+    slice_args: Vec<I>,
+    gys: &[ComputedNDA<T>],
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("slices_backward");
+    let mut gx = NDArray::zeros(shape);
+    for (slice_arg, gy) in slice_args.iter().zip(gys) {
+        gx.slice_mut(slice_arg.clone()).add_assign(&**gy);
+    }
+    let gx = ComputedNDA::new(gx);
+
+    let slice_args_ = slice_args.clone();
+    forward_derivative(gys, [gx.clone()], move |xs, ys, txs| {
+        let txs: Vec<_> = xs
+            .iter()
+            .zip(txs)
+            .map(|(x, t)| {
+                t.clone()
+                    .unwrap_or_else(|| ComputedNDA::new(NDArray::zeros(x.shape())))
+            })
+            .collect();
+        vec![slices_backward(ys[0].shape(), slice_args_, &txs)]
+    });
+
+    chain(
+        gys,
+        std::slice::from_ref(&gx),
+        false,
+        "slices_backward",
+        move |_xs, _ys, gys| slices(&gys[0], slice_args.clone()),
+    );
+
+    gx
+}
+
 // TODO:
 // #[test]
 // fn test_slices() {
@@ -61,3 +129,23 @@ pub fn slices<I: SliceArg<IxDyn> + Clone + Sync + Send + 'static>(
 //     drop(ys);
 //     optimize(&y0);
 // }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+    use ndarray::s;
+
+    let x = ndarray::array![[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]].into_ndarray();
+    let report = gradcheck(|xs| slice(&xs[0], s![.., 1..]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| slice(&xs[0], s![.., 1..]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let f = |xs: &[ComputedNDA]| {
+        let ys = slices(&xs[0], vec![s![0, ..], s![1, ..]]);
+        &ys[0] * &ys[1]
+    };
+    let report = gradcheck(f, &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
@@ -0,0 +1,135 @@
+use crate::error::unwrap_or_panic;
+use crate::functions::*;
+use crate::sparse::{matmul_forward, SparseNDA};
+use crate::*;
+
+/// Multiplies the sparse matrix `x` and the dense matrix `w`.
+/// Also available as `functions::matmul(&x, &w)`.
+pub fn sparse_matmul<T: Float>(x: &SparseNDA<T>, w: &ComputedNDA<T>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_sparse_matmul(x, w))
+}
+
+pub fn try_sparse_matmul<T: Float>(
+    x: &SparseNDA<T>,
+    w: &ComputedNDA<T>,
+) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("sparse_matmul");
+    if w.ndim() != 2 || x.shape()[1] != w.shape()[0] {
+        return Err(Error::IncompatibleShapes {
+            function: "sparse_matmul".into(),
+            shapes: vec![x.shape().to_vec(), w.shape().to_vec()],
+        });
+    }
+    let y = ComputedNDA::new(matmul_forward(&x.pattern, &x.values, w));
+
+    // The values are an input only if their gradients are required.
+    let inputs = if x.requires_grad {
+        vec![x.values.clone(), w.clone()]
+    } else {
+        vec![w.clone()]
+    };
+
+    let x_ = x.clone();
+    forward_derivative(&inputs, [y.clone()], move |xs, ys, txs| {
+        let (tv, tw) = if x_.requires_grad {
+            (txs[0].as_ref(), txs[1].as_ref())
+        } else {
+            (None, txs[0].as_ref())
+        };
+        let w = xs.last().unwrap();
+        vec![sum_tangents(
+            [
+                tv.map(|t| sparse_matmul(&x_.with_values(t.clone()), w)),
+                tw.map(|t| sparse_matmul(&x_, t)),
+            ],
+            ys[0].shape(),
+        )]
+    });
+
+    let x = x.clone();
+    chain(
+        &inputs,
+        std::slice::from_ref(&y),
+        false,
+        "sparse_matmul",
+        move |xs, _, gys| {
+            let x = if x.requires_grad {
+                x.with_values(xs[0].clone())
+            } else {
+                x.clone()
+            };
+            let w = xs.last().unwrap();
+            let gw = sparse_matmul(&x.t(), &gys[0]);
+            if x.requires_grad {
+                let gv = sum(
+                    &(&select(0, x.pattern.rows.clone(), &gys[0])
+                        * &select(0, x.pattern.indices.clone(), w)),
+                    [1],
+                    false,
+                );
+                vec![gv, gw]
+            } else {
+                vec![gw]
+            }
+        },
+    );
+
+    Ok(y)
+}
+
+impl<T: Float> MatMulLhs<T> for SparseNDA<T> {
+    fn try_matmul_lhs(&self, rhs: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+        try_sparse_matmul(self, rhs)
+    }
+}
+
+#[test]
+fn test() {
+    use crate::sparse::Csr;
+
+    let dense = ndarray::array![[0.0, 2.0, 0.0], [0.0, 0.0, 0.0], [-1.0, 0.0, 3.0]].into_ndarray();
+    let w = backprop(ndarray::array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]].into_ndarray());
+
+    let x = SparseNDA::backprop(Csr::from_dense(&dense));
+    let y = matmul(&x, &w);
+    assert_eq!(&*y, &*matmul(&ComputedNDA::new(dense.clone()), &w));
+
+    let gs = gradients(&[y.clone()], &[w.clone(), x.values().clone()], false);
+    let (_, gw) = matmul_backward(&dense, &w, &NDArray::ones(y.shape()));
+    assert_eq!(&*gs[0], &gw);
+    // [0, 1], [2, 0] and [2, 2] of the row sums of `w`
+    assert_eq!(&*gs[1], &ndarray::array![7.0, 3.0, 11.0].into_ndarray());
+
+    // The values are not differentiated.
+    let x = SparseNDA::new(Csr::from_dense(&dense));
+    let y = matmul(&x, &w);
+    let gs = gradients(&[y], &[w.clone()], false);
+    assert_eq!(&*gs[0], &gw);
+
+    assert_eq!(
+        try_sparse_matmul(&x, &w.t()).err(),
+        Some(Error::IncompatibleShapes {
+            function: "sparse_matmul".into(),
+            shapes: vec![vec![3, 3], vec![2, 3]],
+        })
+    );
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+    use crate::sparse::Coo;
+
+    let mut coo = Coo::new([2, 3]);
+    coo.push(0, 1, 0.0);
+    coo.push(1, 0, 0.0);
+    coo.push(1, 2, 0.0);
+    let x = SparseNDA::<f64>::new(coo);
+    let v = ndarray::array![0.5, -1.5, 2.0].into_ndarray();
+    let w = ndarray::array![[1.0, -2.0], [3.0, 0.5], [-1.5, 2.5]].into_ndarray();
+    let f = move |xs: &[ComputedNDA<f64>]| matmul(&x.with_values(xs[0].clone()), &xs[1]);
+    let report = gradcheck(&f, &[v.clone(), w.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(&f, &[v, w], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
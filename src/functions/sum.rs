@@ -1,17 +1,30 @@
 use ndarray::Axis;
-use ndarray::{ArrayBase, OwnedArcRepr};
 use ndarray_rand::rand_distr::num_traits;
 
-use crate::*;
-
-type NDArray<T> = ArrayBase<OwnedArcRepr<T>, ndarray::IxDyn>;
+use crate::{error::unwrap_or_panic, *};
 
 pub fn sum<T: Clone + std::ops::Add<Output = T> + num_traits::Zero + Send + Sync + 'static>(
     x: &Computed<NDArray<T>>,
     axes: impl Into<Vec<usize>>,
     keep_dim: bool,
 ) -> Computed<NDArray<T>> {
+    unwrap_or_panic(try_sum(x, axes, keep_dim))
+}
+
+pub fn try_sum<T: Clone + std::ops::Add<Output = T> + num_traits::Zero + Send + Sync + 'static>(
+    x: &Computed<NDArray<T>>,
+    axes: impl Into<Vec<usize>>,
+    keep_dim: bool,
+) -> Result<Computed<NDArray<T>>, Error> {
+    let _scope = profile_scope("sum");
     let axes = axes.into();
+    if !axes.windows(2).all(|w| w[0] < w[1]) || axes.iter().any(|a| *a >= x.ndim()) {
+        return Err(Error::IllegalAxes {
+            function: "sum".into(),
+            shape: x.shape().to_vec(),
+            axes,
+        });
+    }
     let mut y = (**x).to_owned();
     for axis in axes.iter().rev() {
         y = y.sum_axis(Axis(*axis));
@@ -21,19 +34,41 @@ pub fn sum<T: Clone + std::ops::Add<Output = T> + num_traits::Zero + Send + Sync
     }
     let y = Computed::new(y.into_shared().into_dyn());
 
-    chain(
+    let axes_ = axes.clone();
+    forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+        vec![sum(txs[0].as_ref().unwrap(), axes_, keep_dim)]
+    });
+
+    let args = FunctionArgs::Sum {
+        axes: axes.clone(),
+        keep_dim,
+    };
+    chain_with_args(
         &[x.clone()],
         &[y.clone()],
         false,
         "sum",
+        args,
         move |xs, _ys, gys| {
-            let gx = super::broadcast(&gys[0], xs[0].shape());
+            let mut shape = xs[0].shape().to_vec();
+            for axis in &axes {
+                shape[*axis] = 1;
+            }
+            let gx = super::broadcast(&super::reshape(&gys[0], shape), xs[0].shape());
 
             vec![gx]
         },
     );
 
-    y
+    Ok(y)
+}
+
+/// Sums `x` to `shape` that `x` was broadcast from.
+pub fn sum_to<T: Float>(x: &ComputedNDA<T>, shape: &[usize]) -> ComputedNDA<T> {
+    if x.shape() == shape {
+        return x.clone();
+    }
+    sum(x, sum_axes_to_desire(x.shape(), shape), false).reshape(shape)
 }
 
 pub fn sum_axes_to_desire(src_shape: &[usize], dst_shape: &[usize]) -> Vec<usize> {
@@ -70,4 +105,24 @@ fn test() {
         assert_eq!(y.shape(), &[2]);
         assert_eq!(&*y, &ndarray::array![6., 15.].into_ndarray());
     }
+
+    {
+        let x = ComputedNDA::new(ndarray::array![[1., 2., 3.], [4., 5., 6.]].into_ndarray());
+        assert!(try_sum(&x, vec![2], false).is_err());
+        assert!(try_sum(&x, vec![1, 0], false).is_err());
+    }
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]]].into_ndarray();
+    for keep_dim in [false, true] {
+        let f = |xs: &[ComputedNDA]| sum(&xs[0], vec![0, 2], keep_dim);
+        let report = gradcheck(f, &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+        let report = gradgradcheck(f, &[x.clone()], 1e-3, 1e-2);
+        assert!(report.passed, "{}", report);
+    }
 }
@@ -1,19 +1,25 @@
 use crate::*;
 
-pub fn t(x: &ComputedNDA) -> ComputedNDA {
+pub fn t<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("t");
     let y = ComputedNDA::new((**x).t().into_ndarray());
 
-    chain(
+    chain_with_args(
         &[x.clone()],
         &[y.clone()],
         false,
         "t",
+        FunctionArgs::Transpose((0..x.ndim()).rev().collect()),
         move |_xs, _ys, gys| {
             let gx = gys[0].t();
             vec![gx]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |_, _, txs| {
+        vec![txs[0].as_ref().unwrap().t()]
+    });
+
     y
 }
 
@@ -31,3 +37,14 @@ fn test() {
         assert_eq!(&y.shape(), &[3, 2, 1]);
     }
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]]].into_ndarray();
+    let report = gradcheck(|xs| t(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| t(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
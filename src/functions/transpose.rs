@@ -1,16 +1,36 @@
-use crate::*;
+use crate::{error::unwrap_or_panic, *};
 
-pub fn transpose(x: &ComputedNDA, axes: impl Into<Vec<usize>>) -> ComputedNDA {
+pub fn transpose<T: Float>(x: &ComputedNDA<T>, axes: impl Into<Vec<usize>>) -> ComputedNDA<T> {
+    unwrap_or_panic(try_transpose(x, axes))
+}
+
+pub fn try_transpose<T: Float>(
+    x: &ComputedNDA<T>,
+    axes: impl Into<Vec<usize>>,
+) -> Result<ComputedNDA<T>, Error> {
+    let _scope = profile_scope("transpose");
     let axes = axes.into();
-    assert!((0..axes.len()).all(|i| axes.contains(&i)));
+    if axes.len() != x.ndim() || !(0..axes.len()).all(|i| axes.contains(&i)) {
+        return Err(Error::IllegalAxes {
+            function: "transpose".into(),
+            shape: x.shape().to_vec(),
+            axes,
+        });
+    }
 
     let y = ComputedNDA::new(x.view().permuted_axes(&*axes).into_ndarray());
 
-    chain(
+    let axes_ = axes.clone();
+    forward_derivative([x.clone()], [y.clone()], move |_, _, txs| {
+        vec![transpose(txs[0].as_ref().unwrap(), axes_)]
+    });
+
+    chain_with_args(
         &[x.clone()],
         &[y.clone()],
         false,
         "transpose",
+        FunctionArgs::Transpose(axes.clone()),
         move |_xs, _ys, gys| {
             let gx = gys[0].transpose(
                 (0..axes.len())
@@ -21,17 +41,36 @@ pub fn transpose(x: &ComputedNDA, axes: impl Into<Vec<usize>>) -> ComputedNDA {
         },
     );
 
-    y
+    Ok(y)
 }
 
 #[test]
 fn test() {
     {
-        let x = backprop(ndarray::Array::zeros([1, 2, 3]).into_ndarray());
+        let x = backprop(ndarray::Array::<f32, _>::zeros([1, 2, 3]).into_ndarray());
         let y = transpose(&x, vec![1, 2, 0]);
         assert_eq!(y.shape(), &[2, 3, 1]);
 
-        let grads = gradients(&[y], &[x], false);
+        let grads = gradients(&[y], &[x.clone()], false);
         assert_eq!(grads[0].shape(), &[1, 2, 3]);
+
+        assert!(try_transpose(&x, vec![1, 0]).is_err());
+        assert!(try_transpose(&x, vec![0, 1, 1]).is_err());
     }
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[[0.1, 0.2, 0.3], [0.4, 0.5, 0.6]]].into_ndarray();
+    let report = gradcheck(
+        |xs| transpose(&xs[0], vec![1, 2, 0]),
+        &[x.clone()],
+        1e-3,
+        1e-2,
+    );
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| transpose(&xs[0], vec![1, 2, 0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
@@ -0,0 +1,261 @@
+use ndarray::Dimension;
+use ndarray_rand::{rand::SeedableRng, rand_distr::Uniform, RandomExt};
+
+use crate::*;
+
+/// The worst element found by `gradcheck`.
+#[derive(Debug, Clone)]
+pub struct GradcheckReport {
+    pub passed: bool,
+    /// Index of the input that contains the worst element.
+    pub input: usize,
+    /// Index of the worst element in the input.
+    pub index: Vec<usize>,
+    pub analytical: f64,
+    pub numerical: f64,
+    /// `|analytical - numerical| / max(1, |numerical|)`
+    pub error: f64,
+}
+
+impl std::fmt::Display for GradcheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "input {} at {:?}: analytical {}, numerical {}, error {}",
+            self.input, self.index, self.analytical, self.numerical, self.error
+        )
+    }
+}
+
+/// Compares the gradients computed by `gradients` with central finite differences.
+///
+/// The output of `f` is reduced to a scalar by a weighted sum with fixed random weights,
+/// so outputs whose plain sum is constant (e.g. softmax) are checked as well.
+/// Finite differences are accumulated in f64.
+pub fn gradcheck<T: Float>(
+    f: impl Fn(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    inputs: &[NDArray<T>],
+    eps: f32,
+    tol: f32,
+) -> GradcheckReport {
+    let xs: Vec<_> = inputs.iter().map(|x| backprop(x.clone())).collect();
+    let y = f(&xs);
+    let w = random_array(y.shape(), 0);
+
+    let loss = (&y * &ComputedNDA::new(w.clone())).sum((0..y.ndim()).collect::<Vec<_>>(), false);
+    let analytical: Vec<NDArray<T>> = xs
+        .iter()
+        .map(|x| gradient_or_zeros(&loss, x, false))
+        .map(|g| (*g).clone())
+        .collect();
+
+    let eval = |xs: &[NDArray<T>]| -> f64 {
+        let xs: Vec<_> = xs.iter().map(|x| backprop(x.clone())).collect();
+        let y = f(&xs);
+        y.iter()
+            .zip(w.iter())
+            .map(|(y, w)| to_f64(*y) * to_f64(*w))
+            .sum()
+    };
+
+    let mut report = GradcheckReport {
+        passed: true,
+        input: 0,
+        index: vec![],
+        analytical: 0.0,
+        numerical: 0.0,
+        error: 0.0,
+    };
+    let eps = T::from_f32(eps);
+    let mut xs = inputs.to_vec();
+    for i in 0..inputs.len() {
+        assert_eq!(
+            analytical[i].shape(),
+            inputs[i].shape(),
+            "gradient of input {} has a wrong shape",
+            i
+        );
+        for (index, x) in inputs[i].indexed_iter() {
+            xs[i][&index] = *x + eps;
+            let fp = eval(&xs);
+            xs[i][&index] = *x - eps;
+            let fm = eval(&xs);
+            xs[i][&index] = *x;
+
+            let numerical = (fp - fm) / (to_f64(*x + eps) - to_f64(*x - eps));
+            let analytical = to_f64(analytical[i][&index]);
+            let error = (analytical - numerical).abs() / numerical.abs().max(1.0);
+            if error >= report.error || error.is_nan() {
+                report = GradcheckReport {
+                    passed: error <= tol as f64,
+                    input: i,
+                    index: index.slice().to_vec(),
+                    analytical,
+                    numerical,
+                    error,
+                };
+            }
+        }
+    }
+    report
+}
+
+/// Checks the gradients of the gradients computed with `create_graph = true`.
+///
+/// The gradient of the output is treated as an extra input, so that the backward
+/// functions are checked to be differentiable with respect to it as well.
+/// The report's `input == inputs.len()` refers to that gradient of the output.
+pub fn gradgradcheck<T: Float>(
+    f: impl Fn(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    inputs: &[NDArray<T>],
+    eps: f32,
+    tol: f32,
+) -> GradcheckReport {
+    let n = inputs.len();
+    let y_shape = {
+        let xs: Vec<_> = inputs.iter().map(|x| ComputedNDA::new(x.clone())).collect();
+        f(&xs).shape().to_vec()
+    };
+    let vs: Vec<_> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, x)| ComputedNDA::new(random_array(x.shape(), i as u64 + 2)))
+        .collect();
+
+    let mut inputs = inputs.to_vec();
+    inputs.push(random_array(&y_shape, 1));
+
+    gradcheck(
+        |xs| {
+            let (xs, gy) = xs.split_at(n);
+            let y = f(xs);
+            let loss = (&y * &gy[0]).sum((0..y.ndim()).collect::<Vec<_>>(), false);
+            let mut h = ComputedNDA::new(scalar_of(0.0));
+            for (x, v) in xs.iter().zip(&vs) {
+                let gx = gradient_or_zeros(&loss, x, true);
+                h = h + (&gx * v).sum((0..gx.ndim()).collect::<Vec<_>>(), false);
+            }
+            h
+        },
+        &inputs,
+        eps,
+        tol,
+    )
+}
+
+/// Compares the tangents computed by `jvp` with central finite differences
+/// along random tangents.
+///
+/// The report's `index` refers to the element of the output.
+pub fn jvpcheck<T: Float>(
+    f: impl Fn(&[ComputedNDA<T>]) -> ComputedNDA<T>,
+    inputs: &[NDArray<T>],
+    eps: f32,
+    tol: f32,
+) -> GradcheckReport {
+    let tangents: Vec<_> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, x)| random_array(x.shape(), i as u64 + 2))
+        .collect();
+    let (_, ty) = jvp(|xs| vec![f(xs)], inputs, &tangents);
+
+    let eval = |eps: f32| {
+        let eps = T::from_f32(eps);
+        let xs: Vec<_> = inputs
+            .iter()
+            .zip(&tangents)
+            .map(|(x, t)| ComputedNDA::new((x + &t.mapv(|t| t * eps)).into_ndarray()))
+            .collect();
+        f(&xs)
+    };
+    let (yp, ym) = (eval(eps), eval(-eps));
+
+    let mut report = GradcheckReport {
+        passed: true,
+        input: 0,
+        index: vec![],
+        analytical: 0.0,
+        numerical: 0.0,
+        error: 0.0,
+    };
+    for (index, t) in ty[0].indexed_iter() {
+        let numerical = (to_f64(yp[&index]) - to_f64(ym[&index])) / (2.0 * eps as f64);
+        let analytical = to_f64(*t);
+        let error = (analytical - numerical).abs() / numerical.abs().max(1.0);
+        if error >= report.error || error.is_nan() {
+            report = GradcheckReport {
+                passed: error <= tol as f64,
+                input: 0,
+                index: index.slice().to_vec(),
+                analytical,
+                numerical,
+                error,
+            };
+        }
+    }
+    report
+}
+
+fn gradient_or_zeros<T: Float>(
+    loss: &ComputedNDA<T>,
+    x: &ComputedNDA<T>,
+    create_graph: bool,
+) -> ComputedNDA<T> {
+    match try_gradients(
+        std::slice::from_ref(loss),
+        std::slice::from_ref(x),
+        create_graph,
+    ) {
+        Ok(mut gs) => gs.pop().unwrap(),
+        Err(Error::GradNotFound { .. }) => ComputedNDA::new(NDArray::zeros(x.shape())),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn random_array<T: Float>(shape: &[usize], seed: u64) -> NDArray<T> {
+    let mut rng = DefaultRng::seed_from_u64(seed);
+    <NDArray>::random_using(shape, Uniform::new(0.5, 1.5), &mut rng)
+        .mapv(T::from_f32)
+        .into_ndarray()
+}
+
+fn to_f64<T: Float>(x: T) -> f64 {
+    x.to_f64().unwrap()
+}
+
+#[test]
+fn test() {
+    let x = ndarray::array![[0.1, -0.2, 0.3], [0.4, 0.5, -0.6]].into_ndarray();
+    let report = gradcheck(|xs| xs[0].pow_const(3.0), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| xs[0].pow_const(3.0), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    // a wrong backward is detected
+    let wrong_square = |xs: &[ComputedNDA]| {
+        let y = ComputedNDA::new((&*xs[0] * &*xs[0]).into_ndarray());
+        chain(
+            &xs[..1],
+            &[y.clone()],
+            false,
+            "wrong_square",
+            |xs, _, gys| vec![&gys[0] * &xs[0]],
+        );
+        y
+    };
+    let report = gradcheck(wrong_square, &[x.clone()], 1e-3, 1e-2);
+    assert!(!report.passed);
+    assert_eq!(report.input, 0);
+
+    // a backward that is not differentiable is detected
+    let square = |xs: &[ComputedNDA]| {
+        let y = ComputedNDA::new((&*xs[0] * &*xs[0]).into_ndarray());
+        chain(&xs[..1], &[y.clone()], false, "square", |xs, _, gys| {
+            vec![ComputedNDA::new((&*gys[0] * &*xs[0] * 2.0).into_ndarray())]
+        });
+        y
+    };
+    assert!(gradcheck(square, &[x.clone()], 1e-3, 1e-2).passed);
+    assert!(!gradgradcheck(square, &[x], 1e-3, 1e-2).passed);
+}
@@ -2,16 +2,16 @@ use crate::*;
 
 macro_rules! impl_op {
     ($op:ident, $fn:ident) => {
-        impl std::ops::$op for &ComputedNDA {
-            type Output = ComputedNDA;
+        impl<T: Float> std::ops::$op for &ComputedNDA<T> {
+            type Output = ComputedNDA<T>;
 
             fn $fn(self, rhs: Self) -> Self::Output {
                 functions::$fn(self, rhs)
             }
         }
 
-        impl std::ops::$op for ComputedNDA {
-            type Output = ComputedNDA;
+        impl<T: Float> std::ops::$op for ComputedNDA<T> {
+            type Output = ComputedNDA<T>;
 
             fn $fn(self, rhs: Self) -> Self::Output {
                 functions::$fn(&self, &rhs)
@@ -25,96 +25,124 @@ impl_op!(Sub, sub);
 impl_op!(Mul, mul);
 impl_op!(Div, div);
 
-impl std::ops::Neg for &ComputedNDA {
-    type Output = ComputedNDA;
+impl<T: Float> std::ops::Neg for &ComputedNDA<T> {
+    type Output = ComputedNDA<T>;
 
     fn neg(self) -> Self::Output {
         functions::neg(self)
     }
 }
 
-impl std::ops::Neg for ComputedNDA {
-    type Output = ComputedNDA;
+impl<T: Float> std::ops::Neg for ComputedNDA<T> {
+    type Output = ComputedNDA<T>;
 
     fn neg(self) -> Self::Output {
         functions::neg(&self)
     }
 }
 
-impl ComputedNDA {
-    pub fn abs(&self) -> ComputedNDA {
+impl<T: Float> ComputedNDA<T> {
+    pub fn abs(&self) -> ComputedNDA<T> {
         functions::abs(self)
     }
 
-    pub fn broadcast(&self, shape: impl Into<Vec<usize>>) -> ComputedNDA {
+    pub fn broadcast(&self, shape: impl Into<Vec<usize>>) -> ComputedNDA<T> {
         functions::broadcast(self, shape)
     }
 
-    pub fn exp(&self) -> ComputedNDA {
+    pub fn try_broadcast(&self, shape: impl Into<Vec<usize>>) -> Result<ComputedNDA<T>, Error> {
+        functions::try_broadcast(self, shape)
+    }
+
+    pub fn exp(&self) -> ComputedNDA<T> {
         functions::exp(self)
     }
 
-    pub fn log(&self) -> ComputedNDA {
+    pub fn log(&self) -> ComputedNDA<T> {
         functions::log(self)
     }
 
-    pub fn mat_t(&self) -> ComputedNDA {
+    pub fn mat_t(&self) -> ComputedNDA<T> {
         functions::mat_transpose(self)
     }
 
-    pub fn matmul(&self, rhs: &ComputedNDA) -> ComputedNDA {
+    pub fn try_mat_t(&self) -> Result<ComputedNDA<T>, Error> {
+        functions::try_mat_transpose(self)
+    }
+
+    pub fn matmul(&self, rhs: &ComputedNDA<T>) -> ComputedNDA<T> {
         functions::matmul(self, rhs)
     }
 
-    pub fn pow(&self, rhs: &ComputedNDA) -> ComputedNDA {
+    pub fn try_matmul(&self, rhs: &ComputedNDA<T>) -> Result<ComputedNDA<T>, Error> {
+        functions::try_matmul(self, rhs)
+    }
+
+    pub fn pow(&self, rhs: &ComputedNDA<T>) -> ComputedNDA<T> {
         functions::pow(self, rhs)
     }
 
-    pub fn pow_const(&self, rhs: f32) -> ComputedNDA {
+    pub fn pow_const(&self, rhs: f32) -> ComputedNDA<T> {
         functions::pow_const(self, rhs)
     }
 
-    pub fn reshape(&self, shape: impl Into<Vec<usize>>) -> ComputedNDA {
+    pub fn reshape(&self, shape: impl Into<Vec<usize>>) -> ComputedNDA<T> {
         functions::reshape(self, shape)
     }
 
-    pub fn sin(&self) -> ComputedNDA {
+    pub fn try_reshape(&self, shape: impl Into<Vec<usize>>) -> Result<ComputedNDA<T>, Error> {
+        functions::try_reshape(self, shape)
+    }
+
+    pub fn sin(&self) -> ComputedNDA<T> {
         functions::sin(self)
     }
 
-    pub fn cos(&self) -> ComputedNDA {
+    pub fn cos(&self) -> ComputedNDA<T> {
         functions::cos(self)
     }
 
     pub fn slice<I: ndarray::SliceArg<ndarray::IxDyn> + Clone + Sync + Send + 'static>(
         &self,
         slice_arg: I,
-    ) -> ComputedNDA {
+    ) -> ComputedNDA<T> {
         functions::slice(self, slice_arg)
     }
 
     pub fn slices<I: ndarray::SliceArg<ndarray::IxDyn> + Clone + Sync + Send + 'static>(
         &self,
         slice_args: Vec<I>,
-    ) -> Vec<ComputedNDA> {
+    ) -> Vec<ComputedNDA<T>> {
         functions::slices(self, slice_args)
     }
 
-    pub fn sum(&self, axes: impl Into<Vec<usize>>, keep_dim: bool) -> ComputedNDA {
+    pub fn sum(&self, axes: impl Into<Vec<usize>>, keep_dim: bool) -> ComputedNDA<T> {
         functions::sum(self, axes, keep_dim)
     }
 
-    pub fn t(&self) -> ComputedNDA {
+    pub fn try_sum(
+        &self,
+        axes: impl Into<Vec<usize>>,
+        keep_dim: bool,
+    ) -> Result<ComputedNDA<T>, Error> {
+        functions::try_sum(self, axes, keep_dim)
+    }
+
+    pub fn t(&self) -> ComputedNDA<T> {
         functions::t(self)
     }
 
-    pub fn tanh(&self) -> ComputedNDA {
+    pub fn tanh(&self) -> ComputedNDA<T> {
         functions::tanh(self)
     }
 
-    pub fn transpose(&self, axes: impl Into<Vec<usize>>) -> ComputedNDA {
+    pub fn transpose(&self, axes: impl Into<Vec<usize>>) -> ComputedNDA<T> {
         functions::transpose(self, axes)
     }
+
+    pub fn try_transpose(&self, axes: impl Into<Vec<usize>>) -> Result<ComputedNDA<T>, Error> {
+        functions::try_transpose(self, axes)
+    }
 }
 
 #[test]
@@ -128,4 +156,8 @@ fn test() {
     let y = ComputedNDA::new(scalar(2.0));
     let z = &x - &y;
     assert_eq!(z[[]], -1.0);
+
+    let x = ComputedNDA::new(<NDArray>::zeros(&[2, 3][..]));
+    assert!(x.try_reshape(vec![3, 2]).is_ok());
+    assert!(x.try_matmul(&x).is_err());
 }
@@ -8,6 +8,9 @@ pub trait Initializer<T: Send + Sync> {
 
 pub trait Scope {
     fn scope(&self, name: impl ToString) -> Self;
+
+    /// The names of the scopes joined with `:`, which names the params it initializes.
+    fn path(&self) -> String;
 }
 
 impl<T: Send + Sync> Initializer<T> for () {
@@ -20,4 +23,8 @@ impl Scope for () {
     fn scope(&self, _: impl ToString) -> Self {
         unreachable!()
     }
+
+    fn path(&self) -> String {
+        unreachable!()
+    }
 }
@@ -1,5 +1,5 @@
 use super::Initializer;
-use crate::{DefaultRng, NDArray};
+use crate::{DefaultRng, Float, NDArray};
 
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -10,12 +10,12 @@ use ndarray_rand::{
     RandomExt,
 };
 
-pub struct RandomInitializer<D: Distribution<f32> + Clone> {
+pub struct RandomInitializer<D: Clone> {
     pub distribution: D,
     pub rng: Arc<Mutex<DefaultRng>>,
 }
 
-impl<D: Distribution<f32> + Clone> RandomInitializer<D> {
+impl<D: Clone> RandomInitializer<D> {
     pub fn new(distribution: D) -> Self {
         Self {
             distribution,
@@ -24,14 +24,15 @@ impl<D: Distribution<f32> + Clone> RandomInitializer<D> {
     }
 }
 
-impl<D: Distribution<f32> + Clone> Initializer<NDArray> for RandomInitializer<D> {
-    fn initialize(&self, shape: &[usize]) -> NDArray {
+/// The element type is the one sampled by the distribution.
+impl<T: Float, D: Distribution<T> + Clone> Initializer<NDArray<T>> for RandomInitializer<D> {
+    fn initialize(&self, shape: &[usize]) -> NDArray<T> {
         let mut rng = self.rng.lock().unwrap();
         NDArray::random_using(shape, self.distribution.clone(), &mut *rng)
     }
 }
 
-impl<D: Distribution<f32> + Clone> Clone for RandomInitializer<D> {
+impl<D: Clone> Clone for RandomInitializer<D> {
     fn clone(&self) -> Self {
         let rng = self.rng.clone();
         rng.lock().unwrap().gen::<u32>();
@@ -39,7 +39,7 @@ impl<
 {
     fn initialize(&self, shape: &[usize]) -> Param<T> {
         let data = self.initializer.initialize(shape);
-        Param::new(data, self.path.join(":").into(), self.optimizer.clone())
+        Param::new(data, Scope::path(self).into(), self.optimizer.clone())
     }
 }
 
@@ -54,4 +54,8 @@ impl<
         i.path.push(name.to_string());
         i
     }
+
+    fn path(&self) -> String {
+        self.path.join(":")
+    }
 }
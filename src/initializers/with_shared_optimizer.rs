@@ -42,7 +42,7 @@ impl<
 {
     fn initialize(&self, shape: &[usize]) -> Param<T> {
         let data = self.initializer.initialize(shape);
-        Param::new_shared(data, self.path.join(":").into(), self.optimizer.clone())
+        Param::new_shared(data, Scope::path(self).into(), self.optimizer.clone())
     }
 }
 
@@ -57,4 +57,8 @@ impl<
         i.path.push(name.to_string());
         i
     }
+
+    fn path(&self) -> String {
+        self.path.join(":")
+    }
 }
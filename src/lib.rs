@@ -1,6 +1,9 @@
 pub mod contrib;
 pub mod core;
+mod error;
+mod float;
 pub mod functions;
+pub mod gradcheck;
 mod impl_ops_for_tensor;
 pub mod initializers;
 pub mod losses;
@@ -9,6 +12,9 @@ pub mod ndarray_util;
 pub mod nn;
 pub mod optimizers;
 pub mod regularizers;
+pub mod sparse;
+pub mod symbolic;
+mod tensor;
 pub mod training;
 
 #[cfg(test)]
@@ -16,25 +22,28 @@ mod test;
 
 pub use crate::core::*;
 pub use contrib::{export_dot, param_bin};
+pub use error::Error;
+pub use float::Float;
 pub use metrics::{Metric, Metrics};
-pub use ndarray_util::{scalar, IntoNDArray, NDArray};
+pub use ndarray_util::{scalar, scalar_of, IntoNDArray, NDArray};
 pub use nn::Layer;
+pub use tensor::{MatMulDim, Tensor};
 
 pub use ndarray;
 pub use ndarray_rand;
 
 pub type DefaultRng = rand_isaac::Isaac64Rng;
 
-pub type ComputedNDA = Computed<NDArray>;
-pub type ParamNDA = param::Param<NDArray>;
+pub type ComputedNDA<T = f32> = Computed<NDArray<T>>;
+pub type ParamNDA<T = f32> = param::Param<NDArray<T>>;
 
-impl Into<ComputedNDA> for NDArray {
-    fn into(self) -> ComputedNDA {
-        ComputedNDA::new(self)
+impl<T: 'static> From<NDArray<T>> for ComputedNDA<T> {
+    fn from(x: NDArray<T>) -> Self {
+        ComputedNDA::new(x)
     }
 }
 
-impl graph::One for NDArray {
+impl<T: Float> graph::One for NDArray<T> {
     fn clone_filled_ones(&self) -> Self {
         NDArray::ones(self.shape())
     }
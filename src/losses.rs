@@ -2,32 +2,40 @@ use std::ops::Sub;
 
 use ndarray::{Array1, Axis};
 
-use crate::ndarray_util::onehot;
+use crate::ndarray_util::onehot_of;
 use crate::nn::activations::{relu, softmax};
 use crate::*;
 
-pub fn naive_mean_squared_error(x0: ComputedNDA, x1: ComputedNDA) -> ComputedNDA {
+pub fn naive_mean_squared_error<T: Float>(
+    x0: ComputedNDA<T>,
+    x1: ComputedNDA<T>,
+) -> ComputedNDA<T> {
     let x = (x0 - x1).pow_const(2.0);
     x.sum(Vec::from_iter(0..x.ndim()), false)
-        / ComputedNDA::new(scalar(x.shape().iter().product::<usize>() as f32))
+        / ComputedNDA::new(scalar_of(x.shape().iter().product::<usize>() as f64))
 }
 
-pub fn naive_mean_absolute_error(x0: ComputedNDA, x1: ComputedNDA) -> ComputedNDA {
+pub fn naive_mean_absolute_error<T: Float>(
+    x0: ComputedNDA<T>,
+    x1: ComputedNDA<T>,
+) -> ComputedNDA<T> {
     let x = (x0 - x1).abs();
     x.sum(Vec::from_iter(0..x.ndim()), false)
-        / ComputedNDA::new(scalar(x.shape().iter().product::<usize>() as f32))
+        / ComputedNDA::new(scalar_of(x.shape().iter().product::<usize>() as f64))
 }
 
-pub fn softmax_cross_entropy(t: Vec<usize>, x: &ComputedNDA) -> ComputedNDA {
+pub fn softmax_cross_entropy<T: Float>(t: Vec<usize>, x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("softmax_cross_entropy");
     let n = x.shape().iter().take(x.ndim() - 1).product();
     let log_z = log_sum_exp(&*x);
     let log_p = x.to_shape((n, x.shape()[x.ndim() - 1])).unwrap();
-    let mut y = 0.0;
+    let mut y = T::zero();
     for i in 0..n {
         y -= log_p[[i, t[i]]] - log_z[i];
     }
-    let y = ComputedNDA::new(scalar(y / n as f32));
+    let y = ComputedNDA::new(ndarray::arr0(y / T::from_f64(n as f64)).into_ndarray());
 
+    let t_ = t.clone();
     chain(
         &[x.clone()],
         &[y.clone()],
@@ -36,10 +44,10 @@ pub fn softmax_cross_entropy(t: Vec<usize>, x: &ComputedNDA) -> ComputedNDA {
         move |xs, _ys, gys| {
             let n: usize = xs[0].shape().iter().take(xs[0].ndim() - 1).product();
             let class_num = xs[0].shape()[xs[0].ndim() - 1];
-            let gy = &gys[0] * &ComputedNDA::new(scalar(1.0 / n as f32));
+            let gy = &gys[0] * &ComputedNDA::new(scalar_of(1.0 / n as f64));
             let y = softmax(&xs[0]);
             let t_onehot = ComputedNDA::new(
-                onehot(&Array1::from(t.clone()), class_num)
+                onehot_of(&Array1::from(t.clone()), class_num)
                     .into_shape(y.shape())
                     .unwrap(),
             );
@@ -47,6 +55,19 @@ pub fn softmax_cross_entropy(t: Vec<usize>, x: &ComputedNDA) -> ComputedNDA {
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], move |xs, _ys, txs| {
+        let n: usize = xs[0].shape().iter().take(xs[0].ndim() - 1).product();
+        let class_num = xs[0].shape()[xs[0].ndim() - 1];
+        let y = softmax(&xs[0]);
+        let t_onehot = ComputedNDA::new(
+            onehot_of(&Array1::from(t_.clone()), class_num)
+                .into_shape(y.shape())
+                .unwrap(),
+        );
+        let ty = (y - t_onehot) * txs[0].clone().unwrap();
+        vec![ty.sum(Vec::from_iter(0..ty.ndim()), false) / ComputedNDA::new(scalar_of(n as f64))]
+    });
+
     y
 }
 
@@ -61,11 +82,12 @@ fn test_softmax_cross_entropy() {
     dbg!(&*grads[0]);
 }
 
-pub fn softmax_cross_entropy_with_logits(
-    labels: &ComputedNDA,
-    logits: &ComputedNDA,
+pub fn softmax_cross_entropy_with_logits<T: Float>(
+    labels: &ComputedNDA<T>,
+    logits: &ComputedNDA<T>,
     axis: usize,
-) -> ComputedNDA {
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("softmax_cross_entropy_with_logits");
     let x = softmax(logits);
     let y = -(labels * &x.log()).sum([axis], false);
 
@@ -79,10 +101,10 @@ pub fn softmax_cross_entropy_with_logits(
             let gy = &gys[0];
             let mut shape = labels.shape().to_vec();
             shape[axis] = 1;
-            let n: usize = shape.iter().product();
 
-            let gy = gy.reshape(shape) / ComputedNDA::new(scalar(n as f32));
-            let g_logits = &(&x - labels) * &gy;
+            let gy = gy.reshape(shape);
+            // labels are not necessarily normalized
+            let g_logits = &(&(&x * &labels.sum([axis], true)) - labels) * &gy;
             let g_labels = -(&x.log() * &gy);
             vec![g_labels, g_logits]
         },
@@ -102,15 +124,16 @@ fn test_softmax_cross_entropy_with_logits() {
     dbg!(&*grads[0]);
 }
 
-pub fn sigmoid_cross_entropy_with_logits(
-    labels: &ComputedNDA,
-    logits: &ComputedNDA,
-) -> ComputedNDA {
-    relu(logits) - logits * labels + (ComputedNDA::new(scalar(1.0)) + (-logits.abs()).exp()).log()
+pub fn sigmoid_cross_entropy_with_logits<T: Float>(
+    labels: &ComputedNDA<T>,
+    logits: &ComputedNDA<T>,
+) -> ComputedNDA<T> {
+    relu(logits) - logits * labels
+        + (ComputedNDA::new(scalar_of(1.0)) + (-logits.abs()).exp()).log()
 }
 
 // max(x) + log(sum(exp(x - max(x))))
-pub fn log_sum_exp(x: &NDArray) -> NDArray {
+pub fn log_sum_exp<T: Float>(x: &NDArray<T>) -> NDArray<T> {
     let ndim = x.ndim();
     let x_max = x.map_axis(Axis(ndim - 1), |x| {
         *x.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap()
@@ -135,3 +158,47 @@ fn test_log_sum_exp() {
             < 1e-6
     );
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.1, 0.2, 0.3], [0.0, -1.0, 2.0]].into_ndarray();
+    let t = ndarray::array![[0.0, 1.0, 0.0], [0.2, 0.0, 0.8]].into_ndarray();
+
+    let f = |xs: &[ComputedNDA]| naive_mean_squared_error(xs[0].clone(), xs[1].clone());
+    let report = gradcheck(f, &[x.clone(), t.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[x.clone(), t.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| naive_mean_absolute_error(xs[0].clone(), xs[1].clone());
+    let report = gradcheck(f, &[x.clone(), t.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[x.clone(), t.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| softmax_cross_entropy(vec![1, 2], &xs[0]);
+    let report = gradcheck(f, &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| softmax_cross_entropy_with_logits(&xs[0], &xs[1], 1);
+    let report = gradcheck(f, &[t.clone(), x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[t.clone(), x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    let f = |xs: &[ComputedNDA]| sigmoid_cross_entropy_with_logits(&xs[0], &xs[1]);
+    let report = gradcheck(f, &[t.clone(), x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(f, &[t, x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+
+    // much tighter in f64
+    let x: NDArray<f64> = ndarray::array![[0.1, 0.2, 0.3], [0.0, -1.0, 2.0]].into_ndarray();
+    let f = |xs: &[ComputedNDA<f64>]| softmax_cross_entropy(vec![1, 2], &xs[0]);
+    let report = gradcheck(f, &[x], 1e-6, 1e-8);
+    assert!(report.passed, "{}", report);
+}
@@ -119,6 +119,38 @@ impl Metric for Accuracy {
     }
 }
 
+/// The L2 norm of the gradients before clipping, weighted by the number of samples of the update.
+#[derive(Clone, Default)]
+pub struct GradNorm {
+    acc_norm: f32,
+}
+
+impl GradNorm {
+    pub fn new(norm: f32, count: usize) -> GradNorm {
+        GradNorm {
+            acc_norm: norm * count as f32,
+        }
+    }
+}
+
+impl Metric for GradNorm {
+    fn name(&self) -> &'static str {
+        "grad_norm"
+    }
+
+    fn value(&self) -> f32 {
+        self.acc_norm
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.acc_norm += other.acc_norm;
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
 pub struct Metrics {
     pub total: usize,
     metrics: Vec<(std::any::TypeId, Box<dyn MetricObjectSafe>)>,
@@ -5,35 +5,49 @@ use ndarray::{
 
 pub use ndarray_einsum_beta::tensordot;
 
-pub type NDArray = ArrayBase<OwnedArcRepr<f32>, ndarray::IxDyn>;
+use crate::Float;
+
+pub type NDArray<T = f32> = ArrayBase<OwnedArcRepr<T>, ndarray::IxDyn>;
 
 pub fn scalar(x: f32) -> NDArray {
     ndarray::arr0(x).into_ndarray()
 }
 
-pub trait IntoNDArray {
-    fn into_ndarray(self) -> NDArray;
+/// Like [`scalar`] for any element type.
+pub fn scalar_of<T: Float>(x: f64) -> NDArray<T> {
+    ndarray::arr0(T::from_f64(x)).into_ndarray()
+}
+
+pub trait IntoNDArray<T = f32> {
+    fn into_ndarray(self) -> NDArray<T>;
 }
 
-impl<D: Dimension> IntoNDArray for ArrayBase<OwnedRepr<f32>, D> {
-    fn into_ndarray(self) -> NDArray {
+impl<T: Clone, D: Dimension> IntoNDArray<T> for ArrayBase<OwnedRepr<T>, D> {
+    fn into_ndarray(self) -> NDArray<T> {
         self.into_dyn().into_shared()
     }
 }
 
-impl<D: Dimension> IntoNDArray for ArrayBase<ViewRepr<&f32>, D> {
-    fn into_ndarray(self) -> NDArray {
+impl<T: Clone, D: Dimension> IntoNDArray<T> for ArrayBase<ViewRepr<&T>, D> {
+    fn into_ndarray(self) -> NDArray<T> {
         self.into_dyn().to_shared()
     }
 }
 
-impl<'a, D: Dimension> IntoNDArray for ArrayBase<CowRepr<'a, f32>, D> {
-    fn into_ndarray(self) -> NDArray {
+impl<'a, T: Clone, D: Dimension> IntoNDArray<T> for ArrayBase<CowRepr<'a, T>, D> {
+    fn into_ndarray(self) -> NDArray<T> {
         self.into_dyn().to_shared()
     }
 }
 
-pub fn as_2d(array: &NDArray) -> ArrayBase<ViewRepr<&f32>, Dim<[usize; 2]>> {
+/// Converts the element type, e.g. to keep a table in half precision.
+pub fn cast<T: Float, U: Float>(array: &NDArray<T>) -> NDArray<U> {
+    array
+        .map(|x| U::from_f64(x.to_f64().unwrap()))
+        .into_ndarray()
+}
+
+pub fn as_2d<T>(array: &NDArray<T>) -> ArrayBase<ViewRepr<&T>, Dim<[usize; 2]>> {
     let shape = array.shape();
     array
         .view()
@@ -45,9 +59,13 @@ pub fn as_2d(array: &NDArray) -> ArrayBase<ViewRepr<&f32>, Dim<[usize; 2]>> {
 }
 
 pub fn onehot<D: Dimension>(t: &Array<usize, D>, size: usize) -> NDArray {
-    let mut v = vec![0.0; t.shape().iter().product::<usize>() * size];
+    onehot_of(t, size)
+}
+
+pub fn onehot_of<T: Float, D: Dimension>(t: &Array<usize, D>, size: usize) -> NDArray<T> {
+    let mut v = vec![T::zero(); t.shape().iter().product::<usize>() * size];
     for (i, n) in t.iter().copied().enumerate() {
-        v[i * size + n] = 1.0;
+        v[i * size + n] = T::one();
     }
     ndarray::Array::from_shape_vec(
         t.shape().iter().cloned().chain([size]).collect::<Vec<_>>(),
@@ -61,8 +79,8 @@ pub fn eye(shape: [usize; 2]) -> NDArray {
     ndarray::Array2::from_shape_fn(shape, |(i, j)| if i == j { 1.0 } else { 0.0 }).into_ndarray()
 }
 
-pub fn argmax<D: Dimension + RemoveAxis>(
-    t: &ArrayBase<OwnedArcRepr<f32>, D>,
+pub fn argmax<T: PartialOrd, D: Dimension + RemoveAxis>(
+    t: &ArrayBase<OwnedArcRepr<T>, D>,
 ) -> Array<usize, D::Smaller> {
     t.map_axis(Axis(t.ndim() - 1), |x| {
         x.iter()
@@ -1,11 +1,11 @@
 use crate::*;
 
-pub fn gelu(x: &ComputedNDA) -> ComputedNDA {
+pub fn gelu<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
     // x * &(((x + &(Computed::new(scalar(0.044715)) * x.pow(3.0)))
     //     * Computed::new(scalar((2.0 / std::f32::consts::PI).sqrt())))
     // .tanh()
     //     + Computed::new(scalar(1.0)))
-    x * &super::sigmoid(&(x * &ComputedNDA::new(scalar(1.702))))
+    x * &super::sigmoid(&(x * &ComputedNDA::new(scalar_of(1.702))))
 }
 
 #[test]
@@ -18,3 +18,14 @@ fn test() {
     assert!(y[1] < y[2]);
     assert!(y[2] < y[3]);
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| gelu(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| gelu(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
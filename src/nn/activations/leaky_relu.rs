@@ -1,11 +1,17 @@
-use ndarray::Zip;
-
 use crate::*;
 
-pub fn leaky_relu(negative_slope: f32, x: &ComputedNDA) -> ComputedNDA {
+pub fn leaky_relu<T: Float>(negative_slope: f32, x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("relu");
+    let negative_slope = T::from_f32(negative_slope);
     let y = ComputedNDA::new(
         (**x)
-            .map(|x| if *x > 0.0 { *x } else { *x * negative_slope })
+            .map(|x| {
+                if *x > T::zero() {
+                    *x
+                } else {
+                    *x * negative_slope
+                }
+            })
             .into_ndarray(),
     );
 
@@ -15,16 +21,38 @@ pub fn leaky_relu(negative_slope: f32, x: &ComputedNDA) -> ComputedNDA {
         false,
         "relu",
         move |xs, _ys, gys| {
-            let mut gx = (*gys[0]).to_owned();
-
-            Zip::from(&mut gx).and(&xs[0].view()).for_each(|y, x| {
-                if *x < 0.0 {
-                    *y *= negative_slope;
+            let slope = xs[0].map(|x| {
+                if *x < T::zero() {
+                    negative_slope
+                } else {
+                    T::one()
                 }
             });
-            vec![ComputedNDA::new(gx.into_ndarray())]
+            vec![&gys[0] * &ComputedNDA::new(slope.into_ndarray())]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], move |xs, _, txs| {
+        let slope = xs[0].map(|x| {
+            if *x < T::zero() {
+                negative_slope
+            } else {
+                T::one()
+            }
+        });
+        vec![txs[0].as_ref().unwrap() * &ComputedNDA::new(slope.into_ndarray())]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| leaky_relu(0.1, &xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| leaky_relu(0.1, &xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
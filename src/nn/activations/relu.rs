@@ -1,9 +1,8 @@
-use ndarray::Zip;
-
 use crate::*;
 
-pub fn relu(x: &ComputedNDA) -> ComputedNDA {
-    let y = ComputedNDA::new((**x).map(|x| x.max(0.0)).into_ndarray());
+pub fn relu<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("relu");
+    let y = ComputedNDA::new((**x).map(|x| x.max(T::zero())).into_ndarray());
 
     chain(
         &[x.clone()],
@@ -11,16 +10,26 @@ pub fn relu(x: &ComputedNDA) -> ComputedNDA {
         false,
         "relu",
         move |xs, _ys, gys| {
-            let mut gx = (*gys[0]).to_owned();
-
-            Zip::from(&mut gx).and(&xs[0].view()).for_each(|y, x| {
-                if *x < 0.0 {
-                    *y = 0.0
-                }
-            });
-            vec![ComputedNDA::new(gx.into_ndarray())]
+            let mask = xs[0].map(|x| if *x < T::zero() { T::zero() } else { T::one() });
+            vec![&gys[0] * &ComputedNDA::new(mask.into_ndarray())]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |xs, _, txs| {
+        let mask = xs[0].map(|x| if *x < T::zero() { T::zero() } else { T::one() });
+        vec![txs[0].as_ref().unwrap() * &ComputedNDA::new(mask.into_ndarray())]
+    });
+
     y
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| relu(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| relu(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
@@ -1,7 +1,14 @@
 use crate::*;
 
-pub fn sigmoid(x: &ComputedNDA) -> ComputedNDA {
-    let y = ComputedNDA::new(x.map(|x| (x * 0.5).tanh() * 0.5 + 0.5).into_ndarray());
+pub fn sigmoid<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("sigmoid");
+    let y = ComputedNDA::new(
+        x.map(|x| {
+            let half = T::from_f64(0.5);
+            (*x * half).tanh() * half + half
+        })
+        .into_ndarray(),
+    );
 
     chain(
         &[x.clone()],
@@ -9,14 +16,36 @@ pub fn sigmoid(x: &ComputedNDA) -> ComputedNDA {
         false,
         "sigmoid",
         move |_xs, ys, gys| {
-            let gx = &gys[0] * &(&ComputedNDA::new(scalar(1.0)) - &ys[0]) * ys[0].clone();
+            let gx = &gys[0] * &(&ComputedNDA::new(scalar_of(1.0)) - &ys[0]) * ys[0].clone();
             vec![gx]
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |_, ys, txs| {
+        let ty = txs[0].as_ref().unwrap()
+            * &(&ComputedNDA::new(scalar_of(1.0)) - &ys[0])
+            * ys[0].clone();
+        vec![ty]
+    });
+
     y
 }
 
-pub fn naive_sigmoid(x: ComputedNDA) -> ComputedNDA {
-    ComputedNDA::new(scalar(1.0)) / (ComputedNDA::new(scalar(1.0)) + (-x).exp())
+pub fn naive_sigmoid<T: Float>(x: ComputedNDA<T>) -> ComputedNDA<T> {
+    ComputedNDA::new(scalar_of(1.0)) / (ComputedNDA::new(scalar_of(1.0)) + (-x).exp())
+}
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.5, -1.0], [2.0, -0.3]].into_ndarray();
+    let report = gradcheck(|xs| sigmoid(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| sigmoid(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradcheck(|xs| naive_sigmoid(xs[0].clone()), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| naive_sigmoid(xs[0].clone()), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
 }
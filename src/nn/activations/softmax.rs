@@ -4,7 +4,8 @@ use ndarray::Axis;
 
 use crate::*;
 
-pub fn softmax(x: &ComputedNDA) -> ComputedNDA {
+pub fn softmax<T: Float>(x: &ComputedNDA<T>) -> ComputedNDA<T> {
+    let _scope = profile_scope("softmax");
     let ndim = x.ndim();
     let x_max = x.map_axis(Axis(ndim - 1), |x| {
         *x.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap()
@@ -27,6 +28,12 @@ pub fn softmax(x: &ComputedNDA) -> ComputedNDA {
         },
     );
 
+    forward_derivative([x.clone()], [y.clone()], |xs, ys, txs| {
+        let tx = txs[0].as_ref().unwrap();
+        let mean = (&ys[0] * tx).sum([xs[0].ndim() - 1], true);
+        vec![&ys[0] * &(tx - &mean)]
+    });
+
     y
 }
 
@@ -39,3 +46,14 @@ fn test() {
     let grads = gradients(&[y], &[x], false);
     dbg!(&*grads[0]);
 }
+
+#[test]
+fn test_gradcheck() {
+    use crate::gradcheck::{gradcheck, gradgradcheck};
+
+    let x = ndarray::array![[0.1, 0.2, 0.3], [0.0, -1.0, 2.0]].into_ndarray();
+    let report = gradcheck(|xs| softmax(&xs[0]), &[x.clone()], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+    let report = gradgradcheck(|xs| softmax(&xs[0]), &[x], 1e-3, 1e-2);
+    assert!(report.passed, "{}", report);
+}
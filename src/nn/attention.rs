@@ -131,7 +131,13 @@ impl MHAAddNorm {
         Self {
             attention: MultiHeadAttention::new(dim, num_heads, w.scope("mha"), b.scope("mha")),
             dense: Linear::new(dim, dim, w.scope("dense"), Some(b.scope("dense"))),
-            norm: Normalization::new(vec![1], vec![dim], layer_norm_eps, opt),
+            norm: Normalization::new_with_name(
+                &w.scope("norm").path(),
+                vec![1],
+                vec![dim],
+                layer_norm_eps,
+                opt,
+            ),
         }
     }
 
@@ -175,5 +181,6 @@ fn test() {
 
     let y = mha.call(&x, &attn_mask, true);
     assert_eq!(y.shape(), x.shape());
+    assert_eq!(mha.norm.gamma.name(), "mha:norm:gamma");
     // dbg!(&*y);
 }
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use crate::*;
+
+/// Wraps a layer so that its intermediate graph is recomputed in backward
+/// instead of being kept alive. See `checkpoint`.
+pub struct Checkpointed<L: Layer<Input = ComputedNDA, Output = ComputedNDA> + Send + Sync> {
+    pub layer: Arc<L>,
+}
+
+impl<L: Layer<Input = ComputedNDA, Output = ComputedNDA> + Send + Sync> Checkpointed<L> {
+    pub fn new(layer: L) -> Self {
+        Self {
+            layer: Arc::new(layer),
+        }
+    }
+}
+
+impl<L: Layer<Input = ComputedNDA, Output = ComputedNDA> + Send + Sync> Layer for Checkpointed<L> {
+    type Input = ComputedNDA;
+    type Output = ComputedNDA;
+
+    fn call(&self, x: Self::Input, train: bool) -> Self::Output {
+        let layer = self.layer.clone();
+        checkpoint(move |xs| vec![layer.call(xs[0].clone(), train)], &[x])
+            .pop()
+            .unwrap()
+    }
+
+    fn all_params(&self) -> Vec<ParamNDA> {
+        self.layer.all_params()
+    }
+}
+
+#[test]
+fn test() {
+    use crate::{
+        initializers::{
+            random_initializer::RandomInitializer, with_optimizer::InitializerWithOptimizer,
+        },
+        optimizers::SGD,
+    };
+
+    let init = InitializerWithOptimizer::new(
+        RandomInitializer::new(ndarray_rand::rand_distr::Normal::new(0.0, 0.5).unwrap()),
+        SGD::new(0.1),
+    );
+    let mlp = nn::MLP::new(&[2, 4, 4, 3], None, |x| x.tanh(), init.clone(), Some(init));
+    let params = mlp.all_params();
+    let mlp = Checkpointed::new(mlp);
+    assert_eq!(mlp.all_params().len(), params.len());
+
+    let x = backprop(ndarray::array![[0.1, 0.2], [0.3, -0.4]].into_ndarray());
+
+    let expected = {
+        let y = mlp.layer.call(x.clone(), true);
+        let y = (&y * &y).sum([0, 1], false);
+        let mut ga = GradientsAccumulator::new();
+        ga.compute(&y);
+        let gx = gradients(&[y], &[x.clone()], false);
+        (ga, gx)
+    };
+
+    let y = mlp.call(x.clone(), true);
+    let y = (&y * &y).sum([0, 1], false);
+    let mut ga = GradientsAccumulator::new();
+    ga.compute(&y);
+    let gx = gradients(&[y], &[x.clone()], false);
+
+    assert!((&*gx[0] - &*expected.1[0]).iter().all(|d| d.abs() < 1e-6));
+    for p in &params {
+        let d = &ga.table[p] - &expected.0.table[p];
+        assert!(d.iter().all(|d| d.abs() < 1e-6));
+    }
+}
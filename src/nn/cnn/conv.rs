@@ -9,23 +9,23 @@ use crate::{
 
 use super::im2col::{col2im, get_transposed_conv_outsize, im2col, Col2im};
 
-pub struct Conv2d {
+pub struct Conv2d<T: Float = f32> {
     pub kernel_size: [usize; 2],
     pub stride: [usize; 2],
     pub padding: [usize; 2],
-    pub w: ParamNDA,         // [out_ch, in_ch, kh, kw]
-    pub b: Option<ParamNDA>, // [out_ch]
+    pub w: ParamNDA<T>,         // [out_ch, in_ch, kh, kw]
+    pub b: Option<ParamNDA<T>>, // [out_ch]
 }
 
-impl Conv2d {
+impl<T: Float> Conv2d<T> {
     pub fn new(
         input_channel: usize,
         output_channel: usize,
         kernel_size: [usize; 2],
         stride: [usize; 2],
         padding: [usize; 2],
-        w: impl Initializer<ParamNDA> + Scope,
-        b: Option<impl Initializer<ParamNDA> + Scope>,
+        w: impl Initializer<ParamNDA<T>> + Scope,
+        b: Option<impl Initializer<ParamNDA<T>> + Scope>,
     ) -> Self {
         Self {
             kernel_size,
@@ -42,14 +42,15 @@ impl Conv2d {
     }
 }
 
-impl Layer for Conv2d {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Conv2d<T> {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, train: bool) -> Self::Output
     where
         Self: Sized + 'static,
     {
+        let _scope = profile_scope("Conv2d");
         assert_eq!(x.ndim(), 4);
         let oh = get_conv_outsize(
             x.shape()[2],
@@ -126,7 +127,7 @@ impl Layer for Conv2d {
         // )
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         [self.w.clone()].into_iter().chain(self.b.clone()).collect()
     }
 }
@@ -173,16 +174,35 @@ fn test_conv2d() {
     assert_eq!(&*grads[0], &*grads2[0]);
 }
 
-pub struct Conv2dTranspose {
+#[test]
+fn test_conv2d_f64() {
+    use crate::gradcheck::gradcheck;
+    use ndarray::prelude::*;
+
+    let x: NDArray<f64> = Array::from_shape_fn((2, 2, 3, 3), |(b, c, h, w)| {
+        ((b * 7 + c * 5 + h * 3 + w) % 5) as f64 * 0.3 - 0.6
+    })
+    .into_ndarray();
+    let w = Array::from_shape_fn((3, 2, 2, 2), |(o, i, h, w)| {
+        ((o * 5 + i * 3 + h * 2 + w) % 7) as f64 * 0.2 - 0.5
+    })
+    .into_ndarray();
+    let b = array![0.1, -0.2, 0.3].into_ndarray();
+    let f = |xs: &[ComputedNDA<f64>]| conv2d([1, 1], [1, 1], &xs[1], Some(&xs[2]), &xs[0]);
+    let report = gradcheck(f, &[x, w, b], 1e-6, 1e-8);
+    assert!(report.passed, "{}", report);
+}
+
+pub struct Conv2dTranspose<T: Float = f32> {
     pub kernel_size: [usize; 2],
     pub stride: [usize; 2],
     pub padding: [usize; 2],
     pub out_size: Option<[usize; 2]>,
-    pub w: ParamNDA,         // [out_ch, in_ch, kh, kw]
-    pub b: Option<ParamNDA>, // [out_ch]
+    pub w: ParamNDA<T>,         // [out_ch, in_ch, kh, kw]
+    pub b: Option<ParamNDA<T>>, // [out_ch]
 }
 
-impl Conv2dTranspose {
+impl<T: Float> Conv2dTranspose<T> {
     pub fn new(
         input_channel: usize,
         output_channel: usize,
@@ -190,8 +210,8 @@ impl Conv2dTranspose {
         stride: [usize; 2],
         padding: [usize; 2],
         out_size: Option<[usize; 2]>,
-        w: impl Initializer<ParamNDA> + Scope,
-        b: Option<impl Initializer<ParamNDA> + Scope>,
+        w: impl Initializer<ParamNDA<T>> + Scope,
+        b: Option<impl Initializer<ParamNDA<T>> + Scope>,
     ) -> Self {
         Self {
             kernel_size,
@@ -209,9 +229,9 @@ impl Conv2dTranspose {
     }
 }
 
-impl Layer for Conv2dTranspose {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Conv2dTranspose<T> {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, train: bool) -> Self::Output
     where
@@ -290,20 +310,21 @@ impl Layer for Conv2dTranspose {
         // }
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         [self.w.clone()].into_iter().chain(self.b.clone()).collect()
     }
 }
 
 // TODO: test that Conv2dTranspose is the same as conv2d_transpose
 
-pub fn conv2d(
+pub fn conv2d<T: Float>(
     stride: [usize; 2],
     padding: [usize; 2],
-    kernel: &ComputedNDA,
-    bias: Option<&ComputedNDA>,
-    x: &ComputedNDA,
-) -> ComputedNDA {
+    kernel: &ComputedNDA<T>,
+    bias: Option<&ComputedNDA<T>>,
+    x: &ComputedNDA<T>,
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("conv2d");
     let kh = kernel.shape()[2];
     let kw = kernel.shape()[3];
 
@@ -351,14 +372,15 @@ pub fn conv2d(
     y
 }
 
-pub fn conv2d_transpose(
+pub fn conv2d_transpose<T: Float>(
     stride: [usize; 2],
     padding: [usize; 2],
     out_size: [usize; 2],
-    kernel: &ComputedNDA, // [out_ch, in_ch, kh, kw]
-    bias: Option<&ComputedNDA>,
-    x: &ComputedNDA, // [batch, out_ch, oh, ow]
-) -> ComputedNDA {
+    kernel: &ComputedNDA<T>, // [out_ch, in_ch, kh, kw]
+    bias: Option<&ComputedNDA<T>>,
+    x: &ComputedNDA<T>, // [batch, out_ch, oh, ow]
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("conv2d_transpose");
     let kh = kernel.shape()[2];
     let kw = kernel.shape()[3];
 
@@ -411,13 +433,14 @@ pub fn conv2d_transpose(
     y
 }
 
-pub fn conv2d_grad_w(
+pub fn conv2d_grad_w<T: Float>(
     stride: [usize; 2],
     padding: [usize; 2],
     kernel_size: [usize; 2],
-    x: &ComputedNDA,
-    gy: &ComputedNDA,
-) -> ComputedNDA {
+    x: &ComputedNDA<T>,
+    gy: &ComputedNDA<T>,
+) -> ComputedNDA<T> {
+    let _scope = profile_scope("conv2d_grad_w");
     let col = im2col(x, kernel_size, stride, padding, false);
 
     let gw = ndarray_util::tensordot(
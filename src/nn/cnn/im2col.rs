@@ -27,11 +27,12 @@ impl Im2col {
     }
 }
 
-impl Layer for Im2col {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Im2col {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, input: Self::Input, _train: bool) -> Self::Output {
+        let _scope = profile_scope("Im2col");
         let y = ComputedNDA::new(im2col(
             &*input,
             self.kernel_size,
@@ -59,7 +60,7 @@ impl Layer for Im2col {
         y
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         Vec::new()
     }
 }
@@ -90,11 +91,12 @@ impl Col2im {
     }
 }
 
-impl Layer for Col2im {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Col2im {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, input: Self::Input, _train: bool) -> Self::Output {
+        let _scope = profile_scope("Col2im");
         let y = ComputedNDA::new(col2im(
             &*input,
             self.input_shape,
@@ -117,24 +119,24 @@ impl Layer for Col2im {
         y
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         Vec::new()
     }
 }
 
-pub fn im2col(
-    x: &NDArray,
+pub fn im2col<T: Float>(
+    x: &NDArray<T>,
     [kh, kw]: [usize; 2],
     [sh, sw]: [usize; 2],
     [ph, pw]: [usize; 2],
     to_matrix: bool,
-) -> NDArray {
+) -> NDArray<T> {
     assert_eq!(x.ndim(), 4);
     let s = x.shape();
     let oh = get_conv_outsize(s[2], kh, sh, ph);
     let ow = get_conv_outsize(s[3], kw, sw, pw);
 
-    let f = |x: &NDArray| {
+    let f = |x: &NDArray<T>| {
         let mut cols = Array6::zeros([s[0], s[1], kh, kw, oh, ow]);
         for h in 0..kh {
             let hlim = h + sh * (oh - 1) + 1;
@@ -192,14 +194,14 @@ fn test_im2col() {
     assert_eq!(cols.shape(), [4, 4]);
 }
 
-pub fn col2im(
-    x: &NDArray,
+pub fn col2im<T: Float>(
+    x: &NDArray<T>,
     img_shape: [usize; 4],
     [kh, kw]: [usize; 2],
     [sh, sw]: [usize; 2],
     [ph, pw]: [usize; 2],
     to_matrix: bool,
-) -> NDArray {
+) -> NDArray<T> {
     let s = img_shape;
     let oh = get_conv_outsize(s[2], kh, sh, ph);
     let ow = get_conv_outsize(s[3], kw, sw, pw);
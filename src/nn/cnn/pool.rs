@@ -1,12 +1,12 @@
 use super::im2col::{get_conv_outsize, Im2col};
 use crate::{functions::*, *};
 
-pub fn naive_max_pooling(
-    x: &ComputedNDA,
+pub fn naive_max_pooling<T: Float>(
+    x: &ComputedNDA<T>,
     kernel_size: [usize; 2],
     stride: [usize; 2],
     pad: [usize; 2],
-) -> ComputedNDA {
+) -> ComputedNDA<T> {
     let x_shape = x.shape();
     let [kh, kw] = kernel_size;
     let oh = get_conv_outsize(x_shape[2], kernel_size[0], stride[0], pad[0]);
@@ -35,12 +35,12 @@ fn test_naive_max_pooling() {
     dbg!(&*grads[0]);
 }
 
-pub fn naive_sum_pooling(
-    x: &ComputedNDA,
+pub fn naive_sum_pooling<T: Float>(
+    x: &ComputedNDA<T>,
     kernel_size: [usize; 2],
     stride: [usize; 2],
     pad: [usize; 2],
-) -> ComputedNDA {
+) -> ComputedNDA<T> {
     let x_shape = x.shape();
     let [kh, kw] = kernel_size;
     let oh = get_conv_outsize(x_shape[2], kernel_size[0], stride[0], pad[0]);
@@ -14,11 +14,12 @@ impl UpSampling2d {
     }
 }
 
-impl Layer for UpSampling2d {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for UpSampling2d {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, input: Self::Input, _train: bool) -> Self::Output {
+        let _scope = profile_scope("UpSampling2d");
         let s = input.shape();
         let [sh, sw] = self.size;
         let mut y = Array4::zeros([s[0], s[1], s[2] * sh, s[3] * sw]);
@@ -44,7 +45,7 @@ impl Layer for UpSampling2d {
         y
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         Vec::new()
     }
 }
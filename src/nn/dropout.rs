@@ -26,7 +26,7 @@ impl Dropout {
         }
     }
 
-    pub fn factor(&self, shape: &[usize]) -> ComputedNDA {
+    pub fn factor<T: Float>(&self, shape: &[usize]) -> ComputedNDA<T> {
         let rate = (self.rate_fn)();
 
         ComputedNDA::new(
@@ -35,15 +35,15 @@ impl Dropout {
                 Uniform::new(0.0, 1.0),
                 &mut *self.rng.lock().unwrap(),
             )
-            .map(|x| if *x > rate { 1.0 / (1.0 - rate) } else { 0.0 })
+            .map(|x| T::from_f32(if *x > rate { 1.0 / (1.0 - rate) } else { 0.0 }))
             .into_ndarray(),
         )
     }
 }
 
-impl Layer for Dropout {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Dropout {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, train: bool) -> Self::Output {
         if !train {
@@ -52,7 +52,7 @@ impl Layer for Dropout {
         self.factor(x.shape()) * x
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         vec![]
     }
 }
@@ -1,12 +1,12 @@
 use crate::{functions::select, initializers::Initializer, *};
 
-pub struct Embedding {
+pub struct Embedding<T: Float = f32> {
     pub embedding_size: usize,
-    pub weights: ParamNDA,
+    pub weights: ParamNDA<T>,
 }
 
-impl Embedding {
-    pub fn new(embedding_size: usize, len: usize, init:  impl Initializer<ParamNDA>) -> Self {
+impl<T: Float> Embedding<T> {
+    pub fn new(embedding_size: usize, len: usize, init:  impl Initializer<ParamNDA<T>>) -> Self {
         Self {
             embedding_size,
             weights: init.initialize(&[len, embedding_size]),
@@ -14,9 +14,9 @@ impl Embedding {
     }
 }
 
-impl Layer for Embedding {
+impl<T: Float> Layer<T> for Embedding<T> {
     type Input = Vec<usize>;
-    type Output = ComputedNDA;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, _train: bool) -> Self::Output {
         let w = self.weights.get();
@@ -25,7 +25,7 @@ impl Layer for Embedding {
         select(0, x, &w)
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         vec![self.weights.clone()]
     }
 }
@@ -1,18 +1,58 @@
-use crate::*;
+use super::state_dict::{self, LoadReport, StateDict};
+use crate::{error::unwrap_or_panic, *};
 
-pub trait Layer: 'static {
+pub trait Layer<F: Float = f32>: 'static {
     type Input;
     type Output;
 
     fn call(&self, input: Self::Input, train: bool) -> Self::Output;
-    fn all_params(&self) -> Vec<ParamNDA>;
+    fn all_params(&self) -> Vec<ParamNDA<F>>;
 
     fn name(&self) -> &'static str {
         let name = std::any::type_name::<Self>();
         name.split("::").last().unwrap_or(name)
     }
 
-    fn then<T: Layer<Input = Self::Output>>(self, next: T) -> Then<Self, T>
+    /// Makes all the params untrainable. See `Param::is_trainable`.
+    fn freeze(&self) {
+        for param in self.all_params() {
+            param.set_trainable(false);
+        }
+    }
+
+    fn unfreeze(&self) {
+        for param in self.all_params() {
+            param.set_trainable(true);
+        }
+    }
+
+    /// Returns the data of all the params keyed by their names.
+    /// Panics if params share a name.
+    fn state_dict(&self) -> StateDict<F> {
+        unwrap_or_panic(self.try_state_dict())
+    }
+
+    fn try_state_dict(&self) -> Result<StateDict<F>, Error> {
+        state_dict::state_dict(&self.all_params())
+    }
+
+    /// Sets the data of the params from `state_dict` by their names.
+    ///
+    /// In the strict mode, panics without loading anything unless the keys and the shapes
+    /// match the params exactly. Otherwise, loads the matching params and reports the others.
+    fn load_state_dict(&self, state_dict: &StateDict<F>, strict: bool) -> LoadReport {
+        unwrap_or_panic(self.try_load_state_dict(state_dict, strict))
+    }
+
+    fn try_load_state_dict(
+        &self,
+        state_dict: &StateDict<F>,
+        strict: bool,
+    ) -> Result<LoadReport, Error> {
+        state_dict::load_state_dict(&self.all_params(), state_dict, strict)
+    }
+
+    fn then<T: Layer<F, Input = Self::Output>>(self, next: T) -> Then<Self, T>
     where
         Self: Sized,
     {
@@ -30,18 +70,18 @@ pub trait Layer: 'static {
     }
 }
 
-pub struct Then<T: Layer, U: Layer<Input = T::Output>> {
+pub struct Then<T, U> {
     pub first: T,
     pub second: U,
 }
 
-impl<T: Layer, U: Layer<Input = T::Output>> Then<T, U> {
+impl<T, U> Then<T, U> {
     pub fn new(first: T, second: U) -> Self {
         Self { first, second }
     }
 }
 
-impl<T: Layer, U: Layer<Input = T::Output>> Layer for Then<T, U> {
+impl<F: Float, T: Layer<F>, U: Layer<F, Input = T::Output>> Layer<F> for Then<T, U> {
     type Input = T::Input;
     type Output = U::Output;
 
@@ -49,7 +89,7 @@ impl<T: Layer, U: Layer<Input = T::Output>> Layer for Then<T, U> {
         self.second.call(self.first.call(x, train), train)
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<F>> {
         self.first
             .all_params()
             .into_iter()
@@ -60,7 +100,7 @@ impl<T: Layer, U: Layer<Input = T::Output>> Layer for Then<T, U> {
 
 pub struct FnAsLayer<I: 'static, O: 'static>(pub &'static (dyn Fn(&I) -> O + Sync + Send));
 
-impl<I: 'static, O: 'static> Layer for FnAsLayer<I, O> {
+impl<F: Float, I: 'static, O: 'static> Layer<F> for FnAsLayer<I, O> {
     type Input = I;
     type Output = O;
 
@@ -68,7 +108,7 @@ impl<I: 'static, O: 'static> Layer for FnAsLayer<I, O> {
         (self.0)(&x)
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<F>> {
         vec![]
     }
 }
@@ -88,3 +128,48 @@ fn test() {
     let y = fnn.call(backprop(NDArray::ones(&[1, 2][..])), false);
     assert_eq!(y.shape(), &[1, 2]);
 }
+
+#[test]
+fn test_freeze() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let init = initializers::with_optimizer::InitializerWithOptimizer::new(
+        initializers::random_initializer::RandomInitializer::new(
+            ndarray_rand::rand_distr::Normal::new(0.0, 0.1).unwrap(),
+        ),
+        optimizers::Adam::new(),
+    );
+    let backbone = nn::Linear::new(2, 3, init.clone(), Some(init.clone()));
+    let head = nn::Linear::new(3, 1, init.clone(), Some(init.clone()));
+    let backward_calls = Arc::new(AtomicUsize::new(0));
+    let step = |backbone: &nn::Linear, head: &nn::Linear| {
+        let h = backbone.call(ComputedNDA::new(NDArray::ones(&[4, 2][..])), true);
+        let backward_calls = backward_calls.clone();
+        h.register_hook(move |_| {
+            backward_calls.fetch_add(1, Ordering::Relaxed);
+            None
+        });
+        let loss = head.call(h, true).sum([0, 1], false);
+        let mut ga = GradientsAccumulator::new();
+        ga.compute(&loss);
+        ga
+    };
+
+    backbone.freeze();
+    let w = (*backbone.w.get()).clone();
+    let mut ga = step(&backbone, &head);
+    assert_eq!(ga.table.len(), 2);
+    assert!(head.all_params().iter().all(|p| ga.table.contains_key(p)));
+    // the backward of the backbone is not run
+    assert_eq!(backward_calls.load(Ordering::Relaxed), 0);
+    ga.optimize();
+    assert_eq!(*backbone.w.get(), w);
+
+    backbone.unfreeze();
+    let ga = step(&backbone, &head);
+    assert_eq!(ga.table.len(), 4);
+    assert_eq!(backward_calls.load(Ordering::Relaxed), 1);
+}
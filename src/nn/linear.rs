@@ -2,17 +2,18 @@ use super::Layer;
 use crate::{functions::*, initializers::Initializer, optimizers::Fixed, *};
 
 #[derive(serde::Serialize, serde::Deserialize)]
-pub struct Linear {
-    pub w: ParamNDA,
-    pub b: Option<ParamNDA>,
+#[serde(bound = "")]
+pub struct Linear<T: Float = f32> {
+    pub w: ParamNDA<T>,
+    pub b: Option<ParamNDA<T>>,
 }
 
-impl Linear {
+impl<T: Float> Linear<T> {
     pub fn new(
         input: usize,
         output: usize,
-        w: impl Initializer<ParamNDA>,
-        b: Option<impl Initializer<ParamNDA>>,
+        w: impl Initializer<ParamNDA<T>>,
+        b: Option<impl Initializer<ParamNDA<T>>>,
     ) -> Self {
         Self {
             w: w.initialize(&[input, output]),
@@ -33,11 +34,21 @@ impl Linear {
                 .map(|b| ParamNDA::new((*b.get()).clone(), self.w.get_function_name(), Fixed)),
         }
     }
+
+    /// Same as `call`, but takes a sparse input without densifying it.
+    pub fn call_sparse(&self, x: &sparse::SparseNDA<T>, _train: bool) -> ComputedNDA<T> {
+        let y = matmul(x, &self.w.get());
+        if let Some(b) = &self.b {
+            &y + &b.get()
+        } else {
+            y
+        }
+    }
 }
 
-impl Layer for Linear {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Linear<T> {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, _train: bool) -> Self::Output {
         if let Some(b) = &self.b {
@@ -47,7 +58,28 @@ impl Layer for Linear {
         }
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         [self.w.clone()].into_iter().chain(self.b.clone()).collect()
     }
 }
+
+#[test]
+fn test_sparse() {
+    use ndarray::array;
+    use ndarray_rand::rand_distr::Uniform;
+
+    let init = initializers::with_optimizer::InitializerWithOptimizer::new(
+        initializers::random_initializer::RandomInitializer::new(Uniform::new(-1.0f32, 1.0)),
+        optimizers::SGD::new(0.1),
+    );
+    let linear = Linear::new(3, 2, init.clone(), Some(init.clone()));
+
+    let x = array![[0.0, 2.0, 0.0], [0.0, 0.0, 0.0], [-1.0, 0.0, 3.0]].into_ndarray();
+    let y = linear.call_sparse(&sparse::SparseNDA::new(sparse::Csr::from_dense(&x)), true);
+    let y_dense = linear.call(ComputedNDA::new(x), true);
+    assert!((&*y - &*y_dense).iter().all(|d| d.abs() < 1e-6));
+
+    let gs = gradients(&[y], &[linear.w.get()], false);
+    let gs_dense = gradients(&[y_dense], &[linear.w.get()], false);
+    assert!((&*gs[0] - &*gs_dense[0]).iter().all(|d| d.abs() < 1e-6));
+}
@@ -4,19 +4,19 @@ use crate::{
     *,
 };
 
-pub struct MLP {
-    pub linears: Vec<Linear>,
+pub struct MLP<T: Float = f32> {
+    pub linears: Vec<Linear<T>>,
     pub dropout: Option<Dropout>,
-    pub activation: Box<dyn Fn(ComputedNDA) -> ComputedNDA + Sync + Send>,
+    pub activation: Box<dyn Fn(ComputedNDA<T>) -> ComputedNDA<T> + Sync + Send>,
 }
 
-impl MLP {
+impl<T: Float> MLP<T> {
     pub fn new(
         sizes: &[usize],
         dropout: Option<Dropout>,
-        activation: impl Fn(ComputedNDA) -> ComputedNDA + Sync + Send + 'static,
-        w: impl Initializer<ParamNDA> + Scope,
-        b: Option<impl Initializer<ParamNDA> + Scope>,
+        activation: impl Fn(ComputedNDA<T>) -> ComputedNDA<T> + Sync + Send + 'static,
+        w: impl Initializer<ParamNDA<T>> + Scope,
+        b: Option<impl Initializer<ParamNDA<T>> + Scope>,
     ) -> Self {
         Self {
             linears: sizes
@@ -37,9 +37,9 @@ impl MLP {
     }
 }
 
-impl Layer for MLP {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for MLP<T> {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, train: bool) -> Self::Output {
         let mut y = x.clone();
@@ -53,7 +53,7 @@ impl Layer for MLP {
         self.linears.last().unwrap().call(y, train)
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         self.linears
             .iter()
             .flat_map(|linear| linear.all_params())
@@ -1,5 +1,6 @@
 pub mod activations;
 pub mod attention;
+mod checkpointed;
 mod cnn;
 mod dropout;
 mod embedding;
@@ -8,10 +9,15 @@ mod linear;
 mod mlp;
 pub mod normalization;
 pub mod rnn;
+mod state_dict;
+mod typed;
 
+pub use checkpointed::*;
 pub use cnn::*;
 pub use dropout::*;
 pub use embedding::*;
 pub use layer::*;
 pub use linear::*;
 pub use mlp::*;
+pub use state_dict::{LoadReport, StateDict};
+pub use typed::*;
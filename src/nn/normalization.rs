@@ -1,33 +1,46 @@
 use ndarray::Axis;
+use num_traits::FromPrimitive;
 
 use crate::{ndarray_util::map_axes_keep_dim, *};
 
 // TODO: infer time
 
-pub struct Normalization {
+pub struct Normalization<T: Float = f32> {
     pub axis: Vec<usize>,
-    pub gamma: ParamNDA,
-    pub beta: ParamNDA,
+    pub gamma: ParamNDA<T>,
+    pub beta: ParamNDA<T>,
     pub eps: f32, // 0.001
 }
 
-impl Normalization {
+impl<T: Float> Normalization<T> {
     pub fn new(
         axis: Vec<usize>,
         bias_shape: Vec<usize>,
         eps: f32,
-        optimizer: impl Optimizer<NDArray> + Clone,
+        optimizer: impl Optimizer<NDArray<T>> + Clone,
+    ) -> Self {
+        Self::new_with_name("normalization", axis, bias_shape, eps, optimizer)
+    }
+
+    /// The params are named `{name}:gamma` and `{name}:beta`.
+    /// Give each layer a distinct name to tell them apart in a state dict.
+    pub fn new_with_name(
+        name: &str,
+        axis: Vec<usize>,
+        bias_shape: Vec<usize>,
+        eps: f32,
+        optimizer: impl Optimizer<NDArray<T>> + Clone,
     ) -> Self {
         Self {
             axis,
             gamma: ParamNDA::new(
-                NDArray::from_elem(bias_shape.clone(), 1.0),
-                "normalization".into(),
+                NDArray::from_elem(bias_shape.clone(), T::one()),
+                format!("{}:gamma", name).into(),
                 optimizer.clone(),
             ),
             beta: ParamNDA::new(
-                NDArray::from_elem(bias_shape, 0.0),
-                "normalization".into(),
+                NDArray::from_elem(bias_shape, T::zero()),
+                format!("{}:beta", name).into(),
                 optimizer.clone(),
             ),
             eps,
@@ -35,32 +48,38 @@ impl Normalization {
     }
 }
 
-impl Layer for Normalization {
-    type Input = ComputedNDA;
-    type Output = ComputedNDA;
+impl<T: Float + FromPrimitive> Layer<T> for Normalization<T> {
+    type Input = ComputedNDA<T>;
+    type Output = ComputedNDA<T>;
 
     fn call(&self, x: Self::Input, _train: bool) -> Self::Output {
         let mean = map_axes_keep_dim(&*x, &self.axis, |x| x.mean_axis(Axis(1)).unwrap());
-        let var = map_axes_keep_dim(&*x, &self.axis, |x| x.var_axis(Axis(1), 1.0));
-        let shape = x.shape().to_vec();
+        let var = map_axes_keep_dim(&*x, &self.axis, |x| x.var_axis(Axis(1), T::one()));
+        let eps = <T as Float>::from_f32(self.eps);
+        let std = var.map(|x| (*x + eps).sqrt());
         (x - ComputedNDA::new(mean.into_ndarray()))
-            * (self.gamma.get().broadcast(shape)
-                / ComputedNDA::new((var + self.eps).map(|x| x.sqrt()).into_ndarray()))
+            * (self.gamma.get() / ComputedNDA::new(std.into_ndarray()))
             + self.beta.get()
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         vec![self.gamma.clone(), self.beta.clone()]
     }
 }
 
 #[test]
 fn test() {
-    let x = ComputedNDA::new(ndarray::array![1.0, 2.0, 3.0, 4.0, 5.0, 6.0].into_ndarray());
+    let x: ComputedNDA = ndarray::array![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+        .into_ndarray()
+        .into();
     let bn = Normalization::new(vec![0], vec![6], 0.001, optimizers::Adam::new());
     let y = bn.call(x, false);
     assert!((y.mean().unwrap() - 0.0).abs() < 1e-6);
     assert!((y.var(1.0) - 1.0).abs() < 0.01);
+    assert_eq!(
+        bn.state_dict().keys().collect::<Vec<_>>(),
+        ["normalization:beta", "normalization:gamma"]
+    );
 
     let x = backprop(
         ndarray::Array::from_shape_vec(
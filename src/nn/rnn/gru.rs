@@ -5,19 +5,19 @@ use crate::{
 
 use super::Cell;
 
-pub struct Gru {
+pub struct Gru<T: Float = f32> {
     pub input_size: usize,
     pub state_size: usize,
-    pub ws: [ParamNDA; 3],
-    pub us: [ParamNDA; 3],
-    pub bs: [ParamNDA; 3],
+    pub ws: [ParamNDA<T>; 3],
+    pub us: [ParamNDA<T>; 3],
+    pub bs: [ParamNDA<T>; 3],
 }
 
-impl Gru {
+impl<T: Float> Gru<T> {
     pub fn new(
         input_size: usize,
         state_size: usize,
-        kernel: impl initializers::Initializer<ParamNDA> + initializers::Scope,
+        kernel: impl initializers::Initializer<ParamNDA<T>> + initializers::Scope,
     ) -> Self {
         Self {
             input_size,
@@ -53,9 +53,9 @@ impl Gru {
     }
 }
 
-impl Layer for Gru {
-    type Input = (ComputedNDA, ComputedNDA);
-    type Output = ComputedNDA;
+impl<T: Float> Layer<T> for Gru<T> {
+    type Input = (ComputedNDA<T>, ComputedNDA<T>);
+    type Output = ComputedNDA<T>;
 
     fn call(&self, input: Self::Input, _train: bool) -> Self::Output {
         let (x, state) = input;
@@ -74,7 +74,7 @@ impl Layer for Gru {
         state
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         self.ws
             .iter()
             .chain(self.us.iter())
@@ -84,8 +84,8 @@ impl Layer for Gru {
     }
 }
 
-impl Cell for Gru {
-    type State = ComputedNDA;
+impl<T: Float> Cell<T> for Gru<T> {
+    type State = ComputedNDA<T>;
 
     fn initial_state(&self, batch_size: usize) -> Self::State {
         ComputedNDA::new(NDArray::zeros(&[batch_size, self.state_size][..]))
@@ -95,7 +95,7 @@ impl Cell for Gru {
         self.input_size
     }
 
-    fn step(&self, x: ComputedNDA, state: Self::State) -> (Self::State, ComputedNDA) {
+    fn step(&self, x: ComputedNDA<T>, state: Self::State) -> (Self::State, ComputedNDA<T>) {
         let state = self.call((x, state), false);
         (state.clone(), state)
     }
@@ -5,19 +5,19 @@ use crate::{
 
 use super::Cell;
 
-pub struct Lstm {
+pub struct Lstm<T: Float = f32> {
     pub input_size: usize,
     pub state_size: usize,
-    pub ws: [ParamNDA; 4],
-    pub us: [ParamNDA; 4],
-    pub bs: [ParamNDA; 4],
+    pub ws: [ParamNDA<T>; 4],
+    pub us: [ParamNDA<T>; 4],
+    pub bs: [ParamNDA<T>; 4],
 }
 
-impl Lstm {
+impl<T: Float> Lstm<T> {
     pub fn new(
         input_size: usize,
         state_size: usize,
-        kernel: &mut impl initializers::Initializer<ParamNDA>,
+        kernel: &mut impl initializers::Initializer<ParamNDA<T>>,
     ) -> Self {
         Self {
             input_size,
@@ -44,9 +44,9 @@ impl Lstm {
     }
 }
 
-impl Layer for Lstm {
-    type Input = (ComputedNDA, [ComputedNDA; 2]);
-    type Output = ([ComputedNDA; 2], ComputedNDA);
+impl<T: Float> Layer<T> for Lstm<T> {
+    type Input = (ComputedNDA<T>, [ComputedNDA<T>; 2]);
+    type Output = ([ComputedNDA<T>; 2], ComputedNDA<T>);
 
     fn call(&self, input: Self::Input, _train: bool) -> Self::Output {
         let (x, state) = input;
@@ -76,7 +76,7 @@ impl Layer for Lstm {
         ([c, h.clone()], h)
     }
 
-    fn all_params(&self) -> Vec<ParamNDA> {
+    fn all_params(&self) -> Vec<ParamNDA<T>> {
         self.ws
             .iter()
             .chain(self.us.iter())
@@ -86,8 +86,8 @@ impl Layer for Lstm {
     }
 }
 
-impl Cell for Lstm {
-    type State = [ComputedNDA; 2];
+impl<T: Float> Cell<T> for Lstm<T> {
+    type State = [ComputedNDA<T>; 2];
 
     fn initial_state(&self, batch_size: usize) -> Self::State {
         [
@@ -100,7 +100,7 @@ impl Cell for Lstm {
         self.input_size
     }
 
-    fn step(&self, x: ComputedNDA, state: Self::State) -> (Self::State, ComputedNDA) {
+    fn step(&self, x: ComputedNDA<T>, state: Self::State) -> (Self::State, ComputedNDA<T>) {
         self.call((x, state), false)
     }
 }
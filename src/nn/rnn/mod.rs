@@ -4,20 +4,20 @@ mod lstm;
 pub use gru::Gru;
 pub use lstm::Lstm;
 
-use crate::ComputedNDA;
+use crate::{ComputedNDA, Float};
 
-pub trait Cell {
+pub trait Cell<T: Float = f32> {
     type State: Clone + 'static;
 
     fn initial_state(&self, batch_size: usize) -> Self::State;
     fn get_input_size(&self) -> usize;
-    fn step(&self, x: ComputedNDA, state: Self::State) -> (Self::State, ComputedNDA);
+    fn step(&self, x: ComputedNDA<T>, state: Self::State) -> (Self::State, ComputedNDA<T>);
 
     fn encode(
         &self,
         initial_state: Self::State,
-        x: &Vec<ComputedNDA>,
-    ) -> (Self::State, Vec<ComputedNDA>) {
+        x: &Vec<ComputedNDA<T>>,
+    ) -> (Self::State, Vec<ComputedNDA<T>>) {
         let mut state = initial_state.clone();
         let mut outputs = vec![];
         for x in x {
@@ -31,11 +31,11 @@ pub trait Cell {
     fn decode(
         &self,
         mut state: Self::State,
-        mut input: ComputedNDA,
-        output_fn: impl Fn(ComputedNDA) -> ComputedNDA,
-        output_to_input_fn: impl Fn(ComputedNDA) -> ComputedNDA,
+        mut input: ComputedNDA<T>,
+        output_fn: impl Fn(ComputedNDA<T>) -> ComputedNDA<T>,
+        output_to_input_fn: impl Fn(ComputedNDA<T>) -> ComputedNDA<T>,
         len: usize,
-    ) -> Vec<ComputedNDA> {
+    ) -> Vec<ComputedNDA<T>> {
         let mut outputs = vec![];
         for _ in 0..len {
             let output;
@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::*;
+
+/// The data of params keyed by their names, which are the `Scope` paths joined with `:`.
+pub type StateDict<T = f32> = BTreeMap<String, NDArray<T>>;
+
+/// The keys that did not match in `Layer::load_state_dict`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// The params without data in the state dict. They are left as they are.
+    pub missing: Vec<String>,
+    /// The keys in the state dict without params.
+    pub unexpected: Vec<String>,
+    /// The names with the shapes of the params and the shapes in the state dict.
+    /// These params are not loaded.
+    pub shape_mismatches: Vec<(String, Vec<usize>, Vec<usize>)>,
+}
+
+impl LoadReport {
+    /// Returns true if all the params are loaded and all the keys are used.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.shape_mismatches.is_empty()
+    }
+}
+
+pub(crate) fn params_by_name<T: Float>(
+    params: &[ParamNDA<T>],
+) -> Result<BTreeMap<String, &ParamNDA<T>>, Error> {
+    let mut map = BTreeMap::new();
+    for param in params {
+        let name = param.name().into_owned();
+        match map.get(&name) {
+            // a param shared by layers, such as tied weights
+            Some(&p) if p == param => {}
+            Some(_) => return Err(Error::DuplicateParamName { name }),
+            None => {
+                map.insert(name, param);
+            }
+        }
+    }
+    Ok(map)
+}
+
+pub(crate) fn state_dict<T: Float>(params: &[ParamNDA<T>]) -> Result<StateDict<T>, Error> {
+    Ok(params_by_name(params)?
+        .into_iter()
+        .map(|(name, param)| (name, (*param.get()).clone()))
+        .collect())
+}
+
+pub(crate) fn load_state_dict<T: Float>(
+    params: &[ParamNDA<T>],
+    state_dict: &StateDict<T>,
+    strict: bool,
+) -> Result<LoadReport, Error> {
+    let params = params_by_name(params)?;
+    let mut report = LoadReport::default();
+    let mut loads = HashMap::new();
+    for (name, param) in &params {
+        let Some(data) = state_dict.get(name) else {
+            report.missing.push(name.clone());
+            continue;
+        };
+        let shape = param.get().shape().to_vec();
+        if shape != data.shape() {
+            report
+                .shape_mismatches
+                .push((name.clone(), shape, data.shape().to_vec()));
+            continue;
+        }
+        loads.insert(name, data);
+    }
+    report.unexpected = state_dict
+        .keys()
+        .filter(|name| !params.contains_key(*name))
+        .cloned()
+        .collect();
+
+    if strict && !report.is_complete() {
+        return Err(Error::StateDictMismatch {
+            missing: report.missing,
+            unexpected: report.unexpected,
+            shape_mismatches: report.shape_mismatches,
+        });
+    }
+    for (name, data) in loads {
+        params[name].clone().set(data.clone());
+    }
+    Ok(report)
+}
+
+#[test]
+fn test() {
+    use crate::{
+        initializers::{with_optimizer::InitializerWithOptimizer, Scope},
+        nn::*,
+    };
+
+    let init = InitializerWithOptimizer::new(
+        initializers::random_initializer::RandomInitializer::new(
+            ndarray_rand::rand_distr::Normal::new(0.0, 0.1).unwrap(),
+        ),
+        optimizers::Adam::new(),
+    );
+    let new = |seed: usize| {
+        let init = init.scope(seed);
+        let l1 = Linear::new(
+            2,
+            3,
+            init.scope("w").scope("l1"),
+            Some(init.scope("b").scope("l1")),
+        );
+        let l2 = Linear::new(
+            3,
+            1,
+            init.scope("w").scope("l2"),
+            Some(init.scope("b").scope("l2")),
+        );
+        (l1, l2)
+    };
+    let (l1, l2) = new(0);
+    let model = l1.then(l2);
+    let dict = model.state_dict();
+    assert_eq!(
+        dict.keys().collect::<Vec<_>>(),
+        ["0:b:l1", "0:b:l2", "0:w:l1", "0:w:l2"]
+    );
+
+    // the keys differ by the root scope
+    let (l1, l2) = new(1);
+    let report = l2.load_state_dict(&dict, false);
+    assert_eq!(report.missing, ["1:b:l2", "1:w:l2"]);
+    assert_eq!(report.unexpected.len(), 4);
+    assert!(matches!(
+        l2.try_load_state_dict(&dict, true),
+        Err(Error::StateDictMismatch { .. })
+    ));
+
+    // the layer order does not matter
+    let dict: StateDict = dict
+        .into_iter()
+        .map(|(k, v)| (k.replacen('0', "1", 1), v))
+        .collect();
+    let model = l2.then_fn(&|x: &ComputedNDA| x.clone()).then(l1);
+    let w = (*model.first.first.w.get()).clone();
+    let report = model.load_state_dict(&dict, true);
+    assert!(report.is_complete());
+    assert_ne!(*model.first.first.w.get(), w);
+    assert_eq!(*model.first.first.w.get(), dict["1:w:l2"]);
+
+    // a shape mismatch is reported and the param is not loaded
+    let mut dict = dict;
+    dict.insert("1:b:l2".to_string(), NDArray::zeros(&[2][..]));
+    let b = (*model.first.first.b.as_ref().unwrap().get()).clone();
+    let report = model.load_state_dict(&dict, false);
+    assert_eq!(
+        report.shape_mismatches,
+        [("1:b:l2".to_string(), vec![1], vec![2])]
+    );
+    assert_eq!(*model.first.first.b.as_ref().unwrap().get(), b);
+
+    // tied weights are saved once
+    let l = Linear::new(2, 2, init.scope("w").scope("tied"), None::<()>);
+    let tied = Linear {
+        w: l.w.clone(),
+        b: None,
+    };
+    let model = l.then(tied);
+    assert_eq!(model.all_params().len(), 2);
+    assert_eq!(model.state_dict().keys().collect::<Vec<_>>(), ["w:tied"]);
+    assert!(model
+        .load_state_dict(&model.state_dict(), true)
+        .is_complete());
+
+    // w and b in the same scope
+    let l = Linear::new(2, 2, init.scope("l"), Some(init.scope("l")));
+    assert_eq!(
+        l.try_state_dict(),
+        Err(Error::DuplicateParamName {
+            name: "l".to_string()
+        })
+    );
+}
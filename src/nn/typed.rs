@@ -0,0 +1,90 @@
+use ndarray::{Dimension, Ix2, Ix4};
+
+use super::*;
+use crate::*;
+
+/// A layer whose input and output ranks are known at compile time.
+pub trait TypedLayer<F: Float = f32>:
+    Layer<F, Input = ComputedNDA<F>, Output = ComputedNDA<F>>
+{
+    type InputDim: Dimension;
+    type OutputDim: Dimension;
+}
+
+impl<T: Float> TypedLayer<T> for Linear<T> {
+    type InputDim = Ix2;
+    type OutputDim = Ix2;
+}
+
+impl<T: Float> TypedLayer<T> for MLP<T> {
+    type InputDim = Ix2;
+    type OutputDim = Ix2;
+}
+
+impl<T: Float> TypedLayer<T> for Conv2d<T> {
+    type InputDim = Ix4;
+    type OutputDim = Ix4;
+}
+
+impl<T: Float> TypedLayer<T> for Conv2dTranspose<T> {
+    type InputDim = Ix4;
+    type OutputDim = Ix4;
+}
+
+/// Wraps a `TypedLayer` to take and return `Tensor`s, so that chained layers are checked
+/// to agree on ranks at compile time.
+///
+/// ```compile_fail
+/// use tensorflake::{
+///     initializers::{random_initializer::RandomInitializer, with_optimizer::InitializerWithOptimizer, Scope},
+///     ndarray_rand::rand_distr::Uniform,
+///     nn::*,
+///     *,
+/// };
+///
+/// let init = InitializerWithOptimizer::new(
+///     RandomInitializer::new(Uniform::new(-0.01, 0.01)),
+///     optimizers::SGD::new(0.1),
+/// );
+/// let conv = Conv2d::new(1, 1, [3, 3], [1, 1], [1, 1], init.scope("conv"), Some(init.scope("conv")));
+/// let linear = Linear::new(4, 2, init.clone(), Some(init.clone()));
+/// // `Conv2d` returns a `Tensor<Ix4>`, but `Linear` takes a `Tensor<Ix2>`
+/// let layer = Typed(conv).then(Typed(linear));
+/// ```
+pub struct Typed<L>(pub L);
+
+impl<F: Float, L: TypedLayer<F>> Layer<F> for Typed<L> {
+    type Input = Tensor<L::InputDim, F>;
+    type Output = Tensor<L::OutputDim, F>;
+
+    fn call(&self, x: Self::Input, train: bool) -> Self::Output {
+        Tensor::new(self.0.call(x.into_computed(), train))
+    }
+
+    fn all_params(&self) -> Vec<ParamNDA<F>> {
+        self.0.all_params()
+    }
+
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
+#[test]
+fn test() {
+    use ndarray::array;
+    use ndarray_rand::rand_distr::Uniform;
+
+    let init = initializers::with_optimizer::InitializerWithOptimizer::new(
+        initializers::random_initializer::RandomInitializer::new(Uniform::new(-0.01, 0.01)),
+        optimizers::SGD::new(0.1),
+    );
+
+    let layer = Typed(Linear::new(2, 3, init.clone(), Some(init.clone())))
+        .then(Typed(Linear::new(3, 1, init.clone(), Some(init.clone()))));
+    assert_eq!(layer.all_params().len(), 4);
+
+    let x = Tensor::from_array(array![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+    let y: Tensor<Ix2> = layer.call(x, true);
+    assert_eq!(y.dim(), (4, 1));
+}
@@ -1,6 +1,6 @@
-use crate::*;
+use ndarray::Zip;
 
-const EPS: f32 = 1e-8;
+use crate::*;
 
 #[derive(Clone)]
 pub struct Adam {
@@ -9,9 +9,9 @@ pub struct Adam {
     pub beta2: f32,
 }
 
-pub struct State {
-    mom: NDArray, // TODO: owned mom and vel
-    vel: NDArray,
+pub struct State<T = f32> {
+    mom: NDArray<T>, // TODO: owned mom and vel
+    vel: NDArray<T>,
 }
 
 impl Adam {
@@ -32,8 +32,8 @@ impl Adam {
     }
 }
 
-impl Optimizer<NDArray> for Adam {
-    type State = State;
+impl<T: Float> Optimizer<NDArray<T>> for Adam {
+    type State = State<T>;
 
     fn new_state(&self, shape: &[usize]) -> Self::State {
         State {
@@ -42,13 +42,47 @@ impl Optimizer<NDArray> for Adam {
         }
     }
 
-    fn update(&mut self, data: &mut NDArray, state: &mut Self::State, grad: &NDArray) {
-        state.mom = (&state.mom * self.beta1 + grad * (1.0 - self.beta1)).into_ndarray();
-        state.vel =
-            (&state.vel * self.beta2 + grad.map(|x| x.powi(2)) * (1.0 - self.beta2)).into_ndarray();
-        *data = (&*data + &state.mom / state.vel.map(|x| x.sqrt() + EPS) * -self.learning_rate)
-            .into_ndarray()
-            .into();
+    fn update(&mut self, data: &mut NDArray<T>, state: &mut Self::State, grad: &NDArray<T>) {
+        let (beta1, beta2) = (T::from_f32(self.beta1), T::from_f32(self.beta2));
+        let (beta1_, beta2_) = (T::from_f32(1.0 - self.beta1), T::from_f32(1.0 - self.beta2));
+        let (lr, eps) = (T::from_f32(-self.learning_rate), T::adam_eps());
+        Zip::from(data)
+            .and(&mut state.mom)
+            .and(&mut state.vel)
+            .and(grad)
+            .for_each(|x, m, v, g| {
+                *m = *m * beta1 + *g * beta1_;
+                *v = *v * beta2 + g.powi(2) * beta2_;
+                *x += *m / (v.sqrt() + eps) * lr;
+            });
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.learning_rate = learning_rate;
+        true
+    }
+}
+
+impl<T: Float> SaveOptimizer<NDArray<T>> for Adam {
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_scalar("learning_rate", self.learning_rate);
+        record.set_scalar("beta1", self.beta1);
+        record.set_scalar("beta2", self.beta2);
+        record.set_array("mom", state.mom.clone());
+        record.set_array("vel", state.vel.clone());
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<(Self, Self::State)> {
+        let optimizer = Adam::new_with_params(
+            record.scalar("learning_rate")? as f32,
+            record.scalar("beta1")? as f32,
+            record.scalar("beta2")? as f32,
+        );
+        let state = State {
+            mom: record.array("mom")?,
+            vel: record.array("vel")?,
+        };
+        Some((optimizer, state))
     }
 }
 
@@ -56,3 +90,10 @@ impl Optimizer<NDArray> for Adam {
 fn test() {
     super::test_optimizer(Adam::new_with_params(0.01, 0.9, 0.999));
 }
+
+#[cfg(feature = "half")]
+#[test]
+fn test_half() {
+    super::test_zero_grad::<half::f16>(Adam::new());
+    super::test_zero_grad::<half::bf16>(Adam::new());
+}
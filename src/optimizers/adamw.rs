@@ -1,6 +1,6 @@
-use crate::*;
+use ndarray::Zip;
 
-const EPS: f32 = 1e-8;
+use crate::*;
 
 #[derive(Clone)]
 pub struct AdamW {
@@ -10,9 +10,9 @@ pub struct AdamW {
     pub weight_decay: f32,
 }
 
-pub struct State {
-    mom: NDArray, // TODO: owned mom and vel
-    vel: NDArray,
+pub struct State<T = f32> {
+    mom: NDArray<T>, // TODO: owned mom and vel
+    vel: NDArray<T>,
 }
 
 impl AdamW {
@@ -35,8 +35,8 @@ impl AdamW {
     }
 }
 
-impl Optimizer<NDArray> for AdamW {
-    type State = State;
+impl<T: Float> Optimizer<NDArray<T>> for AdamW {
+    type State = State<T>;
 
     fn new_state(&self, shape: &[usize]) -> Self::State {
         State {
@@ -45,19 +45,55 @@ impl Optimizer<NDArray> for AdamW {
         }
     }
 
-    fn update(&mut self, data: &mut NDArray, state: &mut Self::State, grad: &NDArray) {
-        let wd = &*data * self.weight_decay;
-        let grad = grad + &wd;
-        state.mom = (&state.mom * self.beta1 + &grad * (1.0 - self.beta1)).into_ndarray();
-        state.vel =
-            (&state.vel * self.beta2 + grad.map(|x| x.powi(2)) * (1.0 - self.beta2)).into_ndarray();
-        let mut a =
-            &*data + &state.mom / state.vel.map(|x| x.sqrt() + EPS) * -self.learning_rate + &wd;
+    fn update(&mut self, data: &mut NDArray<T>, state: &mut Self::State, grad: &NDArray<T>) {
+        let (beta1, beta2) = (T::from_f32(self.beta1), T::from_f32(self.beta2));
+        let (beta1_, beta2_) = (T::from_f32(1.0 - self.beta1), T::from_f32(1.0 - self.beta2));
+        let (lr, eps) = (T::from_f32(-self.learning_rate), T::adam_eps());
+        let weight_decay = T::from_f32(self.weight_decay);
+        Zip::from(data)
+            .and(&mut state.mom)
+            .and(&mut state.vel)
+            .and(grad)
+            .for_each(|x, m, v, g| {
+                let wd = *x * weight_decay;
+                let g = *g + wd;
+                *m = *m * beta1 + g * beta1_;
+                *v = *v * beta2 + g.powi(2) * beta2_;
+                let a = *x + *m / (v.sqrt() + eps) * lr + wd;
 
-        // Treat denormals as zero
-        a = a.map(|x| if x.abs() < self.weight_decay { 0.0 } else { *x });
+                // Treat denormals as zero
+                *x = if a.abs() < weight_decay { T::zero() } else { a };
+            });
+    }
 
-        *data = a.into_ndarray().into();
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.learning_rate = learning_rate;
+        true
+    }
+}
+
+impl<T: Float> SaveOptimizer<NDArray<T>> for AdamW {
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_scalar("learning_rate", self.learning_rate);
+        record.set_scalar("beta1", self.beta1);
+        record.set_scalar("beta2", self.beta2);
+        record.set_scalar("weight_decay", self.weight_decay);
+        record.set_array("mom", state.mom.clone());
+        record.set_array("vel", state.vel.clone());
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<(Self, Self::State)> {
+        let optimizer = AdamW::new_with_params(
+            record.scalar("learning_rate")? as f32,
+            record.scalar("beta1")? as f32,
+            record.scalar("beta2")? as f32,
+            record.scalar("weight_decay")? as f32,
+        );
+        let state = State {
+            mom: record.array("mom")?,
+            vel: record.array("vel")?,
+        };
+        Some((optimizer, state))
     }
 }
 
@@ -65,3 +101,10 @@ impl Optimizer<NDArray> for AdamW {
 fn test() {
     super::test_optimizer(AdamW::new_with_params(0.01, 0.9, 0.999, 0.00001));
 }
+
+#[cfg(feature = "half")]
+#[test]
+fn test_half() {
+    super::test_zero_grad::<half::f16>(AdamW::new_with_params(0.001, 0.9, 0.999, 0.0));
+    super::test_zero_grad::<half::bf16>(AdamW::new_with_params(0.001, 0.9, 0.999, 0.0));
+}
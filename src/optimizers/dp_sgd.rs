@@ -0,0 +1,399 @@
+use std::sync::{Arc, Mutex};
+
+use ndarray::Zip;
+use ndarray_rand::{
+    rand::SeedableRng,
+    rand_distr::{Distribution, StandardNormal},
+};
+
+use crate::*;
+
+/// Differentially private SGD (Abadi et al., 2016) on top of `optimizer`.
+///
+/// The gradient of each example must be clipped to the L2 norm `l2_norm_clip` before
+/// it is summed, by giving the losses of the examples to `GradientsAccumulator::compute_clipped`
+/// (`TrainContext::optimize_clipped` in `Train`). On update, Gaussian noise with the standard
+/// deviation `noise_multiplier * l2_norm_clip` is added to the sum, which is then divided by
+/// the number of the clipped examples and passed to `optimizer`. `GradientsAccumulator` panics
+/// when it is given gradients of the params that are not clipped per example or clipped to
+/// a larger norm, since they have no privacy guarantee. So does `update` without the examples.
+///
+/// Each call of `GradientsAccumulator::optimize` is accounted as a step that samples the clipped
+/// examples out of `dataset_size`, so partial batches and updates in the middle of a batch are
+/// accounted as they are.
+///
+/// Clones share the random number generator and the privacy accountant. The accountant is
+/// saved with the params and restored for each of them, which counts the steps the param
+/// is updated in, while the generator is seeded again. Get the loaded `DpSgd` by
+/// `Param::optimizer`.
+#[derive(Clone)]
+pub struct DpSgd<O> {
+    pub optimizer: O,
+    pub l2_norm_clip: f32,
+    pub noise_multiplier: f32,
+    pub dataset_size: usize,
+    rng: Arc<Mutex<DefaultRng>>,
+    accounting: Arc<Mutex<Accounting>>,
+}
+
+struct Accounting {
+    accountant: RdpAccountant,
+    /// The `ClippedExamples::step` counted last, which the other params of the step share.
+    last_step: Option<u64>,
+}
+
+impl<O> DpSgd<O> {
+    /// The noise is drawn from a generator seeded by the OS.
+    pub fn new(
+        optimizer: O,
+        l2_norm_clip: f32,
+        noise_multiplier: f32,
+        dataset_size: usize,
+    ) -> Self {
+        assert!(l2_norm_clip > 0.0);
+        assert!(noise_multiplier >= 0.0);
+        assert!(dataset_size > 0);
+        let accountant = RdpAccountant::new(noise_multiplier as f64);
+        Self::with_accountant(
+            optimizer,
+            l2_norm_clip,
+            noise_multiplier,
+            dataset_size,
+            accountant,
+        )
+    }
+
+    fn with_accountant(
+        optimizer: O,
+        l2_norm_clip: f32,
+        noise_multiplier: f32,
+        dataset_size: usize,
+        accountant: RdpAccountant,
+    ) -> Self {
+        Self {
+            optimizer,
+            l2_norm_clip,
+            noise_multiplier,
+            dataset_size,
+            rng: Arc::new(Mutex::new(DefaultRng::from_entropy())),
+            accounting: Arc::new(Mutex::new(Accounting {
+                accountant,
+                last_step: None,
+            })),
+        }
+    }
+
+    /// Makes the noise reproducible. For tests only; a known seed gives no privacy.
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap() = DefaultRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Returns the accountant, which has counted the steps taken so far.
+    pub fn accountant(&self) -> RdpAccountant {
+        self.accounting.lock().unwrap().accountant.clone()
+    }
+
+    /// Returns the privacy budget spent so far for `delta`.
+    pub fn epsilon(&self, delta: f64) -> f64 {
+        self.accounting.lock().unwrap().accountant.epsilon(delta)
+    }
+}
+
+impl<T: Float, O: Optimizer<NDArray<T>>> Optimizer<NDArray<T>> for DpSgd<O> {
+    type State = O::State;
+
+    fn new_state(&self, shape: &[usize]) -> Self::State {
+        self.optimizer.new_state(shape)
+    }
+
+    fn update(&mut self, data: &mut NDArray<T>, state: &mut Self::State, grad: &NDArray<T>) {
+        self.update_clipped(data, state, grad, None);
+    }
+
+    fn update_clipped(
+        &mut self,
+        data: &mut NDArray<T>,
+        state: &mut Self::State,
+        grad: &NDArray<T>,
+        clipped: Option<ClippedExamples>,
+    ) {
+        let clipped = clipped.expect(
+            "DpSgd needs the gradients of all the examples clipped by GradientsAccumulator::compute_clipped",
+        );
+        assert!(
+            clipped.max_norm <= self.l2_norm_clip,
+            "the gradients are clipped to {}, larger than the l2_norm_clip {} of DpSgd",
+            clipped.max_norm,
+            self.l2_norm_clip
+        );
+        let std = self.noise_multiplier as f64 * self.l2_norm_clip as f64;
+        let scale = T::from_f64(1.0 / clipped.count as f64);
+        let mut grad = grad.to_owned();
+        {
+            let mut rng = self.rng.lock().unwrap();
+            Zip::from(&mut grad).for_each(|g| {
+                let noise: f64 = StandardNormal.sample(&mut *rng);
+                *g = (*g + T::from_f64(noise * std)) * scale;
+            });
+        }
+        self.optimizer.update(data, state, &grad.into_ndarray());
+
+        let mut accounting = self.accounting.lock().unwrap();
+        if accounting.last_step != Some(clipped.step) {
+            accounting.last_step = Some(clipped.step);
+            let sample_rate = (clipped.count as f64 / self.dataset_size as f64).min(1.0);
+            accounting.accountant.add_steps(sample_rate, 1);
+        }
+    }
+
+    fn per_example_clip(&self) -> Option<f32> {
+        Some(self.l2_norm_clip)
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.optimizer.set_learning_rate(learning_rate)
+    }
+}
+
+/// Registered with the inner optimizer by `param::register_optimizer`.
+impl<T: Float, O: SaveOptimizer<NDArray<T>>> SaveOptimizer<NDArray<T>> for DpSgd<O> {
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_child("optimizer", self.optimizer.to_record(state));
+        record.set_scalar("l2_norm_clip", self.l2_norm_clip);
+        record.set_scalar("noise_multiplier", self.noise_multiplier);
+        record.set_scalar("dataset_size", self.dataset_size as f64);
+        let mut accountant = OptimizerRecord::new();
+        self.accountant().save(&mut accountant);
+        record.set_child("accountant", accountant);
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<(Self, Self::State)> {
+        let (optimizer, state) = O::load(record.child("optimizer")?)?;
+        let accountant = RdpAccountant::load(record.child("accountant")?)?;
+        let dp = DpSgd::with_accountant(
+            optimizer,
+            record.scalar("l2_norm_clip")? as f32,
+            record.scalar("noise_multiplier")? as f32,
+            record.scalar("dataset_size")? as usize,
+            accountant,
+        );
+        Some((dp, state))
+    }
+}
+
+/// Tracks the privacy budget of the subsampled Gaussian mechanism with
+/// Rényi differential privacy (Mironov et al., 2019).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RdpAccountant {
+    pub noise_multiplier: f64,
+    /// The sampling rates with the numbers of the consecutive steps taken at them.
+    history: Vec<(f64, usize)>,
+}
+
+impl RdpAccountant {
+    const ORDERS: [usize; 16] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 48, 64, 128, 256];
+
+    pub fn new(noise_multiplier: f64) -> Self {
+        Self {
+            noise_multiplier,
+            history: Vec::new(),
+        }
+    }
+
+    /// Counts `steps` steps that each sample `sample_rate` of the dataset.
+    pub fn add_steps(&mut self, sample_rate: f64, steps: usize) {
+        assert!(0.0 < sample_rate && sample_rate <= 1.0);
+        match self.history.last_mut() {
+            Some((rate, n)) if *rate == sample_rate => *n += steps,
+            _ => self.history.push((sample_rate, steps)),
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.history.iter().map(|(_, n)| n).sum()
+    }
+
+    fn save<T>(&self, record: &mut OptimizerRecord<T>) {
+        record.set_scalar("noise_multiplier", self.noise_multiplier);
+        record.set_scalar("len", self.history.len() as f64);
+        for (i, &(sample_rate, steps)) in self.history.iter().enumerate() {
+            record.set_scalar(&format!("sample_rate.{}", i), sample_rate);
+            record.set_scalar(&format!("steps.{}", i), steps as f64);
+        }
+    }
+
+    fn load<T>(record: &OptimizerRecord<T>) -> Option<Self> {
+        let history = (0..record.scalar("len")? as usize)
+            .map(|i| {
+                Some((
+                    record.scalar(&format!("sample_rate.{}", i))?,
+                    record.scalar(&format!("steps.{}", i))? as usize,
+                ))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            noise_multiplier: record.scalar("noise_multiplier")?,
+            history,
+        })
+    }
+
+    /// Returns the smallest epsilon such that the steps so far are (epsilon, delta)-DP.
+    pub fn epsilon(&self, delta: f64) -> f64 {
+        assert!(0.0 < delta && delta < 1.0);
+        if self.steps() == 0 {
+            return 0.0;
+        }
+        if self.noise_multiplier == 0.0 {
+            return f64::INFINITY;
+        }
+        Self::ORDERS
+            .iter()
+            .map(|&order| {
+                let rdp: f64 = self
+                    .history
+                    .iter()
+                    .map(|&(q, n)| n as f64 * self.rdp(q, order))
+                    .sum();
+                rdp + (1.0 / delta).ln() / (order as f64 - 1.0)
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// The RDP of one step with the sampling rate `q` at the integer `order`.
+    fn rdp(&self, q: f64, order: usize) -> f64 {
+        let sigma = self.noise_multiplier;
+        let alpha = order as f64;
+        if q == 1.0 {
+            return alpha / (2.0 * sigma.powi(2));
+        }
+        // log of sum_k binom(alpha, k) (1 - q)^(alpha - k) q^k exp((k^2 - k) / (2 sigma^2))
+        let mut log_binom = 0.0;
+        let terms: Vec<f64> = (0..=order)
+            .map(|k| {
+                if k > 0 {
+                    log_binom += ((order - k + 1) as f64).ln() - (k as f64).ln();
+                }
+                let k = k as f64;
+                log_binom
+                    + k * q.ln()
+                    + (alpha - k) * (1.0 - q).ln()
+                    + (k * k - k) / (2.0 * sigma.powi(2))
+            })
+            .collect();
+        let max = terms.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let log_a = max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln();
+        log_a / (alpha - 1.0)
+    }
+}
+
+#[test]
+fn test() {
+    use crate::*;
+
+    let dp = DpSgd::new(super::SGD::new(0.5), 1.0, 0.0, 10);
+    let p = ParamNDA::new(scalar(0.0), "p".into(), dp.clone());
+    let q = ParamNDA::new(scalar(0.0), "q".into(), dp.clone());
+    let x = ComputedNDA::new(ndarray::array![[2.0], [0.5], [-0.5], [1.0]].into_ndarray());
+    let losses = |x: &ComputedNDA| (x * &p.get() + x * &q.get()).sum([1], false);
+
+    // clipped to [1, 0.5, -0.5, 1] / sqrt(2) for each param and averaged without noise
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_clipped(&losses(&x), 1.0);
+    ga.optimize();
+    let expected = -0.5 * 2.0 / 2f32.sqrt() / 4.0;
+    assert!((p.get()[[]] - expected).abs() < 1e-6);
+    assert_eq!(dp.accountant().steps(), 1);
+
+    // a partial batch is divided by its own size and accounted with its own rate
+    let mut ga = GradientsAccumulator::new();
+    ga.compute_clipped(&losses(&x.slice(ndarray::s![..1, ..])), 1.0);
+    ga.optimize();
+    let expected = expected - 0.5 / 2f32.sqrt();
+    assert!((p.get()[[]] - expected).abs() < 1e-6);
+    assert_eq!(dp.accountant().history, [(0.4, 1), (0.1, 1)]);
+
+    // gradients clipped to a larger norm are rejected, and so are unclipped ones
+    let r = ParamNDA::new(scalar(0.0), "r".into(), dp.clone());
+    let result = std::panic::catch_unwind(|| {
+        let mut ga = GradientsAccumulator::new();
+        ga.compute_clipped(&(&x * &r.get()).sum([1], false), 2.0);
+        ga.optimize();
+    });
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("clipped to 2"), "{}", message);
+    let result = std::panic::catch_unwind(|| {
+        let mut ga = GradientsAccumulator::new();
+        ga.compute(&p.get());
+    });
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("needs the gradients"), "{}", message);
+
+    // the wrappers need the clipping of the inner optimizer
+    let wrapped = super::WithRegularization::new(dp, regularizers::L2::new(0.1));
+    let r = ParamNDA::new(scalar(0.0), "r".into(), wrapped);
+    assert_eq!(r.per_example_clip(), Some(1.0));
+}
+
+#[test]
+fn test_serde() {
+    use crate::*;
+
+    let dp = DpSgd::new(super::SGD::new(0.5), 1.0, 1.0, 10);
+    let params = [
+        ParamNDA::new(scalar(0.0), "p".into(), dp.clone()),
+        ParamNDA::new(scalar(0.0), "q".into(), dp.clone()),
+    ];
+    let step = |params: &[ParamNDA]| {
+        let x = ComputedNDA::new(ndarray::array![[1.0], [2.0]].into_ndarray());
+        let losses = (&x * &params[0].get() + &x * &params[1].get()).sum([1], false);
+        let mut ga = GradientsAccumulator::new();
+        ga.compute_clipped(&losses, 1.0);
+        ga.optimize();
+    };
+    step(&params);
+    step(&params);
+    let epsilon = dp.epsilon(1e-5);
+    assert_eq!(dp.accountant().steps(), 2);
+
+    let bytes = bincode::serialize(&params).unwrap();
+    drop((dp, params));
+    let params: [ParamNDA; 2] = bincode::deserialize(&bytes).unwrap();
+
+    // each of the loaded params restores the accountant and counts the steps
+    let dp: DpSgd<super::SGD> = params[0].optimizer().unwrap();
+    assert_eq!(dp.accountant().steps(), 2);
+    assert_eq!(dp.epsilon(1e-5), epsilon);
+    step(&params);
+    assert_eq!(dp.accountant().steps(), 3);
+    let other: DpSgd<super::SGD> = params[1].optimizer().unwrap();
+    assert_eq!(other.epsilon(1e-5), dp.epsilon(1e-5));
+}
+
+#[test]
+fn test_accountant() {
+    // without subsampling, the RDP of the Gaussian mechanism is alpha / (2 sigma^2)
+    let mut accountant = RdpAccountant::new(2.0);
+    accountant.add_steps(1.0, 1);
+    let expected = RdpAccountant::ORDERS
+        .iter()
+        .map(|&a| a as f64 / 8.0 + 1e5f64.ln() / (a as f64 - 1.0))
+        .fold(f64::INFINITY, f64::min);
+    assert!((accountant.epsilon(1e-5) - expected).abs() < 1e-9);
+
+    // MNIST in the TensorFlow Privacy tutorial: about 3 after 60 epochs
+    let mut accountant = RdpAccountant::new(1.1);
+    accountant.add_steps(256.0 / 60000.0, 60 * 60000 / 256);
+    let epsilon = accountant.epsilon(1e-5);
+    assert!(2.5 < epsilon && epsilon < 3.5, "{}", epsilon);
+
+    // more noise spends less budget
+    accountant.noise_multiplier = 2.0;
+    assert!(accountant.epsilon(1e-5) < epsilon);
+
+    // smaller batches spend less budget per step
+    let mut half = RdpAccountant::new(1.1);
+    half.add_steps(256.0 / 60000.0, 60 * 60000 / 512);
+    half.add_steps(128.0 / 60000.0, 60 * 60000 / 512);
+    assert!(half.epsilon(1e-5) < epsilon);
+}
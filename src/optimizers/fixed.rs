@@ -21,3 +21,14 @@ impl<T: Send + Sync + 'static> Optimizer<T> for Fixed {
         #![allow(unused_variables)]
     }
 }
+
+impl<T: Send + Sync + 'static> SaveOptimizer<T> for Fixed {
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<T>) {
+        #![allow(unused_variables)]
+    }
+
+    fn load(record: &OptimizerRecord<T>) -> Option<(Self, Self::State)> {
+        let _ = record;
+        Some((Fixed, ()))
+    }
+}
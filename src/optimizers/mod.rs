@@ -1,14 +1,18 @@
 mod adam;
 mod adamw;
+mod dp_sgd;
 mod fixed;
 mod momentum_sgd;
+mod param_group;
 mod sgd;
 mod with_regularization;
 
 pub use adam::Adam;
 pub use adamw::AdamW;
+pub use dp_sgd::{DpSgd, RdpAccountant};
 pub use fixed::Fixed;
 pub use momentum_sgd::MomentumSGD;
+pub use param_group::ParamGroup;
 pub use sgd::SGD;
 pub use with_regularization::WithRegularization;
 
@@ -35,3 +39,19 @@ fn test_optimizer(optimizer: impl crate::Optimizer<crate::NDArray> + Clone) {
     println!("loss: {} -> {}", first_loss, last_loss);
     assert!(last_loss < first_loss * 0.01);
 }
+
+/// Checks that a zero gradient leaves the param as it is in `T`.
+#[cfg(all(test, feature = "half"))]
+fn test_zero_grad<T: crate::Float>(mut optimizer: impl crate::Optimizer<crate::NDArray<T>>) {
+    use crate::{ndarray_util::cast, *};
+
+    let initial = cast::<f32, T>(&ndarray::array![1.0, -2.0].into_ndarray());
+    let mut data = initial.clone();
+    let mut state = optimizer.new_state(data.shape());
+    let grad = NDArray::zeros(data.shape());
+    for _ in 0..3 {
+        optimizer.update(&mut data, &mut state, &grad);
+    }
+    assert!(data.iter().all(|x| x.is_finite()), "{:?}", data);
+    assert_eq!(data, initial);
+}
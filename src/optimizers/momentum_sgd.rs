@@ -1,4 +1,4 @@
-use std::ops::Mul;
+use ndarray::Zip;
 
 use crate::*;
 
@@ -8,8 +8,8 @@ pub struct MomentumSGD {
     pub momentum: f32,
 }
 
-pub struct State {
-    velocity: NDArray,
+pub struct State<T = f32> {
+    velocity: NDArray<T>,
 }
 
 impl MomentumSGD {
@@ -21,8 +21,8 @@ impl MomentumSGD {
     }
 }
 
-impl Optimizer<NDArray> for MomentumSGD {
-    type State = State;
+impl<T: Float> Optimizer<NDArray<T>> for MomentumSGD {
+    type State = State<T>;
 
     fn new_state(&self, shape: &[usize]) -> Self::State {
         State {
@@ -30,10 +30,40 @@ impl Optimizer<NDArray> for MomentumSGD {
         }
     }
 
-    fn update(&mut self, data: &mut NDArray, state: &mut Self::State, grad: &NDArray) {
-        state.velocity *= self.momentum;
-        state.velocity += &grad.mul(scalar(-self.learning_rate));
-        *data = (&*data + &state.velocity).into_ndarray();
+    fn update(&mut self, data: &mut NDArray<T>, state: &mut Self::State, grad: &NDArray<T>) {
+        let momentum = T::from_f32(self.momentum);
+        let lr = T::from_f32(-self.learning_rate);
+        Zip::from(data)
+            .and(&mut state.velocity)
+            .and(grad)
+            .for_each(|x, v, g| {
+                *v = *v * momentum + *g * lr;
+                *x += *v;
+            });
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.learning_rate = learning_rate;
+        true
+    }
+}
+
+impl<T: Float> SaveOptimizer<NDArray<T>> for MomentumSGD {
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_scalar("learning_rate", self.learning_rate);
+        record.set_scalar("momentum", self.momentum);
+        record.set_array("velocity", state.velocity.clone());
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<(Self, Self::State)> {
+        let optimizer = MomentumSGD::new(
+            record.scalar("learning_rate")? as f32,
+            record.scalar("momentum")? as f32,
+        );
+        let state = State {
+            velocity: record.array("velocity")?,
+        };
+        Some((optimizer, state))
     }
 }
 
@@ -0,0 +1,303 @@
+use std::sync::{Arc, Mutex};
+
+use ndarray::Zip;
+
+use crate::{param::OptimizerStateT, *};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hyperparams {
+    learning_rate: Option<f32>,
+    weight_decay: f32,
+}
+
+/// A set of params whose learning rate and weight decay are changed together at runtime.
+///
+/// The learning rate overrides the one of the optimizer of each param if it is set.
+/// `set_learning_rate` returns the params whose optimizers ignore it.
+/// The weight decay is added to the gradients as L2 regularization before the optimizer.
+/// A param belongs to one group at most. It leaves the group when the group is dropped,
+/// keeping the learning rate set to its optimizer, and then it can join another group.
+///
+/// ```
+/// use tensorflake::{
+///     initializers::{random_initializer::RandomInitializer, with_optimizer::InitializerWithOptimizer, Scope},
+///     ndarray_rand::rand_distr::Uniform,
+///     nn::*,
+///     optimizers::ParamGroup,
+///     *,
+/// };
+///
+/// let init = InitializerWithOptimizer::new(
+///     RandomInitializer::new(Uniform::new(-0.01, 0.01)),
+///     optimizers::Adam::new(),
+/// );
+/// let backbone = Linear::new(
+///     4,
+///     8,
+///     init.scope("backbone").scope("w"),
+///     Some(init.scope("backbone").scope("b")),
+/// );
+/// let head = Linear::new(8, 2, init.scope("head").scope("w"), Some(init.scope("head").scope("b")));
+/// let params = [backbone.all_params(), head.all_params()].concat();
+///
+/// let backbone = ParamGroup::from_prefix("backbone", "backbone", &params).unwrap();
+/// assert!(backbone.set_learning_rate(1e-4).is_empty());
+/// let head = ParamGroup::new("head", head.all_params()).unwrap();
+/// assert!(head.set_learning_rate(1e-3).is_empty());
+/// head.set_weight_decay(1e-2);
+/// println!("{}", ParamGroup::summary(&[backbone, head]));
+/// ```
+pub struct ParamGroup<T: Float = f32> {
+    name: String,
+    params: Vec<ParamNDA<T>>,
+    hyperparams: Arc<Mutex<Hyperparams>>,
+}
+
+impl<T: Float> ParamGroup<T> {
+    /// Returns `Error::ParamInGroup` if any of `params` belongs to another group.
+    pub fn new(name: impl Into<String>, params: Vec<ParamNDA<T>>) -> Result<Self, Error> {
+        let mut group = Self {
+            name: name.into(),
+            params: Vec::with_capacity(params.len()),
+            hyperparams: Arc::new(Mutex::new(Hyperparams {
+                learning_rate: None,
+                weight_decay: 0.0,
+            })),
+        };
+        for param in params {
+            // on error, the params already joined leave by dropping `group`
+            param.join_group(&group.name, |inner| {
+                Box::new(GroupState {
+                    inner,
+                    hyperparams: group.hyperparams.clone(),
+                })
+            })?;
+            group.params.push(param);
+        }
+        Ok(group)
+    }
+
+    /// Groups the params in the `Scope` path `prefix`, such as `"encoder"` or `"encoder:layer1"`.
+    pub fn from_prefix(
+        name: impl Into<String>,
+        prefix: &str,
+        params: &[ParamNDA<T>],
+    ) -> Result<Self, Error> {
+        let params = params
+            .iter()
+            .filter(|p| {
+                let name = p.name();
+                name == prefix
+                    || name
+                        .strip_prefix(prefix)
+                        .is_some_and(|rest| rest.starts_with(':'))
+            })
+            .cloned()
+            .collect();
+        Self::new(name, params)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn params(&self) -> &[ParamNDA<T>] {
+        &self.params
+    }
+
+    /// Returns the learning rate if it is set by `set_learning_rate`.
+    pub fn learning_rate(&self) -> Option<f32> {
+        self.hyperparams.lock().unwrap().learning_rate
+    }
+
+    /// Changes the learning rate of the optimizers of the params.
+    /// Returns the params whose optimizers ignore it. See `Optimizer::set_learning_rate`.
+    pub fn set_learning_rate(&self, learning_rate: f32) -> Vec<ParamNDA<T>> {
+        self.hyperparams.lock().unwrap().learning_rate = Some(learning_rate);
+        self.params
+            .iter()
+            .filter(|p| !p.set_learning_rate(learning_rate))
+            .cloned()
+            .collect()
+    }
+
+    pub fn weight_decay(&self) -> f32 {
+        self.hyperparams.lock().unwrap().weight_decay
+    }
+
+    pub fn set_weight_decay(&self, weight_decay: f32) {
+        self.hyperparams.lock().unwrap().weight_decay = weight_decay;
+    }
+
+    /// Returns a table of the groups and their params.
+    pub fn summary(groups: &[Self]) -> String {
+        let mut s = String::new();
+        for group in groups {
+            let h = *group.hyperparams.lock().unwrap();
+            s += &format!(
+                "{} (learning rate: {}, weight decay: {})\n",
+                group.name,
+                h.learning_rate
+                    .map_or("optimizer's".to_string(), |lr| lr.to_string()),
+                h.weight_decay
+            );
+            for param in &group.params {
+                s += &format!("  {:<32} {:?}\n", param.name(), param.get().shape());
+            }
+        }
+        s
+    }
+}
+
+impl<T: Float> Drop for ParamGroup<T> {
+    fn drop(&mut self) {
+        for param in &self.params {
+            param.leave_group();
+        }
+    }
+}
+
+impl<T: Float> std::fmt::Debug for ParamGroup<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParamGroup")
+            .field("name", &self.name)
+            .field("params", &self.params.len())
+            .field("hyperparams", &*self.hyperparams.lock().unwrap())
+            .finish()
+    }
+}
+
+/// Applies the weight decay of the group on each update.
+struct GroupState<T: Float> {
+    inner: Box<dyn OptimizerStateT<NDArray<T>>>,
+    hyperparams: Arc<Mutex<Hyperparams>>,
+}
+
+impl<T: Float> OptimizerStateT<NDArray<T>> for GroupState<T> {
+    fn update(&mut self, data: &mut NDArray<T>, grad: &NDArray<T>) {
+        self.update_clipped(data, grad, None);
+    }
+
+    fn update_clipped(
+        &mut self,
+        data: &mut NDArray<T>,
+        grad: &NDArray<T>,
+        clipped: Option<ClippedExamples>,
+    ) {
+        let weight_decay = self.hyperparams.lock().unwrap().weight_decay;
+        if weight_decay == 0.0 {
+            self.inner.update_clipped(data, grad, clipped);
+        } else {
+            let weight_decay = T::from_f32(weight_decay);
+            let mut grad = grad.to_owned();
+            Zip::from(&mut grad)
+                .and(&*data)
+                .for_each(|g, x| *g += *x * weight_decay);
+            self.inner
+                .update_clipped(data, &grad.into_ndarray(), clipped);
+        }
+    }
+
+    fn per_example_clip(&self) -> Option<f32> {
+        self.inner.per_example_clip()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.inner.set_learning_rate(learning_rate)
+    }
+
+    /// The group is not saved. The param is loaded outside of any group.
+    fn save(&self) -> Result<(String, OptimizerRecord<NDArray<T>>), &'static str> {
+        self.inner.save()
+    }
+
+    fn clone_optimizer(&self) -> Option<Box<dyn std::any::Any>> {
+        self.inner.clone_optimizer()
+    }
+
+    fn leave_group(&mut self) -> Option<Box<dyn OptimizerStateT<NDArray<T>>>> {
+        Some(std::mem::replace(
+            &mut self.inner,
+            param::default_optimizer_state(),
+        ))
+    }
+}
+
+#[test]
+fn test() {
+    use crate::initializers::{with_optimizer::InitializerWithOptimizer, Initializer, Scope};
+
+    let init = InitializerWithOptimizer::new(
+        initializers::random_initializer::RandomInitializer::new(
+            ndarray_rand::rand_distr::Uniform::new(1.0, 1.0001),
+        ),
+        super::SGD::new(1.0),
+    );
+    let a: ParamNDA = init.scope("backbone").scope("w").initialize(&[2]);
+    let b: ParamNDA = init.scope("backbone_2").initialize(&[2]);
+    let c: ParamNDA = init.scope("head").initialize(&[2]);
+    let params = [a.clone(), b.clone(), c.clone()];
+
+    let backbone = ParamGroup::from_prefix("backbone", "backbone", &params).unwrap();
+    assert!(backbone.params() == [a.clone()]);
+    let head = ParamGroup::new("head", vec![c.clone()]).unwrap();
+    assert_eq!(c.group().as_deref(), Some("head"));
+    assert_eq!(b.group(), None);
+
+    // a param joins one group at most
+    let error = ParamGroup::new("other", vec![b.clone(), c.clone()]).unwrap_err();
+    assert_eq!(
+        error,
+        Error::ParamInGroup {
+            name: "head".into(),
+            group: "head".into()
+        }
+    );
+    assert_eq!(b.group(), None);
+
+    let step = || {
+        let before: Vec<_> = params.iter().map(|p| p.get()[[0]]).collect();
+        let loss = params
+            .iter()
+            .map(|p| p.get().sum([0], false))
+            .reduce(|a, b| a + b)
+            .unwrap();
+        optimize(&loss);
+        params
+            .iter()
+            .zip(before)
+            .map(|(p, before)| before - p.get()[[0]])
+            .collect::<Vec<_>>()
+    };
+
+    // the gradients are all 1
+    assert!(backbone.set_learning_rate(0.1).is_empty());
+    assert!(head.set_learning_rate(0.01).is_empty());
+    let deltas = step();
+    assert!((deltas[0] - 0.1).abs() < 1e-6);
+    assert!((deltas[1] - 1.0).abs() < 1e-6);
+    assert!((deltas[2] - 0.01).abs() < 1e-6);
+
+    // changed at runtime
+    assert!(head.set_learning_rate(0.5).is_empty());
+    head.set_weight_decay(1.0);
+    let x = c.get()[[0]];
+    let deltas = step();
+    assert!((deltas[2] - 0.5 * (1.0 + x)).abs() < 1e-5);
+
+    let summary = ParamGroup::summary(&[backbone, head]);
+    assert!(summary.contains("backbone (learning rate: 0.1, weight decay: 0)"));
+    assert!(summary.contains("  head"));
+
+    // left with the groups dropped, keeping the learning rate but not the weight decay
+    assert_eq!(c.group(), None);
+    let head = ParamGroup::new("head", vec![c.clone()]).unwrap();
+    let deltas = step();
+    assert!((deltas[2] - 0.5).abs() < 1e-6);
+    assert_eq!(head.learning_rate(), None);
+
+    // the params whose optimizers ignore the learning rate are reported
+    let fixed = ParamNDA::new(scalar(1.0), "fixed".into(), super::Fixed);
+    let group = ParamGroup::new("group", vec![b.clone(), fixed.clone()]).unwrap();
+    assert!(group.set_learning_rate(0.1) == [fixed]);
+}
@@ -1,4 +1,4 @@
-use std::ops::Mul;
+use ndarray::Zip;
 
 use crate::*;
 
@@ -13,7 +13,7 @@ impl SGD {
     }
 }
 
-impl Optimizer<NDArray> for SGD {
+impl<T: Float> Optimizer<NDArray<T>> for SGD {
     type State = ();
 
     fn new_state(&self, shape: &[usize]) -> Self::State {
@@ -21,9 +21,26 @@ impl Optimizer<NDArray> for SGD {
         ()
     }
 
-    fn update(&mut self, data: &mut NDArray, state: &mut Self::State, grad: &NDArray) {
+    fn update(&mut self, data: &mut NDArray<T>, state: &mut Self::State, grad: &NDArray<T>) {
         let _ = state;
-        *data = &*data + grad.mul(scalar(-self.learning_rate));
+        let lr = T::from_f32(-self.learning_rate);
+        Zip::from(data).and(grad).for_each(|x, g| *x += *g * lr);
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.learning_rate = learning_rate;
+        true
+    }
+}
+
+impl<T: Float> SaveOptimizer<NDArray<T>> for SGD {
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<NDArray<T>>) {
+        let _ = state;
+        record.set_scalar("learning_rate", self.learning_rate);
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<(Self, Self::State)> {
+        Some((SGD::new(record.scalar("learning_rate")? as f32), ()))
     }
 }
 
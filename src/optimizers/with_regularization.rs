@@ -1,12 +1,15 @@
-use crate::{regularizers::Regularizer, *};
+use crate::{
+    regularizers::{Regularizer, SaveRegularizer},
+    *,
+};
 
 #[derive(Clone)]
-pub struct WithRegularization<O: Optimizer<NDArray>, R: Regularizer> {
+pub struct WithRegularization<O, R> {
     pub optimizer: O,
     pub regularizer: R,
 }
 
-impl<O: Optimizer<NDArray>, R: Regularizer> WithRegularization<O, R> {
+impl<O, R> WithRegularization<O, R> {
     pub fn new(optimizer: O, regularizer: R) -> Self {
         Self {
             optimizer,
@@ -15,16 +18,57 @@ impl<O: Optimizer<NDArray>, R: Regularizer> WithRegularization<O, R> {
     }
 }
 
-impl<O: Optimizer<NDArray>, R: Regularizer> Optimizer<NDArray> for WithRegularization<O, R> {
+impl<T: Float, O: Optimizer<NDArray<T>>, R: Regularizer<T>> Optimizer<NDArray<T>>
+    for WithRegularization<O, R>
+{
     type State = O::State;
 
     fn new_state(&self, shape: &[usize]) -> Self::State {
         self.optimizer.new_state(shape)
     }
 
-    fn update(&mut self, data: &mut NDArray, state: &mut Self::State, grad: &NDArray) {
+    fn update(&mut self, data: &mut NDArray<T>, state: &mut Self::State, grad: &NDArray<T>) {
+        self.update_clipped(data, state, grad, None);
+    }
+
+    fn update_clipped(
+        &mut self,
+        data: &mut NDArray<T>,
+        state: &mut Self::State,
+        grad: &NDArray<T>,
+        clipped: Option<ClippedExamples>,
+    ) {
         let grad = (grad + &*self.regularizer.grad(&data.clone().into())).into_ndarray();
-        self.optimizer.update(data, state, &grad);
+        self.optimizer.update_clipped(data, state, &grad, clipped);
+    }
+
+    fn per_example_clip(&self) -> Option<f32> {
+        self.optimizer.per_example_clip()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f32) -> bool {
+        self.optimizer.set_learning_rate(learning_rate)
+    }
+}
+
+/// Registered with the inner optimizer by `param::register_optimizer`.
+impl<T, O, R> SaveOptimizer<NDArray<T>> for WithRegularization<O, R>
+where
+    T: Float,
+    O: SaveOptimizer<NDArray<T>>,
+    R: SaveRegularizer<T>,
+{
+    fn save(&self, state: &Self::State, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_child("optimizer", self.optimizer.to_record(state));
+        let mut regularizer = OptimizerRecord::new();
+        self.regularizer.save(&mut regularizer);
+        record.set_child("regularizer", regularizer);
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<(Self, Self::State)> {
+        let (optimizer, state) = O::load(record.child("optimizer")?)?;
+        let regularizer = R::load(record.child("regularizer")?)?;
+        Some((WithRegularization::new(optimizer, regularizer), state))
     }
 }
 
@@ -1,15 +1,23 @@
 use crate::*;
 
-pub trait Regularizer: Sync + Send + 'static {
-    fn loss(&self, x: &ComputedNDA) -> ComputedNDA;
+pub trait Regularizer<T: Float = f32>: Sync + Send + 'static {
+    fn loss(&self, x: &ComputedNDA<T>) -> ComputedNDA<T>;
 
-    fn grad(&self, x: &ComputedNDA) -> ComputedNDA {
+    fn grad(&self, x: &ComputedNDA<T>) -> ComputedNDA<T> {
+        let _guard = set_grad_enabled(true);
         let x = backprop((**x).clone());
         let loss = self.loss(&x);
         gradients(&[loss], &[x.clone()], false).pop().unwrap()
     }
 }
 
+/// A regularizer saved with `optimizers::WithRegularization`.
+pub trait SaveRegularizer<T: Float = f32>: Regularizer<T> + Clone {
+    fn save(&self, record: &mut OptimizerRecord<NDArray<T>>);
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<Self>;
+}
+
 #[derive(Clone)]
 pub struct L1 {
     pub l1: f32,
@@ -21,9 +29,19 @@ impl L1 {
     }
 }
 
-impl Regularizer for L1 {
-    fn loss(&self, input: &ComputedNDA) -> ComputedNDA {
-        input.abs().sum(Vec::from_iter(0..input.ndim()), false) * scalar(self.l1).into()
+impl<T: Float> Regularizer<T> for L1 {
+    fn loss(&self, input: &ComputedNDA<T>) -> ComputedNDA<T> {
+        input.abs().sum(Vec::from_iter(0..input.ndim()), false) * scalar_of(self.l1 as f64).into()
+    }
+}
+
+impl<T: Float> SaveRegularizer<T> for L1 {
+    fn save(&self, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_scalar("l1", self.l1);
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<Self> {
+        Some(Self::new(record.scalar("l1")? as f32))
     }
 }
 
@@ -38,12 +56,22 @@ impl L2 {
     }
 }
 
-impl Regularizer for L2 {
-    fn loss(&self, input: &ComputedNDA) -> ComputedNDA {
+impl<T: Float> Regularizer<T> for L2 {
+    fn loss(&self, input: &ComputedNDA<T>) -> ComputedNDA<T> {
         input
             .pow_const(2.0)
             .sum(Vec::from_iter(0..input.ndim()), false)
-            * scalar(self.l2).into()
+            * scalar_of(self.l2 as f64).into()
+    }
+}
+
+impl<T: Float> SaveRegularizer<T> for L2 {
+    fn save(&self, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_scalar("l2", self.l2);
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<Self> {
+        Some(Self::new(record.scalar("l2")? as f32))
     }
 }
 
@@ -59,13 +87,27 @@ impl L1L2 {
     }
 }
 
-impl Regularizer for L1L2 {
-    fn loss(&self, input: &ComputedNDA) -> ComputedNDA {
-        input.abs().sum(Vec::from_iter(0..input.ndim()), false) * scalar(self.l1).into()
+impl<T: Float> Regularizer<T> for L1L2 {
+    fn loss(&self, input: &ComputedNDA<T>) -> ComputedNDA<T> {
+        input.abs().sum(Vec::from_iter(0..input.ndim()), false) * scalar_of(self.l1 as f64).into()
             + input
                 .pow_const(2.0)
                 .sum(Vec::from_iter(0..input.ndim()), false)
-                * scalar(self.l2).into()
+                * scalar_of(self.l2 as f64).into()
+    }
+}
+
+impl<T: Float> SaveRegularizer<T> for L1L2 {
+    fn save(&self, record: &mut OptimizerRecord<NDArray<T>>) {
+        record.set_scalar("l1", self.l1);
+        record.set_scalar("l2", self.l2);
+    }
+
+    fn load(record: &OptimizerRecord<NDArray<T>>) -> Option<Self> {
+        Some(Self::new(
+            record.scalar("l1")? as f32,
+            record.scalar("l2")? as f32,
+        ))
     }
 }
 
@@ -0,0 +1,361 @@
+use std::sync::Arc;
+
+use ndarray::{Ix2, Zip};
+
+use crate::*;
+
+/// A sparse matrix in the coordinate format. Duplicate entries are summed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coo<T = f32> {
+    pub shape: [usize; 2],
+    pub rows: Vec<usize>,
+    pub cols: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+impl<T: Float> Coo<T> {
+    pub fn new(shape: [usize; 2]) -> Self {
+        Self {
+            shape,
+            rows: vec![],
+            cols: vec![],
+            values: vec![],
+        }
+    }
+
+    pub fn push(&mut self, row: usize, col: usize, value: T) {
+        assert!(
+            row < self.shape[0] && col < self.shape[1],
+            "index out of bounds"
+        );
+        self.rows.push(row);
+        self.cols.push(col);
+        self.values.push(value);
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn from_dense(x: &NDArray<T>) -> Self {
+        let x = x.view().into_dimensionality::<Ix2>().unwrap();
+        let mut coo = Self::new([x.nrows(), x.ncols()]);
+        for ((i, j), v) in x.indexed_iter() {
+            if !v.is_zero() {
+                coo.push(i, j, *v);
+            }
+        }
+        coo
+    }
+
+    pub fn to_dense(&self) -> NDArray<T> {
+        let mut x = NDArray::zeros(&self.shape[..]);
+        for k in 0..self.nnz() {
+            x[[self.rows[k], self.cols[k]]] += self.values[k];
+        }
+        x
+    }
+
+    pub fn to_csr(&self) -> Csr<T> {
+        let mut order: Vec<usize> = (0..self.nnz()).collect();
+        order.sort_by_key(|&k| (self.rows[k], self.cols[k]));
+
+        let mut indptr = vec![0; self.shape[0] + 1];
+        let mut indices: Vec<usize> = Vec::with_capacity(self.nnz());
+        let mut values: Vec<T> = Vec::with_capacity(self.nnz());
+        let mut last = None;
+        for k in order {
+            let (i, j) = (self.rows[k], self.cols[k]);
+            if last == Some((i, j)) {
+                *values.last_mut().unwrap() += self.values[k];
+            } else {
+                indptr[i + 1] += 1;
+                indices.push(j);
+                values.push(self.values[k]);
+                last = Some((i, j));
+            }
+        }
+        for i in 0..self.shape[0] {
+            indptr[i + 1] += indptr[i];
+        }
+        Csr {
+            shape: self.shape,
+            indptr,
+            indices,
+            values,
+        }
+    }
+}
+
+/// A sparse matrix in the compressed sparse row format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Csr<T = f32> {
+    shape: [usize; 2],
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<T>,
+}
+
+impl<T: Float> Csr<T> {
+    /// The columns of the row `i` are `indices[indptr[i]..indptr[i + 1]]`.
+    pub fn new(shape: [usize; 2], indptr: Vec<usize>, indices: Vec<usize>, values: Vec<T>) -> Self {
+        assert_eq!(indptr.len(), shape[0] + 1);
+        assert_eq!(indptr[0], 0);
+        assert!(indptr.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(indptr[shape[0]], indices.len());
+        assert_eq!(indices.len(), values.len());
+        assert!(indices.iter().all(|&j| j < shape[1]));
+        Self {
+            shape,
+            indptr,
+            indices,
+            values,
+        }
+    }
+
+    pub fn from_dense(x: &NDArray<T>) -> Self {
+        Coo::from_dense(x).to_csr()
+    }
+
+    pub fn shape(&self) -> [usize; 2] {
+        self.shape
+    }
+
+    pub fn indptr(&self) -> &[usize] {
+        &self.indptr
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn to_coo(&self) -> Coo<T> {
+        Coo {
+            shape: self.shape,
+            rows: rows_of(&self.indptr),
+            cols: self.indices.clone(),
+            values: self.values.clone(),
+        }
+    }
+
+    pub fn to_dense(&self) -> NDArray<T> {
+        self.to_coo().to_dense()
+    }
+}
+
+impl<T: Float> From<Coo<T>> for Csr<T> {
+    fn from(x: Coo<T>) -> Self {
+        x.to_csr()
+    }
+}
+
+fn rows_of(indptr: &[usize]) -> Vec<usize> {
+    indptr
+        .windows(2)
+        .enumerate()
+        .flat_map(|(i, w)| std::iter::repeat_n(i, w[1] - w[0]))
+        .collect()
+}
+
+/// The positions of the non-zero elements of a `SparseNDA`.
+#[derive(Debug)]
+pub(crate) struct Pattern {
+    pub shape: [usize; 2],
+    pub indptr: Vec<usize>,
+    pub indices: Vec<usize>,
+    /// The row of each non-zero element.
+    pub rows: Vec<usize>,
+}
+
+impl Pattern {
+    /// Returns the pattern of the transposed matrix and the permutation of the values.
+    fn transpose(&self) -> (Pattern, Vec<usize>) {
+        let mut indptr = vec![0; self.shape[1] + 1];
+        for &j in &self.indices {
+            indptr[j + 1] += 1;
+        }
+        for j in 0..self.shape[1] {
+            indptr[j + 1] += indptr[j];
+        }
+        let mut next = indptr.clone();
+        let mut indices = vec![0; self.indices.len()];
+        let mut perm = vec![0; self.indices.len()];
+        for (k, (&i, &j)) in self.rows.iter().zip(&self.indices).enumerate() {
+            indices[next[j]] = i;
+            perm[next[j]] = k;
+            next[j] += 1;
+        }
+        let pattern = Pattern {
+            shape: [self.shape[1], self.shape[0]],
+            rows: rows_of(&indptr),
+            indptr,
+            indices,
+        };
+        (pattern, perm)
+    }
+}
+
+/// A sparse matrix whose non-zero values are a `ComputedNDA`.
+/// It can be the left operand of `functions::matmul` and the input of `nn::Linear::call_sparse`.
+///
+/// The gradients with respect to the values are computed only if the values are created
+/// by `SparseNDA::backprop` or given by `SparseNDA::with_values`.
+#[derive(Clone)]
+pub struct SparseNDA<T: Float = f32> {
+    pub(crate) pattern: Arc<Pattern>,
+    pub(crate) values: ComputedNDA<T>,
+    pub(crate) requires_grad: bool,
+}
+
+impl<T: Float> SparseNDA<T> {
+    pub fn new(x: impl Into<Csr<T>>) -> Self {
+        let x = x.into();
+        Self::from_csr(x, ComputedNDA::new, false)
+    }
+
+    pub fn backprop(x: impl Into<Csr<T>>) -> Self {
+        let x = x.into();
+        Self::from_csr(x, backprop, true)
+    }
+
+    fn from_csr(
+        x: Csr<T>,
+        f: impl FnOnce(NDArray<T>) -> ComputedNDA<T>,
+        requires_grad: bool,
+    ) -> Self {
+        let values = NDArray::from_shape_vec(&[x.nnz()][..], x.values).unwrap();
+        Self {
+            pattern: Arc::new(Pattern {
+                shape: x.shape,
+                rows: rows_of(&x.indptr),
+                indptr: x.indptr,
+                indices: x.indices,
+            }),
+            values: f(values),
+            requires_grad,
+        }
+    }
+
+    /// Returns a sparse matrix with the same pattern and `values`, which are differentiated.
+    pub fn with_values(&self, values: ComputedNDA<T>) -> Self {
+        assert_eq!(values.shape(), &[self.nnz()]);
+        Self {
+            pattern: self.pattern.clone(),
+            values,
+            requires_grad: true,
+        }
+    }
+
+    pub fn values(&self) -> &ComputedNDA<T> {
+        &self.values
+    }
+
+    pub fn shape(&self) -> [usize; 2] {
+        self.pattern.shape
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.pattern.indices.len()
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        self.requires_grad
+    }
+
+    pub fn t(&self) -> Self {
+        let (pattern, perm) = self.pattern.transpose();
+        Self {
+            pattern: Arc::new(pattern),
+            values: functions::select(0, perm, &self.values),
+            requires_grad: self.requires_grad,
+        }
+    }
+
+    pub fn to_csr(&self) -> Csr<T> {
+        Csr {
+            shape: self.pattern.shape,
+            indptr: self.pattern.indptr.clone(),
+            indices: self.pattern.indices.clone(),
+            values: self.values.iter().copied().collect(),
+        }
+    }
+
+    pub fn to_dense(&self) -> NDArray<T> {
+        self.to_csr().to_dense()
+    }
+}
+
+impl<T: Float> std::fmt::Debug for SparseNDA<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SparseNDA")
+            .field("shape", &self.pattern.shape)
+            .field("indptr", &self.pattern.indptr)
+            .field("indices", &self.pattern.indices)
+            .field("values", &*self.values)
+            .finish()
+    }
+}
+
+impl<T: Float> From<Csr<T>> for SparseNDA<T> {
+    fn from(x: Csr<T>) -> Self {
+        Self::new(x)
+    }
+}
+
+impl<T: Float> From<Coo<T>> for SparseNDA<T> {
+    fn from(x: Coo<T>) -> Self {
+        Self::new(x)
+    }
+}
+
+/// Computes the product of the sparse matrix and the dense matrix `w`.
+pub(crate) fn matmul_forward<T: Float>(
+    pattern: &Pattern,
+    values: &NDArray<T>,
+    w: &NDArray<T>,
+) -> NDArray<T> {
+    let w = w.view().into_dimensionality::<Ix2>().unwrap();
+    let mut y = ndarray::Array2::zeros([pattern.shape[0], w.ncols()]);
+    for (k, (&i, &j)) in pattern.rows.iter().zip(&pattern.indices).enumerate() {
+        let v = values[k];
+        Zip::from(y.row_mut(i))
+            .and(w.row(j))
+            .for_each(|y, w| *y += v * *w);
+    }
+    y.into_ndarray()
+}
+
+#[test]
+fn test() {
+    let mut coo = Coo::new([3, 4]);
+    coo.push(2, 1, 1.0);
+    coo.push(0, 3, 2.0);
+    coo.push(2, 1, 3.0);
+    coo.push(0, 0, -1.0);
+    let dense = ndarray::array![
+        [-1.0, 0.0, 0.0, 2.0],
+        [0.0, 0.0, 0.0, 0.0],
+        [0.0, 4.0, 0.0, 0.0]
+    ]
+    .into_ndarray();
+    assert_eq!(coo.to_dense(), dense);
+
+    let csr = coo.to_csr();
+    assert_eq!(csr.indptr(), &[0, 2, 2, 3]);
+    assert_eq!(csr.indices(), &[0, 3, 1]);
+    assert_eq!(csr.values(), &[-1.0, 2.0, 4.0]);
+    assert_eq!(csr, Csr::from_dense(&dense));
+    assert_eq!(csr.to_dense(), dense);
+
+    let x = SparseNDA::new(csr);
+    assert_eq!(x.t().to_dense(), dense.t().to_owned().into_ndarray());
+    assert_eq!(x.t().t().to_csr(), x.to_csr());
+}
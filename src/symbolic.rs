@@ -0,0 +1,722 @@
+//! Define-then-run graphs.
+//!
+//! A `Graph` is built from placeholders, constants, params and operations without computing
+//! anything. It can be inspected and optimized, and then run many times by a `Session`.
+//! A run calls the ordinary functions, so its outputs can be differentiated as usual.
+//!
+//! The passes work on the `Op`s of the graph rather than on `FunctionCall`s, whose backward
+//! closures cannot be compared, folded or re-run on new inputs. A graph recorded eagerly can
+//! be lifted into a `Graph` by `Graph::from_recorded` if it uses only the functions with an `Op`.
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use tensorflake::{ndarray::array, symbolic::*, *};
+//!
+//! let mut g = Graph::new();
+//! let x = g.placeholder("x");
+//! let w = g.constant(array![[1.0], [2.0]].into_ndarray());
+//! let y = g.matmul(x, w);
+//! let y = g.tanh(y);
+//!
+//! let session = Session::new(g, &[y]);
+//! let feeds = HashMap::from([("x", array![[0.5, -0.25]].into_ndarray())]);
+//! let ys = session.run(&feeds).unwrap();
+//! assert_eq!(ys[0].shape(), &[1, 1]);
+//! ```
+
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, sync::Arc};
+
+use crate::{error::unwrap_or_panic, functions::*, graph, nn::activations, *};
+
+/// A node of a `Graph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Var(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Exp,
+    Log,
+    Sin,
+    Cos,
+    Tanh,
+    Abs,
+    Sigmoid,
+    Relu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    MatMul,
+}
+
+pub type CustomFn<T> =
+    Arc<dyn Fn(&[ComputedNDA<T>]) -> Result<ComputedNDA<T>, Error> + Send + Sync>;
+
+#[derive(Clone)]
+pub enum Op<T: Float = f32> {
+    Placeholder(String),
+    Constant(NDArray<T>),
+    Param(ParamNDA<T>),
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    Sum {
+        axes: Vec<usize>,
+        keep_dim: bool,
+    },
+    Reshape(Vec<usize>),
+    Transpose(Vec<usize>),
+    Broadcast(Vec<usize>),
+    /// An arbitrary function. It is not folded even if the inputs are constants,
+    /// and two custom operations are the same only if they share the `Arc`.
+    Custom {
+        name: &'static str,
+        f: CustomFn<T>,
+    },
+}
+
+impl<T: Float> Op<T> {
+    pub fn name(&self) -> String {
+        match self {
+            Op::Placeholder(name) => format!("placeholder {}", name),
+            Op::Constant(x) => format!("constant {:?}", x.shape()),
+            Op::Param(p) => format!("param {}", p.name()),
+            Op::Unary(op) => format!("{:?}", op).to_lowercase(),
+            Op::Binary(op) => format!("{:?}", op).to_lowercase(),
+            Op::Sum { axes, keep_dim } => format!("sum {:?} {}", axes, keep_dim),
+            Op::Reshape(shape) => format!("reshape {:?}", shape),
+            Op::Transpose(axes) => format!("transpose {:?}", axes),
+            Op::Broadcast(shape) => format!("broadcast {:?}", shape),
+            Op::Custom { name, .. } => name.to_string(),
+        }
+    }
+
+    fn is_same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Op::Placeholder(a), Op::Placeholder(b)) => a == b,
+            (Op::Constant(a), Op::Constant(b)) => a == b,
+            (Op::Param(a), Op::Param(b)) => a == b,
+            (Op::Unary(a), Op::Unary(b)) => a == b,
+            (Op::Binary(a), Op::Binary(b)) => a == b,
+            (
+                Op::Sum { axes, keep_dim },
+                Op::Sum {
+                    axes: axes_,
+                    keep_dim: keep_dim_,
+                },
+            ) => axes == axes_ && keep_dim == keep_dim_,
+            (Op::Reshape(a), Op::Reshape(b)) => a == b,
+            (Op::Transpose(a), Op::Transpose(b)) => a == b,
+            (Op::Broadcast(a), Op::Broadcast(b)) => a == b,
+            (Op::Custom { f: a, .. }, Op::Custom { f: b, .. }) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Returns the operation of a function call recorded eagerly.
+    /// The arguments other than the inputs are taken from the `FunctionArgs` of the call.
+    pub(crate) fn lift(backward: &dyn Backward<NDArray<T>>) -> Result<Self, Error> {
+        let any = backward.as_any();
+        if let Some(p) = any.and_then(|b| b.downcast_ref::<ParamNDA<T>>()) {
+            return Ok(Op::Param(p.clone()));
+        }
+        if let Some(args) = any.and_then(|b| b.downcast_ref::<FunctionArgs>()) {
+            return Ok(match args.clone() {
+                FunctionArgs::Sum { axes, keep_dim } => Op::Sum { axes, keep_dim },
+                FunctionArgs::Reshape(shape) => Op::Reshape(shape),
+                FunctionArgs::Transpose(axes) => Op::Transpose(axes),
+                FunctionArgs::Broadcast(shape) => Op::Broadcast(shape),
+            });
+        }
+        let function = backward.get_function_name();
+        Ok(match &*function {
+            "neg" => Op::Unary(UnaryOp::Neg),
+            "exp" => Op::Unary(UnaryOp::Exp),
+            "log" => Op::Unary(UnaryOp::Log),
+            "sin" => Op::Unary(UnaryOp::Sin),
+            "cos" => Op::Unary(UnaryOp::Cos),
+            "tanh" => Op::Unary(UnaryOp::Tanh),
+            "abs" => Op::Unary(UnaryOp::Abs),
+            "sigmoid" => Op::Unary(UnaryOp::Sigmoid),
+            "relu" => Op::Unary(UnaryOp::Relu),
+            "add" => Op::Binary(BinaryOp::Add),
+            "sub" => Op::Binary(BinaryOp::Sub),
+            "mul" => Op::Binary(BinaryOp::Mul),
+            "div" => Op::Binary(BinaryOp::Div),
+            "matmul" => Op::Binary(BinaryOp::MatMul),
+            _ => return Err(Error::UnliftableFunction { function }),
+        })
+    }
+
+    /// Whether the operation always returns the same value for the same inputs.
+    fn is_pure(&self) -> bool {
+        !matches!(self, Op::Placeholder(_) | Op::Param(_) | Op::Custom { .. })
+    }
+
+    pub(crate) fn apply(&self, xs: &[ComputedNDA<T>]) -> Result<ComputedNDA<T>, Error> {
+        Ok(match self {
+            Op::Placeholder(_) | Op::Constant(_) | Op::Param(_) => unreachable!(),
+            Op::Unary(op) => {
+                let x = &xs[0];
+                match op {
+                    UnaryOp::Neg => neg(x),
+                    UnaryOp::Exp => exp(x),
+                    UnaryOp::Log => log(x),
+                    UnaryOp::Sin => sin(x),
+                    UnaryOp::Cos => cos(x),
+                    UnaryOp::Tanh => tanh(x),
+                    UnaryOp::Abs => abs(x),
+                    UnaryOp::Sigmoid => activations::sigmoid(x),
+                    UnaryOp::Relu => activations::relu(x),
+                }
+            }
+            Op::Binary(op) => {
+                let (a, b) = (&xs[0], &xs[1]);
+                match op {
+                    BinaryOp::Add => try_add(a, b)?,
+                    BinaryOp::Sub => try_sub(a, b)?,
+                    BinaryOp::Mul => try_mul(a, b)?,
+                    BinaryOp::Div => try_div(a, b)?,
+                    BinaryOp::MatMul => try_matmul(a, b)?,
+                }
+            }
+            Op::Sum { axes, keep_dim } => try_sum(&xs[0], axes.clone(), *keep_dim)?,
+            Op::Reshape(shape) => try_reshape(&xs[0], shape.clone())?,
+            Op::Transpose(axes) => try_transpose(&xs[0], axes.clone())?,
+            Op::Broadcast(shape) => try_broadcast(&xs[0], shape.clone())?,
+            Op::Custom { f, .. } => f(xs)?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Node<T: Float = f32> {
+    pub op: Op<T>,
+    pub inputs: Vec<Var>,
+}
+
+/// A graph whose nodes are in a topological order.
+#[derive(Clone)]
+pub struct Graph<T: Float = f32> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T: Float> Graph<T> {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    pub fn nodes(&self) -> &[Node<T>] {
+        &self.nodes
+    }
+
+    pub fn node(&self, var: Var) -> &Node<T> {
+        &self.nodes[var.0]
+    }
+
+    pub fn push(&mut self, op: Op<T>, inputs: &[Var]) -> Var {
+        assert!(inputs.iter().all(|x| x.0 < self.nodes.len()));
+        self.nodes.push(Node {
+            op,
+            inputs: inputs.to_vec(),
+        });
+        Var(self.nodes.len() - 1)
+    }
+
+    pub fn placeholder(&mut self, name: impl Into<String>) -> Var {
+        self.push(Op::Placeholder(name.into()), &[])
+    }
+
+    pub fn constant(&mut self, x: NDArray<T>) -> Var {
+        self.push(Op::Constant(x), &[])
+    }
+
+    pub fn param(&mut self, param: &ParamNDA<T>) -> Var {
+        self.push(Op::Param(param.clone()), &[])
+    }
+
+    pub fn unary(&mut self, op: UnaryOp, x: Var) -> Var {
+        self.push(Op::Unary(op), &[x])
+    }
+
+    pub fn binary(&mut self, op: BinaryOp, a: Var, b: Var) -> Var {
+        self.push(Op::Binary(op), &[a, b])
+    }
+
+    pub fn neg(&mut self, x: Var) -> Var {
+        self.unary(UnaryOp::Neg, x)
+    }
+
+    pub fn exp(&mut self, x: Var) -> Var {
+        self.unary(UnaryOp::Exp, x)
+    }
+
+    pub fn log(&mut self, x: Var) -> Var {
+        self.unary(UnaryOp::Log, x)
+    }
+
+    pub fn tanh(&mut self, x: Var) -> Var {
+        self.unary(UnaryOp::Tanh, x)
+    }
+
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        self.binary(BinaryOp::Add, a, b)
+    }
+
+    pub fn sub(&mut self, a: Var, b: Var) -> Var {
+        self.binary(BinaryOp::Sub, a, b)
+    }
+
+    pub fn mul(&mut self, a: Var, b: Var) -> Var {
+        self.binary(BinaryOp::Mul, a, b)
+    }
+
+    pub fn div(&mut self, a: Var, b: Var) -> Var {
+        self.binary(BinaryOp::Div, a, b)
+    }
+
+    pub fn matmul(&mut self, a: Var, b: Var) -> Var {
+        self.binary(BinaryOp::MatMul, a, b)
+    }
+
+    pub fn sum(&mut self, x: Var, axes: impl Into<Vec<usize>>, keep_dim: bool) -> Var {
+        let axes = axes.into();
+        self.push(Op::Sum { axes, keep_dim }, &[x])
+    }
+
+    pub fn reshape(&mut self, x: Var, shape: impl Into<Vec<usize>>) -> Var {
+        self.push(Op::Reshape(shape.into()), &[x])
+    }
+
+    pub fn transpose(&mut self, x: Var, axes: impl Into<Vec<usize>>) -> Var {
+        self.push(Op::Transpose(axes.into()), &[x])
+    }
+
+    pub fn broadcast(&mut self, x: Var, shape: impl Into<Vec<usize>>) -> Var {
+        self.push(Op::Broadcast(shape.into()), &[x])
+    }
+
+    pub fn custom(
+        &mut self,
+        name: &'static str,
+        f: impl Fn(&[ComputedNDA<T>]) -> Result<ComputedNDA<T>, Error> + Send + Sync + 'static,
+        inputs: &[Var],
+    ) -> Var {
+        let f = Arc::new(f);
+        self.push(Op::Custom { name, f }, inputs)
+    }
+
+    /// Lifts the graph recorded eagerly for `ys` from `graph::collect_function_calls`.
+    ///
+    /// The params become `Op::Param`, the named values without a creator (or created by
+    /// `backprop`) become placeholders of the names, and the other values without a creator
+    /// become constants. Only the values depending on a param or on `backprop` are recorded,
+    /// so the rest are lifted as constants. The shape functions keep their arguments, so
+    /// `reshape` and `broadcast` are lifted with the shapes they were called with.
+    /// Returns `Error::UnliftableFunction` for the functions without an `Op`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use tensorflake::{ndarray::array, symbolic::*, *};
+    ///
+    /// let x = backprop(array![1.0, 2.0].into_ndarray()).named("x");
+    /// let y = (&x * &x).exp();
+    ///
+    /// let (g, ys) = Graph::from_recorded(&[y]).unwrap();
+    /// let session = Session::new(g, &ys);
+    /// let feeds = HashMap::from([("x", array![0.0].into_ndarray())]);
+    /// assert_eq!(&*session.run(&feeds).unwrap()[0], &array![1.0].into_ndarray());
+    /// ```
+    pub fn from_recorded(ys: &[ComputedNDA<T>]) -> Result<(Self, Vec<Var>), Error> {
+        let key = |x: &ComputedNDA<T>| Arc::as_ptr(&x.inner) as *const ();
+        let mut creators = HashMap::new();
+        for fc in graph::collect_function_calls(ys.to_vec()) {
+            for y in &fc.ys {
+                creators.insert(y.as_ptr() as *const (), fc.clone());
+            }
+        }
+
+        let mut g = Self::new();
+        let mut vars = HashMap::new();
+        // post-order: a value is pushed again to be lifted after its inputs
+        let mut stack: Vec<_> = ys.iter().rev().map(|y| (y.clone(), false)).collect();
+        while let Some((y, expanded)) = stack.pop() {
+            if vars.contains_key(&key(&y)) {
+                continue;
+            }
+            let var = match creators.get(&key(&y)) {
+                Some(fc) if fc.backward.get_function_name() != "backprop" => {
+                    if !expanded {
+                        stack.push((y.clone(), true));
+                        stack.extend(fc.xs.iter().rev().map(|x| (x.clone(), false)));
+                        continue;
+                    }
+                    let op = Op::lift(&*fc.backward)?;
+                    let inputs: Vec<_> = fc.xs.iter().map(|x| vars[&key(x)]).collect();
+                    g.push(op, &inputs)
+                }
+                _ => match y.get_name() {
+                    name if name.is_empty() => g.constant((*y).clone()),
+                    name => g.placeholder(name),
+                },
+            };
+            vars.insert(key(&y), var);
+        }
+        let ys = ys.iter().map(|y| vars[&key(y)]).collect();
+        Ok((g, ys))
+    }
+
+    /// Returns the names of the placeholders.
+    pub fn placeholders(&self) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter_map(|n| match &n.op {
+                Op::Placeholder(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Applies all the optimizations. Returns the new graph and the new `outputs`.
+    pub fn optimize(&self, outputs: &[Var]) -> (Self, Vec<Var>) {
+        let (g, outputs) = self.fold_constants(outputs);
+        let (g, outputs) = g.eliminate_common_subexpressions(&outputs);
+        g.remove_dead_nodes(&outputs)
+    }
+
+    /// Replaces the pure operations whose inputs are all constants with their results.
+    pub fn fold_constants(&self, outputs: &[Var]) -> (Self, Vec<Var>) {
+        let _guard = no_grad();
+        let mut values: Vec<Option<ComputedNDA<T>>> = Vec::with_capacity(self.nodes.len());
+        let g = self.rebuild(outputs, |g, node, inputs| {
+            let folded = if let Op::Constant(x) = &node.op {
+                Some(ComputedNDA::new(x.clone()))
+            } else if node.op.is_pure() && !inputs.is_empty() {
+                let xs: Option<Vec<_>> = inputs.iter().map(|x| values[x.0].clone()).collect();
+                // errors are left to be reported by `Session::run`
+                xs.and_then(|xs| node.op.apply(&xs).ok())
+            } else {
+                None
+            };
+            let var = match &folded {
+                Some(x) => g.constant((**x).clone()),
+                None => g.push(node.op.clone(), inputs),
+            };
+            values.resize(g.nodes.len(), None);
+            values[var.0] = folded;
+            var
+        });
+        g
+    }
+
+    /// Merges the nodes that have the same operation and inputs.
+    pub fn eliminate_common_subexpressions(&self, outputs: &[Var]) -> (Self, Vec<Var>) {
+        let mut table: HashMap<(String, Vec<Var>), Vec<Var>> = HashMap::new();
+        self.rebuild(outputs, |g, node, inputs| {
+            let key = (node.op.name(), inputs.to_vec());
+            if let Some(same) = table
+                .get(&key)
+                .and_then(|vs| vs.iter().find(|v| g.nodes[v.0].op.is_same(&node.op)))
+            {
+                return *same;
+            }
+            let var = g.push(node.op.clone(), inputs);
+            table.entry(key).or_default().push(var);
+            var
+        })
+    }
+
+    /// Removes the nodes that the `outputs` do not depend on.
+    pub fn remove_dead_nodes(&self, outputs: &[Var]) -> (Self, Vec<Var>) {
+        let mut live = vec![false; self.nodes.len()];
+        for x in outputs {
+            live[x.0] = true;
+        }
+        for (i, node) in self.nodes.iter().enumerate().rev() {
+            if live[i] {
+                for x in &node.inputs {
+                    live[x.0] = true;
+                }
+            }
+        }
+
+        let mut g = Self::new();
+        let mut map = vec![None; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if live[i] {
+                let inputs: Vec<_> = node.inputs.iter().map(|x| map[x.0].unwrap()).collect();
+                map[i] = Some(g.push(node.op.clone(), &inputs));
+            }
+        }
+        let outputs = outputs.iter().map(|x| map[x.0].unwrap()).collect();
+        (g, outputs)
+    }
+
+    /// Copies the nodes in order with `f`, which pushes a node to the new graph and returns it.
+    fn rebuild(
+        &self,
+        outputs: &[Var],
+        mut f: impl FnMut(&mut Self, &Node<T>, &[Var]) -> Var,
+    ) -> (Self, Vec<Var>) {
+        let mut g = Self::new();
+        let mut map = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let inputs: Vec<_> = node.inputs.iter().map(|x| map[x.0]).collect();
+            let var = f(&mut g, node, &inputs);
+            map.push(var);
+        }
+        let outputs = outputs.iter().map(|x| map[x.0]).collect();
+        (g, outputs)
+    }
+
+    pub fn write_dot(
+        &self,
+        w: &mut impl std::io::Write,
+        outputs: &[Var],
+    ) -> Result<(), std::io::Error> {
+        writeln!(w, "digraph g {{")?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let style = match node.op {
+                Op::Placeholder(_) => "color=orange, style=filled",
+                Op::Constant(_) | Op::Param(_) => "color=lightgray, style=filled",
+                _ => "color=lightblue, style=filled, shape=box",
+            };
+            let peripheries = if outputs.contains(&Var(i)) { 2 } else { 1 };
+            writeln!(
+                w,
+                "{} [label={:?} {} peripheries={}]",
+                i,
+                node.op.name(),
+                style,
+                peripheries
+            )?;
+            for x in &node.inputs {
+                writeln!(w, "{} -> {}", x.0, i)?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+
+    pub fn export_dot(&self, outputs: &[Var], file: &str) -> Result<(), std::io::Error> {
+        let f = std::fs::File::create(file)?;
+        let mut w = std::io::BufWriter::new(f);
+        self.write_dot(&mut w, outputs)
+    }
+}
+
+impl<T: Float> Default for Graph<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs an optimized `Graph`.
+pub struct Session<T: Float = f32> {
+    graph: Graph<T>,
+    outputs: Vec<Var>,
+    /// Constants shared by runs.
+    constants: Vec<Option<ComputedNDA<T>>>,
+}
+
+impl<T: Float> Session<T> {
+    /// Optimizes `graph` for `outputs`.
+    pub fn new(graph: Graph<T>, outputs: &[Var]) -> Self {
+        let (graph, outputs) = graph.optimize(outputs);
+        let constants = graph
+            .nodes
+            .iter()
+            .map(|n| match &n.op {
+                Op::Constant(x) => Some(ComputedNDA::new(x.clone())),
+                _ => None,
+            })
+            .collect();
+        Self {
+            graph,
+            outputs,
+            constants,
+        }
+    }
+
+    pub fn graph(&self) -> &Graph<T> {
+        &self.graph
+    }
+
+    pub fn outputs(&self) -> &[Var] {
+        &self.outputs
+    }
+
+    /// Computes the outputs with the values of the placeholders given by `feeds`.
+    pub fn run<K: Borrow<str> + Hash + Eq>(
+        &self,
+        feeds: &HashMap<K, NDArray<T>>,
+    ) -> Result<Vec<ComputedNDA<T>>, Error> {
+        let mut values: Vec<Option<ComputedNDA<T>>> = Vec::with_capacity(self.graph.nodes.len());
+        for (node, constant) in self.graph.nodes.iter().zip(&self.constants) {
+            let y = match &node.op {
+                Op::Placeholder(name) => match feeds.get(name.as_str()) {
+                    Some(x) => ComputedNDA::new(x.clone()).named(name.clone()),
+                    None => return Err(Error::MissingFeed { name: name.clone() }),
+                },
+                Op::Constant(_) => constant.clone().unwrap(),
+                Op::Param(p) => p.get(),
+                op => {
+                    let xs: Vec<_> = node
+                        .inputs
+                        .iter()
+                        .map(|x| values[x.0].clone().unwrap())
+                        .collect();
+                    op.apply(&xs)?
+                }
+            };
+            values.push(Some(y));
+        }
+        Ok(self
+            .outputs
+            .iter()
+            .map(|x| values[x.0].clone().unwrap())
+            .collect())
+    }
+
+    /// Same as `run` but panics on errors.
+    pub fn run_or_panic<K: Borrow<str> + Hash + Eq>(
+        &self,
+        feeds: &HashMap<K, NDArray<T>>,
+    ) -> Vec<ComputedNDA<T>> {
+        unwrap_or_panic(self.run(feeds))
+    }
+}
+
+#[test]
+fn test() {
+    use ndarray::array;
+
+    let p = ParamNDA::new(
+        array![[0.5], [-1.0]].into_ndarray(),
+        "w".into(),
+        optimizers::SGD::new(0.1),
+    );
+
+    let mut g = Graph::new();
+    let x = g.placeholder("x");
+    let w = g.param(&p);
+    let c1 = g.constant(array![2.0].into_ndarray());
+    let c2 = g.constant(array![3.0].into_ndarray());
+    let c = g.mul(c1, c2); // folded
+    let y1 = g.matmul(x, w);
+    let y2 = g.matmul(x, w); // the same as y1
+    let y = g.add(y1, y2);
+    let y = g.mul(y, c);
+    let _unused = g.exp(x);
+    let loss = g.sum(y, [0, 1], false);
+
+    let session = Session::new(g.clone(), &[loss]);
+    let names: Vec<_> = session
+        .graph()
+        .nodes()
+        .iter()
+        .map(|n| n.op.name())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "placeholder x",
+            "param w",
+            "constant [1]",
+            "matmul",
+            "add",
+            "mul",
+            "sum [0, 1] false"
+        ]
+    );
+
+    let feeds = HashMap::from([("x", array![[1.0, 2.0], [3.0, 4.0]].into_ndarray())]);
+    let eager = {
+        let x = ComputedNDA::new(feeds["x"].clone());
+        let y = x.matmul(&p.get());
+        let y = &(&y + &y) * &ComputedNDA::new(array![6.0].into_ndarray());
+        y.sum(vec![0, 1], false)
+    };
+    let loss = &session.run(&feeds).unwrap()[0];
+    assert_eq!(&**loss, &*eager);
+    assert_eq!(
+        &*gradients(&[loss.clone()], &[p.get()], false)[0],
+        &*gradients(&[eager], &[p.get()], false)[0]
+    );
+
+    assert_eq!(
+        session.run(&HashMap::<&str, _>::new()).err(),
+        Some(Error::MissingFeed { name: "x".into() })
+    );
+    let feeds = HashMap::from([("x", array![[1.0, 2.0, 3.0]].into_ndarray())]);
+    assert!(session.run(&feeds).is_err());
+}
+
+#[test]
+fn test_from_recorded() {
+    use ndarray::array;
+
+    let p = ParamNDA::new(
+        array![[0.5], [-1.0]].into_ndarray(),
+        "w".into(),
+        optimizers::SGD::new(0.1),
+    );
+    let f = |x: &ComputedNDA| {
+        let c = ComputedNDA::new(array![2.0].into_ndarray()) * ComputedNDA::new(scalar(3.0));
+        let y1 = x.matmul(&p.get());
+        let y2 = x.matmul(&p.get());
+        let y = (&y1 + &y2) * c;
+        (y.sum([1], true).tanh() - y.exp()).sum([0, 1], false)
+    };
+    let x = backprop(array![[1.0, 2.0], [3.0, 4.0]].into_ndarray()).named("x");
+    let loss = f(&x);
+
+    let (g, outputs) = Graph::from_recorded(&[loss.clone()]).unwrap();
+    assert_eq!(g.placeholders(), ["x"]);
+    let session = Session::new(g, &outputs);
+    // the constants are folded and the two matmuls are merged
+    let count = |name: &str| {
+        let nodes = session.graph().nodes();
+        nodes.iter().filter(|n| n.op.name() == name).count()
+    };
+    assert_eq!(
+        (count("matmul"), count("param w"), count("constant [1]")),
+        (1, 1, 1)
+    );
+
+    // the session computes the same with the new feeds
+    let x = array![[0.5, -1.0]].into_ndarray();
+    let y = &session.run(&HashMap::from([("x", x.clone())])).unwrap()[0];
+    let eager = f(&ComputedNDA::new(x));
+    assert_eq!(&**y, &*eager);
+    assert_eq!(
+        &*gradients(&[y.clone()], &[p.get()], false)[0],
+        &*gradients(&[eager], &[p.get()], false)[0]
+    );
+
+    // the shape functions are rebuilt from their arguments
+    let x = backprop(array![[[1.0, 2.0, 3.0]], [[4.0, 5.0, 6.0]]].into_ndarray()).named("x");
+    let f = |x: &ComputedNDA| {
+        x.transpose([2, 0, 1])
+            .sum([1], false)
+            .t()
+            .broadcast([4, 1, 3])
+    };
+    let (g, outputs) = Graph::from_recorded(&[f(&x)]).unwrap();
+    let session = Session::new(g, &outputs);
+    let x = array![[[1.0, 2.0, 3.0]], [[4.0, 5.0, 6.0]], [[7.0, 8.0, 9.0]]].into_ndarray();
+    let y = &session.run(&HashMap::from([("x", x.clone())])).unwrap()[0];
+    assert_eq!(&**y, &*f(&ComputedNDA::new(x)));
+
+    // functions without an operation
+    let x = backprop(array![[1.0, 2.0]].into_ndarray()).named("x");
+    assert_eq!(
+        Graph::from_recorded(&[x.pow_const(2.0)]).err(),
+        Some(Error::UnliftableFunction {
+            function: "pow_const".into()
+        })
+    );
+}
@@ -0,0 +1,262 @@
+use std::marker::PhantomData;
+
+use ndarray::{Array, Axis, Dimension, IntoDimension, Ix0, Ix2, Ix3, Ix4};
+
+use crate::{error::unwrap_or_panic, *};
+
+/// A `ComputedNDA` whose rank is known at compile time.
+///
+/// The ranks of the operands of `matmul`, `transpose`, `reshape` and `sum` are checked
+/// statically, while the sizes of the axes are still checked at runtime.
+///
+/// ```
+/// use tensorflake::{ndarray::{array, Axis}, Tensor};
+///
+/// let x = Tensor::backprop(array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]);
+/// let w = Tensor::from_array(array![[1.0], [-1.0]]);
+/// let y = x.matmul(&w).sum(Axis(1));
+/// assert_eq!(y.dim(), 3);
+/// ```
+pub struct Tensor<D: Dimension, T: Float = f32> {
+    inner: ComputedNDA<T>,
+    _dim: PhantomData<D>,
+}
+
+impl<D: Dimension, T: Float> Tensor<D, T> {
+    /// Wraps `x`. Panics if the rank of `x` is not the rank of `D`.
+    pub fn new(x: ComputedNDA<T>) -> Self {
+        unwrap_or_panic(Self::try_new(x))
+    }
+
+    pub fn try_new(x: ComputedNDA<T>) -> Result<Self, Error> {
+        match D::NDIM {
+            Some(ndim) if ndim != x.ndim() => Err(Error::RankMismatch {
+                expected: ndim,
+                shape: x.shape().to_vec(),
+            }),
+            _ => Ok(Self {
+                inner: x,
+                _dim: PhantomData,
+            }),
+        }
+    }
+
+    pub fn from_array(x: Array<T, D>) -> Self {
+        Self::new(ComputedNDA::new(x.into_ndarray()))
+    }
+
+    pub fn backprop(x: Array<T, D>) -> Self {
+        Self::new(backprop(x.into_ndarray()))
+    }
+
+    pub fn computed(&self) -> &ComputedNDA<T> {
+        &self.inner
+    }
+
+    pub fn into_computed(self) -> ComputedNDA<T> {
+        self.inner
+    }
+
+    pub fn dim(&self) -> D::Pattern {
+        self.inner.view().into_dimensionality::<D>().unwrap().dim()
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        self.inner.shape()
+    }
+
+    /// Applies a function that keeps the rank, such as an element-wise function or an activation.
+    /// Panics if the rank is changed.
+    pub fn map(&self, f: impl FnOnce(&ComputedNDA<T>) -> ComputedNDA<T>) -> Self {
+        Self::new(f(&self.inner))
+    }
+
+    pub fn matmul<E: Dimension>(&self, rhs: &Tensor<E, T>) -> Tensor<<D as MatMulDim<E>>::Output, T>
+    where
+        D: MatMulDim<E>,
+    {
+        unwrap_or_panic(self.try_matmul(rhs))
+    }
+
+    pub fn try_matmul<E: Dimension>(
+        &self,
+        rhs: &Tensor<E, T>,
+    ) -> Result<Tensor<<D as MatMulDim<E>>::Output, T>, Error>
+    where
+        D: MatMulDim<E>,
+    {
+        functions::try_matmul(&self.inner, &rhs.inner).map(Tensor::new)
+    }
+
+    pub fn transpose(&self, axes: impl IntoDimension<Dim = D>) -> Self {
+        unwrap_or_panic(self.try_transpose(axes))
+    }
+
+    pub fn try_transpose(&self, axes: impl IntoDimension<Dim = D>) -> Result<Self, Error> {
+        let axes = axes.into_dimension();
+        functions::try_transpose(&self.inner, axes.slice()).map(Self::new)
+    }
+
+    pub fn reshape<E: Dimension>(&self, shape: impl IntoDimension<Dim = E>) -> Tensor<E, T> {
+        unwrap_or_panic(self.try_reshape(shape))
+    }
+
+    pub fn try_reshape<E: Dimension>(
+        &self,
+        shape: impl IntoDimension<Dim = E>,
+    ) -> Result<Tensor<E, T>, Error> {
+        let shape = shape.into_dimension();
+        functions::try_reshape(&self.inner, shape.slice()).map(Tensor::new)
+    }
+
+    /// Sums over `axis` removing it.
+    pub fn sum(&self, axis: Axis) -> Tensor<D::Smaller, T> {
+        unwrap_or_panic(self.try_sum(axis))
+    }
+
+    pub fn try_sum(&self, axis: Axis) -> Result<Tensor<D::Smaller, T>, Error> {
+        functions::try_sum(&self.inner, [axis.index()], false).map(Tensor::new)
+    }
+
+    /// Sums over `axis` keeping it with the size 1.
+    pub fn sum_keep_dim(&self, axis: Axis) -> Self {
+        unwrap_or_panic(functions::try_sum(&self.inner, [axis.index()], true).map(Self::new))
+    }
+
+    pub fn sum_all(&self) -> Tensor<Ix0, T> {
+        Tensor::new(functions::sum(
+            &self.inner,
+            Vec::from_iter(0..self.inner.ndim()),
+            false,
+        ))
+    }
+}
+
+impl<T: Float> Tensor<Ix2, T> {
+    pub fn t(&self) -> Self {
+        Self::new(functions::t(&self.inner))
+    }
+}
+
+impl<D: Dimension, T: Float> Clone for Tensor<D, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _dim: PhantomData,
+        }
+    }
+}
+
+impl<D: Dimension, T: Float> std::fmt::Debug for Tensor<D, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Tensor").field(&*self.inner).finish()
+    }
+}
+
+impl<D: Dimension, T: Float> From<Tensor<D, T>> for ComputedNDA<T> {
+    fn from(x: Tensor<D, T>) -> Self {
+        x.inner
+    }
+}
+
+/// Ranks of the operands of `Tensor::matmul`, which multiplies (batches of) matrices.
+pub trait MatMulDim<Rhs: Dimension>: Dimension {
+    type Output: Dimension;
+}
+
+macro_rules! impl_matmul_dim {
+    ($($lhs:ty, $rhs:ty => $output:ty;)*) => {
+        $(
+            impl MatMulDim<$rhs> for $lhs {
+                type Output = $output;
+            }
+        )*
+    };
+}
+
+impl_matmul_dim! {
+    Ix2, Ix2 => Ix2;
+    Ix3, Ix2 => Ix3;
+    Ix2, Ix3 => Ix3;
+    Ix3, Ix3 => Ix3;
+    Ix4, Ix2 => Ix4;
+    Ix2, Ix4 => Ix4;
+    Ix4, Ix4 => Ix4;
+}
+
+macro_rules! impl_op {
+    ($op:ident, $fn:ident) => {
+        impl<D: Dimension, T: Float> std::ops::$op for &Tensor<D, T> {
+            type Output = Tensor<D, T>;
+
+            fn $fn(self, rhs: Self) -> Self::Output {
+                Tensor::new(functions::$fn(&self.inner, &rhs.inner))
+            }
+        }
+
+        impl<D: Dimension, T: Float> std::ops::$op for Tensor<D, T> {
+            type Output = Tensor<D, T>;
+
+            fn $fn(self, rhs: Self) -> Self::Output {
+                Tensor::new(functions::$fn(&self.inner, &rhs.inner))
+            }
+        }
+    };
+}
+
+impl_op!(Add, add);
+impl_op!(Sub, sub);
+impl_op!(Mul, mul);
+impl_op!(Div, div);
+
+impl<D: Dimension, T: Float> std::ops::Neg for &Tensor<D, T> {
+    type Output = Tensor<D, T>;
+
+    fn neg(self) -> Self::Output {
+        Tensor::new(functions::neg(&self.inner))
+    }
+}
+
+impl<D: Dimension, T: Float> std::ops::Neg for Tensor<D, T> {
+    type Output = Tensor<D, T>;
+
+    fn neg(self) -> Self::Output {
+        Tensor::new(functions::neg(&self.inner))
+    }
+}
+
+#[test]
+fn test() {
+    use ndarray::{array, Ix1};
+
+    let x = Tensor::backprop(array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+    let w = Tensor::backprop(array![[1.0], [0.0], [-1.0]]);
+    let y: Tensor<Ix2> = x.matmul(&w);
+    assert_eq!(y.dim(), (2, 1));
+    let y: Tensor<Ix1> = (&y * &y).sum(Axis(1));
+    assert_eq!(y.dim(), 2);
+    let loss = y.sum_all();
+    let gs = gradients(&[loss.into()], &[w.computed().clone()], false);
+    assert_eq!(&*gs[0], &array![[-20.0], [-28.0], [-36.0]].into_ndarray());
+
+    let z = x.t().reshape((3, 1, 2)).transpose((2, 1, 0));
+    assert_eq!(z.dim(), (2, 1, 3));
+    assert_eq!(z.matmul(&w).dim(), (2, 1, 1));
+
+    // the sizes are still checked at runtime
+    assert_eq!(
+        x.try_matmul(&x).err(),
+        Some(Error::IncompatibleShapes {
+            function: "matmul".into(),
+            shapes: vec![vec![2, 3], vec![2, 3]],
+        })
+    );
+    assert!(x.try_reshape((4, 2)).is_err());
+    assert_eq!(
+        Tensor::<Ix2>::try_new(ComputedNDA::new(scalar(1.0))).err(),
+        Some(Error::RankMismatch {
+            expected: 2,
+            shape: vec![],
+        })
+    );
+}
@@ -16,6 +16,10 @@ pub struct TrainConfig<T> {
     pub shuffle: bool,
     pub update_strategy: UpdateStrategy,
     pub update_async: bool,
+    /// Traces the training closure and replays it while the shapes do not change. See `Trace`.
+    pub compile: bool,
+    /// Clips the gradients before each update. The norm before clipping is added to the metrics.
+    pub clip_grad: Option<ClipGrad>,
 }
 
 impl<T> Default for TrainConfig<T> {
@@ -31,6 +35,8 @@ impl<T> Default for TrainConfig<T> {
             shuffle: true,
             update_strategy: UpdateStrategy::Chunk(1),
             update_async: false,
+            compile: false,
+            clip_grad: None,
         }
     }
 }
@@ -45,6 +51,7 @@ pub struct Train<T> {
     pub config: TrainConfig<T>,
     rng: rand_isaac::Isaac64Rng,
     shuffle_table: Vec<usize>,
+    traces: Mutex<Vec<Trace<NDArray>>>,
     pub epoch: usize,
 }
 
@@ -59,6 +66,7 @@ impl<T> Train<T> {
         Self {
             rng: rand_isaac::Isaac64Rng::seed_from_u64(42),
             shuffle_table: (0..config.train_data.len()).collect(),
+            traces: Mutex::new(Vec::new()),
             epoch: config.initial_epoch - 1,
             config,
         }
@@ -98,7 +106,7 @@ impl<T: Sync + Send> Train<T> {
             let progress = Arc::new(Mutex::new(Progress::new(self.config.train_data.len())));
 
             for batch in self.shuffle_table.chunks(self.config.batch_size) {
-                let (metrics, mut ga, _) = batch
+                let (mut metrics, mut ga, samples) = batch
                     .par_chunks(self.config.parallel_chunk_size)
                     .map(|shuffle_table| {
                         let data = shuffle_table
@@ -106,7 +114,7 @@ impl<T: Sync + Send> Train<T> {
                             .map(|i| &self.config.train_data[*i])
                             .collect::<Vec<_>>();
                         let mut ctx = ctx.child();
-                        f(&data, &mut ctx);
+                        self.run_traced(|| f(&data, &mut ctx));
                         let samples = ctx.metrics.total;
                         if batch.len() <= self.config.parallel_chunk_size {
                             progress.lock().unwrap().update(samples);
@@ -120,14 +128,14 @@ impl<T: Sync + Send> Train<T> {
                             a.1.merge(b.1);
                             a.2 += b.2;
                             if a.2 > samples_threshold {
-                                a.1.optimize();
+                                self.optimize(&mut a.1, &mut a.0, a.2);
                                 a.2 = 0;
                             }
                             a
                         },
                     );
+                self.optimize(&mut ga, &mut metrics, samples);
                 ctx.merge_metrics(metrics);
-                ga.optimize();
                 ctx.print_progress();
             }
             ctx.print_result();
@@ -144,6 +152,7 @@ impl<T: Sync + Send> Train<T> {
                 .validation_data
                 .par_chunks(self.config.parallel_chunk_size.min(self.config.batch_size))
                 .map(|data| {
+                    let _guard = no_grad();
                     let data: Vec<_> = data.iter().collect();
                     let mut ctx = ctx.child();
                     f(&data, &mut ctx);
@@ -167,8 +176,8 @@ impl<T: Sync + Send> Train<T> {
                     .iter()
                     .map(|i| &self.config.train_data[*i])
                     .collect::<Vec<_>>();
-                f(&data, &mut ctx);
-                ctx.gradients_accumulator.optimize();
+                self.run_traced(|| f(&data, &mut ctx));
+                self.optimize(&mut ctx.gradients_accumulator, &mut ctx.metrics, data.len());
                 ctx.print_progress();
             }
             ctx.print_result();
@@ -178,6 +187,7 @@ impl<T: Sync + Send> Train<T> {
                 return;
             }
 
+            let _guard = no_grad();
             let mut ctx = self.context(false);
             for data in self.config.validation_data.chunks(self.config.batch_size) {
                 let data: Vec<_> = data.iter().collect();
@@ -197,6 +207,40 @@ impl<T: Sync + Send> Train<T> {
         }
     }
 
+    /// Updates the params with the gradients of `samples` samples, clipping them if configured.
+    fn optimize(
+        &self,
+        ga: &mut GradientsAccumulator<NDArray>,
+        metrics: &mut Metrics,
+        samples: usize,
+    ) {
+        if ga.table.is_empty() {
+            return;
+        }
+        match self.config.clip_grad {
+            Some(ClipGrad::Norm(max_norm)) => {
+                let norm = ga.clip_grad_norm(max_norm);
+                metrics.add(metrics::GradNorm::new(norm, samples));
+            }
+            Some(ClipGrad::Value(value)) => {
+                metrics.add(metrics::GradNorm::new(ga.grad_norm(), samples));
+                ga.clip_grad_value(value);
+            }
+            None => {}
+        }
+        ga.optimize();
+    }
+
+    /// Runs `f` with a trace of the pool if `compile` is enabled.
+    fn run_traced(&self, f: impl FnOnce()) {
+        if !self.config.compile {
+            return f();
+        }
+        let mut trace = self.traces.lock().unwrap().pop().unwrap_or_default();
+        trace.run(f);
+        self.traces.lock().unwrap().push(trace);
+    }
+
     fn context(&self, train: bool) -> TrainContext {
         TrainContext {
             total: Some(if train {
@@ -224,6 +268,15 @@ pub enum UpdateStrategy {
     Batch,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum ClipGrad {
+    /// Scales the gradients so that their total L2 norm is at most the value.
+    /// See `GradientsAccumulator::clip_grad_norm`.
+    Norm(f32),
+    /// Clamps each element of the gradients. See `GradientsAccumulator::clip_grad_value`.
+    Value(f32),
+}
+
 pub struct TrainContext {
     pub total: Option<usize>,
     pub epoch: usize,
@@ -257,6 +310,25 @@ impl TrainContext {
         }
     }
 
+    /// Same as `optimize`, but releases the graph of `loss` during the backward to reduce
+    /// the peak memory. Other losses sharing intermediate values with `loss` no longer get
+    /// gradients through them, so call this only for the last loss of the graph.
+    /// See `GradientsAccumulator::compute_and_release`.
+    pub fn optimize_and_release(&mut self, loss: &ComputedNDA) {
+        if self.train {
+            self.gradients_accumulator.compute_and_release(loss);
+        }
+    }
+
+    /// Same as `optimize`, but takes the losses of the examples of the shape `[batch size]`
+    /// and clips the gradients of each example to the L2 norm `max_norm`, as `optimizers::DpSgd` needs.
+    /// See `GradientsAccumulator::compute_clipped`.
+    pub fn optimize_clipped(&mut self, losses: &ComputedNDA, max_norm: f32) {
+        if self.train {
+            self.gradients_accumulator.compute_clipped(losses, max_norm);
+        }
+    }
+
     pub fn count(&mut self, n: usize) {
         self.metrics.count(n);
     }
@@ -337,8 +409,71 @@ fn test() {
     .build()
     .fit(|batch, ctx| {
         std::thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(is_grad_enabled(), ctx.train);
         let loss = ComputedNDA::new(scalar(0.0));
         ctx.finish_batch(&loss, batch.len());
         ctx.add_metric(metrics::Loss::new(loss[[]], batch.len()));
     })
 }
+
+#[test]
+fn test_compile() {
+    use ndarray::array;
+
+    let fit = |compile: bool| {
+        let init = initializers::with_optimizer::InitializerWithOptimizer::new(
+            initializers::random_initializer::RandomInitializer::new(
+                ndarray_rand::rand_distr::Uniform::new(-0.1f32, 0.1),
+            ),
+            optimizers::Adam::new(),
+        );
+        let linear = nn::Linear::new(2, 1, init.clone(), Some(init));
+        TrainConfig {
+            epoch: 3,
+            train_data: (0..30)
+                .map(|i| [i as f32 / 30.0, 1.0 - i as f32 / 15.0])
+                .collect(),
+            batch_size: 5,
+            compile,
+            ..Default::default()
+        }
+        .build()
+        .fit(|batch, ctx| {
+            let x: Vec<_> = batch.iter().flat_map(|x| x.iter().copied()).collect();
+            let x = ComputedNDA::new(NDArray::from_shape_vec(&[batch.len(), 2][..], x).unwrap());
+            let y = linear.call(x, ctx.train);
+            let loss = (&y - &ComputedNDA::new(array![[1.0]].into_ndarray()))
+                .pow_const(2.0)
+                .sum(vec![0, 1], false);
+            ctx.finish_batch(&loss, batch.len());
+        });
+        (*linear.w.get()).clone()
+    };
+    assert_eq!(fit(true), fit(false));
+}
+
+#[test]
+fn test_clip_grad() {
+    let p = ParamNDA::new(scalar(0.0), "p".into(), optimizers::SGD::new(1.0));
+    let mut train = TrainConfig {
+        train_data: (0..20).collect(),
+        batch_size: 10,
+        clip_grad: Some(ClipGrad::Norm(1.0)),
+        ..Default::default()
+    }
+    .build();
+    train.fit(|batch, ctx| {
+        let loss = &p.get() * &ComputedNDA::new(scalar(100.0));
+        ctx.finish_batch(&loss, batch.len());
+    });
+    // the gradient 100 is clipped to 1 on each of the 2 updates
+    assert_eq!(p.get()[[]], -2.0);
+
+    let mut ga = GradientsAccumulator::new();
+    ga.push(p.clone(), scalar(3.0));
+    let mut metrics = Metrics::new();
+    metrics.count(10);
+    train.optimize(&mut ga, &mut metrics, 10);
+    assert!(metrics.display_metrics().contains("grad_norm: 3.0000"));
+    assert_eq!(p.get()[[]], -3.0);
+}